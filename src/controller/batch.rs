@@ -0,0 +1,175 @@
+//! Analysing a list of positions (FENs or EPD records, one per line) to a fixed depth, e.g. to
+//! annotate a repertoire. The transposition table is kept from one position to the next and
+//! the positions are searched in an order keeping similar ones together, so that a position a
+//! move or two away from the previous one starts from what its search already found.
+
+use crate::controller::controller::GameController;
+use crate::engine::search::results::SearchResult;
+use crate::error::ProkopakopError;
+use crate::game::board::Game;
+use crate::game::pieces::{Color, Piece};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::time::Instant;
+use strum::IntoEnumIterator;
+
+/// Configuration for the analyze-batch mode
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub input: String,          // one FEN or EPD record per line
+    pub depth: usize,           // searched to in every position
+    pub output: Option<String>, // the annotated records, stdout if not given
+}
+
+/// How far apart two positions are: the pieces that would have to disappear or appear to get
+/// from one to the other (two for a quiet move, three for a capture), and one more for a
+/// different side to move
+pub(crate) fn position_distance(a: &Game, b: &Game) -> u32 {
+    let mut distance = (a.side != b.side) as u32;
+
+    for color in [Color::White, Color::Black] {
+        for piece in Piece::iter() {
+            let a_pieces = a.color_bitboards[color as usize] & a.piece_bitboards[piece as usize];
+            let b_pieces = b.color_bitboards[color as usize] & b.piece_bitboards[piece as usize];
+            distance += (a_pieces ^ b_pieces).count_ones();
+        }
+    }
+
+    distance
+}
+
+/// Order in which to search the positions: starting with the first one, always the closest
+/// of the remaining ones to the last (the earlier one in the list when tied)
+pub(crate) fn similarity_order(games: &[Game]) -> Vec<usize> {
+    let mut remaining = (0..games.len()).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(games.len());
+
+    while !remaining.is_empty() {
+        let next = match order.last() {
+            Some(&last) => remaining
+                .iter()
+                .enumerate()
+                .min_by_key(|&(_, &index)| position_distance(&games[last], &games[index]))
+                .map(|(position, _)| position)
+                .unwrap(),
+            None => 0,
+        };
+
+        order.push(remaining.remove(next));
+    }
+
+    order
+}
+
+/// The position as an EPD record with the result of its search: the best move, the score in
+/// centipawns (side to move's view), the depth and the principal variation
+pub(crate) fn annotate(
+    record: &str,
+    game: &Game,
+    result: Option<&SearchResult>,
+    depth: usize,
+) -> String {
+    let position = record
+        .split_whitespace()
+        .take(4)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let Some(result) = result else {
+        return position;
+    };
+
+    let mut game = game.clone();
+    let best_move = game.move_to_san(result.best_move);
+
+    let mut pv = Vec::with_capacity(result.pv.len());
+    for &board_move in &result.pv {
+        pv.push(game.move_to_san(board_move));
+        game.make_move(board_move);
+    }
+
+    format!(
+        "{} bm {}; ce {}; acd {}; pv {};",
+        position,
+        best_move,
+        result.evaluation,
+        depth,
+        pv.join(" ")
+    )
+}
+
+/// Read the positions of the input file, skipping empty lines and `#` comments
+fn read_positions(path: &str) -> Result<Vec<(String, Game)>, ProkopakopError> {
+    let mut positions = Vec::new();
+
+    for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let game = Game::from_fen(line)
+            .and_then(|game| game.validate().map(|_| game))
+            .map_err(|e| ProkopakopError::InvalidFen(format!("line {}: {}", number + 1, e)))?;
+
+        positions.push((line.to_string(), game));
+    }
+
+    Ok(positions)
+}
+
+/// Search every position of the input to the given depth and write the annotated records, in
+/// the order of the input
+pub fn run_batch_analysis(config: &BatchConfig) -> Result<(), ProkopakopError> {
+    let positions = read_positions(&config.input)?;
+    let games = positions
+        .iter()
+        .map(|(_, game)| game.clone())
+        .collect::<Vec<_>>();
+
+    let mut controller = GameController::new();
+    controller.initialize();
+    controller.search_options.silent = true; // the records may go to stdout
+
+    let mut annotated = vec![String::new(); positions.len()];
+    let mut nodes = 0;
+    let start = Instant::now();
+
+    for (done, index) in similarity_order(&games).into_iter().enumerate() {
+        let (record, game) = &positions[index];
+        controller.set_board_from_fen(record)?;
+
+        let (move_count, _) = controller.game.get_moves();
+        let result = if move_count > 0 {
+            controller.search(vec!["depth".to_string(), config.depth.to_string()], false);
+            let result = controller.wait_for_search();
+            nodes += controller.last_node_counts().total();
+            result
+        } else {
+            None
+        };
+
+        annotated[index] = annotate(record, game, result.as_ref(), config.depth);
+        eprintln!("[{}/{}] {}", done + 1, positions.len(), annotated[index]);
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!(
+        "Analysed {} positions to depth {} in {:.1} s ({} nodes)",
+        positions.len(),
+        config.depth,
+        elapsed,
+        nodes
+    );
+
+    let mut output: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    for line in annotated {
+        writeln!(output, "{}", line)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,294 @@
+//! Microbenchmarks of the hot paths (move generation, make/unmake, the evaluations and copying
+//! positions for search threads) over a fixed set of positions. They are timed by hand, so no
+//! benchmarking crate is needed, and the results can be saved and compared against those of
+//! another commit. The same positions also give a checksum of the move generator, to compare
+//! builds on different platforms with.
+
+use crate::engine::evaluate::evaluate_classical;
+use crate::game::board::Game;
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Positions from all phases of the game, quiet and tactical ones alike
+pub(crate) const BENCH_FENS: &[&str] = &[
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2r3k1/pp3ppp/2n1b3/q2pP3/3P4/P1Q2N2/1P3PPP/R4RK1 b - - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "8/8/4k3/8/2P5/8/3K4/8 w - - 0 1",
+];
+
+// Plies played from each position for the cloning benchmarks, so that there is a history to copy
+const CLONE_PLIES: usize = 80;
+
+// Each benchmark is timed in this many samples, of which the median is reported
+const SAMPLES: usize = 10;
+
+// FNV-1a, which unlike the std hashers is specified, so the same on every platform and build
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub sample_time: Duration,
+    pub save: Option<String>,     // file to save the results to
+    pub baseline: Option<String>, // file with saved results to compare against
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub ns_per_op: f64, // median of the samples
+    pub spread: f64,    // (slowest - fastest sample) / median
+}
+
+/// Time the routine (a pass over the positions, returning the number of operations it did)
+/// in several samples of at least the sample time each
+fn measure(name: &str, sample_time: Duration, mut routine: impl FnMut() -> u64) -> BenchResult {
+    // warm up the caches and the branch predictors
+    routine();
+
+    let mut samples = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            let mut ops = 0;
+            while start.elapsed() < sample_time || ops == 0 {
+                ops += routine();
+            }
+            start.elapsed().as_nanos() as f64 / ops as f64
+        })
+        .collect::<Vec<_>>();
+    samples.sort_by(f64::total_cmp);
+
+    let median = samples[SAMPLES / 2];
+    BenchResult {
+        name: name.to_string(),
+        ns_per_op: median,
+        spread: (samples[SAMPLES - 1] - samples[0]) / median,
+    }
+}
+
+/// Run all benchmarks over the positions
+pub fn run_benchmarks(sample_time: Duration) -> Vec<BenchResult> {
+    let mut games = BENCH_FENS
+        .iter()
+        .map(|fen| Game::from_fen(fen).expect("Invalid benchmark FEN"))
+        .collect::<Vec<_>>();
+
+    let get_moves = measure("get_moves", sample_time, || {
+        for game in &games {
+            black_box(game.get_moves());
+        }
+        games.len() as u64
+    });
+
+    let make_unmake = measure("make_unmake", sample_time, || {
+        let mut ops = 0;
+        for game in &mut games {
+            let (move_count, moves) = game.get_moves();
+            for &board_move in &moves[..move_count] {
+                game.make_move(black_box(board_move));
+                game.unmake_move();
+            }
+            ops += move_count as u64;
+        }
+        ops
+    });
+
+    let evaluate = measure("evaluate_hce", sample_time, || {
+        for game in &games {
+            black_box(evaluate_classical(game));
+        }
+        games.len() as u64
+    });
+
+    let evaluate_nnue = measure("evaluate_nnue", sample_time, || {
+        for game in &games {
+            black_box(game.evaluate_network_stm());
+        }
+        games.len() as u64
+    });
+
+    let played = games
+        .iter()
+        .map(|game| {
+            let mut game = game.clone();
+            for _ in 0..CLONE_PLIES {
+                let (move_count, moves) = game.get_moves();
+                if move_count == 0 {
+                    break;
+                }
+                game.make_move(moves[0]);
+            }
+            game
+        })
+        .collect::<Vec<_>>();
+
+    let clone = measure("clone", sample_time, || {
+        for game in &played {
+            black_box(game.clone());
+        }
+        played.len() as u64
+    });
+
+    let fork = measure("fork", sample_time, || {
+        for game in &played {
+            black_box(game.fork());
+        }
+        played.len() as u64
+    });
+
+    vec![get_moves, make_unmake, evaluate, evaluate_nnue, clone, fork]
+}
+
+/// Fingerprint of the move generator over the benchmark positions, depending on neither the
+/// hash table nor the evaluation: the perft node counts, and every move list in the order the
+/// moves were generated in (which search results depend on, even when the counts agree)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovegenChecksum {
+    pub nodes: u64,
+    pub perft: u64, // hash of the node count of each position
+    pub moves: u64, // hash of the move lists of all the nodes
+}
+
+impl MovegenChecksum {
+    /// Both hashes as one short value to compare
+    pub fn fingerprint(&self) -> u64 {
+        fnv_hash(
+            fnv_hash(FNV_OFFSET, &self.perft.to_le_bytes()),
+            &self.moves.to_le_bytes(),
+        )
+    }
+}
+
+impl std::fmt::Display for MovegenChecksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Nodes:        {}", self.nodes)?;
+        writeln!(f, "Perft:        {:016x}", self.perft)?;
+        writeln!(f, "Move lists:   {:016x}", self.moves)?;
+        write!(f, "Fingerprint:  {:016x}", self.fingerprint())
+    }
+}
+
+fn fnv_hash(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Hash the move lists to the depth into `moves`, returning the number of leaves
+fn checksum_moves(game: &mut Game, depth: usize, moves: &mut u64) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let (move_count, move_list) = game.get_moves();
+    *moves = fnv_hash(*moves, &(move_count as u16).to_le_bytes());
+
+    let mut nodes = 0;
+    for &board_move in &move_list[..move_count] {
+        *moves = fnv_hash(*moves, &board_move.to_bits().to_le_bytes());
+
+        game.make_move(board_move);
+        nodes += checksum_moves(game, depth - 1, moves);
+        game.unmake_move();
+    }
+
+    nodes
+}
+
+/// Checksum of the move generator to the depth, over the benchmark positions
+pub fn movegen_checksum(depth: usize) -> MovegenChecksum {
+    let mut checksum = MovegenChecksum {
+        nodes: 0,
+        perft: FNV_OFFSET,
+        moves: FNV_OFFSET,
+    };
+
+    for fen in BENCH_FENS {
+        let mut game = Game::from_fen(fen).expect("Invalid benchmark FEN");
+        let nodes = checksum_moves(&mut game, depth, &mut checksum.moves);
+
+        checksum.nodes += nodes;
+        checksum.perft = fnv_hash(checksum.perft, &nodes.to_le_bytes());
+    }
+
+    checksum
+}
+
+fn save_results(path: &str, results: &[BenchResult]) -> Result<(), String> {
+    let timings = results
+        .iter()
+        .map(|result| (result.name.clone(), json!(result.ns_per_op)))
+        .collect::<Map<_, _>>();
+
+    let saved = json!({ "commit": env!("GIT_HASH"), "results": timings });
+    fs::write(path, format!("{:#}\n", saved))
+        .map_err(|e| format!("Failed to save the results to {}: {}", path, e))
+}
+
+/// The commit and the timings (ns per operation) of saved results
+pub(crate) fn load_results(path: &str) -> Result<(String, Map<String, Value>), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read the baseline {}: {}", path, e))?;
+    let saved: Value =
+        serde_json::from_str(&text).map_err(|e| format!("Invalid baseline {}: {}", path, e))?;
+
+    let commit = saved["commit"].as_str().unwrap_or("unknown").to_string();
+    let Some(timings) = saved["results"].as_object() else {
+        return Err(format!("Invalid baseline {}: no results", path));
+    };
+
+    Ok((commit, timings.clone()))
+}
+
+/// One line of the report, with the change against the baseline (if it has the benchmark)
+pub(crate) fn format_result(result: &BenchResult, baseline: Option<f64>) -> String {
+    let line = format!(
+        "{:<14} {:>10.1} ns/op  ±{:>4.1}%",
+        result.name,
+        result.ns_per_op,
+        result.spread * 100.0
+    );
+
+    match baseline {
+        Some(before) => format!(
+            "{}  {:>10.1} ns/op  {:>+6.1}%",
+            line,
+            before,
+            (result.ns_per_op / before - 1.0) * 100.0
+        ),
+        None => line,
+    }
+}
+
+pub fn run_bench(config: BenchConfig) -> Result<(), String> {
+    // read the baseline first, so that a wrong path doesn't waste a whole run
+    let baseline = config.baseline.as_deref().map(load_results).transpose()?;
+
+    let results = run_benchmarks(config.sample_time);
+
+    match &baseline {
+        Some((commit, _)) => println!("Commit {} (baseline {})", env!("GIT_HASH"), commit),
+        None => println!("Commit {}", env!("GIT_HASH")),
+    }
+
+    for result in &results {
+        let before = baseline
+            .as_ref()
+            .and_then(|(_, timings)| timings.get(&result.name)?.as_f64());
+        println!("{}", format_result(result, before));
+    }
+
+    if let Some(path) = &config.save {
+        save_results(path, &results)?;
+        eprintln!("Results saved to {}", path);
+    }
+
+    Ok(())
+}
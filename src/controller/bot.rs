@@ -0,0 +1,318 @@
+//! Lichess bot bridge.
+//!
+//! Talks to the Lichess Bot API through `curl` (so the engine doesn't need a TLS stack):
+//! accepts standard challenges with a clock, follows the state stream of each game and
+//! plays the move the controller finds whenever it's the bot's turn.
+
+use crate::controller::controller::{GameController, MoveResultType};
+use crate::game::pieces::Color;
+use serde_json::Value;
+use std::io::{BufRead, BufReader, Lines, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+pub const TOKEN_VARIABLE: &str = "LICHESS_BOT_TOKEN";
+pub const LICHESS_URL: &str = "https://lichess.org";
+
+// Moves travel over the network, which takes longer than talking to a local GUI
+const BOT_MOVE_OVERHEAD_MS: u64 = 300;
+
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub token: String,
+    pub url: String,
+    pub max_games: Option<u32>, // stop after this many games, or keep playing
+    pub book: Option<String>,   // opening book to play from
+    pub book_learning: bool,    // adjust the book weights from the game results
+}
+
+// ============================================================================
+// Lichess API
+// ============================================================================
+
+struct Lichess {
+    token: String,
+    url: String,
+}
+
+impl Lichess {
+    /// A curl request, which reads the authorization header from stdin so that the token
+    /// doesn't show up in the process list
+    fn request(&self, method: &str, path: &str, form: Option<&str>) -> Result<Child, String> {
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--fail", "--no-buffer"])
+            .args(["--request", method, "--header", "@-"]);
+        if let Some(form) = form {
+            command.args(["--data", form]);
+        }
+
+        let mut child = command
+            .arg(format!("{}{}", self.url, path))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to start curl: {}", e))?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        writeln!(stdin, "Authorization: Bearer {}", self.token)
+            .map_err(|e| format!("Failed to pass the token to curl: {}", e))?;
+
+        Ok(child)
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, String> {
+        let output = self
+            .request("GET", path, None)?
+            .wait_with_output()
+            .map_err(|e| format!("Request to {} failed: {}", path, e))?;
+
+        if !output.status.success() {
+            return Err(format!("Request to {} failed", path));
+        }
+
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Invalid response from {}: {}", path, e))
+    }
+
+    fn post(&self, path: &str, form: Option<&str>) -> Result<(), String> {
+        let output = self
+            .request("POST", path, form)?
+            .wait_with_output()
+            .map_err(|e| format!("Request to {} failed: {}", path, e))?;
+
+        match output.status.success() {
+            true => Ok(()),
+            false => Err(format!("Request to {} failed", path)),
+        }
+    }
+
+    /// The events of a streamed (newline delimited JSON) endpoint, until it closes
+    fn stream(&self, path: &str) -> Result<EventStream, String> {
+        let mut child = self.request("GET", path, None)?;
+        let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+        Ok(EventStream { child, lines })
+    }
+}
+
+struct EventStream {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Iterator for EventStream {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        loop {
+            let line = self.lines.next()?.ok()?;
+
+            // empty lines keep the connection alive
+            if let Ok(event) = serde_json::from_str(&line) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// ============================================================================
+// Games
+// ============================================================================
+
+/// Why a challenge is declined (as a Lichess decline reason), or None to accept it
+pub(crate) fn decline_reason(challenge: &Value) -> Option<&'static str> {
+    if challenge["variant"]["key"].as_str() != Some("standard") {
+        return Some("standard");
+    }
+
+    // correspondence games and games without a clock can't be managed by the time control
+    if challenge["timeControl"]["type"].as_str() != Some("clock") {
+        return Some("timeControl");
+    }
+
+    None
+}
+
+/// Set the controller to the position of a game state and return the `go` parameters for
+/// searching it with the remaining clock times
+pub(crate) fn apply_game_state(
+    controller: &mut GameController,
+    initial_fen: Option<&str>,
+    state: &Value,
+) -> Result<Vec<String>, String> {
+    match initial_fen {
+        Some(fen) => controller
+            .set_board_from_fen(fen)
+            .map_err(|e| e.to_string())?,
+        None => controller.reset_board(),
+    }
+
+    for notation in state["moves"].as_str().unwrap_or("").split_whitespace() {
+        if !matches!(controller.try_move_piece(notation), MoveResultType::Success) {
+            return Err(format!("Invalid move in the game state: {}", notation));
+        }
+    }
+
+    let mut params = Vec::new();
+    for name in ["wtime", "btime", "winc", "binc"] {
+        if let Some(value) = state[name].as_u64() {
+            params.push(name.to_string());
+            params.push(value.to_string());
+        }
+    }
+
+    Ok(params)
+}
+
+/// The winner of a finished game state, None for draws (and aborted games)
+pub(crate) fn winner_of(state: &Value) -> Option<Color> {
+    match state["winner"].as_str()? {
+        "white" => Some(Color::White),
+        "black" => Some(Color::Black),
+        _ => None,
+    }
+}
+
+fn play_game(
+    lichess: &Lichess,
+    config: &BotConfig,
+    bot_id: &str,
+    game_id: &str,
+) -> Result<(), String> {
+    let mut controller = GameController::new();
+    controller.initialize();
+    controller.move_overhead = BOT_MOVE_OVERHEAD_MS;
+
+    // the book is opened for every game, so that it has the weights learned from the last one
+    if let Some(book) = &config.book {
+        controller
+            .set_option("BookFile", book)
+            .map_err(|e| e.to_string())?;
+        controller
+            .set_option("BookLearning", &config.book_learning.to_string())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut color = Color::White;
+    let mut initial_fen = None;
+
+    for event in lichess.stream(&format!("/api/bot/game/stream/{}", game_id))? {
+        let state = match event["type"].as_str() {
+            Some("gameFull") => {
+                if event["white"]["id"].as_str() != Some(bot_id) {
+                    color = Color::Black;
+                }
+
+                initial_fen = event["initialFen"]
+                    .as_str()
+                    .filter(|fen| *fen != "startpos")
+                    .map(|fen| fen.to_string());
+
+                event["state"].clone()
+            }
+            Some("gameState") => event,
+            _ => continue,
+        };
+
+        if state["status"].as_str() != Some("started") {
+            eprintln!("Game {} over: {}", game_id, state["status"]);
+
+            apply_game_state(&mut controller, initial_fen.as_deref(), &state)?;
+            controller.learn_from_game(winner_of(&state));
+            break;
+        }
+
+        let params = apply_game_state(&mut controller, initial_fen.as_deref(), &state)?;
+        if controller.game.side != color {
+            continue;
+        }
+
+        controller.search(params, false);
+        let Some(result) = controller.wait_for_search() else {
+            return Err(format!("No move found in game {}", game_id));
+        };
+
+        let path = format!(
+            "/api/bot/game/{}/move/{}",
+            game_id,
+            result.best_move.unparse()
+        );
+        lichess.post(&path, None)?;
+    }
+
+    Ok(())
+}
+
+/// Play on Lichess as the bot account of the token, one game at a time
+pub fn run_bot(config: BotConfig) -> Result<(), String> {
+    let lichess = Lichess {
+        token: config.token.clone(),
+        url: config.url.clone(),
+    };
+
+    let account = lichess.get_json("/api/account")?;
+    let bot_id = account["id"]
+        .as_str()
+        .ok_or("The account response has no id")?
+        .to_string();
+    eprintln!(
+        "Logged in as {}",
+        account["username"].as_str().unwrap_or(&bot_id)
+    );
+
+    let mut games_played = 0;
+
+    for event in lichess.stream("/api/stream/event")? {
+        match event["type"].as_str() {
+            Some("challenge") => {
+                let challenge = &event["challenge"];
+                let Some(id) = challenge["id"].as_str() else {
+                    continue;
+                };
+
+                // the bot's own challenges show up too
+                if challenge["challenger"]["id"].as_str() == Some(bot_id.as_str()) {
+                    continue;
+                }
+
+                let result = match decline_reason(challenge) {
+                    None => lichess.post(&format!("/api/challenge/{}/accept", id), None),
+                    Some(reason) => lichess.post(
+                        &format!("/api/challenge/{}/decline", id),
+                        Some(&format!("reason={}", reason)),
+                    ),
+                };
+
+                // the challenge may have been withdrawn in the meantime
+                if let Err(e) = result {
+                    eprintln!("{}", e);
+                }
+            }
+            Some("gameStart") => {
+                let Some(game_id) = event["game"]["gameId"].as_str() else {
+                    continue;
+                };
+
+                eprintln!("Playing game {}/{}", lichess.url, game_id);
+                if let Err(e) = play_game(&lichess, &config, &bot_id, game_id) {
+                    eprintln!("Game {} failed: {}", game_id, e);
+                }
+
+                games_played += 1;
+                if config.max_games.is_some_and(|max| games_played >= max) {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
@@ -2,17 +2,36 @@ use std::io;
 
 pub enum GUICommand {
     UCI,
+    XBoard, // xboard - speak the XBoard protocol instead (as the first command)
+    Kibitz(Option<String>), // kibitz [ms] - comment on the moves of a game fed in one by one
     IsReady,
-    FenPosition(String),               // position fen <fen>
+    Debug(bool),         // debug [on|off] - print diagnostics after searches
+    UciNewGame,          // ucinewgame - the following positions are a new game
+    FenPosition(String), // position fen <fen>
     MovePosition(Option<Vec<String>>), // position startpos <maybe some moves>
-    SetOption(String, String),         // setoption name <name> value <value>
-    Perft(String),                     // go perft <depth>
-    Search(Vec<String>),               // go (with params)
-    PonderHit,                         // ponderhit
-    Stop,                              // stop
-    Quit,                              // quit the program
-    Eval,                              // eval - print evaluation
-    Joke,                              // joke - tell a random joke
+    SetOption(String, String), // setoption name <name> value <value>
+    Perft(String),       // go perft <depth>
+    PerftStats(String),  // go perft <depth> stats - perft with move categories
+    Checksum(String),    // checksum <depth> - fingerprint of the move generator
+    Estimate(String),    // estimate <depth> - estimated tree size of a search
+    LegalMoves(bool),    // legalmoves [san] - print the legal moves
+    SelftestFen(Option<String>), // selftest fen [games] - FEN round trips of random games
+    Search(Vec<String>), // go (with params)
+    PonderHit,           // ponderhit
+    Stop,                // stop
+    Quit,                // quit the program
+    Eval,                // eval - print evaluation
+    Stats,               // stats - print statistics of the last search
+    PvPgn(bool),         // pv pgn [fen] - print the last PV as PGN
+    Trend,               // trend - print the evaluations of our moves as CSV
+    Joke,                // joke - tell a random joke
+    SetPiece(String, String), // setpiece <square> <piece> - put a piece on a square
+    ClearSquare(String), // clearsquare <square> - remove the piece on a square
+    SideTo(String),      // sideto <w|b> - set the side to move
+    Castling(String),    // castling <KQkq|-> - set the castling rights
+    EnPassant(String),   // enpassant <square|-> - set the en passant square
+    Exclude(Vec<String>), // exclude [moves] - leave root moves out of searches
+    Include(Vec<String>), // include <moves|all> - search excluded moves again
 
     Invalid(String), // placeholder for invalid commands so we can pattern match
 }
@@ -29,17 +48,29 @@ impl GUICommand {
             Ok(_) => {
                 // Input received successfully
             }
-            Err(_) => {
-                panic!("Failed to read line");
+            Err(e) => {
+                // stdin is unusable, so there's nothing more to read commands from
+                eprintln!("Failed to read command: {}", e);
+                return GUICommand::Quit;
             }
         }
 
-        let parts = input.as_str().trim().split_whitespace().collect::<Vec<_>>();
+        Self::parse(&input)
+    }
+
+    /// Parse a single line of input (unknown commands become `Invalid`)
+    pub fn parse(input: &str) -> GUICommand {
+        let parts = input.trim().split_whitespace().collect::<Vec<_>>();
 
         match parts.as_slice() {
             ["uci"] => GUICommand::UCI,
+            ["xboard"] => GUICommand::XBoard,
+            ["kibitz"] => GUICommand::Kibitz(None),
+            ["kibitz", delay] => GUICommand::Kibitz(Some(delay.to_string())),
             ["isready"] => GUICommand::IsReady,
-            ["ucinewgame"] => GUICommand::MovePosition(None),
+            ["debug"] | ["debug", "on"] => GUICommand::Debug(true),
+            ["debug", "off"] => GUICommand::Debug(false),
+            ["ucinewgame"] => GUICommand::UciNewGame,
             ["position", "startpos"] => GUICommand::MovePosition(None),
             ["position", "startpos", "moves", moves @ ..] => {
                 GUICommand::MovePosition(Some(moves.iter().map(|m| m.to_string()).collect()))
@@ -51,6 +82,13 @@ impl GUICommand {
                 Self::parse_setoption(name_and_rest)
             }
             ["go", "perft", depth] => GUICommand::Perft(depth.to_string()),
+            ["go", "perft", depth, "stats"] => GUICommand::PerftStats(depth.to_string()),
+            ["checksum", depth] => GUICommand::Checksum(depth.to_string()),
+            ["estimate", depth] => GUICommand::Estimate(depth.to_string()),
+            ["legalmoves"] => GUICommand::LegalMoves(false),
+            ["legalmoves", "san"] => GUICommand::LegalMoves(true),
+            ["selftest", "fen"] => GUICommand::SelftestFen(None),
+            ["selftest", "fen", games] => GUICommand::SelftestFen(Some(games.to_string())),
             ["go", params @ ..] => {
                 GUICommand::Search(params.iter().map(|p| p.to_string()).collect())
             }
@@ -58,8 +96,25 @@ impl GUICommand {
             ["stop"] => GUICommand::Stop,
             ["quit"] => GUICommand::Quit,
             ["eval"] => GUICommand::Eval,
+            ["stats"] => GUICommand::Stats,
+            ["trend"] => GUICommand::Trend,
+            ["pv", "pgn"] => GUICommand::PvPgn(false),
+            ["pv", "pgn", "fen"] => GUICommand::PvPgn(true),
             ["joke"] => GUICommand::Joke,
-            _ => GUICommand::Invalid(input),
+            ["setpiece", square, piece] => {
+                GUICommand::SetPiece(square.to_string(), piece.to_string())
+            }
+            ["clearsquare", square] => GUICommand::ClearSquare(square.to_string()),
+            ["sideto", side] => GUICommand::SideTo(side.to_string()),
+            ["castling", castling] => GUICommand::Castling(castling.to_string()),
+            ["enpassant", square] => GUICommand::EnPassant(square.to_string()),
+            ["exclude", moves @ ..] => {
+                GUICommand::Exclude(moves.iter().map(|m| m.to_string()).collect())
+            }
+            ["include", moves @ ..] if !moves.is_empty() => {
+                GUICommand::Include(moves.iter().map(|m| m.to_string()).collect())
+            }
+            _ => GUICommand::Invalid(input.to_string()),
         }
     }
 
@@ -71,7 +126,8 @@ impl GUICommand {
             // Everything after "value" is the option value
             let value = parts[value_pos + 1..].join(" ");
 
-            if !name.is_empty() && !value.is_empty() {
+            // an empty value is passed on too (a button sent with a trailing "value")
+            if !name.is_empty() {
                 GUICommand::SetOption(name, value)
             } else {
                 GUICommand::Invalid(format!("setoption name {} value {}", name, value))
@@ -0,0 +1,121 @@
+//! Default option values from a config file, so that an engine used from many GUIs doesn't
+//! have to be set up in each of them. The file is `prokopakop.toml` next to the binary (or
+//! the one given with `--config`), and its values are applied before any `setoption` from the
+//! GUI, which can still override them.
+//!
+//! Only the flat `key = value` part of TOML is understood, the keys being UCI option names:
+//!
+//! ```toml
+//! Hash = 256
+//! Threads = 4
+//! MoveOverhead = 30
+//! NNUE = "nets/latest.nnue"
+//! BookFile = "books/main.bin"
+//! ```
+
+use crate::error::ProkopakopError;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const CONFIG_FILE_NAME: &str = "prokopakop.toml";
+
+/// Option values of a config file, in the order they appear in it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineConfig {
+    pub options: Vec<(String, String)>, // (option name, value)
+}
+
+impl EngineConfig {
+    pub fn load(path: &Path) -> Result<Self, ProkopakopError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The config file next to the engine binary, if there is one
+    pub fn default_path() -> Option<PathBuf> {
+        let path = std::env::current_exe()
+            .ok()?
+            .parent()?
+            .join(CONFIG_FILE_NAME);
+        path.is_file().then_some(path)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ProkopakopError> {
+        let mut options = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let invalid = |reason: &str| {
+                ProkopakopError::InvalidConfig(format!("line {}: {}", index + 1, reason))
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(invalid(
+                    "tables aren't supported, options go at the top level",
+                ));
+            }
+
+            let (key, rest) = match line.strip_prefix('"') {
+                Some(quoted) => {
+                    let (key, rest) = quoted
+                        .split_once('"')
+                        .ok_or_else(|| invalid("unclosed key"))?;
+                    (key, rest.trim_start())
+                }
+                None => {
+                    let end = line.find(['=', ' ', '\t']).unwrap_or(line.len());
+                    line.split_at(end)
+                }
+            };
+
+            let value = rest
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or_else(|| invalid("expected `key = value`"))?;
+            let value = parse_value(value.trim()).ok_or_else(|| invalid("invalid value"))?;
+
+            if key.is_empty() {
+                return Err(invalid("empty key"));
+            }
+
+            options.push((key.to_string(), value));
+        }
+
+        Ok(Self { options })
+    }
+}
+
+/// A quoted string (with its escapes), or a bare number or boolean, without a trailing comment
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        let (string, rest) = literal.split_once('\'')?;
+        return is_comment_or_empty(rest).then(|| string.to_string());
+    }
+
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = quoted.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => string.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    escaped => escaped, // \" and \\
+                }),
+                c => string.push(c),
+            }
+        }
+        return is_comment_or_empty(chars.as_str()).then_some(string);
+    }
+
+    let bare = value.split('#').next().unwrap().trim();
+    (!bare.is_empty() && !bare.contains(char::is_whitespace)).then(|| bare.to_string())
+}
+
+fn is_comment_or_empty(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
@@ -1,10 +1,27 @@
+use crate::controller::estimate::{DEFAULT_PLAYOUTS, TreeEstimate, estimate_tree};
+use crate::engine::affinity::{self, NumaPolicy};
+use crate::engine::book::OpeningBook;
+use crate::engine::cache::{AnalysisCache, CacheEntry};
+use crate::engine::evaluate::is_mate_score;
 use crate::engine::nnue::load_nnue_from_file;
+use crate::engine::repertoire::Repertoire;
 use crate::engine::search::history::History;
 use crate::engine::search::limits::{SearchLimits, SearchParams};
-use crate::engine::search::results::SearchResult;
-use crate::engine::search::searcher::Search;
-use crate::engine::table::TranspositionTable;
-use crate::game::board::{BoardMove, BoardMoveExt, Game};
+use crate::engine::search::options::{MateSearch, SearchOptions, ThinkingFormat};
+use crate::engine::search::params::{CONTEMPT, QUIESCENCE_CHECK_PLIES, QUIESCENCE_DEPTH};
+use crate::engine::search::results::{
+    AspirationAdaptation, EvalTrend, NodeCounts, NodeTypeStats, PruningStats, SearchClock,
+    SearchResult, ThreadNodes, TrendEntry,
+};
+use crate::engine::search::searcher::{Search, SearchContext};
+use crate::engine::table::{NodeType, TranspositionTable, entry_depth};
+use crate::error::ProkopakopError;
+use crate::game::board::{BoardMove, Game, MoveKind};
+use crate::game::pieces::{Color, Piece};
+use crate::game::rules::Variant;
+use crate::game::square::BoardSquare;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::Path;
 
 use fxhash::FxHashMap;
@@ -13,7 +30,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
 };
 use std::thread::{self, JoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 // I literally have a text file of jokes that I gathered over the years
 // Now there is a chance that somebody actually reads some of them
@@ -32,21 +49,45 @@ const JOKES: &[&str] = &[
     "As I handed my dad his 50th birthday card, he looked at me and said: one would have been enough.",
 ];
 
+// How long `quit` waits for a stopped search to finish before exiting without it
+pub const QUIT_SEARCH_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct GameController {
     pub game: Game,
     pub perft_hash: bool,
     pub hash_table_size: usize,
     pub move_overhead: u64,
+    pub node_odds_divisor: u64, // handicap: the engine's nodes are divided by this
+    pub time_odds_divisor: u64, // handicap: the engine's time is divided by this
     pub threads: u64,
+    pub thread_affinity: bool,   // pin the search threads to CPUs
+    pub numa_policy: NumaPolicy, // where the TT pages are allocated
+    pub debug: bool,             // UCI debug mode, with diagnostics after each search
     pub history: History,
+    pub search_options: SearchOptions,
     initialized: bool,
     search_thread: Option<JoinHandle<SearchResult>>,
+    search_progress: ThreadNodes, // live depth and node counts of the running search
     stop_flag: Arc<AtomicBool>,
     ponder_flag: Arc<AtomicBool>,
-    search_start: Arc<Mutex<Instant>>,
+    search_clock: SearchClock, // when the running search started (or its ponderhit came)
     tt: Arc<Mutex<TranspositionTable>>,
     used_jokes: Vec<bool>,
     last_search_result: Option<SearchResult>,
+    last_search_key: u64, // zobrist key of the position the last search was started from
+    last_node_stats: Arc<Mutex<NodeTypeStats>>,
+    last_node_counts: Arc<Mutex<NodeCounts>>,
+    last_pruning_stats: Arc<Mutex<PruningStats>>,
+    aspiration: Arc<Mutex<AspirationAdaptation>>, // window fail rates over the game
+    eval_trend: Arc<Mutex<EvalTrend>>,            // evaluations of the moves played this game
+    analysis_cache: Option<Arc<Mutex<AnalysisCache>>>,
+    deferred_options: Vec<(String, String)>, // options received mid-search, applied after it
+    excluded_moves: Vec<String>,             // root moves left out of searches, for analysis
+    time_log: Option<String>,                // file to log the searches that ran over their time to
+    book: Option<OpeningBook>,               // moves played without searching, if any
+    book_learning: bool,                     // adjust the book weights from game results
+    repertoire: Option<Repertoire>,          // the only moves searched in its positions, if any
+    variant: Variant,                        // rules of the games set up from now on
 }
 
 #[derive(Debug)]
@@ -58,6 +99,93 @@ pub enum MoveResultType {
 
 type PerftTable = FxHashMap<u64, usize>;
 
+/// Leaf move tallies at the final perft depth, as in the standard perft tables
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerftCategories {
+    pub nodes: u64,
+    pub captures: u64, // including en passants
+    pub en_passants: u64,
+    pub castles: u64,
+    pub promotions: u64,
+    pub checks: u64,
+    pub checkmates: u64,
+}
+
+impl std::ops::AddAssign for PerftCategories {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes += other.nodes;
+        self.captures += other.captures;
+        self.en_passants += other.en_passants;
+        self.castles += other.castles;
+        self.promotions += other.promotions;
+        self.checks += other.checks;
+        self.checkmates += other.checkmates;
+    }
+}
+
+/// A timed search that took longer than its hard time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOverrun {
+    pub elapsed_ms: u64,
+    pub limit_ms: u64,
+    pub depth: u64,             // iteration the search was in when it ran out of time
+    pub completed_depth: usize, // last iteration it finished
+}
+
+impl TimeOverrun {
+    /// The overrun of a search, if it took longer than its limit
+    pub fn check(
+        elapsed_ms: u64,
+        limit_ms: Option<u64>,
+        depth: u64,
+        completed_depth: usize,
+    ) -> Option<Self> {
+        let limit_ms = limit_ms?;
+
+        (elapsed_ms > limit_ms).then_some(Self {
+            elapsed_ms,
+            limit_ms,
+            depth,
+            completed_depth,
+        })
+    }
+
+    /// Append the overrun (with what was searched) to the time log
+    fn log(&self, path: &str, fen: &str, go: &str) -> std::io::Result<()> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{} | go {} | {}", fen, go, self))
+    }
+}
+
+impl std::fmt::Display for TimeOverrun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ms used of {} ms allotted (+{} ms) at depth {} ({} completed)",
+            self.elapsed_ms,
+            self.limit_ms,
+            self.elapsed_ms - self.limit_ms,
+            self.depth,
+            self.completed_depth
+        )
+    }
+}
+
+impl std::fmt::Display for PerftCategories {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Nodes:       {}", self.nodes)?;
+        writeln!(f, "Captures:    {}", self.captures)?;
+        writeln!(f, "E.p.:        {}", self.en_passants)?;
+        writeln!(f, "Castles:     {}", self.castles)?;
+        writeln!(f, "Promotions:  {}", self.promotions)?;
+        writeln!(f, "Checks:      {}", self.checks)?;
+        write!(f, "Checkmates:  {}", self.checkmates)
+    }
+}
+
 impl GameController {
     pub fn new() -> Self {
         Self {
@@ -65,29 +193,82 @@ impl GameController {
             perft_hash: true,
             hash_table_size: 128,
             move_overhead: 10,
+            node_odds_divisor: 1,
+            time_odds_divisor: 1,
             threads: 1,
+            thread_affinity: false,
+            numa_policy: NumaPolicy::Default,
+            debug: false,
             history: History::new(),
+            search_options: SearchOptions::default(),
             initialized: false,
             search_thread: None,
+            search_progress: ThreadNodes::new(1),
             stop_flag: Arc::new(AtomicBool::new(false)),
             ponder_flag: Arc::new(AtomicBool::new(false)),
-            search_start: Arc::new(Mutex::new(Instant::now())),
+            search_clock: SearchClock::default(),
             tt: Arc::new(Mutex::new(TranspositionTable::new(128))),
             used_jokes: vec![false; JOKES.len()],
             last_search_result: None,
+            last_search_key: 0,
+            last_node_stats: Arc::new(Mutex::new(NodeTypeStats::default())),
+            last_node_counts: Arc::new(Mutex::new(NodeCounts::default())),
+            last_pruning_stats: Arc::new(Mutex::new(PruningStats::default())),
+            aspiration: Arc::new(Mutex::new(AspirationAdaptation::default())),
+            eval_trend: Arc::new(Mutex::new(EvalTrend::default())),
+            analysis_cache: None,
+            deferred_options: Vec::new(),
+            excluded_moves: Vec::new(),
+            time_log: None,
+            book: None,
+            book_learning: false,
+            repertoire: None,
+            variant: Variant::Standard,
         }
     }
 
     pub fn reset_board(&mut self) {
         self.game = Game::new(None);
+        self.game.variant = self.variant;
         self.history = History::new();
         self.history.push_position(self.game.zobrist_key);
     }
 
-    pub fn set_board_from_fen(&mut self, fen: &str) {
-        self.game = Game::new(Some(fen));
+    /// Set up the position from a FEN, keeping the current one if it can't be parsed or is
+    /// illegal (see `Game::validate`)
+    pub fn set_board_from_fen(&mut self, fen: &str) -> Result<(), ProkopakopError> {
+        let game = Game::from_fen(fen)?;
+        game.validate()?;
+
+        self.game = game;
+        self.game.variant = self.variant;
         self.history = History::new();
         self.history.push_position(self.game.zobrist_key);
+
+        Ok(())
+    }
+
+    /// Replace the TT by an empty one of the current size, placed by the NUMA policy
+    fn allocate_transposition_table(&mut self) {
+        let size = self.hash_table_size;
+        let tt = affinity::allocate_with_policy(self.numa_policy, || TranspositionTable::new(size));
+        self.tt = Arc::new(Mutex::new(tt));
+    }
+
+    fn warn_unsupported_placement(&self, option: &str) {
+        if !affinity::is_supported() {
+            self.info(format_args!(
+                "{} has no effect, it needs a Linux build with the numa feature",
+                option
+            ));
+        }
+    }
+
+    /// Print an `info string` line, unless the Silent option is on
+    fn info(&self, message: impl std::fmt::Display) {
+        if !self.search_options.silent {
+            println!("info string {}", message);
+        }
     }
 
     pub fn reset_transposition_table(&mut self) {
@@ -96,6 +277,22 @@ impl GameController {
         }
     }
 
+    /// Age out the previous game's TT entries (lazily, unlike clearing the table), and start
+    /// adapting the aspiration windows and recording the evaluation trend anew.
+    pub fn new_game(&mut self) {
+        if let Ok(mut tt) = self.tt.lock() {
+            tt.new_game();
+        }
+
+        if let Ok(mut aspiration) = self.aspiration.lock() {
+            *aspiration = AspirationAdaptation::default();
+        }
+
+        if let Ok(mut eval_trend) = self.eval_trend.lock() {
+            *eval_trend = EvalTrend::default();
+        }
+    }
+
     pub fn initialize(&mut self) {
         self.initialized = true;
 
@@ -106,95 +303,675 @@ impl GameController {
         self.initialized
     }
 
-    pub fn set_option(&mut self, name: &str, value: &str) {
-        match name.to_lowercase().as_str() {
+    pub fn is_searching(&self) -> bool {
+        self.search_thread
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
+    /// Set an option, failing if the value couldn't be used at all (an NNUE file that can't be
+    /// loaded); invalid values of the other options are reported and ignored.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), ProkopakopError> {
+        // Options like Hash or NNUE replace state the search thread is using,
+        // so changes that arrive mid-search are queued until it finishes
+        if self.is_searching() {
+            self.info(format_args!("option deferred: {}", name));
+            self.deferred_options
+                .push((name.to_string(), value.to_string()));
+            return Ok(());
+        }
+
+        self.apply_deferred_options();
+        self.apply_option(name, value)
+    }
+
+    /// Apply options queued during a search, once no search is running.
+    pub fn apply_deferred_options(&mut self) {
+        if self.is_searching() {
+            return;
+        }
+
+        for (name, value) in std::mem::take(&mut self.deferred_options) {
+            if let Err(e) = self.apply_option(&name, &value) {
+                self.info(format_args!("Failed to set option {}: {}", name, e));
+            }
+        }
+    }
+
+    fn apply_option(&mut self, name: &str, value: &str) -> Result<(), ProkopakopError> {
+        // option names are case insensitive, and may contain spaces
+        let normalized = name
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+
+        match normalized.as_str() {
             "perfthash" => match value.to_lowercase().as_str() {
                 "true" => self.perft_hash = true,
                 "false" => self.perft_hash = false,
-                _ => eprintln!(
+                _ => self.info(format_args!(
                     "Invalid value for PerftHash option: {}. Expected 'true' or 'false'",
                     value
-                ),
+                )),
             },
-            "move overhead" => match value.parse::<u64>() {
+            // without the space too, as a config file key can't have one unquoted
+            "move overhead" | "moveoverhead" => match value.parse::<u64>() {
                 Ok(overhead) => {
                     if overhead <= 5000 {
                         self.move_overhead = overhead;
                     } else {
-                        eprintln!(
+                        self.info(format_args!(
                             "Invalid value for Move Overhead option: {}. Expected value between 0 and 5000",
                             value
-                        );
+                        ));
                     }
                 }
                 Err(_) => {
-                    eprintln!(
+                    self.info(format_args!(
                         "Invalid value for Move Overhead option: {}. Expected numeric value",
                         value
-                    );
+                    ));
                 }
             },
+            "nodeoddsdivisor" => match value.parse::<u64>() {
+                Ok(divisor) if (1..=1000).contains(&divisor) => self.node_odds_divisor = divisor,
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for NodeOddsDivisor option: {}. Expected value between 1 and 1000",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for NodeOddsDivisor option: {}. Expected numeric value",
+                    value
+                )),
+            },
+            "timeoddsdivisor" => match value.parse::<u64>() {
+                Ok(divisor) if (1..=1000).contains(&divisor) => self.time_odds_divisor = divisor,
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for TimeOddsDivisor option: {}. Expected value between 1 and 1000",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for TimeOddsDivisor option: {}. Expected numeric value",
+                    value
+                )),
+            },
             "hash" => match value.parse::<usize>() {
                 Ok(val) => {
-                    if val <= 33554432 {
+                    if (1..=33554432).contains(&val) {
                         self.hash_table_size = val;
-                        self.tt = Arc::new(Mutex::new(TranspositionTable::new(val)));
+                        self.allocate_transposition_table();
                     } else {
-                        eprintln!(
+                        self.info(format_args!(
                             "Invalid value for Hash option: {}. Expected value between 1 and 33554432",
                             value
-                        );
+                        ));
                     }
                 }
                 Err(_) => {
-                    eprintln!(
+                    self.info(format_args!(
                         "Invalid value for Hash option: {}. Expected numeric value",
                         value
-                    );
+                    ));
                 }
             },
             "threads" => match value.parse::<u64>() {
                 Ok(threads) => {
-                    if threads <= 1024 {
+                    if (1..=1024).contains(&threads) {
                         self.threads = threads;
                     } else {
-                        eprintln!(
+                        self.info(format_args!(
                             "Invalid value for Threads option: {}. Expected value between 1 and 1024",
                             value
-                        );
+                        ));
                     }
                 }
                 Err(_) => {
-                    eprintln!(
+                    self.info(format_args!(
                         "Invalid value for Threads option: {}. Expected numeric value",
                         value
-                    );
+                    ));
+                }
+            },
+            "multipv" => match value.parse::<usize>() {
+                Ok(lines) if (1..=256).contains(&lines) => self.search_options.multi_pv = lines,
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for MultiPV option: {}. Expected value between 1 and 256",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for MultiPV option: {}. Expected numeric value",
+                    value
+                )),
+            },
+            "threadaffinity" => match value.to_lowercase().as_str() {
+                "true" => {
+                    self.thread_affinity = true;
+                    self.warn_unsupported_placement("ThreadAffinity");
+                }
+                "false" => self.thread_affinity = false,
+                _ => self.info(format_args!(
+                    "Invalid value for ThreadAffinity option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "numapolicy" => match NumaPolicy::parse(value) {
+                Some(policy) => {
+                    if policy != NumaPolicy::Default {
+                        self.warn_unsupported_placement("NUMAPolicy");
+                    }
+                    if policy != self.numa_policy {
+                        self.numa_policy = policy;
+                        self.allocate_transposition_table();
+                    }
+                }
+                None => self.info(format_args!(
+                    "Invalid value for NUMAPolicy option: {}. Expected one of {}",
+                    value,
+                    NumaPolicy::ALL.map(NumaPolicy::name).join(", ")
+                )),
+            },
+            "underpromotionpruning" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.underpromotion_pruning = true,
+                "false" => self.search_options.underpromotion_pruning = false,
+                _ => self.info(format_args!(
+                    "Invalid value for UnderpromotionPruning option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "avoidrepetition" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.avoid_repetition = true,
+                "false" => self.search_options.avoid_repetition = false,
+                _ => self.info(format_args!(
+                    "Invalid value for AvoidRepetition option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "roottimepruning" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.root_time_pruning = true,
+                "false" => self.search_options.root_time_pruning = false,
+                _ => self.info(format_args!(
+                    "Invalid value for RootTimePruning option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "verifybestmove" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.verify_best_move = true,
+                "false" => self.search_options.verify_best_move = false,
+                _ => self.info(format_args!(
+                    "Invalid value for VerifyBestMove option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "uci_showrefutations" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.show_refutations = true,
+                "false" => self.search_options.show_refutations = false,
+                _ => self.info(format_args!(
+                    "Invalid value for UCI_ShowRefutations option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "uci_showcurrline" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.show_currline = true,
+                "false" => self.search_options.show_currline = false,
+                _ => self.info(format_args!(
+                    "Invalid value for UCI_ShowCurrLine option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "uci_showwdl" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.show_wdl = true,
+                "false" => self.search_options.show_wdl = false,
+                _ => self.info(format_args!(
+                    "Invalid value for UCI_ShowWDL option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "contempt" => match value.parse::<i32>() {
+                Ok(contempt) if (0..=200).contains(&contempt) => {
+                    self.search_options.contempt = contempt
+                }
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for Contempt option: {}. Expected value between 0 and 200",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for Contempt option: {}. Expected numeric value",
+                    value
+                )),
+            },
+            "silent" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.silent = true,
+                "false" => self.search_options.silent = false,
+                _ => self.info(format_args!(
+                    "Invalid value for Silent option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "uci_analysemode" => match value.to_lowercase().as_str() {
+                "true" => self.search_options.analyse_mode = true,
+                "false" => self.search_options.analyse_mode = false,
+                _ => self.info(format_args!(
+                    "Invalid value for UCI_AnalyseMode option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "quiescencedepth" => match value.parse::<usize>() {
+                Ok(depth) if depth <= 64 => self.search_options.quiescence_depth = depth,
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for QuiescenceDepth option: {}. Expected value between 0 and 64",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for QuiescenceDepth option: {}. Expected numeric value",
+                    value
+                )),
+            },
+            "quiescencechecks" => match value.parse::<usize>() {
+                Ok(plies) if plies <= 8 => self.search_options.quiescence_check_plies = plies,
+                Ok(_) => self.info(format_args!(
+                    "Invalid value for QuiescenceChecks option: {}. Expected value between 0 and 8",
+                    value
+                )),
+                Err(_) => self.info(format_args!(
+                    "Invalid value for QuiescenceChecks option: {}. Expected numeric value",
+                    value
+                )),
+            },
+            "matesearch" => match value.to_lowercase().as_str() {
+                "alphabeta" => self.search_options.mate_search = MateSearch::AlphaBeta,
+                "fallback" => self.search_options.mate_search = MateSearch::Fallback,
+                "proofnumber" => self.search_options.mate_search = MateSearch::ProofNumber,
+                _ => self.info(format_args!(
+                    "Invalid value for MateSearch option: {}. Expected 'AlphaBeta', 'Fallback' or 'ProofNumber'",
+                    value
+                )),
+            },
+            "uci_variant" => match Variant::parse(value) {
+                Some(variant) => {
+                    self.variant = variant;
+                    self.game.variant = variant;
+                }
+                None => self.info(format_args!(
+                    "Invalid value for UCI_Variant option: {}. Expected one of {}",
+                    value,
+                    Variant::ALL.map(Variant::name).join(", ")
+                )),
+            },
+            "clear hash" => self.reset_transposition_table(),
+            // only tells whether the GUI will send `go ponder`, which needs no preparation
+            "ponder" => {}
+            "nnue" => match value {
+                "" | "<none>" => {} // the built-in network
+                path => {
+                    load_nnue_from_file(Path::new(path))?;
+                    self.info("NNUE loaded successfully!");
                 }
             },
-            "nnue" => load_nnue_from_file(Path::new(value)),
+            "timelog" => match value {
+                "" | "<none>" => self.time_log = None,
+                path => self.time_log = Some(path.to_string()),
+            },
+            "bookfile" => match value {
+                "" | "<none>" => self.book = None,
+                path => match OpeningBook::open(Path::new(path)) {
+                    Ok(book) => {
+                        self.info(format_args!(
+                            "BookFile loaded {} positions",
+                            book.position_count()
+                        ));
+                        self.book = Some(book);
+                    }
+                    Err(e) => self.info(format_args!("Failed to open BookFile {}: {}", path, e)),
+                },
+            },
+            "repertoirefile" => match value {
+                "" | "<none>" => self.repertoire = None,
+                path => match Repertoire::open(Path::new(path)) {
+                    Ok(repertoire) => {
+                        self.info(format_args!(
+                            "RepertoireFile loaded {} positions",
+                            repertoire.position_count()
+                        ));
+                        self.repertoire = Some(repertoire);
+                    }
+                    Err(e) => self.info(format_args!("Failed to open RepertoireFile {}: {}", path, e)),
+                },
+            },
+            "booklearning" => match value.to_lowercase().as_str() {
+                "true" => self.book_learning = true,
+                "false" => self.book_learning = false,
+                _ => self.info(format_args!(
+                    "Invalid value for BookLearning option: {}. Expected 'true' or 'false'",
+                    value
+                )),
+            },
+            "analysiscache" => match value {
+                "" | "<none>" => self.analysis_cache = None,
+                path => match AnalysisCache::open(Path::new(path)) {
+                    Ok(cache) => {
+                        self.info(format_args!(
+                            "AnalysisCache has {} positions",
+                            cache.position_count()
+                        ));
+                        self.analysis_cache = Some(Arc::new(Mutex::new(cache)));
+                    }
+                    Err(e) => self.info(format_args!(
+                        "Failed to open AnalysisCache file {}: {}",
+                        path, e
+                    )),
+                },
+            },
             _ => {
-                eprintln!("Unknown option: {}", name);
+                self.info(format_args!("Unknown option: {}", name));
             }
         }
+
+        Ok(())
     }
 
     pub fn try_move_piece(&mut self, long_algebraic_notation: &str) -> MoveResultType {
         match BoardMove::parse(long_algebraic_notation) {
-            Some(board_move) => {
-                let (move_count, valid_moves) = self.game.get_moves();
-
-                // Check if the move is in the valid moves array
-                if valid_moves[0..move_count].contains(&board_move) {
-                    self.game.make_move(board_move);
+            Some(board_move) => match self.game.make_move_checked(board_move) {
+                Ok(()) => {
                     self.history.push_position(self.game.zobrist_key);
-
                     MoveResultType::Success
+                }
+                Err(_) => MoveResultType::InvalidMove,
+            },
+            _ => MoveResultType::InvalidNotation,
+        }
+    }
+
+    /// Set up the starting position with the given moves played (`position startpos moves`).
+    /// A move that can't be played leaves the position as it was before the command, instead
+    /// of going on with the rest of the moves from a position the GUI doesn't have.
+    pub fn set_board_from_moves(&mut self, moves: &[String]) -> Result<(), ProkopakopError> {
+        let previous = (self.game.clone(), self.history.clone());
+        self.reset_board();
+
+        for (index, notation) in moves.iter().enumerate() {
+            let reason = match self.try_move_piece(notation) {
+                MoveResultType::Success => continue,
+                MoveResultType::InvalidMove => "illegal",
+                MoveResultType::InvalidNotation => "unparsable",
+            };
+
+            (self.game, self.history) = previous;
+            return Err(ProkopakopError::IllegalMove(format!(
+                "{} ({} move at index {}), position left unchanged",
+                notation, reason, index
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Take back the last move played, if there is one
+    pub fn undo_move(&mut self) -> bool {
+        if self.game.history.is_empty() {
+            return false;
+        }
+
+        self.game.unmake_move();
+        self.history.pop_position();
+        true
+    }
+
+    /// Put a piece (`N` for a white knight, `n` for a black one) on a square.
+    pub fn edit_set_piece(&mut self, square: &str, piece: &str) {
+        let square = match Self::parse_edit_square(square) {
+            Ok(square) => square,
+            Err(e) => {
+                self.info(e);
+                return;
+            }
+        };
+
+        let mut chars = piece.chars();
+        let colored_piece = match (chars.next(), chars.next()) {
+            (Some(c), None) => Piece::from_char(c.to_ascii_lowercase()).map(|piece| {
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
                 } else {
-                    MoveResultType::InvalidMove
+                    Color::Black
+                };
+                (piece, color)
+            }),
+            _ => None,
+        };
+
+        match colored_piece {
+            Some(colored_piece) => {
+                self.game.edit_square(square, Some(colored_piece));
+                self.reset_position_history();
+            }
+            None => self.info(ProkopakopError::InvalidNotation(format!("piece {}", piece))),
+        }
+    }
+
+    pub fn edit_clear_square(&mut self, square: &str) {
+        match Self::parse_edit_square(square) {
+            Ok(square) => {
+                self.game.edit_square(square, None);
+                self.reset_position_history();
+            }
+            Err(e) => self.info(e),
+        }
+    }
+
+    pub fn edit_side_to_move(&mut self, side: &str) {
+        let side = match side {
+            "w" | "white" => Color::White,
+            "b" | "black" => Color::Black,
+            _ => {
+                self.info(ProkopakopError::InvalidNotation(format!("side {}", side)));
+                return;
+            }
+        };
+
+        self.game.edit_side(side);
+        self.reset_position_history();
+    }
+
+    pub fn edit_castling(&mut self, castling: &str) {
+        if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+            self.info(ProkopakopError::InvalidNotation(format!(
+                "castling rights {}",
+                castling
+            )));
+            return;
+        }
+
+        self.game
+            .edit_castling_flags(Game::parse_castling_flags(castling));
+        self.reset_position_history();
+    }
+
+    pub fn edit_en_passant(&mut self, square: &str) {
+        let square = match square {
+            "-" => None,
+            _ => match Self::parse_edit_square(square) {
+                Ok(square) => Some(square),
+                Err(e) => {
+                    self.info(e);
+                    return;
                 }
+            },
+        };
+
+        self.game.edit_en_passant(square);
+        self.reset_position_history();
+    }
+
+    fn parse_edit_square(square: &str) -> Result<BoardSquare, ProkopakopError> {
+        BoardSquare::parse(square)
+            .filter(|_| square.len() == 2)
+            .ok_or_else(|| ProkopakopError::InvalidSquare(square.to_string()))
+    }
+
+    /// Leave the moves out of the following searches (in every position where they're legal),
+    /// so that known lines don't have to be searched over and over
+    pub fn exclude_moves(&mut self, notations: &[String]) {
+        for notation in notations {
+            let Some(board_move) = BoardMove::parse(notation) else {
+                self.info(ProkopakopError::InvalidNotation(notation.clone()));
+                continue;
+            };
+
+            let notation = board_move.unparse();
+            if !self.excluded_moves.contains(&notation) {
+                self.excluded_moves.push(notation);
+            }
+        }
+
+        self.print_excluded_moves();
+    }
+
+    /// Search the excluded moves again (all of them for `all`)
+    pub fn include_moves(&mut self, notations: &[String]) {
+        for notation in notations {
+            if notation == "all" {
+                self.excluded_moves.clear();
+                continue;
+            }
+
+            match BoardMove::parse(notation) {
+                Some(board_move) => {
+                    let notation = board_move.unparse();
+                    self.excluded_moves.retain(|excluded| *excluded != notation);
+                }
+                None => self.info(ProkopakopError::InvalidNotation(notation.clone())),
             }
-            _ => MoveResultType::InvalidNotation,
         }
+
+        self.print_excluded_moves();
+    }
+
+    fn print_excluded_moves(&self) {
+        match self.excluded_moves.is_empty() {
+            true => self.info(format_args!("Excluded moves: none")),
+            false => self.info(format_args!(
+                "Excluded moves: {}",
+                self.excluded_moves.join(" ")
+            )),
+        }
+    }
+
+    /// Repertoire moves of the current position, none if it's not in the repertoire
+    fn repertoire_moves(&self) -> &[BoardMove] {
+        self.repertoire
+            .as_ref()
+            .map_or(&[], |repertoire| repertoire.moves(self.game.zobrist_key))
+    }
+
+    /// The root moves left to search after the searchmoves of the `go` command (if any), the
+    /// repertoire restriction and the exclusions, or none (meaning all moves) if no move of the
+    /// position is left out. A restriction that would leave no move at all is ignored.
+    pub(crate) fn root_moves(&self, searchmoves: &[BoardMove]) -> Vec<BoardMove> {
+        let (move_count, moves) = self.game.get_moves();
+        let legal = &moves[..move_count];
+
+        let (searchmoves, illegal): (Vec<BoardMove>, Vec<BoardMove>) = searchmoves
+            .iter()
+            .partition(|board_move| legal.contains(board_move));
+        if !illegal.is_empty() {
+            self.info(format_args!(
+                "Ignoring illegal searchmoves: {}",
+                illegal
+                    .iter()
+                    .map(|board_move| board_move.unparse())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+
+        let repertoire = self.repertoire_moves();
+        let mut remaining = legal.to_vec();
+
+        self.restrict_root_moves(&mut remaining, "searchmoves", |board_move| {
+            searchmoves.is_empty() || searchmoves.contains(board_move)
+        });
+        self.restrict_root_moves(&mut remaining, "repertoire moves", |board_move| {
+            repertoire.is_empty() || repertoire.contains(board_move)
+        });
+        self.restrict_root_moves(&mut remaining, "excluded moves", |board_move| {
+            !self.excluded_moves.contains(&board_move.unparse())
+        });
+
+        if remaining.len() == move_count {
+            return Vec::new();
+        }
+
+        remaining
+    }
+
+    /// Keep the root moves a restriction keeps, unless that would leave none of them
+    fn restrict_root_moves(
+        &self,
+        root_moves: &mut Vec<BoardMove>,
+        restriction: &str,
+        keeps: impl Fn(&BoardMove) -> bool,
+    ) {
+        let restricted = root_moves
+            .iter()
+            .copied()
+            .filter(|board_move| keeps(board_move))
+            .collect::<Vec<_>>();
+
+        match restricted.is_empty() {
+            true => self.info(format_args!(
+                "The {} leave no move to search, ignoring them",
+                restriction
+            )),
+            false => *root_moves = restricted,
+        }
+    }
+
+    /// Legal moves of the current position, ordered by their notation
+    pub fn legal_moves(&self) -> Vec<BoardMove> {
+        let (move_count, moves) = self.game.get_moves();
+        let mut moves = moves[..move_count].to_vec();
+        moves.sort_by_cached_key(|board_move| board_move.unparse());
+        moves
+    }
+
+    /// Print the legal moves on one line (empty once the game is over), so that clients can
+    /// leave the rules to the engine, and their SAN on another (in the same order) if asked to
+    pub fn print_legal_moves(&mut self, with_san: bool) {
+        let moves = self.legal_moves();
+
+        let notations = moves.iter().map(|board_move| board_move.unparse());
+        let line = std::iter::once("legalmoves".to_string()).chain(notations);
+        println!("{}", line.collect::<Vec<_>>().join(" "));
+
+        if with_san {
+            let sans = moves
+                .iter()
+                .map(|&board_move| self.game.move_to_san(board_move))
+                .collect::<Vec<_>>();
+            let line = std::iter::once("san".to_string()).chain(sans);
+            println!("{}", line.collect::<Vec<_>>().join(" "));
+        }
+    }
+
+    /// After editing the board, the earlier positions no longer lead to this one
+    fn reset_position_history(&mut self) {
+        self.history = History::new();
+        self.history.push_position(self.game.zobrist_key);
+    }
+
+    /// Print the perft divide (leaf nodes after each move) in the usual format
+    pub fn print_perft(&mut self, depth: usize) {
+        let moves = self.perft(depth);
+
+        let mut total = 0;
+        for (board_move, count) in &moves {
+            println!("{}: {}", board_move.unparse(), count);
+            total += count;
+        }
+
+        println!("\nNodes searched: {}", total);
     }
 
     pub fn perft(&mut self, depth: usize) -> Vec<(BoardMove, usize)> {
@@ -285,61 +1062,449 @@ impl GameController {
         total_count
     }
 
+    /// Perft with the leaf moves broken down by category, for pinpointing which kind of move
+    /// diverges from reference numbers. Visits every leaf (no hashing or bulk counting).
+    pub fn perft_categories(&mut self, depth: usize) -> Vec<(BoardMove, PerftCategories)> {
+        let (move_count, valid_moves) = self.game.get_moves();
+
+        valid_moves[0..move_count]
+            .iter()
+            .map(|&board_move| {
+                let mut categories = PerftCategories::default();
+                self.dfs_count_categories(board_move, depth, &mut categories);
+                (board_move, categories)
+            })
+            .collect()
+    }
+
+    fn dfs_count_categories(
+        &mut self,
+        board_move: BoardMove,
+        depth: usize,
+        categories: &mut PerftCategories,
+    ) {
+        if depth <= 1 {
+            if self.game.is_capture(board_move) {
+                categories.captures += 1;
+            }
+
+            match board_move.get_kind() {
+                MoveKind::EnPassant => categories.en_passants += 1,
+                MoveKind::Castling => categories.castles += 1,
+                _ => {}
+            }
+
+            if board_move.get_promotion().is_some() {
+                categories.promotions += 1;
+            }
+
+            self.game.make_move(board_move);
+            if self.game.is_king_in_check(self.game.side) {
+                categories.checks += 1;
+
+                if self.game.get_moves().0 == 0 {
+                    categories.checkmates += 1;
+                }
+            }
+            self.game.unmake_move();
+
+            categories.nodes += 1;
+            return;
+        }
+
+        self.game.make_move(board_move);
+
+        let (move_count, moves) = self.game.get_moves();
+        for &next_move in &moves[0..move_count] {
+            self.dfs_count_categories(next_move, depth - 1, categories);
+        }
+
+        self.game.unmake_move();
+    }
+
+    /// Report why a `go` isn't searched, answering with a null move so that the GUI isn't left
+    /// waiting for one
+    fn refuse_search(&self, e: ProkopakopError, uci_info: bool) {
+        self.info(e);
+        if uci_info && self.search_options.thinking_format == ThinkingFormat::Uci {
+            println!("bestmove 0000");
+        }
+    }
+
     pub fn search(&mut self, params: Vec<String>, uci_info: bool) {
         // Stop + reset any existing search
         self.stop_search();
         self.stop_flag.store(false, Ordering::Relaxed);
 
-        let search_params = SearchParams::parse(params);
+        let go = params.join(" ");
+        let search_params = match SearchParams::parse(params) {
+            Ok(search_params) => search_params,
+            Err(e) => return self.refuse_search(e, uci_info),
+        };
+
+        // `go perft <depth>` is a move count, not a search
+        if let Some(depth) = search_params.perft {
+            self.print_perft(depth);
+            return;
+        }
 
         // Set ponder flag if this is a ponder search
         let is_ponder = search_params.ponder;
         self.ponder_flag.store(is_ponder, Ordering::Relaxed);
 
         // Reset search start time
-        if let Ok(mut start) = self.search_start.lock() {
-            *start = Instant::now();
+        self.search_clock.restart();
+
+        // an edited board can be anything, and searching an impossible position is undefined
+        if let Err(e) = self.game.validate() {
+            return self.refuse_search(e, uci_info);
+        }
+
+        let previous_pv = self.continued_pv();
+        self.last_search_key = self.game.zobrist_key;
+
+        if let Some(book_move) = self.book_move(&search_params) {
+            let result = SearchResult::with_pv(book_move, 0, Vec::new());
+            let print_bestmove =
+                uci_info && self.search_options.thinking_format == ThinkingFormat::Uci;
+
+            if uci_info {
+                self.info(format_args!("Book move {}", book_move.unparse()));
+            }
+
+            self.search_thread = Some(thread::spawn(move || {
+                if print_bestmove {
+                    println!("bestmove {}", result.best_move.unparse());
+                }
+                result
+            }));
+            return;
         }
 
         let mut game_clone = self.game.clone();
         let mut history_clone = self.history.clone();
         let stop_flag = Arc::clone(&self.stop_flag);
         let ponder_flag = Arc::clone(&self.ponder_flag);
-        let search_start = Arc::clone(&self.search_start);
+        let search_clock = self.search_clock.clone();
         let move_overhead = self.move_overhead;
+        let node_odds_divisor = self.node_odds_divisor;
+        let time_odds_divisor = self.time_odds_divisor;
+        let search_options = self.search_options.clone();
+        let thread_affinity = self.thread_affinity;
         // Clone the shared transposition table reference
         let tt = Arc::clone(&self.tt);
+        let node_stats = Arc::clone(&self.last_node_stats);
+        let node_counts = Arc::clone(&self.last_node_counts);
+        let pruning_stats = Arc::clone(&self.last_pruning_stats);
+        let aspiration = Arc::clone(&self.aspiration);
+        let eval_trend = Arc::clone(&self.eval_trend);
+        let ply = self.game.get_ply();
+        let infinite = search_params.infinite;
+        let aspiration_scale = self
+            .aspiration
+            .lock()
+            .map(|aspiration| aspiration.scale)
+            .unwrap_or(1.0);
+        let analysis_cache = self.analysis_cache.clone();
+        let time_log = self.time_log.clone();
+
+        let excluded_root = self.root_moves(&search_params.searchmoves);
+        if uci_info && !excluded_root.is_empty() && !self.repertoire_moves().is_empty() {
+            self.info(format_args!(
+                "Repertoire moves: {}",
+                excluded_root
+                    .iter()
+                    .map(|board_move| board_move.unparse())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+        let root_move_count = match excluded_root.is_empty() {
+            true => self.game.get_moves().0,
+            false => excluded_root.len(),
+        };
+
+        // Until there's a proper parallel search, a second thread takes half of the root
+        // moves (more threads than that aren't used yet); MultiPV lines need all of them
+        let split = self.threads >= 2 && root_move_count >= 2 && self.search_options.multi_pv == 1;
+        let thread_nodes = ThreadNodes::new(if split { 2 } else { 1 });
+        self.search_progress = thread_nodes.clone();
+
+        let print_bestmove = uci_info && search_options.thinking_format == ThinkingFormat::Uci;
+        let print_info = uci_info && !search_options.silent;
+        let print_debug = print_info && self.debug;
+        let print_errors = !search_options.silent;
 
         let handle = thread::spawn(move || {
+            if thread_affinity {
+                affinity::pin_current_thread(0);
+            }
+
+            // handicaps against weaker opponents only ever shorten the search
+            let time_limits = search_params
+                .calculate_time_limits(game_clone.side, move_overhead, game_clone.get_fullmoves())
+                .map(|(move_time, extended_time)| {
+                    (
+                        (move_time / time_odds_divisor).max(1),
+                        (extended_time / time_odds_divisor).max(1),
+                    )
+                });
+
             let limits = SearchLimits {
-                max_depth: search_params.depth,
-                max_nodes: search_params.nodes,
-                max_time_ms: search_params.calculate_move_time(game_clone.side, move_overhead),
+                // a mate in n moves needs no more than 2n - 1 plies
+                max_depth: search_params
+                    .depth
+                    .or(search_params.mate.map(|moves| 2 * moves - 1)),
+                // handicaps against weaker opponents only ever shorten the search
+                max_nodes: search_params
+                    .nodes
+                    .map(|nodes| (nodes / node_odds_divisor).max(1)),
+                max_time_ms: time_limits.map(|(move_time, _)| move_time),
+                extended_time_ms: time_limits.map(|(_, extended_time)| extended_time),
                 exact: search_params.movetime.is_some(),
                 moves: search_params.searchmoves,
                 infinite: search_params.infinite,
+                mate: search_params.mate,
             };
 
+            let time_limit = limits.extended_time_ms.filter(|_| !limits.infinite);
+            let fen = game_clone.get_fen();
+            let overrun_clock = search_clock.clone();
+            let clock_ponder_flag = Arc::clone(&ponder_flag);
+            let clock_stop_flag = Arc::clone(&stop_flag);
+            let search_depths; // iteration searched when stopping, last completed
+
             let result = {
                 if let Ok(mut tt_guard) = tt.lock() {
-                    let mut search = Search::new(
-                        &mut game_clone,
-                        limits,
+                    // Earlier analysis of this position serves as the TT entry for the root
+                    let root_key = game_clone.zobrist_key;
+                    let cached = analysis_cache
+                        .as_ref()
+                        .and_then(|cache| cache.lock().ok()?.get(root_key));
+                    let cached_move =
+                        cached.and_then(|entry| game_clone.legal_move(entry.best_move));
+                    if let (Some(entry), Some(best_move)) = (cached, cached_move) {
+                        tt_guard.store(
+                            root_key,
+                            entry_depth(entry.depth as usize),
+                            entry.evaluation,
+                            best_move,
+                            NodeType::Exact,
+                            game_clone.get_halfmove_clock(),
+                        );
+                    }
+
+                    tt_guard.new_search(root_key);
+                    let tt = &*tt_guard;
+
+                    let (move_count, moves) = game_clone.get_moves();
+
+                    let (main_moves, helper_moves) = if split {
+                        let root_moves = match excluded_root.is_empty() {
+                            true => &moves[..move_count],
+                            false => &excluded_root[..],
+                        };
+                        (
+                            root_moves.iter().step_by(2).copied().collect(),
+                            root_moves.iter().skip(1).step_by(2).copied().collect(),
+                        )
+                    } else {
+                        (excluded_root.clone(), Vec::new())
+                    };
+
+                    let mut limits = limits;
+                    if split {
+                        limits.max_nodes = limits.max_nodes.map(|nodes| nodes.div_ceil(2));
+                    }
+
+                    let root_game = game_clone.fork();
+                    let mut helper_game = game_clone.fork();
+                    let mut helper_history = history_clone.clone();
+                    let helper_limits = limits.clone();
+                    let helper_options = search_options.clone();
+                    let helper_stop_flag = Arc::clone(&stop_flag);
+                    let helper_search_clock = search_clock.clone();
+                    let helper_ponder_flag = Arc::clone(&ponder_flag);
+                    let helper_thread_nodes = thread_nodes.clone();
+
+                    let context = SearchContext {
+                        options: search_options,
+                        tt,
+                        history: &mut history_clone,
                         stop_flag,
-                        &mut *tt_guard,
-                        &mut history_clone,
-                        uci_info,
-                        search_start,
                         ponder_flag,
-                    );
-                    search.run()
+                        clock: search_clock,
+                    };
+                    let mut search = Search::new(&mut game_clone, limits, context, uci_info)
+                        .with_thread(thread_nodes, 0)
+                        .with_root_moves(main_moves)
+                        .with_previous_pv(previous_pv.clone())
+                        .with_aspiration_scale(aspiration_scale);
+
+                    let (result, completed_depth, helper_counts) = thread::scope(|scope| {
+                        let helper = split.then(|| {
+                            scope.spawn(move || {
+                                if thread_affinity {
+                                    affinity::pin_current_thread(1);
+                                }
+
+                                let context = SearchContext {
+                                    options: helper_options,
+                                    tt,
+                                    history: &mut helper_history,
+                                    stop_flag: helper_stop_flag,
+                                    ponder_flag: helper_ponder_flag,
+                                    clock: helper_search_clock,
+                                };
+                                let mut helper =
+                                    Search::new(&mut helper_game, helper_limits, context, false)
+                                        .with_thread(helper_thread_nodes, 1)
+                                        .with_root_moves(helper_moves)
+                                        .with_previous_pv(previous_pv)
+                                        .with_aspiration_scale(aspiration_scale);
+
+                                let result = helper.run();
+                                (result, helper.completed_depth, helper.stats.node_counts())
+                            })
+                        });
+
+                        let result = search.run();
+                        let Some(helper) = helper else {
+                            return (result, search.completed_depth, NodeCounts::default());
+                        };
+
+                        let (helper_result, helper_depth, helper_counts) =
+                            helper.join().expect("Helper search thread panicked");
+                        let completed_depth = search.completed_depth.min(helper_depth);
+
+                        // scores of different depths don't compare, so the deeper result wins,
+                        // unless there's a mate (which holds whatever the depth)
+                        let comparable = helper_depth == search.completed_depth
+                            || is_mate_score(result.evaluation)
+                            || is_mate_score(helper_result.evaluation);
+                        let helper_wins = helper_depth > 0
+                            && match comparable {
+                                true => helper_result.evaluation > result.evaluation,
+                                false => helper_depth > search.completed_depth,
+                            };
+
+                        if helper_wins {
+                            if print_info {
+                                search.print_uci_info(
+                                    helper_depth,
+                                    helper_result.evaluation,
+                                    &helper_result.pv,
+                                );
+                            }
+                            (helper_result, completed_depth, helper_counts)
+                        } else {
+                            (result, completed_depth, helper_counts)
+                        }
+                    });
+
+                    // the score of an iteration cut short isn't exact, so it isn't stored
+                    let exact = completed_depth > 0 && !result.is_partial();
+                    if exact {
+                        tt.store_pv(&root_game, &result.pv, result.evaluation, completed_depth);
+                    }
+
+                    if let Ok(mut stats) = node_stats.lock() {
+                        *stats = search.stats.node_types.clone();
+                    }
+
+                    if let Ok(mut counts) = node_counts.lock() {
+                        *counts = search.stats.node_counts();
+                        counts.merge(&helper_counts);
+                    }
+
+                    if let Ok(mut stats) = pruning_stats.lock() {
+                        *stats = search.stats.pruning.clone();
+                    }
+
+                    if let Ok(mut aspiration) = aspiration.lock() {
+                        aspiration.update(&search.stats.aspiration);
+                    }
+
+                    search_depths = (search.stats.current_depth, completed_depth);
+
+                    if let Some(cache) = &analysis_cache
+                        && exact
+                        && let Ok(mut cache) = cache.lock()
+                    {
+                        let entry = CacheEntry {
+                            best_move: result.best_move,
+                            evaluation: result.evaluation,
+                            depth: completed_depth as u8,
+                        };
+
+                        if let Err(e) = cache.store(root_key, entry)
+                            && print_errors
+                        {
+                            println!("info string Failed to write to AnalysisCache: {}", e);
+                        }
+                    }
+
+                    result
                 } else {
                     unreachable!();
                 }
             };
 
+            // A ponder search may end on its own (a mate, the depth limit, a single legal
+            // move), but its move can't be played until the GUI says whether the predicted
+            // move was (`ponderhit`) or wasn't (`stop`)
+            while clock_ponder_flag.load(Ordering::Relaxed)
+                && !clock_stop_flag.load(Ordering::Relaxed)
+            {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            // Time losses at fast time controls are hard to diagnose afterwards, so the
+            // searches that ran over their hard limit (not while pondering) are reported
+            let elapsed_ms = overrun_clock.elapsed_ms();
+            let overrun = TimeOverrun::check(
+                elapsed_ms,
+                time_limit.filter(|_| !clock_ponder_flag.load(Ordering::Relaxed)),
+                search_depths.0,
+                search_depths.1,
+            );
+
+            if let Some(overrun) = overrun {
+                if print_info {
+                    println!("info string Time overrun: {}", overrun);
+                }
+                if let Some(path) = &time_log
+                    && let Err(e) = overrun.log(path, &fen, &go)
+                    && print_errors
+                {
+                    println!(
+                        "info string Failed to write to the time log {}: {}",
+                        path, e
+                    );
+                }
+            }
+
+            // Searches for a move to play make up the game's evaluation trend; infinite ones
+            // are analysis, and ones still pondering were stopped on a ponder miss
+            if !infinite
+                && !clock_ponder_flag.load(Ordering::Relaxed)
+                && let Ok(mut eval_trend) = eval_trend.lock()
+            {
+                let entry = TrendEntry {
+                    ply,
+                    evaluation: result.evaluation,
+                    depth: search_depths.1,
+                    time_ms: elapsed_ms,
+                };
+
+                if print_debug {
+                    println!("info string evaltrend {}", entry);
+                }
+                eval_trend.record(entry);
+            }
+
             // Output the best move in UCI format
-            if uci_info {
+            if print_bestmove {
                 if result.pv.len() >= 2 {
                     println!(
                         "bestmove {} ponder {}",
@@ -349,11 +1514,6 @@ impl GameController {
                 } else {
                     println!("bestmove {}", result.best_move.unparse());
                 }
-
-                if let Ok(mut tt_guard) = tt.lock() {
-                    let pruned = tt_guard.prune_old_entries();
-                    println!("info string Pruned {} old TT entries", pruned);
-                }
             }
 
             result
@@ -362,6 +1522,83 @@ impl GameController {
         self.search_thread = Some(handle);
     }
 
+    /// A move from the opening book for the current position, when playing a game rather than
+    /// analysing, pondering or looking for particular moves
+    fn book_move(&self, params: &SearchParams) -> Option<BoardMove> {
+        if self.search_options.analyse_mode
+            || params.infinite
+            || params.ponder
+            || params.mate.is_some()
+            || !params.searchmoves.is_empty()
+            || !self.excluded_moves.is_empty()
+        {
+            return None;
+        }
+
+        let book_move = self.book.as_ref()?.pick(self.game.zobrist_key)?;
+
+        // a zobrist collision could suggest a move from some other position
+        let book_move = self.game.legal_move(book_move)?;
+        let repertoire = self.repertoire_moves();
+        (repertoire.is_empty() || repertoire.contains(&book_move)).then_some(book_move)
+    }
+
+    /// Learn from the result of the game that led to the current position (with BookLearning
+    /// on), storing the adjusted book weights back to the book file
+    pub fn learn_from_game(&mut self, winner: Option<Color>) {
+        if !self.book_learning {
+            return;
+        }
+
+        let Some(book) = &mut self.book else {
+            return;
+        };
+
+        if !book.is_writable() {
+            self.info(format_args!("BookLearning needs a book file, not a PGN"));
+            return;
+        }
+
+        let adjusted = book.learn(&self.game, winner);
+        if adjusted == 0 {
+            return;
+        }
+
+        match book.save() {
+            Ok(()) => self.info(format_args!("Book learned from {} moves", adjusted)),
+            Err(e) => self.info(format_args!("Failed to write BookFile: {}", e)),
+        }
+    }
+
+    /// The rest of the last search's PV, if the game has since followed it (typically our move
+    /// and the expected reply), to start the next search from
+    pub(crate) fn continued_pv(&self) -> Vec<BoardMove> {
+        let Some(last_result) = &self.last_search_result else {
+            return Vec::new();
+        };
+
+        let pv = &last_result.pv;
+        let max_played = pv.len().min(self.game.history.len());
+        let mut game = self.game.clone();
+
+        for played in 0..=max_played {
+            let followed = self.game.history[self.game.history.len() - played..]
+                .iter()
+                .map(|&(board_move, ..)| board_move)
+                .eq(pv[..played].iter().copied());
+
+            if followed && game.zobrist_key == self.last_search_key {
+                return pv[played..].to_vec();
+            }
+
+            if played < max_played {
+                game.unmake_move();
+            }
+        }
+
+        Vec::new()
+    }
+
     pub fn stop_search(&mut self) -> Option<SearchResult> {
         // Signal the search to stop (used for UCI "stop" command)
         self.stop_flag.store(true, Ordering::Relaxed);
@@ -369,17 +1606,53 @@ impl GameController {
         if let Some(handle) = self.search_thread.take() {
             if let Ok(result) = handle.join() {
                 self.last_search_result = Some(result.clone());
+                self.apply_deferred_options();
                 return Some(result);
             }
         }
         None
     }
 
+    /// Stop the search like `stop_search`, but give up on it if it doesn't finish within the
+    /// timeout (a hung search), returning its last depth and node count instead
+    pub fn stop_search_within(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<SearchResult>, (u64, u64)> {
+        self.stop_flag.store(true, Ordering::Relaxed);
+
+        let start = Instant::now();
+        while self
+            .search_thread
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            if start.elapsed() >= timeout {
+                return Err((self.search_progress.depth(), self.search_progress.total()));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        Ok(self.stop_search())
+    }
+
+    /// Stop the search before quitting; a search that hangs (a bug) can't keep the engine
+    /// from exiting, the process exits after logging how far it got
+    pub fn stop_search_for_quit(&mut self) {
+        if let Err((depth, nodes)) = self.stop_search_within(QUIT_SEARCH_TIMEOUT) {
+            eprintln!(
+                "Search did not stop within {} s (depth {}, {} nodes), exiting anyway",
+                QUIT_SEARCH_TIMEOUT.as_secs(),
+                depth,
+                nodes
+            );
+            std::process::exit(1);
+        }
+    }
+
     pub fn ponderhit(&mut self) {
         // Reset the search timer so time management starts fresh from now
-        if let Ok(mut start) = self.search_start.lock() {
-            *start = Instant::now();
-        }
+        self.search_clock.restart();
         // Clear ponder flag so the search thread starts respecting time limits
         self.ponder_flag.store(false, Ordering::Relaxed);
     }
@@ -390,6 +1663,7 @@ impl GameController {
         if let Some(handle) = self.search_thread.take() {
             if let Ok(result) = handle.join() {
                 self.last_search_result = Some(result.clone());
+                self.apply_deferred_options();
                 return Some(result);
             }
         }
@@ -400,13 +1674,159 @@ impl GameController {
         println!("option name Hash type spin default 128 min 1 max 33554432");
         println!("option name Move Overhead type spin default 10 min 0 max 5000");
         println!("option name Threads type spin default 1 min 1 max 1024");
+        println!("option name MultiPV type spin default 1 min 1 max 256");
+        println!("option name ThreadAffinity type check default false");
+        println!(
+            "option name NUMAPolicy type combo default {}{}",
+            NumaPolicy::Default.name(),
+            NumaPolicy::ALL
+                .map(|policy| format!(" var {}", policy.name()))
+                .concat()
+        );
+        println!("option name NodeOddsDivisor type spin default 1 min 1 max 1000");
+        println!("option name TimeOddsDivisor type spin default 1 min 1 max 1000");
+        println!("option name Clear Hash type button");
+        println!("option name Ponder type check default false");
         println!("option name PerftHash type check default true");
+        println!("option name UnderpromotionPruning type check default true");
+        println!("option name AvoidRepetition type check default false");
+        println!("option name RootTimePruning type check default false");
+        println!("option name VerifyBestMove type check default false");
+        println!("option name UCI_ShowRefutations type check default false");
+        println!("option name UCI_ShowCurrLine type check default false");
+        println!("option name UCI_ShowWDL type check default false");
+        println!(
+            "option name Contempt type spin default {} min 0 max 200",
+            CONTEMPT
+        );
+        println!("option name UCI_AnalyseMode type check default false");
+        println!("option name Silent type check default false");
+        println!(
+            "option name QuiescenceDepth type spin default {} min 0 max 64",
+            QUIESCENCE_DEPTH
+        );
+        println!(
+            "option name QuiescenceChecks type spin default {} min 0 max 8",
+            QUIESCENCE_CHECK_PLIES
+        );
+        println!(
+            "option name MateSearch type combo default AlphaBeta var AlphaBeta var Fallback var ProofNumber"
+        );
+        println!(
+            "option name UCI_Variant type combo default {}{}",
+            Variant::Standard.name(),
+            Variant::ALL
+                .map(|variant| format!(" var {}", variant.name()))
+                .concat()
+        );
         println!("option name NNUE type string default <none>");
+        println!("option name AnalysisCache type string default <none>");
+        println!("option name BookFile type string default <none>");
+        println!("option name BookLearning type check default false");
+        println!("option name RepertoireFile type string default <none>");
+        println!("option name TimeLog type string default <none>");
     }
 
     pub fn print_evaluation(&self) {
         let nnue_score = self.game.evaluate();
-        println!("{:.2}", nnue_score);
+        println!("{}", nnue_score);
+    }
+
+    /// Print the PV of the last search as a PGN move list, optionally with FEN headers.
+    pub fn print_pv_pgn(&mut self, with_fen: bool) {
+        // a search that finished on its own still needs to be joined to get its result
+        if self.search_thread.is_some() && !self.is_searching() {
+            self.wait_for_search();
+        }
+
+        let pv = match &self.last_search_result {
+            Some(result) if self.last_search_key == self.game.zobrist_key => result.pv.clone(),
+            _ => {
+                self.info(format_args!("no PV for the current position"));
+                return;
+            }
+        };
+
+        if with_fen {
+            println!("[FEN \"{}\"]", self.game.get_fen());
+            println!("[SetUp \"1\"]");
+            println!();
+        }
+
+        println!("{} *", self.game.line_to_san(&pv));
+    }
+
+    pub fn print_search_stats(&self) {
+        if let Ok(counts) = self.last_node_counts.lock() {
+            print!("{}", counts);
+        }
+
+        if let Ok(stats) = self.last_node_stats.lock() {
+            print!("{}", stats);
+        }
+
+        // only collected in verify-pruning mode
+        if let Ok(stats) = self.last_pruning_stats.lock()
+            && stats.checked.iter().any(|&checked| checked > 0)
+        {
+            print!("{}", stats);
+        }
+
+        if let Ok(aspiration) = self.aspiration.lock() {
+            print!("{}", aspiration);
+        }
+    }
+
+    /// Print the evaluations of the engine's moves this game as CSV
+    pub fn print_eval_trend(&self) {
+        if let Ok(eval_trend) = self.eval_trend.lock() {
+            print!("{}", eval_trend);
+        }
+    }
+
+    pub fn eval_trend(&self) -> EvalTrend {
+        self.eval_trend
+            .lock()
+            .map(|eval_trend| eval_trend.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn last_node_stats(&self) -> NodeTypeStats {
+        self.last_node_stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// Estimate the tree of a search of the current position to the depth, timed at the
+    /// speed of the last search (if there was one)
+    pub fn estimate_tree(&self, depth: usize) -> TreeEstimate {
+        let counts = self.last_node_counts();
+        let nodes_per_second = (counts.time_ms > 0 && counts.total() > 0)
+            .then(|| counts.total() as f64 * 1000.0 / counts.time_ms as f64);
+
+        estimate_tree(&self.game, depth, DEFAULT_PLAYOUTS, nodes_per_second)
+    }
+
+    pub fn last_node_counts(&self) -> NodeCounts {
+        self.last_node_counts
+            .lock()
+            .map(|counts| counts.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn aspiration(&self) -> AspirationAdaptation {
+        self.aspiration
+            .lock()
+            .map(|aspiration| *aspiration)
+            .unwrap_or_default()
+    }
+
+    pub fn last_pruning_stats(&self) -> PruningStats {
+        self.last_pruning_stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
     }
 
     pub fn tell_joke(&mut self) {
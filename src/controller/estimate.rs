@@ -0,0 +1,148 @@
+//! Estimating the size of the game tree to a depth by random playouts (Knuth's estimator):
+//! the product of the numbers of legal moves along a random line is an unbiased estimate of
+//! the number of positions at its depth, so averaging many lines sizes a tree far too big to
+//! count with perft, and tells how long a search to the depth would take.
+
+use crate::game::board::Game;
+use rand::Rng;
+use std::fmt::{Display, Formatter, Result};
+use std::time::Instant;
+
+pub const DEFAULT_PLAYOUTS: u32 = 2000;
+
+/// Estimated size of the minimax tree of a position to a depth
+#[derive(Debug, Clone)]
+pub struct TreeEstimate {
+    pub depth: usize,
+    pub playouts: u32,
+    pub nodes: f64,  // of the whole tree, interior nodes included
+    pub leaves: f64, // at the depth (lines ending in mate or stalemate sooner have none)
+    pub nodes_per_second: f64,
+    pub measured_by_search: bool, // whether the speed is the last search's, not the playouts'
+}
+
+impl TreeEstimate {
+    /// The branching factor of a uniform tree with as many leaves
+    pub fn branching_factor(&self) -> f64 {
+        match self.depth {
+            0 => 1.0,
+            depth => self.leaves.powf(1.0 / depth as f64),
+        }
+    }
+
+    /// Leaves of the minimal tree alpha-beta searches with perfect move ordering (Knuth and
+    /// Moore), b^ceil(d/2) + b^floor(d/2) - 1, which a real search gets within a few times of
+    pub fn alpha_beta_leaves(&self) -> f64 {
+        let b = self.branching_factor();
+        b.powi(self.depth.div_ceil(2) as i32) + b.powi((self.depth / 2) as i32) - 1.0
+    }
+}
+
+impl Display for TreeEstimate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let speed = match self.measured_by_search {
+            true => "last search",
+            false => "playouts",
+        };
+
+        writeln!(
+            f,
+            "estimate depth {}: {:.3e} nodes, {:.3e} leaves, branching factor {:.2} ({} playouts)",
+            self.depth,
+            self.nodes,
+            self.leaves,
+            self.branching_factor(),
+            self.playouts
+        )?;
+        writeln!(
+            f,
+            "alpha-beta with perfect ordering: {:.3e} leaves",
+            self.alpha_beta_leaves()
+        )?;
+        write!(
+            f,
+            "time at {:.0} nodes/s ({}): minimax {}, alpha-beta {}",
+            self.nodes_per_second,
+            speed,
+            format_duration(self.nodes / self.nodes_per_second),
+            format_duration(self.alpha_beta_leaves() / self.nodes_per_second)
+        )
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    const UNITS: [(&str, f64); 4] = [
+        ("years", 365.25 * 24.0 * 3600.0),
+        ("days", 24.0 * 3600.0),
+        ("h", 3600.0),
+        ("min", 60.0),
+    ];
+
+    for (unit, length) in UNITS {
+        if seconds >= length {
+            return format!("{:.1} {}", seconds / length, unit);
+        }
+    }
+
+    format!("{:.3} s", seconds)
+}
+
+/// Estimate the tree of the position to the depth from random playouts; the time is at the
+/// given search speed, or else at the speed of the playouts themselves (each node a move
+/// generation, a move and an evaluation, as a minimax node without any ordering)
+pub fn estimate_tree(
+    game: &Game,
+    depth: usize,
+    playouts: u32,
+    search_nodes_per_second: Option<f64>,
+) -> TreeEstimate {
+    let mut rng = rand::rng();
+    let mut game = game.fork();
+
+    let start = Instant::now();
+    let mut visited = 0u64;
+    let (mut nodes, mut leaves) = (0.0, 0.0);
+
+    for _ in 0..playouts {
+        // the root, and the estimated count of every depth along the line
+        let mut width = 1.0;
+        nodes += 1.0;
+
+        let mut ply = 0;
+        while ply < depth {
+            visited += 1;
+            std::hint::black_box(game.evaluate());
+
+            let (move_count, moves) = game.get_moves();
+            if move_count == 0 {
+                break;
+            }
+
+            width *= move_count as f64;
+            nodes += width;
+
+            game.make_move(moves[rng.random_range(0..move_count)]);
+            ply += 1;
+        }
+
+        if ply == depth {
+            leaves += width;
+        }
+
+        for _ in 0..ply {
+            game.unmake_move();
+        }
+    }
+
+    let playout_speed = visited as f64 / start.elapsed().as_secs_f64().max(1e-9);
+    let playouts_f = playouts.max(1) as f64;
+
+    TreeEstimate {
+        depth,
+        playouts,
+        nodes: nodes / playouts_f,
+        leaves: leaves / playouts_f,
+        nodes_per_second: search_nodes_per_second.unwrap_or(playout_speed),
+        measured_by_search: search_nodes_per_second.is_some(),
+    }
+}
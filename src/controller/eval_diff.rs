@@ -0,0 +1,176 @@
+//! Comparing the two evaluations over the positions of the games of a PGN: the positions the
+//! NNUE and the hand-crafted evaluation disagree on most give a quick feel for how a new net
+//! sees things differently. They are written as EPD records (which --analyze-batch reads).
+
+use crate::engine::evaluate::evaluate_classical;
+use crate::error::ProkopakopError;
+use crate::game::pgn::PgnReader;
+use fxhash::FxHashSet;
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+
+/// Configuration for the eval-diff mode
+#[derive(Debug, Clone)]
+pub struct EvalDiffConfig {
+    pub input: String,          // the PGN to replay
+    pub output: Option<String>, // the EPD records, stdout if not given
+    pub count: usize,           // of the positions with the largest differences written
+}
+
+/// A position with both of its static evaluations (white's view, in centipawns)
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalDiff {
+    pub fen: String,
+    pub game: usize, // 1-based, in the order of the PGN
+    pub ply: usize,  // of the game, 0 for its starting position
+    pub nnue: i32,
+    pub hce: i32,
+}
+
+impl EvalDiff {
+    pub fn difference(&self) -> i32 {
+        self.nnue - self.hce
+    }
+
+    /// The position as an EPD record, with the evaluations and where it's from as comments
+    pub fn to_epd(&self) -> String {
+        let position = self
+            .fen
+            .split_whitespace()
+            .take(4)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "{} c0 \"nnue {:+} hce {:+} diff {:+}\"; c1 \"game {} ply {}\";",
+            position,
+            self.nnue,
+            self.hce,
+            self.difference(),
+            self.game,
+            self.ply
+        )
+    }
+}
+
+/// Totals over all the compared positions, and the ones with the largest differences
+#[derive(Debug, Clone, Default)]
+pub struct EvalDiffSummary {
+    pub games: usize,
+    pub skipped_games: usize, // that couldn't be parsed
+    pub positions: usize,
+    pub total_difference: i64,
+    pub total_abs_difference: i64,
+    pub largest: Vec<EvalDiff>, // by the absolute difference, largest first
+}
+
+impl Display for EvalDiffSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let positions = self.positions.max(1) as f64;
+
+        write!(
+            f,
+            "{} games ({} skipped), {} positions, mean NNUE - HCE {:+.1}, mean |NNUE - HCE| {:.1}",
+            self.games,
+            self.skipped_games,
+            self.positions,
+            self.total_difference as f64 / positions,
+            self.total_abs_difference as f64 / positions
+        )
+    }
+}
+
+fn keep_largest(diffs: &mut Vec<EvalDiff>, count: usize) {
+    // stable, so that of equal differences the earlier positions are kept
+    diffs.sort_by_key(|diff| Reverse(diff.difference().abs()));
+    diffs.truncate(count);
+}
+
+/// Evaluate every position of the games (each distinct one once, and none in check, where a
+/// static evaluation means little), keeping the `count` with the largest differences
+pub fn collect_eval_diffs<R: BufRead>(
+    reader: PgnReader<R>,
+    count: usize,
+) -> Result<EvalDiffSummary, ProkopakopError> {
+    let mut summary = EvalDiffSummary::default();
+    let mut seen = FxHashSet::default();
+
+    // candidates are collected up to twice the count before cutting them down, and only
+    // differences above the smallest kept one can make it in after that
+    let mut threshold = 0;
+
+    for pgn_game in reader {
+        let Ok(pgn_game) = pgn_game else {
+            summary.skipped_games += 1;
+            continue;
+        };
+        let Ok(mut game) = pgn_game.starting_game() else {
+            summary.skipped_games += 1;
+            continue;
+        };
+        summary.games += 1;
+
+        for ply in 0..=pgn_game.moves.len() {
+            if ply > 0 {
+                game.make_move(pgn_game.moves[ply - 1]);
+            }
+
+            if !seen.insert(game.zobrist_key)
+                || game.is_king_in_check(game.side)
+                || game.get_moves().0 == 0
+            {
+                continue;
+            }
+
+            let nnue = game.evaluate();
+            let hce = evaluate_classical(&game);
+            let difference = (nnue - hce) as i64;
+
+            summary.positions += 1;
+            summary.total_difference += difference;
+            summary.total_abs_difference += difference.abs();
+
+            if difference.abs() <= threshold && summary.largest.len() >= count {
+                continue;
+            }
+
+            summary.largest.push(EvalDiff {
+                fen: game.get_fen(),
+                game: summary.games,
+                ply,
+                nnue,
+                hce,
+            });
+
+            if summary.largest.len() >= 2 * count.max(1) {
+                keep_largest(&mut summary.largest, count);
+                threshold = summary
+                    .largest
+                    .last()
+                    .map_or(0, |diff| diff.difference().abs() as i64);
+            }
+        }
+    }
+
+    keep_largest(&mut summary.largest, count);
+    Ok(summary)
+}
+
+/// Replay the games of the PGN and write the positions the evaluations disagree on most
+pub fn run_eval_diff(config: &EvalDiffConfig) -> Result<EvalDiffSummary, ProkopakopError> {
+    let summary = collect_eval_diffs(PgnReader::open(&config.input)?, config.count)?;
+
+    let mut output: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    for diff in &summary.largest {
+        writeln!(output, "{}", diff.to_epd())?;
+    }
+    output.flush()?;
+
+    Ok(summary)
+}
@@ -0,0 +1,262 @@
+//! Kibitzing a live game: the moves of the game come in on stdin one by one (in SAN or long
+//! algebraic notation), the engine analyses the current position in the background all along,
+//! and comments on every move with the swing of the evaluation and, for a mistake, the move it
+//! would have played instead.
+
+use crate::controller::controller::{GameController, MoveResultType};
+use crate::controller::xboard::spawn_input_reader;
+use crate::engine::evaluate::{CHECKMATE_SCORE, is_mate_score, moves_to_mate};
+use crate::engine::search::results::SearchResult;
+use crate::game::board::BoardMove;
+use crate::game::pieces::Color;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the analysis is checked for being due a report while waiting for input
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// Analysis a position gets at least before it is stopped, so that there's a search to go by
+const MIN_ANALYSIS: Duration = Duration::from_millis(50);
+
+// Centipawns a move has to lose against the engine's choice to get an alternative suggested
+const ALTERNATIVE_MARGIN: i32 = 30;
+
+/// A move waiting for the analysis of the position after it, to be commented on
+pub(crate) struct PlayedMove {
+    pub label: String, // move number and SAN, e.g. "12... Nf6"
+    pub mover: Color,
+    pub board_move: BoardMove,
+    pub best: Option<(BoardMove, String)>, // the engine's choice before the move (and its SAN)
+    pub before: i32,                       // evaluation before the move, from the mover's view
+}
+
+impl PlayedMove {
+    /// The comment on the move given the evaluation after it (from the opponent's view, like
+    /// any search result of the position after the move)
+    pub(crate) fn report(&self, after: i32) -> String {
+        let white_view = |score: i32| match self.mover {
+            Color::White => score,
+            Color::Black => -score,
+        };
+        let played = -after;
+
+        let mut line = format!(
+            "kibitz {} score {}",
+            self.label,
+            format_score(white_view(played))
+        );
+
+        if !is_mate_score(played) && !is_mate_score(self.before) {
+            let swing = white_view(played) - white_view(self.before);
+            line.push_str(&format!(" swing {:+.2}", swing as f32 / 100.0));
+        }
+
+        if let Some((best, san)) = &self.best
+            && *best != self.board_move
+            && self.before - played >= ALTERNATIVE_MARGIN
+        {
+            line.push_str(&format!(
+                " better {} ({})",
+                san,
+                format_score(white_view(self.before))
+            ));
+        }
+
+        line
+    }
+}
+
+/// A score in pawns (`+0.35`), or the moves to mate (`#3`, `#-2` when getting mated)
+pub(crate) fn format_score(score: i32) -> String {
+    if !is_mate_score(score) {
+        return format!("{:+.2}", score as f32 / 100.0);
+    }
+
+    format!("#{}", moves_to_mate(score))
+}
+
+pub struct Kibitz {
+    pub controller: GameController,
+    report_delay: Duration, // analysis the position after a move gets before the comment
+    pending: Option<PlayedMove>,
+    analysis_start: Option<Instant>, // when the running analysis started, if there is one
+}
+
+impl Kibitz {
+    pub fn new(mut controller: GameController, report_delay: Duration) -> Self {
+        // a position set up before (over UCI) is where the game goes on from
+        if !controller.is_initialized() {
+            controller.initialize();
+        }
+
+        Self {
+            controller,
+            report_delay,
+            pending: None,
+            analysis_start: None,
+        }
+    }
+
+    /// Analyse the current position in the background, unless the game is over
+    fn start_analysis(&mut self) {
+        let (move_count, _) = self.controller.game.get_moves();
+        if move_count == 0 {
+            return;
+        }
+
+        self.controller.search(vec!["infinite".to_string()], false);
+        self.analysis_start = Some(Instant::now());
+    }
+
+    /// Stop the analysis of the current position, returning its evaluation (side to move's
+    /// view) and best move; a finished game is evaluated by its result instead
+    fn stop_analysis(&mut self) -> (i32, Option<BoardMove>) {
+        let Some(start) = self.analysis_start.take() else {
+            let game = &self.controller.game;
+            return match game.is_lost() {
+                true => (-CHECKMATE_SCORE, None),
+                false => (0, None),
+            };
+        };
+
+        if let Some(remaining) = MIN_ANALYSIS.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+
+        match self.controller.stop_search() {
+            Some(SearchResult {
+                best_move,
+                evaluation,
+                ..
+            }) if best_move != BoardMove::empty() => (evaluation, Some(best_move)),
+            _ => (0, None),
+        }
+    }
+
+    /// Comment on the last move with the analysis of the position after it so far
+    fn report(&mut self, after: i32) {
+        if let Some(played) = self.pending.take() {
+            println!("{}", played.report(after));
+        }
+    }
+
+    /// Comment on the last move once the position after it was analysed for long enough,
+    /// going on with the analysis afterwards
+    pub fn poll(&mut self) {
+        let due = self
+            .analysis_start
+            .is_some_and(|start| start.elapsed() >= self.report_delay);
+
+        if self.pending.is_some() && (due || self.analysis_start.is_none()) {
+            let (after, _) = self.stop_analysis();
+            self.report(after);
+            self.start_analysis();
+        }
+    }
+
+    /// A move given in long algebraic notation or SAN, if it's legal in the current position
+    fn parse_move(&self, notation: &str) -> Option<BoardMove> {
+        let game = &self.controller.game;
+
+        BoardMove::parse(notation)
+            .and_then(|board_move| game.legal_move(board_move))
+            .or_else(|| game.san_to_move(notation).ok())
+    }
+
+    fn play(&mut self, notation: &str) {
+        let Some(board_move) = self.parse_move(notation) else {
+            println!("Illegal move: {}", notation);
+            return;
+        };
+
+        let (before, best) = self.stop_analysis();
+        // moves coming faster than the reports get commented on with what there is
+        self.report(before);
+
+        let game = &mut self.controller.game;
+        let mover = game.side;
+        let number = match mover {
+            Color::White => format!("{}.", game.get_fullmoves()),
+            Color::Black => format!("{}...", game.get_fullmoves()),
+        };
+        let label = format!("{} {}", number, game.move_to_san(board_move));
+        let best = best.map(|best| (best, game.move_to_san(best)));
+
+        if let MoveResultType::Success = self.controller.try_move_piece(&board_move.unparse()) {
+            self.pending = Some(PlayedMove {
+                label,
+                mover,
+                board_move,
+                best,
+                before,
+            });
+        }
+
+        self.start_analysis();
+    }
+
+    /// Handle a line of input, returning false once kibitzing should end
+    pub fn handle(&mut self, line: &str) -> bool {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+
+        match parts.as_slice() {
+            [] => {}
+            ["quit"] => {
+                self.controller.stop_search_for_quit();
+                return false;
+            }
+            ["new"] => {
+                self.stop_analysis();
+                self.pending = None;
+                self.controller.reset_board();
+                self.controller.new_game();
+                self.start_analysis();
+            }
+            ["fen", fen @ ..] => {
+                self.stop_analysis();
+                self.pending = None;
+                if let Err(e) = self.controller.set_board_from_fen(&fen.join(" ")) {
+                    println!("{}", e);
+                }
+                self.start_analysis();
+            }
+            ["undo"] => {
+                self.stop_analysis();
+                self.pending = None;
+                self.controller.undo_move();
+                self.start_analysis();
+            }
+            [notation] => self.play(notation),
+            _ => println!("Invalid command: {}", line.trim()),
+        }
+
+        true
+    }
+}
+
+/// Kibitz the moves coming in on stdin until `quit` (or the end of the input), commenting on
+/// each after analysing the position it led to for the given time
+pub fn run_kibitz(controller: GameController, report_delay: Duration) {
+    let mut kibitz = Kibitz::new(controller, report_delay);
+    let input = spawn_input_reader();
+
+    kibitz.start_analysis();
+
+    loop {
+        kibitz.poll();
+
+        match input.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                if !kibitz.handle(&line) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                kibitz.handle("quit");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,640 @@
+//! Engine vs. engine matches over UCI.
+//!
+//! Spawns both engines as subprocesses, keeps the clocks, adjudicates the games
+//! (checkmate, draw rules, time forfeits and score-based resignations/draws)
+//! and writes them to a PGN file, so quick strength checks don't need fastchess.
+
+use crate::controller::training::GameResult;
+use crate::engine::book::OpeningBook;
+use crate::game::bitboard::Bitboard;
+use crate::game::board::{BoardMove, Game};
+use crate::game::pieces::{Color, Piece};
+use fxhash::FxHashMap;
+use rand::Rng;
+use rayon::prelude::*;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long an engine gets to answer uci/isready
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How late a move may come in before the engine loses on time without the GUI noticing
+const TIME_GRACE_MS: i64 = 100;
+
+// Resign adjudication: both engines agree that one side is this far ahead for this many plies
+const RESIGN_SCORE: i32 = 1000;
+const RESIGN_PLIES: u32 = 6;
+
+// Squares of the same color as h1
+const LIGHT_SQUARES: Bitboard = Bitboard(0x55AA_55AA_55AA_55AA);
+
+// Draw adjudication: both engines see a dead equal position for this many plies after this move
+const DRAW_SCORE: i32 = 10;
+const DRAW_PLIES: u32 = 16;
+const DRAW_MIN_FULLMOVES: usize = 40;
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct MatchConfig {
+    pub engine_a: String,
+    pub engine_b: String,
+    pub games: u32,
+    pub time_control: String,
+    pub concurrency: u32,
+    pub start_moves_min: u32,
+    pub start_moves_max: u32,
+    pub pgn_path: String,
+    pub book: Option<String>, // opening book the engines play from instead of random openings
+    pub book_learning: bool,  // adjust the book weights from the game results
+    pub material_rule: MaterialRule, // when games are drawn for lack of mating material
+}
+
+/// What counts as too little material to mate, drawing the game on the spot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaterialRule {
+    /// Only dead positions, where no sequence of legal moves mates (the FIDE rule): bare kings,
+    /// a single minor piece, or bishops all on squares of the same color
+    #[default]
+    Fide,
+    /// Also positions where neither side has the material to force a mate, even though a
+    /// blunder could still get mated (like the USCF rules): a minor piece each, or two knights
+    /// against a bare king
+    Uscf,
+}
+
+impl MaterialRule {
+    pub fn parse(name: &str) -> Option<MaterialRule> {
+        match name.to_ascii_lowercase().as_str() {
+            "fide" => Some(MaterialRule::Fide),
+            "uscf" => Some(MaterialRule::Uscf),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MaterialRule::Fide => "FIDE",
+            MaterialRule::Uscf => "USCF",
+        }
+    }
+}
+
+/// Base time and increment of a `base+increment` time control (in seconds, like `10+0.1`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub base_ms: u64,
+    pub increment_ms: u64,
+}
+
+impl TimeControl {
+    pub fn parse(tc: &str) -> Option<Self> {
+        let (base, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+
+        let seconds_to_ms = |seconds: &str| {
+            let seconds = seconds.trim().parse::<f64>().ok()?;
+            (seconds >= 0.0).then(|| (seconds * 1000.0).round() as u64)
+        };
+
+        Some(Self {
+            base_ms: seconds_to_ms(base)?,
+            increment_ms: seconds_to_ms(increment)?,
+        })
+    }
+}
+
+// ============================================================================
+// UCI Engine Process
+// ============================================================================
+
+struct UciEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    fn start(path: &str) -> Result<Self, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Failed to start engine {}: {}", path, e))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // Read the output on a separate thread, so that waiting for it can time out
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            name: path.to_string(),
+            child,
+            stdin,
+            lines,
+        };
+
+        engine.send("uci")?;
+        for line in engine.wait_for("uciok", HANDSHAKE_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+        }
+
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Failed to write to {}: {}", self.name, e))
+    }
+
+    /// Collect output lines until one starting with `prefix` (included) arrives.
+    fn wait_for(&mut self, prefix: &str, timeout: Duration) -> Result<Vec<String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) => {
+                    let done = line.starts_with(prefix);
+                    lines.push(line);
+
+                    if done {
+                        return Ok(lines);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!("{} timed out waiting for {}", self.name, prefix));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(format!("{} exited while waiting for {}", self.name, prefix));
+                }
+            }
+        }
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", HANDSHAKE_TIMEOUT)?;
+        Ok(())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+
+        // Give the engine a moment to exit on its own before killing it
+        for _ in 0..50 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Score in centipawns from the last `info ... score` line (mates count as +-100000).
+pub(crate) fn parse_score(lines: &[String]) -> Option<i32> {
+    lines.iter().rev().find_map(|line| {
+        let mut parts = line.split_whitespace().skip_while(|&part| part != "score");
+        parts.next()?;
+
+        match (parts.next()?, parts.next()?.parse::<i32>().ok()?) {
+            ("cp", cp) => Some(cp),
+            ("mate", moves) if moves > 0 => Some(100_000 - moves),
+            ("mate", moves) => Some(-100_000 - moves),
+            _ => None,
+        }
+    })
+}
+
+// ============================================================================
+// Playing Games
+// ============================================================================
+
+pub struct PlayedGame {
+    pub white: String,
+    pub black: String,
+    pub moves: Vec<BoardMove>,
+    pub result: GameResult,
+    pub termination: String,
+}
+
+impl PlayedGame {
+    fn result_string(&self) -> &'static str {
+        match self.result {
+            GameResult::WhiteWin => "1-0",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::BlackWin => "0-1",
+        }
+    }
+
+    pub fn to_pgn(&self, round: u32, time_control: &str) -> String {
+        let mut game = Game::new(None);
+
+        format!(
+            "[Event \"Prokopakop match\"]\n\
+             [Round \"{}\"]\n\
+             [White \"{}\"]\n\
+             [Black \"{}\"]\n\
+             [Result \"{}\"]\n\
+             [TimeControl \"{}\"]\n\
+             [Termination \"{}\"]\n\n\
+             {} {}\n",
+            round,
+            self.white,
+            self.black,
+            self.result_string(),
+            time_control,
+            self.termination,
+            game.line_to_san(&self.moves),
+            self.result_string()
+        )
+    }
+}
+
+/// Whether the game is drawn for lack of mating material, by the given rule.
+pub(crate) fn is_insufficient_material(game: &Game, rule: MaterialRule) -> bool {
+    let heavy_or_pawns = game.piece_bitboards[Piece::Pawn as usize]
+        | game.piece_bitboards[Piece::Rook as usize]
+        | game.piece_bitboards[Piece::Queen as usize];
+    if !heavy_or_pawns.is_empty() {
+        return false;
+    }
+
+    let knights = game.piece_bitboards[Piece::Knight as usize];
+    let bishops = game.piece_bitboards[Piece::Bishop as usize];
+    let minors = knights | bishops;
+
+    // bishops of one color can never cover the squares next to a king in a corner
+    let same_colored_bishops = knights.is_empty()
+        && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty());
+    if minors.count_ones() <= 1 || same_colored_bishops {
+        return true;
+    }
+
+    match rule {
+        MaterialRule::Fide => false,
+        MaterialRule::Uscf => {
+            let white = (minors & game.color_bitboards[Color::White as usize]).count_ones();
+            let black = (minors & game.color_bitboards[Color::Black as usize]).count_ones();
+            let two_knights = knights == minors && minors.count_ones() == 2;
+
+            (white <= 1 && black <= 1) || (two_knights && (white == 0 || black == 0))
+        }
+    }
+}
+
+fn win_for(color: Color) -> GameResult {
+    match color {
+        Color::White => GameResult::WhiteWin,
+        Color::Black => GameResult::BlackWin,
+    }
+}
+
+/// Play a game from the given opening moves, `engines[0]` being white.
+fn play_game(
+    engines: [&mut UciEngine; 2],
+    opening: &[BoardMove],
+    time_control: TimeControl,
+    material_rule: MaterialRule,
+) -> Result<PlayedGame, String> {
+    let [white, black] = engines;
+    white.new_game()?;
+    black.new_game()?;
+
+    let mut game = Game::new(None);
+    let mut moves = Vec::new();
+    let mut repetitions: FxHashMap<u64, u32> = FxHashMap::default();
+    *repetitions.entry(game.zobrist_key).or_insert(0) += 1;
+
+    for board_move in opening {
+        game.make_move(*board_move);
+        moves.push(*board_move);
+        *repetitions.entry(game.zobrist_key).or_insert(0) += 1;
+    }
+
+    let mut clocks = [time_control.base_ms as i64; 2]; // indexed by color
+    let mut resign_plies = 0;
+    let mut draw_plies = 0;
+    let mut last_score: Option<i32> = None;
+
+    let (result, termination) = loop {
+        let (move_count, _) = game.get_moves();
+        if move_count == 0 {
+            break if game.is_king_in_check(game.side) {
+                (win_for(!game.side), "checkmate")
+            } else {
+                (GameResult::Draw, "stalemate")
+            };
+        }
+
+        if game.is_fifty_move_rule() {
+            break (GameResult::Draw, "fifty move rule");
+        }
+
+        if repetitions[&game.zobrist_key] >= 3 {
+            break (GameResult::Draw, "threefold repetition");
+        }
+
+        if is_insufficient_material(&game, material_rule) {
+            break (GameResult::Draw, "insufficient material");
+        }
+
+        let side = game.side;
+        let engine = match side {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+
+        let position = if moves.is_empty() {
+            "position startpos".to_string()
+        } else {
+            let moves = moves.iter().map(|m| m.unparse()).collect::<Vec<_>>();
+            format!("position startpos moves {}", moves.join(" "))
+        };
+
+        engine.send(&position)?;
+        engine.send(&format!(
+            "go wtime {} btime {} winc {} binc {}",
+            clocks[Color::White as usize].max(1),
+            clocks[Color::Black as usize].max(1),
+            time_control.increment_ms,
+            time_control.increment_ms
+        ))?;
+
+        let start = Instant::now();
+        let timeout = Duration::from_millis((clocks[side as usize] + TIME_GRACE_MS) as u64);
+        let Ok(output) = engine.wait_for("bestmove", timeout) else {
+            break (win_for(!side), "time forfeit");
+        };
+
+        clocks[side as usize] -= start.elapsed().as_millis() as i64;
+        if clocks[side as usize] < 0 {
+            break (win_for(!side), "time forfeit");
+        }
+        clocks[side as usize] += time_control.increment_ms as i64;
+
+        let best_move = output
+            .last()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(BoardMove::parse)
+            .and_then(|best_move| game.legal_move(best_move));
+
+        let Some(best_move) = best_move else {
+            break (win_for(!side), "illegal move");
+        };
+        game.make_move(best_move);
+
+        // Adjudication from the engines' scores, which are from the mover's point of view
+        let score = parse_score(&output);
+        match (last_score, score) {
+            (Some(previous), Some(current))
+                if (previous >= RESIGN_SCORE && current <= -RESIGN_SCORE)
+                    || (previous <= -RESIGN_SCORE && current >= RESIGN_SCORE) =>
+            {
+                resign_plies += 1
+            }
+            _ => resign_plies = 0,
+        }
+        match score {
+            Some(current) if current.abs() <= DRAW_SCORE => draw_plies += 1,
+            _ => draw_plies = 0,
+        }
+        last_score = score;
+
+        moves.push(best_move);
+        *repetitions.entry(game.zobrist_key).or_insert(0) += 1;
+
+        if resign_plies >= RESIGN_PLIES {
+            let mover_winning = score.unwrap_or(0) > 0;
+            break if mover_winning {
+                (win_for(side), "adjudication")
+            } else {
+                (win_for(!side), "adjudication")
+            };
+        }
+
+        if draw_plies >= DRAW_PLIES && game.get_fullmoves() >= DRAW_MIN_FULLMOVES {
+            break (GameResult::Draw, "adjudication");
+        }
+    };
+
+    Ok(PlayedGame {
+        white: white.name.clone(),
+        black: black.name.clone(),
+        moves,
+        result,
+        termination: termination.to_string(),
+    })
+}
+
+/// Random legal moves from the starting position (that don't end the game).
+fn random_opening(config: &MatchConfig) -> Vec<BoardMove> {
+    let mut rng = rand::rng();
+
+    loop {
+        let mut game = Game::new(None);
+        let mut opening = Vec::new();
+        let plies = rng.random_range(config.start_moves_min..=config.start_moves_max);
+
+        for _ in 0..plies {
+            let (move_count, moves) = game.get_moves();
+            if move_count == 0 {
+                break;
+            }
+
+            let board_move = moves[rng.random_range(0..move_count)];
+            game.make_move(board_move);
+            opening.push(board_move);
+        }
+
+        let (move_count, _) = game.get_moves();
+        if move_count != 0 {
+            return opening;
+        }
+    }
+}
+
+/// Play the same opening with both colors.
+fn play_game_pair(
+    config: &MatchConfig,
+    time_control: TimeControl,
+) -> Result<[PlayedGame; 2], String> {
+    let mut engine_a = UciEngine::start(&config.engine_a)?;
+    let mut engine_b = UciEngine::start(&config.engine_b)?;
+
+    // the engines are started for every pair, so they read the weights learned so far
+    let opening = match &config.book {
+        Some(book) => {
+            engine_a.send(&format!("setoption name BookFile value {}", book))?;
+            engine_b.send(&format!("setoption name BookFile value {}", book))?;
+            Vec::new()
+        }
+        None => random_opening(config),
+    };
+
+    let first = play_game(
+        [&mut engine_a, &mut engine_b],
+        &opening,
+        time_control,
+        config.material_rule,
+    )?;
+    let second = play_game(
+        [&mut engine_b, &mut engine_a],
+        &opening,
+        time_control,
+        config.material_rule,
+    )?;
+
+    Ok([first, second])
+}
+
+/// Elo difference corresponding to a score fraction.
+pub fn elo_difference(score: f64) -> f64 {
+    let score = score.clamp(0.001, 0.999);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// ============================================================================
+// Entry Point
+// ============================================================================
+
+/// Score of an engine in a game, given which color it played.
+fn score_of(game: &PlayedGame, engine_is_white: bool) -> f64 {
+    let white_score = game.result.to_white_score() as f64;
+    if engine_is_white {
+        white_score
+    } else {
+        1.0 - white_score
+    }
+}
+
+pub fn run_match(config: MatchConfig) -> Result<(), String> {
+    let time_control = TimeControl::parse(&config.time_control)
+        .ok_or_else(|| format!("Invalid time control: {}", config.time_control))?;
+
+    let pgn_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.pgn_path)
+        .map_err(|e| format!("Failed to open {}: {}", config.pgn_path, e))?;
+
+    // (wins, draws, losses) of engine A, the PGN file and the number of games played
+    let state = Mutex::new(([0u32; 3], pgn_file, 0u32));
+
+    // the engines only see the book, so the results are learned from here
+    let mut book = None;
+    if let Some(path) = config.book.as_ref().filter(|_| config.book_learning) {
+        let opened = OpeningBook::open(Path::new(path))
+            .map_err(|e| format!("Failed to open book {}: {}", path, e))?;
+        if !opened.is_writable() {
+            return Err("Book learning needs a book file, not a PGN".to_string());
+        }
+        book = Some(Mutex::new(opened));
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.concurrency.max(1) as usize)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let pairs = config.games.div_ceil(2);
+    pool.install(|| {
+        (0..pairs).into_par_iter().try_for_each(|_| {
+            let games = play_game_pair(&config, time_control)?;
+
+            let mut state = state.lock().unwrap();
+            let (tally, pgn_file, played) = &mut *state;
+
+            // engine A is white in the first game of each pair
+            for (game, a_is_white) in games.iter().zip([true, false]) {
+                *played += 1;
+
+                let score = score_of(game, a_is_white);
+                let outcome = if score == 1.0 {
+                    0
+                } else if score == 0.5 {
+                    1
+                } else {
+                    2
+                };
+                tally[outcome] += 1;
+
+                writeln!(pgn_file, "{}", game.to_pgn(*played, &config.time_control))
+                    .map_err(|e| format!("Failed to write PGN: {}", e))?;
+
+                if let Some(book) = &book {
+                    let mut book = book.lock().unwrap();
+                    let mut final_position = Game::new(None);
+                    for &board_move in &game.moves {
+                        final_position.make_move(board_move);
+                    }
+
+                    if book.learn(&final_position, game.result.winner()) > 0 {
+                        book.save()
+                            .map_err(|e| format!("Failed to write the book: {}", e))?;
+                    }
+                }
+
+                eprintln!(
+                    "Game {}/{}: {} vs {} {} ({}) | A: +{} ={} -{}",
+                    played,
+                    pairs * 2,
+                    game.white,
+                    game.black,
+                    game.result_string(),
+                    game.termination,
+                    tally[0],
+                    tally[1],
+                    tally[2]
+                );
+            }
+
+            Ok::<(), String>(())
+        })
+    })?;
+
+    let ([wins, draws, losses], _, played) = state.into_inner().unwrap();
+    let score = (wins as f64 + 0.5 * draws as f64) / played.max(1) as f64;
+
+    eprintln!();
+    eprintln!("Engine A: {}", config.engine_a);
+    eprintln!("Engine B: {}", config.engine_b);
+    eprintln!(
+        "Score of A: +{} ={} -{} ({:.1}%), Elo difference: {:+.1}",
+        wins,
+        draws,
+        losses,
+        score * 100.0,
+        elo_difference(score)
+    );
+
+    Ok(())
+}
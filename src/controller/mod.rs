@@ -1,4 +1,15 @@
+pub mod batch;
+pub mod bench;
+pub mod bot;
 pub mod cli;
+pub mod config;
 pub mod controller;
+pub mod estimate;
+pub mod eval_diff;
+pub mod kibitz;
+pub mod match_runner;
 pub mod optimize;
+pub mod selftest;
 pub mod training;
+pub mod verify;
+pub mod xboard;
@@ -75,10 +75,14 @@ pub struct TunableParameter {
     pub value: f64,
     pub min_val: f64,
     pub max_val: f64,
-    pub is_int: bool,
+    pub type_name: String, // of the constant in params.rs (f32, i32 or usize)
 }
 
 impl TunableParameter {
+    fn is_int(&self) -> bool {
+        self.type_name != "f32"
+    }
+
     fn normalized(&self) -> f64 {
         (self.value - self.min_val) / (self.max_val - self.min_val)
     }
@@ -90,10 +94,10 @@ impl TunableParameter {
     fn with_value(&self, value: f64) -> Self {
         Self {
             name: self.name.clone(),
-            value: if self.is_int { value.round() } else { value },
+            value: if self.is_int() { value.round() } else { value },
             min_val: self.min_val,
             max_val: self.max_val,
-            is_int: self.is_int,
+            type_name: self.type_name.clone(),
         }
     }
 
@@ -110,7 +114,7 @@ impl TunableParameter {
 
 impl fmt::Display for TunableParameter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_int {
+        if self.is_int() {
             write!(f, "{}", self.value as i64)
         } else {
             write!(f, "{:.1}", self.value)
@@ -262,8 +266,7 @@ impl SpsaOptimizer {
         eprintln!("Final tuned parameters:");
         eprintln!("{}", "=".repeat(50));
         for p in &self.params {
-            let type_str = if p.is_int { "usize" } else { "f32" };
-            eprintln!("pub const {}: {} = {};", p.name, type_str, p);
+            eprintln!("pub const {}: {} = {};", p.name, p.type_name, p);
         }
         eprintln!();
     }
@@ -307,7 +310,7 @@ impl Drop for SpsaOptimizer {
 
 fn parse_params_file(content: &str) -> Result<Vec<TunableParameter>, String> {
     let re = Regex::new(
-        r"pub const (\w+): (f32|usize) = ([\d.]+);.*?//.*?min:\s*([\d.]+),\s*max:\s*([\d.]+)",
+        r"pub const (\w+): (f32|i32|usize) = ([\d.]+);.*?//.*?min:\s*([\d.]+),\s*max:\s*([\d.]+)",
     )
     .map_err(|e| e.to_string())?;
 
@@ -318,7 +321,7 @@ fn parse_params_file(content: &str) -> Result<Vec<TunableParameter>, String> {
                 value: cap[3].parse().map_err(|e| format!("Parse error: {}", e))?,
                 min_val: cap[4].parse().map_err(|e| format!("Parse error: {}", e))?,
                 max_val: cap[5].parse().map_err(|e| format!("Parse error: {}", e))?,
-                is_int: &cap[2] == "usize",
+                type_name: cap[2].to_string(),
             })
         })
         .collect()
@@ -327,7 +330,10 @@ fn parse_params_file(content: &str) -> Result<Vec<TunableParameter>, String> {
 fn update_params_content(params: &[TunableParameter], content: &str) -> String {
     let mut result = content.to_string();
     for param in params {
-        let pattern = format!(r"(pub const {}: (?:f32|usize) = )([\d.]+)(;)", param.name);
+        let pattern = format!(
+            r"(pub const {}: (?:f32|i32|usize) = )([\d.]+)(;)",
+            param.name
+        );
         let re = Regex::new(&pattern).unwrap();
         result = re
             .replace(&result, format!("${{1}}{}${{3}}", param))
@@ -0,0 +1,124 @@
+//! Self-tests over long random games, checking invariants at every ply rather than in the
+//! handful of positions the unit tests have.
+
+use crate::game::board::{BoardMove, Game};
+use rand::Rng;
+use std::fmt::{Display, Formatter, Result};
+
+// Random games are stopped after this many plies, unless they end sooner
+const MAX_PLIES: usize = 400;
+
+/// Position whose FEN doesn't describe it faithfully
+#[derive(Debug, Clone)]
+pub struct FenMismatch {
+    pub game: u32,
+    pub moves: Vec<BoardMove>, // played from the starting position
+    pub fen: String,
+    pub reason: String,
+}
+
+/// Result of playing random games, round-tripping every position through its FEN
+#[derive(Debug, Clone, Default)]
+pub struct FenSelftest {
+    pub games: u32,
+    pub positions: u64,
+    pub mismatch: Option<FenMismatch>, // the first one, which stops the test
+}
+
+impl Display for FenSelftest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "selftest fen: {} games, {} positions, ",
+            self.games, self.positions
+        )?;
+
+        let Some(mismatch) = &self.mismatch else {
+            return write!(f, "ok");
+        };
+
+        let moves = mismatch
+            .moves
+            .iter()
+            .map(|m| m.unparse())
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "FAILED in game {}: {} ({})\nposition startpos moves {}",
+            mismatch.game,
+            mismatch.reason,
+            mismatch.fen,
+            moves.join(" ")
+        )
+    }
+}
+
+/// Legal moves in a canonical order, for comparing the move sets of two positions
+fn sorted_moves(game: &Game) -> Vec<BoardMove> {
+    let (move_count, moves) = game.get_moves();
+    let mut moves = moves[..move_count].to_vec();
+    moves.sort_unstable();
+    moves
+}
+
+/// Whether the position parsed back from the game's FEN is the same one: the same FEN again
+/// (so the en passant square and the move counters survive), zobrist key and legal moves
+pub fn check_fen_roundtrip(game: &Game) -> std::result::Result<(), String> {
+    let fen = game.get_fen();
+    let parsed = Game::from_fen(&fen).map_err(|e| format!("unparsable FEN: {}", e))?;
+
+    if parsed.get_fen() != fen {
+        return Err(format!("FEN parsed back as {}", parsed.get_fen()));
+    }
+
+    if parsed.zobrist_key != game.zobrist_key {
+        return Err(format!(
+            "zobrist key {:016x} parsed back as {:016x}",
+            game.zobrist_key, parsed.zobrist_key
+        ));
+    }
+
+    if sorted_moves(&parsed) != sorted_moves(game) {
+        return Err("different legal moves".to_string());
+    }
+
+    Ok(())
+}
+
+/// Play random games from the starting position, checking the FEN round trip of every
+/// position (see `check_fen_roundtrip`) until the first one that fails
+pub fn fen_selftest(games: u32) -> FenSelftest {
+    let mut rng = rand::rng();
+    let mut result = FenSelftest::default();
+
+    for index in 0..games {
+        let mut game = Game::new(None);
+        let mut moves = Vec::new();
+        result.games += 1;
+
+        loop {
+            result.positions += 1;
+
+            if let Err(reason) = check_fen_roundtrip(&game) {
+                result.mismatch = Some(FenMismatch {
+                    game: index + 1,
+                    moves,
+                    fen: game.get_fen(),
+                    reason,
+                });
+                return result;
+            }
+
+            let (move_count, legal_moves) = game.get_moves();
+            if move_count == 0 || moves.len() >= MAX_PLIES || game.is_fifty_move_rule() {
+                break;
+            }
+
+            let board_move = legal_moves[rng.random_range(0..move_count)];
+            game.make_move(board_move);
+            moves.push(board_move);
+        }
+    }
+
+    result
+}
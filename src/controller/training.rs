@@ -1,29 +1,43 @@
 use crate::controller::controller::GameController;
+use crate::controller::match_runner::{MaterialRule, is_insufficient_material};
+use crate::engine::evaluate::{evaluate_classical, is_mate_score};
+use crate::game::board::{BoardMove, Game};
 use crate::game::pieces::Color;
-use fxhash::FxHashMap;
+use fxhash::FxHashSet;
 use rand::Rng;
 use rayon::prelude::*;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+// How often the progress line is refreshed
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+pub const DEFAULT_MAX_PLIES: usize = 400;
 
 /// Represents a single training position with evaluation and game result
 #[derive(Debug, Clone)]
 pub struct TrainingPosition {
     pub fen: String,
     pub zobrist_key: u64,
-    pub evaluation: f32, // White-relative, in centipawns
+    pub evaluation: i32, // White-relative, in centipawns
     pub result: f32,     // White-relative (1.0 = white win, 0.5 = draw, 0.0 = white loss)
+    pub nnue_eval: i32,  // White-relative static NNUE evaluation
+    pub hce_eval: i32,   // White-relative static hand-crafted evaluation
 }
 
 impl TrainingPosition {
+    /// How much the static NNUE and hand-crafted evaluations disagree on the position
+    pub fn eval_disagreement(&self) -> i32 {
+        (self.nnue_eval - self.hce_eval).abs()
+    }
+
     pub fn to_line(&self) -> String {
-        format!(
-            "{} | {} | {}",
-            self.fen, self.evaluation as i32, self.result
-        )
+        format!("{} | {} | {}", self.fen, self.evaluation, self.result)
     }
 }
 
@@ -44,6 +58,167 @@ impl GameResult {
             GameResult::BlackWin => 0.0,
         }
     }
+
+    /// The side that won, None for a draw
+    pub fn winner(self) -> Option<Color> {
+        match self {
+            GameResult::WhiteWin => Some(Color::White),
+            GameResult::Draw => None,
+            GameResult::BlackWin => Some(Color::Black),
+        }
+    }
+}
+
+/// Why a selfplay game ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameTermination {
+    Checkmate = 0,
+    Stalemate = 1,
+    FiftyMoveRule = 2,
+    Repetition = 3,
+    InsufficientMaterial = 4,
+    MaxPlies = 5, // cut off without a result, so its positions aren't used
+}
+
+impl GameTermination {
+    pub const ALL: [GameTermination; 6] = [
+        GameTermination::Checkmate,
+        GameTermination::Stalemate,
+        GameTermination::FiftyMoveRule,
+        GameTermination::Repetition,
+        GameTermination::InsufficientMaterial,
+        GameTermination::MaxPlies,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            GameTermination::Checkmate => "checkmate",
+            GameTermination::Stalemate => "stalemate",
+            GameTermination::FiftyMoveRule => "fifty move rule",
+            GameTermination::Repetition => "repetition",
+            GameTermination::InsufficientMaterial => "insufficient material",
+            GameTermination::MaxPlies => "max plies",
+        }
+    }
+}
+
+/// Games ended by each termination
+#[derive(Debug, Clone, Default)]
+pub struct TerminationCounts {
+    pub counts: [u64; GameTermination::ALL.len()], // [termination]
+}
+
+impl TerminationCounts {
+    pub fn record(&mut self, termination: GameTermination) {
+        self.counts[termination as usize] += 1;
+    }
+}
+
+impl std::fmt::Display for TerminationCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<String> = GameTermination::ALL
+            .iter()
+            .map(|&termination| {
+                format!(
+                    "{} {}",
+                    termination.name(),
+                    self.counts[termination as usize]
+                )
+            })
+            .collect();
+
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Leaving out positions whose zobrist key was already written in the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dedupe {
+    #[default]
+    Off,
+    Exact, // every written key is kept in a set
+    // A bloom filter of this many megabytes, for runs with too many positions for the set;
+    // no duplicate gets through, but a small fraction of new positions is dropped as well
+    Approximate(usize),
+}
+
+impl Dedupe {
+    pub fn parse(name: &str, memory_mb: usize) -> Option<Dedupe> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Some(Dedupe::Off),
+            "exact" => Some(Dedupe::Exact),
+            "bloom" | "approximate" => Some(Dedupe::Approximate(memory_mb)),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dedupe::Off => "off",
+            Dedupe::Exact => "exact",
+            Dedupe::Approximate(_) => "bloom",
+        }
+    }
+}
+
+/// Fixed size set of keys that can answer "maybe seen" for a key it never got
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    const NUM_HASHES: u64 = 4;
+
+    pub(crate) fn new(memory_mb: usize) -> Self {
+        let words = (memory_mb * 1024 * 1024 / 8).max(1);
+        Self {
+            bits: vec![0; words],
+        }
+    }
+
+    /// Set the bits of the key, returning whether any of them wasn't set yet
+    pub(crate) fn insert(&mut self, key: u64) -> bool {
+        let num_bits = self.bits.len() as u64 * 64;
+
+        // zobrist keys are already random, so the two halves make the hashes (double hashing)
+        let step = key.rotate_left(32) | 1;
+        let mut new = false;
+        for i in 0..Self::NUM_HASHES {
+            let bit = key.wrapping_add(i.wrapping_mul(step)) % num_bits;
+            let (word, mask) = ((bit / 64) as usize, 1u64 << (bit % 64));
+
+            new |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+
+        new
+    }
+}
+
+/// Zobrist keys of the positions generated so far in a run
+pub(crate) enum SeenPositions {
+    Exact(FxHashSet<u64>),
+    Approximate(BloomFilter),
+}
+
+impl SeenPositions {
+    pub(crate) fn new(dedupe: Dedupe) -> Self {
+        match dedupe {
+            // without dedupe the keys are still counted, for the uniqueness in the progress
+            Dedupe::Off | Dedupe::Exact => SeenPositions::Exact(FxHashSet::default()),
+            Dedupe::Approximate(memory_mb) => {
+                SeenPositions::Approximate(BloomFilter::new(memory_mb))
+            }
+        }
+    }
+
+    /// Record the key, returning whether it is new (for the bloom filter, very likely new)
+    pub(crate) fn insert(&mut self, key: u64) -> bool {
+        match self {
+            SeenPositions::Exact(keys) => keys.insert(key),
+            SeenPositions::Approximate(filter) => filter.insert(key),
+        }
+    }
 }
 
 /// Configuration for training data generation
@@ -53,6 +228,18 @@ pub struct TrainingConfig {
     pub search_depth: usize,
     pub start_moves_min: u32,
     pub start_moves_max: u32,
+    // When set, only positions where NNUE and HCE disagree by at least this many centipawns
+    // are kept, plus a random `keep_fraction` of the rest
+    pub disagreement_margin: Option<i32>,
+    pub keep_fraction: f64,
+    pub max_plies: usize, // games still going after this many plies are cut off
+    // Label positions with the static evaluation of the (quiescent) PV leaf, instead of the
+    // root search score
+    pub pv_leaf_eval: bool,
+    // Games reaching too little material to mate by this rule end as draws right away, so
+    // their positions aren't labeled with a win that could never happen
+    pub material_rule: MaterialRule,
+    pub dedupe: Dedupe,
 }
 
 impl TrainingConfig {
@@ -67,34 +254,140 @@ impl TrainingConfig {
             search_depth,
             start_moves_min,
             start_moves_max,
+            disagreement_margin: None,
+            keep_fraction: 1.0,
+            max_plies: DEFAULT_MAX_PLIES,
+            pv_leaf_eval: false,
+            material_rule: MaterialRule::default(),
+            dedupe: Dedupe::default(),
         }
     }
 
+    /// Label the positions with the evaluation of the quiet position at the end of the PV
+    /// (which includes the quiescence search captures) rather than the root score
+    pub fn with_pv_leaf_eval(mut self) -> Self {
+        self.pv_leaf_eval = true;
+        self
+    }
+
+    /// Adjudicate games without the material to mate by the given rule as draws
+    pub fn with_material_rule(mut self, material_rule: MaterialRule) -> Self {
+        self.material_rule = material_rule;
+        self
+    }
+
+    /// Write each position at most once in the run
+    pub fn with_dedupe(mut self, dedupe: Dedupe) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Cut off games (that would otherwise shuffle on for a long time) after this many plies
+    pub fn with_max_plies(mut self, max_plies: usize) -> Self {
+        self.max_plies = max_plies;
+        self
+    }
+
+    /// Prefer positions where the static evaluations disagree, which carry more information
+    /// for training than the ones both already agree on.
+    pub fn with_disagreement_sampling(mut self, margin: i32, keep_fraction: f64) -> Self {
+        self.disagreement_margin = Some(margin);
+        self.keep_fraction = keep_fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Filtering pass over the positions of a game, according to the sampling settings
+    pub fn select_positions(&self, positions: Vec<TrainingPosition>) -> Vec<TrainingPosition> {
+        let Some(margin) = self.disagreement_margin else {
+            return positions;
+        };
+
+        let mut rng = rand::rng();
+        positions
+            .into_iter()
+            .filter(|pos| pos.eval_disagreement() >= margin || rng.random_bool(self.keep_fraction))
+            .collect()
+    }
+
     pub fn random_starting_moves(&self) -> u32 {
         let mut rng = rand::rng();
         rng.random_range(self.start_moves_min..=self.start_moves_max)
     }
 }
 
+/// White-relative static evaluation of the position at the end of the PV, or None if that
+/// position isn't quiet (in check, or the game is over there), e.g. when the PV was cut short
+pub fn pv_leaf_eval(game: &Game, pv: &[BoardMove]) -> Option<i32> {
+    let mut leaf = game.clone();
+    for &board_move in pv {
+        leaf.make_move(board_move);
+    }
+
+    if leaf.is_king_in_check(leaf.side) || leaf.get_moves().0 == 0 {
+        return None;
+    }
+
+    Some(leaf.evaluate())
+}
+
+// Set by the Ctrl-C handler; workers stop and the writer flushes what it has
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn handle_interrupt(_: libc::c_int) {
+    // a second Ctrl-C kills the process right away
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Make Ctrl-C stop the data generation gracefully instead of killing the process (on Unix,
+/// elsewhere it keeps killing it)
+pub fn install_interrupt_handler() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
 /// Generates training data through self-play with parallel game execution
 pub struct TrainingDataGenerator {
     config: TrainingConfig,
+    stop: &'static AtomicBool,
 }
 
 impl TrainingDataGenerator {
     pub fn new(config: TrainingConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            stop: &INTERRUPTED,
+        }
+    }
+
+    /// Use a different flag than the Ctrl-C one to stop the generation
+    pub fn with_stop_flag(mut self, stop: &'static AtomicBool) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
     }
 
-    /// Play a single game and collect training data
-    fn play_game(&self) -> Vec<TrainingPosition> {
+    /// Play a single game and collect training data, along with how the game ended.
+    /// A game that couldn't be finished (interrupted, or without a move from the search)
+    /// has no termination and no positions.
+    fn play_game(&self) -> (Vec<TrainingPosition>, Option<GameTermination>) {
         let mut controller = GameController::new();
         controller.initialize();
         controller.move_overhead = 0;
+        controller.search_options.silent = true;
 
         let mut positions = Vec::new();
-
-        let mut game_result = None;
+        let mut plies = 0;
 
         // Play random starting moves before collecting training data
         let num_starting_moves = self.config.random_starting_moves();
@@ -111,25 +404,43 @@ impl TrainingDataGenerator {
             controller
                 .history
                 .push_position(controller.game.zobrist_key);
+            plies += 1;
         }
 
-        // Play until game ends or max halfmoves reached
-        loop {
+        // Play until game ends or max plies reached
+        let (game_result, termination) = loop {
+            // an unfinished game has no result to label the positions with
+            if self.is_stopped() {
+                return (Vec::new(), None);
+            }
+
+            if let Some(ending) = game_ending(&controller) {
+                break ending;
+            }
+
             if controller.game.is_fifty_move_rule() {
-                game_result = Some(GameResult::Draw);
-                break;
+                break (GameResult::Draw, GameTermination::FiftyMoveRule);
             }
 
             if controller
                 .history
                 .is_threefold_repetition(controller.game.zobrist_key)
             {
-                game_result = Some(GameResult::Draw);
-                break;
+                break (GameResult::Draw, GameTermination::Repetition);
+            }
+
+            if is_insufficient_material(&controller.game, self.config.material_rule) {
+                break (GameResult::Draw, GameTermination::InsufficientMaterial);
+            }
+
+            if plies >= self.config.max_plies {
+                break (GameResult::Draw, GameTermination::MaxPlies);
             }
 
             // Store current position before search
             let current_fen = controller.game.get_fen();
+            let nnue_eval = controller.game.evaluate();
+            let hce_eval = evaluate_classical(&controller.game);
 
             let search_params = vec!["depth".to_string(), self.config.search_depth.to_string()];
             controller.search(search_params, false);
@@ -137,17 +448,27 @@ impl TrainingDataGenerator {
             // Wait for search to complete naturally (don't interrupt)
             let search_result = controller.wait_for_search();
 
-            if let Some(result) = search_result {
-                // Check if move is valid (not empty)
-                if result.best_move != 0 {
+            match search_result {
+                Some(result) if result.best_move != BoardMove::empty() => {
+                    let root_eval = match controller.game.side {
+                        Color::White => result.evaluation,
+                        Color::Black => -result.evaluation,
+                    };
+
+                    // a mate score is exact, while the leaf of a mating PV is no quiet position
+                    let is_mate = is_mate_score(result.evaluation);
+                    let evaluation = match self.config.pv_leaf_eval && !is_mate {
+                        true => pv_leaf_eval(&controller.game, &result.pv).unwrap_or(root_eval),
+                        false => root_eval,
+                    };
+
                     positions.push(TrainingPosition {
                         fen: current_fen,
                         zobrist_key: controller.game.zobrist_key,
-                        evaluation: match controller.game.side {
-                            Color::White => result.evaluation,
-                            Color::Black => -result.evaluation,
-                        },
+                        evaluation,
                         result: 0.0, // Will be set after determining game result
+                        nnue_eval,
+                        hce_eval,
                     });
 
                     // Make the best move
@@ -155,27 +476,25 @@ impl TrainingDataGenerator {
                     controller
                         .history
                         .push_position(controller.game.zobrist_key);
-                } else {
-                    // No move found - likely checkmate or stalemate
-                    break;
+                    plies += 1;
                 }
-            } else {
-                break;
+                // the game isn't over (that's checked above), so the search failed
+                _ => return (Vec::new(), None),
             }
-        }
+        };
 
-        // Determine game result
-        if game_result.is_none() {
-            game_result = Some(determine_game_result(&controller));
+        // A game cut off by the ply limit has no real result to learn from
+        if termination == GameTermination::MaxPlies {
+            return (Vec::new(), Some(termination));
         }
 
         // Set the result for all positions now that we know the final result
-        let final_result = game_result.unwrap().to_white_score();
+        let final_result = game_result.to_white_score();
         for pos in &mut positions {
             pos.result = final_result;
         }
 
-        positions
+        (self.config.select_positions(positions), Some(termination))
     }
 
     /// Generate all training data with parallel game playing and immediate file writes
@@ -188,45 +507,86 @@ impl TrainingDataGenerator {
         );
 
         // Create channel for sending training positions from worker threads to writer thread
-        let (sender, receiver) = mpsc::channel::<Vec<TrainingPosition>>();
+        let (sender, receiver) =
+            mpsc::channel::<(Vec<TrainingPosition>, Option<GameTermination>)>();
         let path = path.to_string();
+        let num_games = self.config.num_games as u64;
+        let dedupe = self.config.dedupe;
 
         // Spawn writer thread that immediately writes positions to file
         let writer_thread = thread::spawn(move || {
-            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut file =
+                BufWriter::new(OpenOptions::new().create(true).append(true).open(&path)?);
             let mut total_positions = 0u64;
+            let mut generated_positions = 0u64;
+            let mut unique_positions = 0u64;
             let mut games_processed = 0u32;
-            let mut unique_positions = FxHashMap::default();
+            let mut seen = SeenPositions::new(dedupe);
             let writer_start_time = Instant::now();
+            let mut last_progress = writer_start_time;
+            let mut terminations = TerminationCounts::default();
+
+            for (positions_batch, termination) in receiver {
+                if let Some(termination) = termination {
+                    terminations.record(termination);
+                }
 
-            for positions_batch in receiver {
                 for pos in positions_batch {
+                    generated_positions += 1;
+                    let is_new = seen.insert(pos.zobrist_key);
+                    if is_new {
+                        unique_positions += 1;
+                    } else if dedupe != Dedupe::Off {
+                        continue;
+                    }
+
                     writeln!(file, "{}", pos.to_line())?;
-                    unique_positions.insert(pos.zobrist_key, ());
                     total_positions += 1;
                 }
                 games_processed += 1;
 
-                if games_processed % 10 == 0 {
-                    let elapsed = writer_start_time.elapsed();
-                    let duration_secs = elapsed.as_secs_f64();
+                if last_progress.elapsed() >= PROGRESS_INTERVAL
+                    || games_processed as u64 == num_games
+                {
+                    last_progress = Instant::now();
+
+                    let duration_secs = writer_start_time.elapsed().as_secs_f64();
                     let positions_per_sec = total_positions as f64 / duration_secs;
-                    let unique_count = unique_positions.len() as f64;
-                    let uniqueness_pct = (unique_count / total_positions as f64) * 100.0;
-                    println!(
-                        "Completed {} games ({} positions written, {:.2} positions/sec, {:.2}% unique)",
-                        games_processed, total_positions, positions_per_sec, uniqueness_pct
+                    let uniqueness_pct =
+                        (unique_positions as f64 / generated_positions.max(1) as f64) * 100.0;
+
+                    eprint!(
+                        "\r{}, {} positions ({:.2}/sec, {:.2}% unique)",
+                        progress_line(games_processed as u64, num_games, duration_secs),
+                        total_positions,
+                        positions_per_sec,
+                        uniqueness_pct
                     );
                 }
             }
 
+            // finish the progress line
+            if games_processed > 0 {
+                eprintln!();
+                eprintln!("Game endings: {}", terminations);
+            }
+
+            file.flush()?;
+
             Ok::<u64, std::io::Error>(total_positions)
         });
 
         (1..=self.config.num_games).into_par_iter().for_each_with(
             sender.clone(),
             |tx, _game_num| {
-                let _ = tx.send(self.play_game());
+                if self.is_stopped() {
+                    return;
+                }
+
+                let (positions, termination) = self.play_game();
+                if !self.is_stopped() || !positions.is_empty() {
+                    let _ = tx.send((positions, termination));
+                }
             },
         );
 
@@ -245,6 +605,10 @@ impl TrainingDataGenerator {
         let elapsed = start_time.elapsed();
         let duration_secs = elapsed.as_secs_f64();
 
+        if self.is_stopped() {
+            eprintln!("Interrupted, {} positions written so far", total_positions);
+        }
+
         eprintln!(
             "Training data generation complete. Total positions: {}",
             total_positions
@@ -259,25 +623,53 @@ impl TrainingDataGenerator {
     }
 }
 
-/// Determine the result of the game
-fn determine_game_result(controller: &GameController) -> GameResult {
+fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Progress bar of `done` out of `total` units of work, with the ETA extrapolated from the
+/// time taken so far
+pub fn progress_line(done: u64, total: u64, elapsed_secs: f64) -> String {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    };
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64) as usize;
+
+    let eta = if done == 0 {
+        "?".to_string()
+    } else {
+        format_duration(elapsed_secs / fraction - elapsed_secs)
+    };
+
+    format!(
+        "[{}{}] {:5.1}% ({}/{}), elapsed {}, ETA {}",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        fraction * 100.0,
+        done,
+        total,
+        format_duration(elapsed_secs),
+        eta
+    )
+}
+
+/// Result of a game that is over by checkmate or stalemate
+fn game_ending(controller: &GameController) -> Option<(GameResult, GameTermination)> {
     let (move_count, _) = controller.game.get_moves();
+    if move_count != 0 {
+        return None;
+    }
 
-    // No legal moves means either checkmate or stalemate
-    if move_count == 0 {
-        if controller.game.is_king_in_check(controller.game.side) {
-            // Checkmate
-            match controller.game.side {
-                Color::White => GameResult::BlackWin,
-                Color::Black => GameResult::WhiteWin,
-            }
-        } else {
-            // Stalemate
-            GameResult::Draw
-        }
+    if controller.game.is_king_in_check(controller.game.side) {
+        let result = match controller.game.side {
+            Color::White => GameResult::BlackWin,
+            Color::Black => GameResult::WhiteWin,
+        };
+        Some((result, GameTermination::Checkmate))
     } else {
-        // Game is still ongoing, treat as draw for training purposes
-        // (or could stop at a certain depth)
-        GameResult::Draw
+        Some((GameResult::Draw, GameTermination::Stalemate))
     }
 }
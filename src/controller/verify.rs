@@ -0,0 +1,70 @@
+use crate::controller::controller::GameController;
+use crate::engine::search::results::PruningStats;
+use rand::Rng;
+use rayon::prelude::*;
+
+/// Configuration for the verify-pruning mode
+#[derive(Debug, Clone)]
+pub struct VerifyPruningConfig {
+    pub num_positions: u32,
+    pub search_depth: usize,
+    pub start_moves_min: u32,
+    pub start_moves_max: u32,
+    pub sample_rate: f64, // fraction of pruned nodes that are re-searched without pruning
+    pub tolerance: i32,   // centipawns
+}
+
+/// Play random moves from the starting position to get a test position,
+/// returning whether there is still something to search
+fn play_random_moves(controller: &mut GameController, config: &VerifyPruningConfig) -> bool {
+    let mut rng = rand::rng();
+    let num_moves = rng.random_range(config.start_moves_min..=config.start_moves_max);
+
+    for _ in 0..num_moves {
+        let (move_count, moves) = controller.game.get_moves();
+        if move_count == 0 {
+            return false;
+        }
+
+        controller
+            .game
+            .make_move(moves[rng.random_range(0..move_count)]);
+        controller
+            .history
+            .push_position(controller.game.zobrist_key);
+    }
+
+    let (move_count, _) = controller.game.get_moves();
+    move_count != 0
+}
+
+/// Search random positions, re-searching a sample of the pruned nodes without pruning,
+/// and collect how often pruning changed the value of the node
+pub fn run_pruning_verification(config: &VerifyPruningConfig) -> PruningStats {
+    (0..config.num_positions)
+        .into_par_iter()
+        .map(|_| {
+            let mut controller = GameController::new();
+            controller.set_option("Hash", "16").unwrap();
+            controller.initialize();
+            controller.search_options.verify_pruning_rate = config.sample_rate;
+            controller.search_options.verify_pruning_tolerance = config.tolerance;
+            controller.search_options.silent = true;
+
+            if !play_random_moves(&mut controller, config) {
+                return PruningStats::default();
+            }
+
+            controller.search(
+                vec!["depth".to_string(), config.search_depth.to_string()],
+                false,
+            );
+            controller.wait_for_search();
+
+            controller.last_pruning_stats()
+        })
+        .reduce(PruningStats::default, |mut total, stats| {
+            total.merge(&stats);
+            total
+        })
+}
@@ -0,0 +1,323 @@
+//! XBoard (CECP) protocol adapter.
+//!
+//! Chosen when the first command is `xboard` instead of `uci`. Unlike UCI, the engine keeps
+//! the game itself: it plays its moves on its own board, tracks which side it plays and
+//! its clock, and starts thinking on its own whenever it is the engine's turn.
+
+use crate::controller::controller::{GameController, MoveResultType};
+use crate::engine::search::options::ThinkingFormat;
+use crate::engine::search::results::SearchResult;
+use crate::game::board::BoardMove;
+use crate::game::pieces::Color;
+use std::io;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// How often a running search is checked for having finished while waiting for input
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+// Time per move when the GUI sent neither a time control nor a depth
+const DEFAULT_MOVE_TIME_MS: u64 = 5000;
+
+pub struct XBoard {
+    pub controller: GameController,
+    force: bool,        // only play the moves received, don't think
+    engine_side: Color, // the side the engine plays when not in force mode
+    post: bool,         // print thinking output
+    thinking: bool,     // the running search is for the engine's move (to be played)
+    engine_time_ms: Option<u64>,
+    opponent_time_ms: Option<u64>,
+    moves_per_session: Option<usize>, // conventional time control: moves until the clock resets
+    increment_ms: u64,
+    move_time_ms: Option<u64>, // fixed time per move (st)
+    max_depth: Option<usize>,  // fixed depth (sd)
+}
+
+impl XBoard {
+    pub fn new(mut controller: GameController) -> Self {
+        controller.initialize();
+        controller.search_options.thinking_format = ThinkingFormat::XBoard;
+
+        Self {
+            controller,
+            force: false,
+            engine_side: Color::Black,
+            post: false,
+            thinking: false,
+            engine_time_ms: None,
+            opponent_time_ms: None,
+            moves_per_session: None,
+            increment_ms: 0,
+            move_time_ms: None,
+            max_depth: None,
+        }
+    }
+
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    /// Parameters of `go` for the current time control and clocks
+    pub(crate) fn search_params(&self) -> Vec<String> {
+        let mut params = Vec::new();
+        let mut push = |name: &str, value: u64| {
+            params.push(name.to_string());
+            params.push(value.to_string());
+        };
+
+        if let Some(depth) = self.max_depth {
+            push("depth", depth as u64);
+        }
+
+        if let Some(move_time) = self.move_time_ms {
+            push("movetime", move_time);
+            return params;
+        }
+
+        // time is the engine's clock, which may belong to either side
+        let (white_time, black_time) = match self.engine_side {
+            Color::White => (self.engine_time_ms, self.opponent_time_ms),
+            Color::Black => (self.opponent_time_ms, self.engine_time_ms),
+        };
+
+        if let (Some(white_time), Some(black_time)) = (white_time, black_time) {
+            push("wtime", white_time);
+            push("btime", black_time);
+            push("winc", self.increment_ms);
+            push("binc", self.increment_ms);
+
+            if let Some(moves) = self.moves_per_session {
+                let played = (self.controller.game.get_fullmoves() - 1) % moves;
+                push("movestogo", (moves - played) as u64);
+            }
+        } else if self.max_depth.is_none() {
+            push("movetime", DEFAULT_MOVE_TIME_MS);
+        }
+
+        params
+    }
+
+    /// Start thinking if it's the engine's turn (and the game isn't over)
+    fn think_if_engine_to_move(&mut self) {
+        if self.force || self.thinking || self.controller.game.side != self.engine_side {
+            return;
+        }
+
+        let (move_count, _) = self.controller.game.get_moves();
+        if move_count == 0 {
+            self.print_game_over();
+            return;
+        }
+
+        self.controller.search(self.search_params(), self.post);
+        self.thinking = true;
+    }
+
+    fn print_game_over(&mut self) {
+        let game = &self.controller.game;
+        if game.is_king_in_check(game.side) {
+            match game.side {
+                Color::White => println!("0-1 {{Black mates}}"),
+                Color::Black => println!("1-0 {{White mates}}"),
+            }
+        } else {
+            println!("1/2-1/2 {{Stalemate}}");
+        }
+    }
+
+    /// Play the result of the engine's search on the board and send it to the GUI
+    fn play_result(&mut self, result: Option<SearchResult>) {
+        self.thinking = false;
+
+        let Some(result) = result else {
+            return;
+        };
+        if result.best_move == BoardMove::empty() {
+            return;
+        }
+
+        let notation = result.best_move.unparse();
+        if let MoveResultType::Success = self.controller.try_move_piece(&notation) {
+            println!("move {}", notation);
+        }
+
+        let (move_count, _) = self.controller.game.get_moves();
+        if move_count == 0 {
+            self.print_game_over();
+        }
+    }
+
+    /// Play the engine's move if its search has finished
+    pub fn poll_search(&mut self) {
+        if self.thinking && !self.controller.is_searching() {
+            let result = self.controller.wait_for_search();
+            self.play_result(result);
+        }
+    }
+
+    /// Stop a running search without playing its move
+    fn abandon_search(&mut self) {
+        self.controller.stop_search();
+        self.thinking = false;
+    }
+
+    fn user_move(&mut self, notation: &str) {
+        self.abandon_search();
+
+        match self.controller.try_move_piece(notation) {
+            MoveResultType::Success => self.think_if_engine_to_move(),
+            _ => println!("Illegal move: {}", notation),
+        }
+    }
+
+    /// Handle a line of input, returning false once the engine should quit
+    pub fn handle(&mut self, line: &str) -> bool {
+        let parts = line.split_whitespace().collect::<Vec<_>>();
+
+        match parts.as_slice() {
+            [] | ["xboard"] | ["accepted", ..] | ["rejected", ..] => {}
+            ["protover", ..] => {
+                println!(
+                    "feature myname=\"Prokopakop\" ping=1 setboard=1 usermove=1 time=1 \
+                     sigint=0 sigterm=0 reuse=1 analyze=0 colors=0 done=1"
+                );
+            }
+            ["new"] => {
+                self.abandon_search();
+                self.controller.reset_board();
+                self.controller.new_game();
+                self.force = false;
+                self.engine_side = Color::Black;
+                self.move_time_ms = None;
+                self.max_depth = None;
+            }
+            ["quit"] => {
+                self.controller.stop_search_for_quit();
+                self.thinking = false;
+                return false;
+            }
+            ["force"] | ["result", ..] => {
+                self.abandon_search();
+                self.force = true;
+            }
+            ["go"] => {
+                self.force = false;
+                self.engine_side = self.controller.game.side;
+                self.think_if_engine_to_move();
+            }
+            ["playother"] => {
+                self.force = false;
+                self.engine_side = !self.controller.game.side;
+            }
+            ["?"] => {
+                if self.thinking {
+                    let result = self.controller.stop_search();
+                    self.play_result(result);
+                }
+            }
+            ["ping", n] => println!("pong {}", n),
+            ["post"] => self.post = true,
+            ["nopost"] => self.post = false,
+            ["usermove", notation] => self.user_move(notation),
+            ["setboard", fen @ ..] => {
+                self.abandon_search();
+                if let Err(e) = self.controller.set_board_from_fen(&fen.join(" ")) {
+                    println!("tellusererror {}", e);
+                }
+            }
+            ["undo"] => {
+                self.abandon_search();
+                self.controller.undo_move();
+            }
+            ["remove"] => {
+                self.abandon_search();
+                self.controller.undo_move();
+                self.controller.undo_move();
+            }
+            ["time", centiseconds] => {
+                self.engine_time_ms = centiseconds.parse::<u64>().ok().map(|cs| cs * 10)
+            }
+            ["otim", centiseconds] => {
+                self.opponent_time_ms = centiseconds.parse::<u64>().ok().map(|cs| cs * 10)
+            }
+            ["level", moves, base, increment] => {
+                self.moves_per_session = moves.parse::<usize>().ok().filter(|&m| m > 0);
+                self.increment_ms = increment
+                    .parse::<f64>()
+                    .map(|seconds| (seconds * 1000.0) as u64)
+                    .unwrap_or(0);
+                self.move_time_ms = None;
+
+                // the base time is in minutes, optionally with seconds (5:30)
+                let (minutes, seconds) = base.split_once(':').unwrap_or((base, "0"));
+                if let (Ok(minutes), Ok(seconds)) = (minutes.parse::<u64>(), seconds.parse::<u64>())
+                {
+                    let base_ms = (minutes * 60 + seconds) * 1000;
+                    self.engine_time_ms = Some(base_ms);
+                    self.opponent_time_ms = Some(base_ms);
+                }
+            }
+            ["st", seconds] => {
+                self.move_time_ms = seconds
+                    .parse::<f64>()
+                    .ok()
+                    .map(|seconds| (seconds * 1000.0) as u64)
+            }
+            ["sd", depth] => self.max_depth = depth.parse().ok(),
+            // commands that don't affect how the engine plays
+            ["random"]
+            | ["hard"]
+            | ["easy"]
+            | ["computer"]
+            | ["name", ..]
+            | ["rating", ..]
+            | ["ics", ..]
+            | ["draw"] => {}
+            // moves without the usermove prefix, if the GUI ignored the feature
+            [notation] if BoardMove::parse(notation).is_some() => self.user_move(notation),
+            _ => println!("Error (unknown command): {}", line.trim()),
+        }
+
+        true
+    }
+}
+
+/// Read stdin on a separate thread, so that the engine can play its move as soon as the
+/// search finishes instead of on the next line of input
+pub(crate) fn spawn_input_reader() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in io::stdin().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Speak XBoard on stdin/stdout until `quit` (or the end of the input)
+pub fn run_xboard(controller: GameController) {
+    let mut xboard = XBoard::new(controller);
+    let input = spawn_input_reader();
+
+    loop {
+        xboard.poll_search();
+
+        match input.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                if !xboard.handle(&line) {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                xboard.handle("quit");
+                break;
+            }
+        }
+    }
+}
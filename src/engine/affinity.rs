@@ -0,0 +1,155 @@
+//! Placement of the search threads on CPUs and of the transposition table in NUMA memory, for
+//! big multi-core machines. Only Linux builds with the `numa` feature do anything here; other
+//! builds keep the defaults of the OS.
+
+/// Where the transposition table's pages are allocated on a NUMA machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumaPolicy {
+    Default,    // wherever the OS puts them
+    Interleave, // round-robin over all the nodes, so every thread gets the same (average) speed
+    Local,      // on the node of the first search thread's CPU
+}
+
+impl NumaPolicy {
+    pub const ALL: [NumaPolicy; 3] = [
+        NumaPolicy::Default,
+        NumaPolicy::Interleave,
+        NumaPolicy::Local,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NumaPolicy::Default => "Default",
+            NumaPolicy::Interleave => "Interleave",
+            NumaPolicy::Local => "Local",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<NumaPolicy> {
+        NumaPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Whether this build can pin threads and place memory at all
+pub const fn is_supported() -> bool {
+    cfg!(all(feature = "numa", target_os = "linux"))
+}
+
+/// Pin the calling thread to a single CPU, the search thread with the given index getting
+/// the index-th CPU the process may run on (wrapping around). Returns whether it was pinned.
+pub fn pin_current_thread(thread_index: usize) -> bool {
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    {
+        linux::pin_current_thread(thread_index)
+    }
+
+    #[cfg(not(all(feature = "numa", target_os = "linux")))]
+    {
+        let _ = thread_index;
+        false
+    }
+}
+
+/// Allocate memory (e.g. a new transposition table) with the given policy. The pages are
+/// placed when first touched, so `allocate` should write all of them, like zeroing does.
+pub fn allocate_with_policy<T: Send>(policy: NumaPolicy, allocate: impl FnOnce() -> T + Send) -> T {
+    #[cfg(all(feature = "numa", target_os = "linux"))]
+    {
+        match policy {
+            NumaPolicy::Default => allocate(),
+            NumaPolicy::Interleave => linux::with_interleaved_memory(allocate),
+            // first touch places the pages on the node of the touching thread's CPU
+            NumaPolicy::Local => std::thread::scope(|scope| {
+                scope
+                    .spawn(|| {
+                        linux::pin_current_thread(0);
+                        allocate()
+                    })
+                    .join()
+                    .expect("Allocating thread panicked.")
+            }),
+        }
+    }
+
+    #[cfg(not(all(feature = "numa", target_os = "linux")))]
+    {
+        let _ = policy;
+        allocate()
+    }
+}
+
+#[cfg(all(feature = "numa", target_os = "linux"))]
+mod linux {
+    use std::mem;
+
+    const MPOL_DEFAULT: libc::c_int = 0;
+    const MPOL_INTERLEAVE: libc::c_int = 3;
+
+    /// CPUs the process may run on, in increasing order
+    fn allowed_cpus() -> Vec<usize> {
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+                return Vec::new();
+            }
+
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect()
+        }
+    }
+
+    pub fn pin_current_thread(thread_index: usize) -> bool {
+        let cpus = allowed_cpus();
+        if cpus.is_empty() {
+            return false;
+        }
+
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            libc::CPU_SET(cpus[thread_index % cpus.len()], &mut set);
+            libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        }
+    }
+
+    /// Mask of the online NUMA nodes, from a list like "0-3,6"
+    fn online_nodes() -> Option<u64> {
+        let list = std::fs::read_to_string("/sys/devices/system/node/online").ok()?;
+
+        let mut mask = 0u64;
+        for range in list.trim().split(',') {
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            for node in first.parse::<u32>().ok()?..=last.parse::<u32>().ok()? {
+                mask |= 1u64.checked_shl(node)?;
+            }
+        }
+
+        Some(mask)
+    }
+
+    fn set_memory_policy(mode: libc::c_int, nodes: Option<u64>) -> bool {
+        let (mask, max_node) = match &nodes {
+            // the kernel reads one bit less than it's told to
+            Some(mask) => (mask as *const u64, u64::BITS as libc::c_ulong + 1),
+            None => (std::ptr::null(), 0),
+        };
+
+        unsafe { libc::syscall(libc::SYS_set_mempolicy, mode, mask, max_node) == 0 }
+    }
+
+    /// Run `allocate` with the calling thread's new pages spread over all the nodes
+    pub fn with_interleaved_memory<T>(allocate: impl FnOnce() -> T) -> T {
+        let interleaved =
+            online_nodes().is_some_and(|nodes| set_memory_policy(MPOL_INTERLEAVE, Some(nodes)));
+
+        let result = allocate();
+
+        if interleaved {
+            set_memory_policy(MPOL_DEFAULT, None);
+        }
+
+        result
+    }
+}
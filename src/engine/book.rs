@@ -0,0 +1,212 @@
+use crate::game::board::{BoardMove, Game};
+use crate::game::pgn::PgnReader;
+use crate::game::pieces::Color;
+use fxhash::FxHashMap;
+use rand::Rng;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// First line of a book file; the version changes whenever the line format does
+const BOOK_HEADER: &str = "prokopakop-book";
+const BOOK_VERSION: u32 = 1;
+
+// Plies of each PGN game that go into a book built from it
+const PGN_BOOK_PLIES: usize = 24;
+
+// Weight of a book move seen once in a PGN, and the most any move can get to
+const PGN_MOVE_WEIGHT: u16 = 10;
+const MAX_WEIGHT: u16 = 10_000;
+
+// How much a won (lost) game raises (lowers) the weights of the winner's (loser's) book moves
+const LEARNING_STEP: u16 = 2;
+
+/// Opening book of weighted moves per position, keyed by zobrist.
+///
+/// The file starts with a `prokopakop-book <version>` line, followed by `<key> <move> <weight>`
+/// lines; unlike books built from a PGN, it can be written back after learning from games.
+pub struct OpeningBook {
+    path: Option<PathBuf>, // where learned weights are stored, None if read-only
+    entries: FxHashMap<u64, Vec<(BoardMove, u16)>>,
+}
+
+impl OpeningBook {
+    /// Open a book file, or build a (read-only) book from the first plies of a PGN's games.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        match path.extension().is_some_and(|extension| extension == "pgn") {
+            true => Self::from_pgn(path),
+            false => Self::from_book_file(path),
+        }
+    }
+
+    fn from_book_file(path: &Path) -> io::Result<Self> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let version = header
+            .strip_prefix(BOOK_HEADER)
+            .and_then(|version| version.trim().parse::<u32>().ok());
+        if version != Some(BOOK_VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a version {} book file", BOOK_VERSION),
+            ));
+        }
+
+        let mut entries: FxHashMap<u64, Vec<(BoardMove, u16)>> = FxHashMap::default();
+        for line in lines {
+            if let Some((key, board_move, weight)) = Self::parse_line(&line?) {
+                entries.entry(key).or_default().push((board_move, weight));
+            }
+        }
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            entries,
+        })
+    }
+
+    fn parse_line(line: &str) -> Option<(u64, BoardMove, u16)> {
+        let mut parts = line.split_whitespace();
+
+        let key = u64::from_str_radix(parts.next()?, 16).ok()?;
+        let board_move = BoardMove::parse(parts.next()?)?;
+        let weight = parts.next()?.parse().ok()?;
+
+        Some((key, board_move, weight))
+    }
+
+    fn from_pgn(path: &Path) -> io::Result<Self> {
+        let mut reader = PgnReader::open(&path.to_string_lossy()).map_err(io::Error::other)?;
+        let mut book = Self {
+            path: None,
+            entries: FxHashMap::default(),
+        };
+
+        while let Some(pgn_game) = reader.next_game().map_err(io::Error::other)? {
+            let Ok(mut game) = pgn_game.starting_game() else {
+                continue;
+            };
+
+            for &board_move in pgn_game.moves.iter().take(PGN_BOOK_PLIES) {
+                book.add_weight(game.zobrist_key, board_move, PGN_MOVE_WEIGHT);
+                game.make_move(board_move);
+            }
+        }
+
+        Ok(book)
+    }
+
+    fn add_weight(&mut self, key: u64, board_move: BoardMove, weight: u16) {
+        let moves = self.entries.entry(key).or_default();
+
+        match moves
+            .iter_mut()
+            .find(|(book_move, _)| *book_move == board_move)
+        {
+            Some((_, existing)) => *existing = existing.saturating_add(weight).min(MAX_WEIGHT),
+            None => moves.push((board_move, weight)),
+        }
+    }
+
+    pub fn position_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether learned weights can be stored back (a book file rather than a PGN)
+    pub fn is_writable(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn moves(&self, key: u64) -> &[(BoardMove, u16)] {
+        self.entries.get(&key).map_or(&[], |moves| moves.as_slice())
+    }
+
+    /// A random book move for the position, more likely the higher its weight
+    pub fn pick(&self, key: u64) -> Option<BoardMove> {
+        let moves = self.moves(key);
+        let total: u32 = moves.iter().map(|&(_, weight)| weight as u32).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut remaining = rand::rng().random_range(0..total);
+        moves.iter().find_map(|&(board_move, weight)| {
+            if remaining < weight as u32 {
+                Some(board_move)
+            } else {
+                remaining -= weight as u32;
+                None
+            }
+        })
+    }
+
+    /// Adjust the weights of the book moves played in a finished game (up to where it left the
+    /// book), given its final position: the winner's moves gain weight and the loser's lose it,
+    /// a draw changes nothing. Returns the number of moves adjusted.
+    pub fn learn(&mut self, game: &Game, winner: Option<Color>) -> usize {
+        let Some(winner) = winner else {
+            return 0;
+        };
+
+        let mut start = game.clone();
+        let mut played = Vec::with_capacity(game.history.len());
+        while let Some(&(board_move, ..)) = start.history.last() {
+            played.push(board_move);
+            start.unmake_move();
+        }
+
+        let mut adjusted = 0;
+        for &board_move in played.iter().rev() {
+            let side = start.side;
+            let Some((_, weight)) = self.entries.get_mut(&start.zobrist_key).and_then(|moves| {
+                moves
+                    .iter_mut()
+                    .find(|(book_move, _)| start.legal_move(*book_move) == Some(board_move))
+            }) else {
+                break;
+            };
+
+            *weight = match side == winner {
+                true => weight.saturating_add(LEARNING_STEP).min(MAX_WEIGHT),
+                false => weight.saturating_sub(LEARNING_STEP),
+            };
+
+            adjusted += 1;
+            start.make_move(board_move);
+        }
+
+        adjusted
+    }
+
+    /// Write the book (with its learned weights) back to its file, through a temporary file so
+    /// that a concurrent reader never sees it half written.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "books built from a PGN can't be written",
+            ));
+        };
+
+        let temporary = path.with_extension("tmp");
+        let mut file = BufWriter::new(File::create(&temporary)?);
+
+        writeln!(file, "{} {}", BOOK_HEADER, BOOK_VERSION)?;
+        for (key, moves) in &self.entries {
+            for (board_move, weight) in moves {
+                writeln!(file, "{:016x} {} {}", key, board_move.unparse(), weight)?;
+            }
+        }
+
+        file.into_inner()?.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+
+    /// Start a writable book at the given path from this one's moves and weights
+    pub fn save_as(mut self, path: &Path) -> io::Result<Self> {
+        self.path = Some(path.to_path_buf());
+        self.save()?;
+        Ok(self)
+    }
+}
@@ -0,0 +1,83 @@
+use crate::game::board::BoardMove;
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheEntry {
+    pub best_move: BoardMove,
+    pub evaluation: i32,
+    pub depth: u8,
+}
+
+/// Durable store of search results across sessions, keyed by zobrist.
+///
+/// The results are kept in an SQLite database, in an `analysis` table with a row per position
+/// (the key as a signed integer, the move in UCI notation); the deepest (earliest, on ties)
+/// result for a position wins.
+pub struct AnalysisCache {
+    connection: Connection,
+}
+
+impl AnalysisCache {
+    /// Open (or create) the cache database.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS analysis (
+                key INTEGER PRIMARY KEY,
+                best_move TEXT NOT NULL,
+                evaluation INTEGER NOT NULL,
+                depth INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(Self { connection })
+    }
+
+    /// The cached result for the position, if there is one (and it can be read)
+    pub fn get(&self, key: u64) -> Option<CacheEntry> {
+        let (best_move, evaluation, depth) = self
+            .connection
+            .query_row(
+                "SELECT best_move, evaluation, depth FROM analysis WHERE key = ?1",
+                params![key as i64],
+                |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .ok()??;
+
+        Some(CacheEntry {
+            best_move: BoardMove::parse(&best_move)?,
+            evaluation,
+            depth,
+        })
+    }
+
+    pub fn position_count(&self) -> usize {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM analysis", [], |row| row.get(0))
+            .unwrap_or(0)
+    }
+
+    /// Store a search result, unless a result at least as deep is already cached for the position.
+    pub fn store(&mut self, key: u64, entry: CacheEntry) -> rusqlite::Result<()> {
+        self.connection.execute(
+            "INSERT INTO analysis (key, best_move, evaluation, depth) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (key) DO UPDATE SET
+                best_move = excluded.best_move,
+                evaluation = excluded.evaluation,
+                depth = excluded.depth
+             WHERE excluded.depth > analysis.depth",
+            params![
+                key as i64,
+                entry.best_move.unparse(),
+                entry.evaluation,
+                entry.depth
+            ],
+        )?;
+
+        Ok(())
+    }
+}
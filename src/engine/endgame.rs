@@ -0,0 +1,270 @@
+use crate::engine::evaluate::{PAWN_VALUE, QUEEN_VALUE, get_piece_value};
+use crate::game::bitboard::{FRONT_SPANS, PASSED_PAWN_SPANS};
+use crate::game::board::{BoardMove, Game};
+use crate::game::pieces::{Color, Piece};
+use crate::game::square::BoardSquare;
+
+// Score of a pawn ending known to be won, on top of which advancing the pawn is rewarded
+// (well below mate scores, so that the search still prefers actual mates)
+const KNOWN_WIN_SCORE: i32 = 1000;
+const PAWN_ADVANCE_BONUS: i32 = 20;
+
+// Bonus for a passed pawn that the enemy king can't catch, when the enemy has none
+const UNSTOPPABLE_PASSER_BONUS: i32 = QUEEN_VALUE - PAWN_VALUE;
+
+// Pawnless endings with at most this many pieces besides the kings (KRK, KQK, KBNK, KRKB...)
+// are about driving the enemy king to the edge
+const MATING_ENDING_MAX_PIECES: u32 = 3;
+
+// Move ordering of quiet moves in such endings: checks first, then the attacking king
+// approaching the enemy one (per step closer) or the defending king heading to the center
+const MATING_CHECK_SCORE: i32 = 20000;
+const MATING_KING_APPROACH_SCORE: i32 = 1_000;
+const MATING_KING_CENTER_SCORE: i32 = 2_000;
+
+/// Rank of the square from the perspective of the given color (0 = own back rank).
+fn relative_rank(square: BoardSquare, color: Color) -> u8 {
+    match color {
+        Color::White => square.get_y(),
+        Color::Black => 7 - square.get_y(),
+    }
+}
+
+/// Number of king moves between the two squares.
+fn king_distance(a: BoardSquare, b: BoardSquare) -> u8 {
+    a.get_x()
+        .abs_diff(b.get_x())
+        .max(a.get_y().abs_diff(b.get_y()))
+}
+
+fn promotion_square(pawn: BoardSquare, color: Color) -> BoardSquare {
+    match color {
+        Color::White => BoardSquare::from_position(pawn.get_x(), 7),
+        Color::Black => BoardSquare::from_position(pawn.get_x(), 0),
+    }
+}
+
+fn is_passed(game: &Game, pawn: BoardSquare, color: Color) -> bool {
+    let enemy_pawns =
+        game.piece_bitboards[Piece::Pawn as usize] & game.color_bitboards[!color as usize];
+
+    (enemy_pawns & PASSED_PAWN_SPANS[color as usize][pawn.index()]).is_empty()
+}
+
+/// Moves the pawn needs to promote (counting the double push from its starting rank).
+fn promotion_distance(pawn: BoardSquare, color: Color) -> u8 {
+    let rank = relative_rank(pawn, color);
+    if rank == 1 { 5 } else { 7 - rank }
+}
+
+/// Rule of the square: a passed pawn the enemy king can't catch before it promotes.
+pub fn is_unstoppable_passer(game: &Game, pawn: BoardSquare, color: Color) -> bool {
+    if !is_passed(game, pawn, color) {
+        return false;
+    }
+
+    // our own king standing in the way blocks the pawn
+    let own_king = game.get_king_position(color);
+    if FRONT_SPANS[color as usize][pawn.index()].is_set(own_king) {
+        return false;
+    }
+
+    let defender_king = game.get_king_position(!color);
+    let mut defender_distance = king_distance(defender_king, promotion_square(pawn, color)) as i32;
+
+    // with the defender to move, the king gets one step closer before the pawn moves
+    if game.side != color {
+        defender_distance -= 1;
+    }
+
+    defender_distance > promotion_distance(pawn, color) as i32
+}
+
+/// King moves from the square to the closest of the four center squares.
+fn center_distance(square: BoardSquare) -> u8 {
+    let x = square.get_x();
+    let y = square.get_y();
+
+    (3 - x.min(7 - x)).max(3 - y.min(7 - y))
+}
+
+/// Material signature of a pawnless ending with little material left, where there's (almost)
+/// nothing to capture and the game is about driving the enemy king into a corner. Returns the
+/// side with more material, which is trying to mate, or None for any other position.
+pub fn mating_ending_attacker(game: &Game) -> Option<Color> {
+    if !game.piece_bitboards[Piece::Pawn as usize].is_empty() {
+        return None;
+    }
+
+    let kings = game.piece_bitboards[Piece::King as usize];
+    let pieces = (game.color_bitboards[Color::White as usize]
+        | game.color_bitboards[Color::Black as usize])
+        & !kings;
+    if pieces.count_ones() > MATING_ENDING_MAX_PIECES {
+        return None;
+    }
+
+    let material = |color: Color| -> i32 {
+        (game.color_bitboards[color as usize] & !kings)
+            .iter_positions()
+            .filter_map(|square| game.pieces.get(square))
+            .map(|(piece, _)| get_piece_value(piece))
+            .sum()
+    };
+
+    let (white, black) = (material(Color::White), material(Color::Black));
+    if white > black {
+        Some(Color::White)
+    } else if black > white {
+        Some(Color::Black)
+    } else {
+        None
+    }
+}
+
+/// Ordering score of a quiet move in a mating ending (see `mating_ending_attacker`), higher
+/// for moves more likely to make progress: MVV-LVA has nothing to sort there
+pub fn mating_ending_move_score(game: &Game, board_move: BoardMove, attacker: Color) -> i32 {
+    let Some((piece, _)) = game.pieces.get(board_move.get_from()) else {
+        return 0;
+    };
+
+    let mut score = 0;
+    let to = board_move.get_to();
+
+    // direct checks only: making the move to find discovered ones too would cost more than
+    // the better ordering saves
+    let enemy_king = game.get_king_position(!game.side);
+    if game
+        .get_piece_attacks(piece, game.side, to)
+        .is_set(enemy_king)
+    {
+        score += MATING_CHECK_SCORE;
+    }
+
+    if piece == Piece::King {
+        score += if game.side == attacker {
+            (7 - king_distance(to, enemy_king) as i32) * MATING_KING_APPROACH_SCORE
+        } else {
+            (3 - center_distance(to) as i32) * MATING_KING_CENTER_SCORE
+        };
+    }
+
+    score
+}
+
+/// Key squares of a (non-rook) pawn: with the attacking king on one of them, the pawn promotes
+/// regardless of who is to move.
+fn is_key_square(square: BoardSquare, pawn: BoardSquare, color: Color) -> bool {
+    let rank = relative_rank(pawn, color);
+    let square_rank = relative_rank(square, color);
+
+    if square.get_x().abs_diff(pawn.get_x()) > 1 || square == pawn {
+        return false;
+    }
+
+    match rank {
+        1..=3 => square_rank == rank + 2,
+        4 | 5 => square_rank == rank + 1 || square_rank == rank + 2,
+        _ => square_rank >= 6,
+    }
+}
+
+/// King and pawn versus king, from the perspective of the side with the pawn.
+/// Returns None where the key square heuristics don't give a clear verdict.
+fn evaluate_kpk(game: &Game, pawn: BoardSquare, color: Color) -> Option<i32> {
+    let attacker_king = game.get_king_position(color);
+    let defender_king = game.get_king_position(!color);
+
+    let win = KNOWN_WIN_SCORE + relative_rank(pawn, color) as i32 * PAWN_ADVANCE_BONUS;
+
+    // an undefended pawn next to the defending king is simply taken
+    if game.side != color
+        && king_distance(defender_king, pawn) == 1
+        && king_distance(attacker_king, pawn) > 1
+    {
+        return Some(0);
+    }
+
+    if is_unstoppable_passer(game, pawn, color) {
+        return Some(win);
+    }
+
+    let defender_in_front = FRONT_SPANS[color as usize][pawn.index()].is_set(defender_king);
+
+    // rook pawns are drawn once the defending king gets in front of the pawn, next to the file
+    if pawn.get_x() == 0 || pawn.get_x() == 7 {
+        let near_file = PASSED_PAWN_SPANS[color as usize][pawn.index()].is_set(defender_king);
+
+        return near_file.then_some(0);
+    }
+
+    if is_key_square(attacker_king, pawn, color) {
+        return Some(win);
+    }
+
+    // with the defending king in front and the attacking one behind, the defender holds the
+    // opposition
+    if defender_in_front && relative_rank(attacker_king, color) <= relative_rank(pawn, color) {
+        return Some(0);
+    }
+
+    None
+}
+
+fn fastest_unstoppable_passer(game: &Game, color: Color) -> Option<u8> {
+    let pawns = game.piece_bitboards[Piece::Pawn as usize] & game.color_bitboards[color as usize];
+
+    pawns
+        .iter_positions()
+        .filter(|&pawn| is_unstoppable_passer(game, pawn, color))
+        .map(|pawn| promotion_distance(pawn, color))
+        .min()
+}
+
+/// Specialized evaluation of positions with only kings and pawns, from the perspective of the
+/// side to move. Where none of the rules decide the position, `generic` provides the score.
+/// Returns None for positions that aren't pawn endings.
+pub fn evaluate_pawn_endgame_stm(game: &Game, generic: impl FnOnce() -> i32) -> Option<i32> {
+    let pieces =
+        game.color_bitboards[Color::White as usize] | game.color_bitboards[Color::Black as usize];
+    let pawns = game.piece_bitboards[Piece::Pawn as usize];
+    let kings = game.piece_bitboards[Piece::King as usize];
+
+    if pieces != pawns | kings {
+        return None;
+    }
+
+    let stm = game.side;
+
+    if pawns.count_ones() == 1 {
+        let pawn = pawns.next_index();
+        let color = if game.color_bitboards[Color::White as usize].is_set(pawn) {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        if let Some(score) = evaluate_kpk(game, pawn, color) {
+            return Some(if color == stm { score } else { -score });
+        }
+    }
+
+    let score = generic();
+
+    // a passer running away from the enemy king decides the game when the enemy has none
+    let score = match (
+        fastest_unstoppable_passer(game, stm),
+        fastest_unstoppable_passer(game, !stm),
+    ) {
+        (Some(distance), None) => {
+            score + UNSTOPPABLE_PASSER_BONUS - distance as i32 * PAWN_ADVANCE_BONUS
+        }
+        (None, Some(distance)) => {
+            score - UNSTOPPABLE_PASSER_BONUS + distance as i32 * PAWN_ADVANCE_BONUS
+        }
+        _ => score,
+    };
+
+    Some(score)
+}
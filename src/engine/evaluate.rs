@@ -1,27 +1,64 @@
+use crate::game::bitboard::{Bitboard, PIECE_MOVE_BITBOARDS, RAY_BETWEEN, east, forward, west};
 use crate::game::board::Game;
-use crate::game::pieces::Piece;
+use crate::game::pieces::{Color, Piece};
+use strum::IntoEnumIterator;
 
-pub const CHECKMATE_SCORE: f32 = 32767.0;
+pub const CHECKMATE_SCORE: i32 = 32767;
+
+// Mates are scored CHECKMATE_SCORE less the plies to them (from the root), so every score
+// beyond MATE_BOUND is a mate and none of the evaluations get there
+pub const MAX_MATE_PLIES: i32 = 1000;
+pub const MATE_BOUND: i32 = CHECKMATE_SCORE - MAX_MATE_PLIES;
+
+// Bound of the search window, beyond any score a position can have
+pub const INFINITE_SCORE: i32 = CHECKMATE_SCORE + 1;
+
+/// Score of mating at the given ply
+pub const fn mate_in(ply: usize) -> i32 {
+    CHECKMATE_SCORE - ply as i32
+}
+
+/// Score of getting mated at the given ply
+pub const fn mated_in(ply: usize) -> i32 {
+    -CHECKMATE_SCORE + ply as i32
+}
+
+pub const fn is_mate_score(score: i32) -> bool {
+    score.abs() > MATE_BOUND
+}
+
+/// Moves (not plies) to the mate of a mate score, negative when getting mated, like the UCI
+/// `score mate`
+pub const fn moves_to_mate(score: i32) -> i32 {
+    let moves = (CHECKMATE_SCORE - score.abs() + 1) / 2;
+    if score > 0 { moves } else { -moves }
+}
 
 // Base piece values
-pub const PAWN_VALUE: f32 = 100.0;
-pub const KNIGHT_VALUE: f32 = 320.0;
-pub const BISHOP_VALUE: f32 = 330.0;
-pub const ROOK_VALUE: f32 = 500.0;
-pub const QUEEN_VALUE: f32 = 900.0;
+pub const PAWN_VALUE: i32 = 100;
+pub const KNIGHT_VALUE: i32 = 320;
+pub const BISHOP_VALUE: i32 = 330;
+pub const ROOK_VALUE: i32 = 500;
+pub const QUEEN_VALUE: i32 = 900;
+
+// Bonus for having the move, since the side to move can usually improve its position
+pub const TEMPO_BONUS: i32 = 15;
+
+// Resolution of the game phase, so that it can be interpolated with in whole centipawns
+pub const GAME_PHASE_MAX: i32 = 256;
 
-pub fn get_piece_value(piece: Piece) -> f32 {
+pub fn get_piece_value(piece: Piece) -> i32 {
     match piece {
         Piece::Pawn => PAWN_VALUE,
         Piece::Knight => KNIGHT_VALUE,
         Piece::Bishop => BISHOP_VALUE,
         Piece::Rook => ROOK_VALUE,
         Piece::Queen => QUEEN_VALUE,
-        Piece::King => 0.0,
+        Piece::King => 0,
     }
 }
 
-pub fn get_see_piece_value(piece: Piece) -> f32 {
+pub fn get_see_piece_value(piece: Piece) -> i32 {
     match piece {
         Piece::Pawn => PAWN_VALUE,
         Piece::Knight => KNIGHT_VALUE,
@@ -32,18 +69,239 @@ pub fn get_see_piece_value(piece: Piece) -> f32 {
     }
 }
 
-pub fn calculate_game_phase(game: &Game) -> f32 {
-    const STARTING_MATERIAL: f32 =
-        2.0 * QUEEN_VALUE + 4.0 * ROOK_VALUE + 4.0 * BISHOP_VALUE + 4.0 * KNIGHT_VALUE;
+/// Game phase in 0..=GAME_PHASE_MAX, from the opening (0) to a bare endgame (GAME_PHASE_MAX).
+pub fn calculate_game_phase(game: &Game) -> i32 {
+    const STARTING_MATERIAL: i32 =
+        2 * QUEEN_VALUE + 4 * ROOK_VALUE + 4 * BISHOP_VALUE + 4 * KNIGHT_VALUE;
+
+    let material = game.piece_bitboards[Piece::Pawn as usize].count_ones() as i32 * PAWN_VALUE
+        + game.piece_bitboards[Piece::Knight as usize].count_ones() as i32 * KNIGHT_VALUE
+        + game.piece_bitboards[Piece::Bishop as usize].count_ones() as i32 * BISHOP_VALUE
+        + game.piece_bitboards[Piece::Rook as usize].count_ones() as i32 * ROOK_VALUE
+        + game.piece_bitboards[Piece::Queen as usize].count_ones() as i32 * QUEEN_VALUE;
+
+    let phase = GAME_PHASE_MAX - material * GAME_PHASE_MAX / STARTING_MATERIAL;
+    phase.clamp(0, GAME_PHASE_MAX)
+}
+
+// Attack units contributed by a piece attacking a single king zone square,
+// indexed by the Piece discriminant (Rook, Bishop, Queen, Knight, Pawn, King)
+const KING_ATTACK_WEIGHTS: [i32; 6] = [3, 2, 5, 2, 0, 0];
+
+// Bonus per square of proximity (7 - distance) of an enemy piece to the king, same indexing
+const KING_TROPISM_WEIGHTS: [i32; 6] = [2, 1, 4, 3, 0, 0];
+
+// Non-linear penalty indexed by accumulated attack units (centipawns)
+// https://www.chessprogramming.org/King_Safety#Attack_Units
+const KING_SAFETY_TABLE: [i32; 100] = [
+    0, 0, 1, 2, 3, 5, 7, 9, 12, 15, 18, 22, 26, 30, 35, 39, 44, 50, 56, 62, 68, 75, 82, 85, 89, 97,
+    105, 113, 122, 131, 140, 150, 169, 180, 191, 202, 213, 225, 237, 248, 260, 272, 283, 295, 307,
+    319, 330, 342, 354, 366, 377, 389, 401, 412, 424, 436, 448, 459, 471, 483, 494, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+];
+
+/// The squares around the king (including its own) plus the pawn shield squares in front of them.
+pub fn get_king_zone(game: &Game, color: Color) -> Bitboard {
+    let king_square = game.get_king_position(color);
+    let king_ring =
+        PIECE_MOVE_BITBOARDS[Piece::King as usize][king_square.index()] | king_square.to_mask();
+
+    let pawn_shield = forward(king_ring, color);
+
+    king_ring | pawn_shield
+}
+
+/// King safety of the given color via the attack units model: enemy pieces hitting the king zone
+/// accumulate weighted units that index a non-linear table, plus a tropism term for enemy pieces
+/// close to the king. Returns a (non-positive) score from the perspective of the given color.
+pub fn evaluate_king_safety(game: &Game, color: Color) -> i32 {
+    let king_square = game.get_king_position(color);
+    let king_zone = get_king_zone(game, color);
+    let enemy = !color;
+
+    let mut attackers = 0;
+    let mut attack_units = 0;
+    let mut tropism = 0;
+
+    for piece in Piece::iter().filter(|p| *p != Piece::Pawn && *p != Piece::King) {
+        let bitboard = game.piece_bitboards[piece as usize] & game.color_bitboards[enemy as usize];
+
+        for square in bitboard.iter_positions() {
+            let zone_hits = (game.get_piece_attacks(piece, enemy, square) & king_zone).count_ones();
+
+            if zone_hits > 0 {
+                attackers += 1;
+                attack_units += KING_ATTACK_WEIGHTS[piece as usize] * zone_hits as i32;
+            }
+
+            let distance = square
+                .get_x()
+                .abs_diff(king_square.get_x())
+                .max(square.get_y().abs_diff(king_square.get_y()));
+
+            tropism += KING_TROPISM_WEIGHTS[piece as usize] * (7 - distance) as i32;
+        }
+    }
+
+    // Zone squares the enemy hits that no piece but our king defends are the weakest
+    let attack_map = game.get_attack_map();
+    let defended = Piece::iter()
+        .filter(|p| *p != Piece::King)
+        .fold(Bitboard::EMPTY, |acc, piece| {
+            acc | attack_map.get(piece, color)
+        });
+    attack_units +=
+        (king_zone & attack_map.by_color[enemy as usize] & !defended).count_ones() as i32;
+
+    // A lone attacker is rarely dangerous, so only count coordinated attacks
+    let attack_penalty = if attackers >= 2 {
+        KING_SAFETY_TABLE[(attack_units as usize).min(KING_SAFETY_TABLE.len() - 1)]
+    } else {
+        0
+    };
+
+    // King safety matters less as material comes off the board
+    -(attack_penalty + tropism) * (GAME_PHASE_MAX - calculate_game_phase(game)) / GAME_PHASE_MAX
+}
+
+// Centipawns per square of the mobility area a piece reaches, same indexing as above
+const MOBILITY_WEIGHTS: [i32; 6] = [2, 4, 1, 4, 0, 0];
+
+// Typical number of reachable mobility area squares, so that average mobility scores zero
+const MOBILITY_BASELINES: [i32; 6] = [7, 6, 13, 4, 0, 0];
+
+/// Squares where mobility is worth counting for the given color: everything except squares
+/// controlled by enemy pawns, squares of our own king/queen and our own blocked pawns.
+pub fn get_mobility_area(game: &Game, color: Color) -> Bitboard {
+    let own = game.color_bitboards[color as usize];
+    let occupied =
+        game.color_bitboards[Color::White as usize] | game.color_bitboards[Color::Black as usize];
+
+    let own_pawns = game.piece_bitboards[Piece::Pawn as usize] & own;
+    let blocked_pawns = own_pawns & forward(occupied, !color);
+
+    let enemy_pawns =
+        game.piece_bitboards[Piece::Pawn as usize] & game.color_bitboards[!color as usize];
+    let enemy_pawn_attacks = {
+        let pushed = forward(enemy_pawns, !color);
+        east(pushed) | west(pushed)
+    };
+
+    let king_and_queen = (game.piece_bitboards[Piece::King as usize]
+        | game.piece_bitboards[Piece::Queen as usize])
+        & own;
+
+    !(enemy_pawn_attacks | king_and_queen | blocked_pawns)
+}
+
+/// Mobility of the given color's minor and major pieces, counting only squares
+/// in the mobility area. Returns a score from the perspective of the given color.
+pub fn evaluate_mobility(game: &Game, color: Color) -> i32 {
+    let mobility_area = get_mobility_area(game, color);
+    let mut score = 0;
+
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        let bitboard = game.piece_bitboards[piece as usize] & game.color_bitboards[color as usize];
+
+        for square in bitboard.iter_positions() {
+            let reachable =
+                (game.get_piece_attacks(piece, color, square) & mobility_area).count_ones();
+
+            score += MOBILITY_WEIGHTS[piece as usize]
+                * (reachable as i32 - MOBILITY_BASELINES[piece as usize]);
+        }
+    }
+
+    score
+}
+
+// Penalty for a piece pinned to its own king, same indexing as above
+const PINNED_TO_KING_PENALTIES: [i32; 6] = [30, 20, 50, 20, 10, 0];
+
+// Penalty for a piece pinned to its own queen; the pin is only relative, so it's smaller
+const PINNED_TO_QUEEN_PENALTIES: [i32; 6] = [15, 10, 0, 10, 5, 0];
+
+// Penalty for a king or queen attacked by a slider with another (non-pawn) piece behind it
+const SKEWER_PENALTY: i32 = 25;
+
+/// Pins and skewers against the given color's king and queen, found through the x-ray attacks
+/// of enemy sliders. Returns a (non-positive) score from the perspective of the given color.
+pub fn evaluate_pins(game: &Game, color: Color) -> i32 {
+    let own = game.color_bitboards[color as usize];
+    let occupied = own | game.color_bitboards[!color as usize];
+    let enemy = !color;
 
-    let material = game.piece_bitboards[Piece::Pawn as usize].count_ones() as f32 * PAWN_VALUE
-        + game.piece_bitboards[Piece::Knight as usize].count_ones() as f32 * KNIGHT_VALUE
-        + game.piece_bitboards[Piece::Bishop as usize].count_ones() as f32 * BISHOP_VALUE
-        + game.piece_bitboards[Piece::Rook as usize].count_ones() as f32 * ROOK_VALUE
-        + game.piece_bitboards[Piece::Queen as usize].count_ones() as f32 * QUEEN_VALUE;
+    let king_and_queens = (game.piece_bitboards[Piece::King as usize]
+        | game.piece_bitboards[Piece::Queen as usize])
+        & own;
 
-    let material_ratio = material / STARTING_MATERIAL;
+    let mut penalty = 0;
+
+    for piece in [Piece::Rook, Piece::Bishop, Piece::Queen] {
+        let sliders = game.piece_bitboards[piece as usize] & game.color_bitboards[enemy as usize];
+
+        for square in sliders.iter_positions() {
+            // each of our pieces hit through one of our pieces forms a line with it
+            for behind in (game.get_xray_attacks(piece, square, own) & own).iter_positions() {
+                let front = (RAY_BETWEEN[square.index()][behind.index()] & occupied).next_index();
+
+                let (front_piece, _) = game.pieces.get(front).unwrap();
+                let (behind_piece, _) = game.pieces.get(behind).unwrap();
+
+                if king_and_queens.is_set(behind) {
+                    // a queen pinning something to our queen can simply be taken
+                    if behind_piece == Piece::Queen && piece == Piece::Queen {
+                        continue;
+                    }
+
+                    penalty += match behind_piece {
+                        Piece::King => PINNED_TO_KING_PENALTIES[front_piece as usize],
+                        _ => PINNED_TO_QUEEN_PENALTIES[front_piece as usize],
+                    };
+                } else if king_and_queens.is_set(front)
+                    && behind_piece != Piece::Pawn
+                    && !(front_piece == Piece::Queen && piece == Piece::Queen)
+                {
+                    penalty += SKEWER_PENALTY;
+                }
+            }
+        }
+    }
+
+    -penalty
+}
+
+/// Material balance of the given color (kings excluded).
+pub fn evaluate_material(game: &Game, color: Color) -> i32 {
+    Piece::iter()
+        .map(|piece| {
+            let count = (game.piece_bitboards[piece as usize]
+                & game.color_bitboards[color as usize])
+                .count_ones();
+
+            count as i32 * get_piece_value(piece)
+        })
+        .sum()
+}
+
+/// Hand-crafted evaluation of the position, from white's perspective.
+pub fn evaluate_classical(game: &Game) -> i32 {
+    evaluate_classical_stm(game) * game.side
+}
+
+/// Hand-crafted evaluation of the position, from the perspective of the side to move.
+pub fn evaluate_classical_stm(game: &Game) -> i32 {
+    let mut score = 0;
+
+    for color in [Color::White, Color::Black] {
+        let color_score = evaluate_material(game, color)
+            + evaluate_king_safety(game, color)
+            + evaluate_mobility(game, color)
+            + evaluate_pins(game, color);
+
+        score += color_score * color;
+    }
 
-    let phase = 1.0 - material_ratio;
-    phase.clamp(0.0, 1.0)
+    score * game.side + TEMPO_BONUS
 }
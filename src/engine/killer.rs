@@ -1,4 +1,4 @@
-use crate::game::board::{BoardMove, BoardMoveExt};
+use crate::game::board::BoardMove;
 
 #[derive(Debug, Clone)]
 pub struct KillerMoves {
@@ -1,5 +1,11 @@
+pub mod affinity;
+pub mod book;
+pub mod cache;
+pub mod endgame;
 pub mod evaluate;
 pub mod killer;
 pub mod nnue;
+pub mod repertoire;
 pub mod search;
 pub mod table;
+pub mod wdl;
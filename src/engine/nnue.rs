@@ -1,3 +1,4 @@
+use crate::error::ProkopakopError;
 use std::fs;
 use std::path::Path;
 use std::sync::OnceLock;
@@ -8,19 +9,13 @@ const SCALE: i32 = 400;
 const QA: i16 = 255;
 const QB: i16 = 64;
 
+// Largest output weight magnitude for which the clipped input times the weight fits in an i16
+const MAX_OUTPUT_WEIGHT: u16 = (i16::MAX / QA) as u16;
+
 static DEFAULT_NNUE: Network =
     unsafe { std::mem::transmute(*include_bytes!("../../data/nnue.bin")) };
 static LOADED_NNUE: OnceLock<Box<Network>> = OnceLock::new();
 
-#[inline]
-/// Square Clipped ReLU - Activation Function.
-/// Note that this takes the i16s in the accumulator to i32s.
-/// Range is 0.0 .. 1.0 (in other words, 0 to QA*QA quantized).
-fn screlu(x: i16) -> i32 {
-    let y = i32::from(x).clamp(0, i32::from(QA));
-    y * y
-}
-
 #[inline]
 /// Calculate output bucket based on material count (all pieces on board).
 /// Uses the same formula as Bullet's MaterialCount<8>:
@@ -54,6 +49,17 @@ impl Network {
     /// calculated hidden layer (done efficiently during makemoves).
     /// piece_count is the total number of pieces on the board for bucket selection.
     pub fn evaluate(&self, us: &Accumulator, them: &Accumulator, piece_count: u32) -> i32 {
+        self.evaluate_batch([(us, them)], piece_count)[0]
+    }
+
+    /// Calculates the outputs for several positions with the same piece count (such as the
+    /// positions after sibling captures) at once: each chunk of output weights is loaded
+    /// once for the whole batch, instead of once per position.
+    pub fn evaluate_batch<const N: usize>(
+        &self,
+        positions: [(&Accumulator, &Accumulator); N],
+        piece_count: u32,
+    ) -> [i32; N] {
         let bucket = get_output_bucket(piece_count);
 
         // With transposed weights, each bucket's weights are contiguous
@@ -61,33 +67,94 @@ impl Network {
         let bucket_weights =
             &self.output_weights[bucket_weights_start..bucket_weights_start + 2 * HIDDEN_SIZE];
 
-        // Initialise output.
-        let mut output = 0;
-
         // Side-To-Move Accumulator -> Output.
-        for (&input, &weight) in us.vals.iter().zip(&bucket_weights[..HIDDEN_SIZE]) {
-            output += screlu(input) * i32::from(weight);
-        }
+        let us = output_sums(
+            &bucket_weights[..HIDDEN_SIZE],
+            positions.map(|(us, _)| &us.vals),
+        );
 
         // Not-Side-To-Move Accumulator -> Output.
-        for (&input, &weight) in them.vals.iter().zip(&bucket_weights[HIDDEN_SIZE..]) {
-            output += screlu(input) * i32::from(weight);
-        }
+        let them = output_sums(
+            &bucket_weights[HIDDEN_SIZE..],
+            positions.map(|(_, them)| &them.vals),
+        );
+
+        std::array::from_fn(|i| {
+            let mut output = us[i].wrapping_add(them[i]);
+
+            // Reduce quantization from QA * QA * QB to QA * QB.
+            output /= i32::from(QA);
+
+            // Add bias for this bucket.
+            output += i32::from(self.output_bias[bucket]);
+
+            // Apply eval scale.
+            output *= SCALE;
+
+            // Remove quantisation altogether.
+            output /= i32::from(QA) * i32::from(QB);
+
+            output
+        })
+    }
 
-        // Reduce quantization from QA * QA * QB to QA * QB.
-        output /= i32::from(QA);
+    /// Whether all output weights fit the range the output layer relies on (see `output_sums`).
+    pub(crate) fn has_valid_output_weights(&self) -> bool {
+        self.output_weights
+            .iter()
+            .all(|weight| weight.unsigned_abs() <= MAX_OUTPUT_WEIGHT)
+    }
+}
 
-        // Add bias for this bucket.
-        output += i32::from(self.output_bias[bucket]);
+/// Sums of screlu(input) * weight for each of the inputs, using AVX2 when available.
+fn output_sums<const N: usize>(weights: &[i16], inputs: [&[i16; HIDDEN_SIZE]; N]) -> [i32; N] {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: the CPU supports AVX2, which was just checked
+        return unsafe { output_sums_avx2(weights, inputs) };
+    }
 
-        // Apply eval scale.
-        output *= SCALE;
+    output_sums_generic(weights, inputs)
+}
 
-        // Remove quantisation altogether.
-        output /= i32::from(QA) * i32::from(QB);
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn output_sums_avx2<const N: usize>(
+    weights: &[i16],
+    inputs: [&[i16; HIDDEN_SIZE]; N],
+) -> [i32; N] {
+    // the same code, just compiled (and auto-vectorized) for AVX2
+    output_sums_generic(weights, inputs)
+}
 
-        output
+/// The clipped input times the weight fits in an i16 (255 * 127 < 2^15), so it's computed in
+/// i16 and only multiplied by the clipped input again in i32; this form vectorizes well
+/// (16-bit multiplies followed by multiply-adds into 32-bit lanes).
+#[inline(always)]
+fn output_sums_generic<const N: usize>(
+    weights: &[i16],
+    inputs: [&[i16; HIDDEN_SIZE]; N],
+) -> [i32; N] {
+    const CHUNK: usize = 64;
+
+    let mut sums = [0i32; N];
+
+    for (chunk, chunk_weights) in weights.chunks_exact(CHUNK).enumerate() {
+        for (sum, input) in sums.iter_mut().zip(&inputs) {
+            let chunk_inputs = &input[chunk * CHUNK..(chunk + 1) * CHUNK];
+
+            *sum = chunk_inputs
+                .iter()
+                .zip(chunk_weights)
+                .map(|(&x, &w)| {
+                    let clipped = x.clamp(0, QA);
+                    i32::from(clipped.wrapping_mul(w)) * i32::from(clipped)
+                })
+                .fold(*sum, i32::wrapping_add);
+        }
     }
+
+    sums
 }
 
 /// A column of the feature-weights matrix.
@@ -128,41 +195,44 @@ impl Accumulator {
     }
 }
 
-/// Load a NNUE network from a file path.
-/// Panics if the path is invalid or the network fails to load.
-pub fn load_nnue_from_file(path: &Path) {
-    // Fail if a network is already loaded
+/// Load a NNUE network from a file path (only once, since the evaluation holds on to it).
+pub fn load_nnue_from_file(path: &Path) -> Result<(), ProkopakopError> {
     if LOADED_NNUE.get().is_some() {
-        panic!("NNUE network already loaded, please restart the engine.");
+        return Err(ProkopakopError::InvalidNetwork(
+            "a network is already loaded, restart the engine to load another".to_string(),
+        ));
     }
 
-    match fs::read(path) {
-        Ok(data) => {
-            if data.len() != std::mem::size_of::<Network>() {
-                panic!(
-                    "NNUE file size mismatch: expected {}, got {}",
-                    std::mem::size_of::<Network>(),
-                    data.len()
-                );
-            }
-
-            // Create a boxed Network from the binary data
-            let mut network = Box::new(unsafe { std::mem::zeroed::<Network>() });
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    data.as_ptr() as *const Network,
-                    &mut *network as *mut Network,
-                    1,
-                );
-            }
-
-            let _ = LOADED_NNUE.get_or_init(|| network);
-            println!("info string NNUE loaded successfully!");
-        }
-        Err(e) => {
-            panic!("Failed to load NNUE file {}: {}", path.display(), e);
-        }
+    let data = fs::read(path)?;
+    if data.len() != std::mem::size_of::<Network>() {
+        return Err(ProkopakopError::InvalidNetwork(format!(
+            "{} is {} bytes, expected {}",
+            path.display(),
+            data.len(),
+            std::mem::size_of::<Network>()
+        )));
+    }
+
+    // Create a boxed Network from the binary data (on the heap right away, it's too big for
+    // the stack)
+    let mut network = unsafe { Box::<Network>::new_zeroed().assume_init() };
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            data.as_ptr() as *const Network,
+            &mut *network as *mut Network,
+            1,
+        );
     }
+
+    if !network.has_valid_output_weights() {
+        return Err(ProkopakopError::InvalidNetwork(format!(
+            "output weights must be within +-{} (clipped during training)",
+            MAX_OUTPUT_WEIGHT
+        )));
+    }
+
+    let _ = LOADED_NNUE.get_or_init(|| network);
+    Ok(())
 }
 
 /// Get a reference to the active NNUE network.
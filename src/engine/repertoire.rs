@@ -0,0 +1,96 @@
+use crate::error::ProkopakopError;
+use crate::game::board::{BoardMove, Game};
+use crate::game::epd::parse_epd_opcodes;
+use crate::game::pgn::PgnReader;
+use fxhash::FxHashMap;
+use std::fs;
+use std::path::Path;
+
+/// Opening repertoire to spar against: while the position is in it, only its moves are searched.
+///
+/// Read from a PGN, whose every game puts the positions along it in the repertoire with the
+/// move played from them (variations are skipped, so each line has to be a game of its own),
+/// or from an EPD, whose records give the repertoire moves of their positions as `bm` operands.
+pub struct Repertoire {
+    entries: FxHashMap<u64, Vec<BoardMove>>,
+}
+
+impl Repertoire {
+    pub fn open(path: &Path) -> Result<Self, ProkopakopError> {
+        match path.extension().is_some_and(|extension| extension == "pgn") {
+            true => Self::from_pgn(path),
+            false => Self::from_epd(&fs::read_to_string(path)?),
+        }
+    }
+
+    fn from_pgn(path: &Path) -> Result<Self, ProkopakopError> {
+        let mut reader = PgnReader::open(&path.to_string_lossy())?;
+        let mut repertoire = Self {
+            entries: FxHashMap::default(),
+        };
+
+        while let Some(pgn_game) = reader.next_game()? {
+            let mut game = pgn_game.starting_game()?;
+
+            for &board_move in &pgn_game.moves {
+                repertoire.add(game.zobrist_key, board_move);
+                game.make_move(board_move);
+            }
+        }
+
+        Ok(repertoire)
+    }
+
+    /// Read the records of an EPD, skipping empty lines and `#` comments; every record needs
+    /// a `bm` operation with the repertoire moves in SAN
+    pub fn from_epd(epd: &str) -> Result<Self, ProkopakopError> {
+        let mut repertoire = Self {
+            entries: FxHashMap::default(),
+        };
+
+        for line in epd.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let game = Game::from_fen(line)?;
+            game.validate()?;
+
+            let operations = line
+                .split_whitespace()
+                .skip(4)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let best_moves = parse_epd_opcodes(&operations)
+                .into_iter()
+                .find(|(opcode, _)| opcode == "bm")
+                .map(|(_, operands)| operands)
+                .ok_or_else(|| {
+                    ProkopakopError::InvalidFen(format!("no bm operation in {}", line))
+                })?;
+
+            for san in best_moves.split_whitespace() {
+                let board_move = game.san_to_move(san)?;
+                repertoire.add(game.zobrist_key, board_move);
+            }
+        }
+
+        Ok(repertoire)
+    }
+
+    fn add(&mut self, key: u64, board_move: BoardMove) {
+        let moves = self.entries.entry(key).or_default();
+        if !moves.contains(&board_move) {
+            moves.push(board_move);
+        }
+    }
+
+    pub fn position_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Repertoire moves of a position, none if it's not in the repertoire
+    pub fn moves(&self, key: u64) -> &[BoardMove] {
+        self.entries.get(&key).map_or(&[], |moves| moves.as_slice())
+    }
+}
@@ -1,7 +1,11 @@
 use fxhash::FxHashMap;
 
-use crate::game::board::{BoardMove, BoardMoveExt};
-use crate::game::pieces::Color;
+use crate::game::board::BoardMove;
+use crate::game::pieces::{Color, Piece};
+
+/// A move together with the piece making it, which is what the continuation history and
+/// countermoves are keyed by (the from square alone doesn't say much about the previous move)
+pub type PieceMove = (Piece, BoardMove);
 
 /// Combined history tracking for move metrics and position repetitions
 #[derive(Debug, Clone)]
@@ -10,6 +14,13 @@ pub struct History {
     move_scores: [[[i32; 64]; 64]; 2],
     max_score: i32,
 
+    // Continuation history indexed by [color][previous piece * 64 + previous to][piece][to],
+    // scoring a move by how well it did as a reply to the opponent's previous move
+    continuation_scores: Vec<[[i32; 64]; 6]>,
+
+    // The move that last refuted the previous move, indexed by [color][previous piece][previous to]
+    counter_moves: [[[BoardMove; 64]; 6]; 2],
+
     // Position repetition tracking
     positions: FxHashMap<u64, u32>,
     position_history: Vec<u64>, // Keep track of order for undo
@@ -20,6 +31,8 @@ impl History {
         Self {
             move_scores: [[[0; 64]; 64]; 2],
             max_score: 8192, // Threshold for scaling
+            continuation_scores: vec![[[0; 64]; 6]; 2 * 6 * 64],
+            counter_moves: [[[BoardMove::empty(); 64]; 6]; 2],
             positions: FxHashMap::default(),
             position_history: Vec::with_capacity(256),
         }
@@ -27,8 +40,8 @@ impl History {
 
     // Move history methods
     pub fn add_history(&mut self, board_move: BoardMove, color: Color, depth: usize) {
-        let from = board_move.get_from() as usize;
-        let to = board_move.get_to() as usize;
+        let from = board_move.get_from().index();
+        let to = board_move.get_to().index();
         let color_idx = color as usize;
 
         // Bonus is proportional to depth squared (more weight for deeper cutoffs)
@@ -43,8 +56,8 @@ impl History {
     }
 
     pub fn add_history_penalty(&mut self, board_move: BoardMove, color: Color, depth: usize) {
-        let from = board_move.get_from() as usize;
-        let to = board_move.get_to() as usize;
+        let from = board_move.get_from().index();
+        let to = board_move.get_to().index();
         let color_idx = color as usize;
 
         // Smaller penalty to not over-penalize moves
@@ -55,12 +68,75 @@ impl History {
     }
 
     pub fn get_history_score(&self, board_move: &BoardMove, color: Color) -> i32 {
-        let from = board_move.get_from() as usize;
-        let to = board_move.get_to() as usize;
+        let from = board_move.get_from().index();
+        let to = board_move.get_to().index();
         let color_idx = color as usize;
         self.move_scores[color_idx][from][to]
     }
 
+    fn continuation_index(color: Color, previous: PieceMove) -> usize {
+        let (piece, board_move) = previous;
+        (color as usize * 6 + piece as usize) * 64 + board_move.get_to().index()
+    }
+
+    fn update_continuation(
+        &mut self,
+        previous: PieceMove,
+        current: PieceMove,
+        color: Color,
+        delta: i32,
+    ) {
+        let index = Self::continuation_index(color, previous);
+        let (piece, board_move) = current;
+        let score =
+            &mut self.continuation_scores[index][piece as usize][board_move.get_to().index()];
+
+        *score = (*score + delta).clamp(-self.max_score, self.max_score);
+    }
+
+    /// Reward a move that caused a cutoff in reply to the previous move
+    pub fn add_continuation_history(
+        &mut self,
+        previous: PieceMove,
+        current: PieceMove,
+        color: Color,
+        depth: usize,
+    ) {
+        self.update_continuation(previous, current, color, (depth * depth) as i32);
+    }
+
+    pub fn add_continuation_penalty(
+        &mut self,
+        previous: PieceMove,
+        current: PieceMove,
+        color: Color,
+        depth: usize,
+    ) {
+        self.update_continuation(previous, current, color, -(((depth * depth) / 2) as i32));
+    }
+
+    pub fn get_continuation_score(
+        &self,
+        previous: PieceMove,
+        current: PieceMove,
+        color: Color,
+    ) -> i32 {
+        let index = Self::continuation_index(color, previous);
+        let (piece, board_move) = current;
+        self.continuation_scores[index][piece as usize][board_move.get_to().index()]
+    }
+
+    pub fn set_counter_move(&mut self, previous: PieceMove, color: Color, board_move: BoardMove) {
+        let (piece, previous_move) = previous;
+        self.counter_moves[color as usize][piece as usize][previous_move.get_to().index()] =
+            board_move;
+    }
+
+    pub fn get_counter_move(&self, previous: PieceMove, color: Color) -> BoardMove {
+        let (piece, previous_move) = previous;
+        self.counter_moves[color as usize][piece as usize][previous_move.get_to().index()]
+    }
+
     fn age_history(&mut self) {
         for color_idx in 0..2 {
             for from in 0..64 {
@@ -1,6 +1,40 @@
-use crate::game::board::{BoardMove, BoardMoveExt};
+use crate::error::{ProkopakopError, parse_number};
+use crate::game::board::BoardMove;
 use crate::game::pieces::Color;
 
+// Estimated moves left in sudden death, shrinking from the maximum as the game goes on
+const SUDDEN_DEATH_MOVES_MAX: u64 = 50;
+const SUDDEN_DEATH_MOVES_MIN: u64 = 20;
+
+// Never go below this per move, and keep this much per remaining move before the time control
+const MIN_MOVE_TIME_MS: u64 = 10;
+
+// A search whose score drops may take up to this many times the time allocated to the move
+const EXTENDED_TIME_FACTOR: u64 = 3;
+
+// Keywords of the go command, which end the move list of searchmoves
+const GO_PARAMETERS: [&str; 13] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+    "perft",
+];
+
+/// Milliseconds of a clock value, which GUIs lagging behind can send slightly negative
+/// (as good as no time left)
+fn parse_milliseconds(value: &str) -> Result<u64, ProkopakopError> {
+    parse_number::<u64>(value).or_else(|e| parse_number::<i64>(value).map(|_| 0).map_err(|_| e))
+}
+
 /// Search limits and parameters
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
@@ -8,9 +42,11 @@ pub struct SearchLimits {
     pub max_depth: Option<usize>,
     pub max_nodes: Option<u64>,
     pub max_time_ms: Option<u64>,
-    pub moves: Vec<BoardMove>, // TODO: implement this!
+    pub extended_time_ms: Option<u64>, // hard limit max_time_ms is extended to when the score drops
+    pub moves: Vec<BoardMove>,         // when not empty, the only root moves searched
     pub infinite: bool,
     pub exact: bool, // Whether to actually search for this amount (even for forced moves)
+    pub mate: Option<usize>, // Only looking for a mate in this many moves
 }
 
 /// Search parameters from UCI go command
@@ -24,9 +60,11 @@ pub struct SearchParams {
     pub binc: Option<u64>,           // black increment per move in milliseconds
     pub movestogo: Option<usize>,    // there are x moves to the next time control
     pub nodes: Option<u64>,          // search x nodes only
+    pub mate: Option<usize>,         // search for a mate in x moves
     pub infinite: bool,              // search until "stop" command
     pub ponder: bool,                // search in ponder mode
     pub searchmoves: Vec<BoardMove>, // restrict search to these moves only
+    pub perft: Option<usize>,        // count the leaf nodes to depth x instead of searching
 }
 
 impl Default for SearchParams {
@@ -40,109 +78,94 @@ impl Default for SearchParams {
             binc: None,
             movestogo: None,
             nodes: None,
+            mate: None,
             infinite: false,
             ponder: false,
             searchmoves: Vec::new(),
+            perft: None,
         }
     }
 }
 
 impl SearchParams {
-    pub fn parse(params: Vec<String>) -> Self {
+    /// Parse the parameters of a `go` command, rejecting unknown parameters and malformed
+    /// values rather than skipping them, so that a misconfigured GUI doesn't go unnoticed
+    pub fn parse(params: Vec<String>) -> Result<Self, ProkopakopError> {
         let mut search_params = SearchParams::default();
-        let mut iter = params.iter();
+        let mut iter = params.iter().peekable();
 
         while let Some(param) = iter.next() {
+            // the value following the parameter
+            let mut value = || {
+                iter.next().map(String::as_str).ok_or_else(|| {
+                    ProkopakopError::InvalidSearchParameter(format!("{} without a value", param))
+                })
+            };
+
             match param.as_str() {
-                "depth" => {
-                    if let Some(value) = iter.next() {
-                        search_params.depth = value.parse().ok();
-                    }
-                }
-                "movetime" => {
-                    if let Some(value) = iter.next() {
-                        search_params.movetime = value.parse().ok();
+                "depth" => search_params.depth = Some(parse_number(value()?)?),
+                "movetime" => search_params.movetime = Some(parse_milliseconds(value()?)?),
+                "wtime" => search_params.wtime = Some(parse_milliseconds(value()?)?),
+                "btime" => search_params.btime = Some(parse_milliseconds(value()?)?),
+                "winc" => search_params.winc = Some(parse_milliseconds(value()?)?),
+                "binc" => search_params.binc = Some(parse_milliseconds(value()?)?),
+                "movestogo" => search_params.movestogo = Some(parse_number(value()?)?),
+                "nodes" => search_params.nodes = Some(parse_number(value()?)?),
+                "mate" => match parse_number(value()?)? {
+                    0 => {
+                        return Err(ProkopakopError::InvalidSearchParameter(
+                            "mate 0".to_string(),
+                        ));
                     }
-                }
-                "wtime" => {
-                    if let Some(value) = iter.next() {
-                        search_params.wtime = value.parse().ok();
-                    }
-                }
-                "btime" => {
-                    if let Some(value) = iter.next() {
-                        search_params.btime = value.parse().ok();
-                    }
-                }
-                "winc" => {
-                    if let Some(value) = iter.next() {
-                        search_params.winc = value.parse().ok();
-                    }
-                }
-                "binc" => {
-                    if let Some(value) = iter.next() {
-                        search_params.binc = value.parse().ok();
-                    }
-                }
-                "movestogo" => {
-                    if let Some(value) = iter.next() {
-                        search_params.movestogo = value.parse().ok();
-                    }
-                }
-                "nodes" => {
-                    if let Some(value) = iter.next() {
-                        search_params.nodes = value.parse().ok();
-                    }
-                }
-                "infinite" => {
-                    search_params.infinite = true;
-                }
-                "ponder" => {
-                    search_params.ponder = true;
-                }
+                    moves => search_params.mate = Some(moves),
+                },
+                "perft" => search_params.perft = Some(parse_number(value()?)?),
+                "infinite" => search_params.infinite = true,
+                "ponder" => search_params.ponder = true,
                 "searchmoves" => {
-                    // Collect all remaining moves
-                    while let Some(move_str) = iter.next() {
-                        // Check if this is another parameter (not a move)
-                        if [
-                            "depth",
-                            "movetime",
-                            "wtime",
-                            "btime",
-                            "winc",
-                            "binc",
-                            "movestogo",
-                            "nodes",
-                            "infinite",
-                            "ponder",
-                        ]
-                        .contains(&move_str.as_str())
-                        {
-                            // Put it back by breaking and letting the outer loop handle it
-                            // Note: This is a simplified approach. In production, you might want
-                            // to handle this differently
-                            break;
-                        }
-
-                        if let Some(board_move) = BoardMove::parse(move_str) {
-                            search_params.searchmoves.push(board_move);
-                        }
+                    // All moves up to the next parameter
+                    while let Some(move_str) =
+                        iter.next_if(|next| !GO_PARAMETERS.contains(&next.as_str()))
+                    {
+                        let board_move = BoardMove::parse(move_str).ok_or_else(|| {
+                            ProkopakopError::IllegalMove(format!("{} in searchmoves", move_str))
+                        })?;
+                        search_params.searchmoves.push(board_move);
                     }
                 }
-                _ => {
-                    // Unknown parameter, skip
-                    eprintln!("Unknown go parameter: {}", param);
-                }
+                _ => return Err(ProkopakopError::InvalidSearchParameter(param.clone())),
             }
         }
 
-        search_params
+        Ok(search_params)
+    }
+
+    /// Time to spend on the current move. Without `movestogo` the number of moves left is
+    /// estimated from the move number, so the fraction of the clock used per move grows as the
+    /// game goes on; with it, enough time is kept for the remaining moves before the control.
+    /// With only an increment (no clock) most of the increment is used.
+    pub fn calculate_move_time(
+        &self,
+        color: Color,
+        move_overhead: u64,
+        fullmoves: usize,
+    ) -> Option<u64> {
+        self.calculate_time_limits(color, move_overhead, fullmoves)
+            .map(|(move_time, _)| move_time)
     }
 
-    pub fn calculate_move_time(&self, color: Color, move_overhead: u64) -> Option<u64> {
+    /// The time to spend on the current move (see `calculate_move_time`) and the hard limit
+    /// a search whose score drops may extend it to, which is only above it with a clock
+    pub fn calculate_time_limits(
+        &self,
+        color: Color,
+        move_overhead: u64,
+        fullmoves: usize,
+    ) -> Option<(u64, u64)> {
         // If movetime is specified, use that (subtract move overhead)
         if let Some(movetime) = self.movetime {
-            return Some(movetime.saturating_sub(move_overhead));
+            let move_time = movetime.saturating_sub(move_overhead);
+            return Some((move_time, move_time));
         }
 
         // If infinite search, no time limit
@@ -156,25 +179,51 @@ impl SearchParams {
             Color::Black => (self.btime, self.binc.unwrap_or(0)),
         };
 
-        // If we have time control information
-        if let Some(time) = time_left {
-            let moves_remaining = self.movestogo.unwrap_or(30) as u64; // Assume 30 moves if not specified
+        // without a clock, only the increment is known to be there every move
+        let Some(time) = time_left else {
+            return (increment > 0).then(|| {
+                let move_time = (increment.saturating_mul(8) / 10)
+                    .saturating_sub(move_overhead)
+                    .max(1);
+                (move_time, move_time)
+            });
+        };
+
+        // Apply move overhead - this accounts for network/GUI delays
+        let available_time = time.saturating_sub(move_overhead);
 
-            // Apply move overhead - this accounts for network/GUI delays
-            let available_time = time.saturating_sub(move_overhead);
+        let moves_left = match self.movestogo {
+            Some(movestogo) => movestogo.max(1) as u64,
+            None => SUDDEN_DEATH_MOVES_MAX
+                .saturating_sub(fullmoves as u64 / 2)
+                .max(SUDDEN_DEATH_MOVES_MIN),
+        };
 
-            // Spend most of increment
-            let base_time = available_time / moves_remaining.max(1);
-            let allocated_time = base_time + (increment * 8 / 10);
+        // Even share of the clock, plus most of the increment
+        let allocated_time =
+            (available_time / moves_left).saturating_add(increment.saturating_mul(8) / 10);
 
-            // Min 10ms for move (but ensure we don't go below 1ms due to overhead)
-            Some(
-                allocated_time
-                    .max(10)
-                    .saturating_sub(move_overhead.min(allocated_time / 2)),
-            )
+        // The last move before the time control can use most of the clock since it gets
+        // replenished right after; otherwise never bet more than half of it on one move
+        let max_time = if moves_left == 1 {
+            available_time.saturating_mul(9) / 10
         } else {
-            None
-        }
+            available_time / 2
+        };
+
+        // Keep enough for the remaining moves before the time control
+        let reserve = (moves_left - 1).saturating_mul(MIN_MOVE_TIME_MS);
+        let max_time = max_time.min(available_time.saturating_sub(reserve));
+
+        let move_time = allocated_time
+            .min(max_time)
+            .max(MIN_MOVE_TIME_MS.min(available_time))
+            .max(1);
+        let extended_time = move_time
+            .saturating_mul(EXTENDED_TIME_FACTOR)
+            .min(max_time)
+            .max(move_time);
+
+        Some((move_time, extended_time))
     }
 }
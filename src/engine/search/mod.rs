@@ -1,5 +1,7 @@
 pub mod history;
 pub mod limits;
+pub mod options;
 pub mod params;
+pub mod pns;
 pub mod results;
 pub mod searcher;
@@ -0,0 +1,64 @@
+use super::params::{CONTEMPT, QUIESCENCE_CHECK_PLIES, QUIESCENCE_DEPTH};
+
+/// How search progress is reported: UCI `info` lines or XBoard thinking output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThinkingFormat {
+    Uci,
+    XBoard,
+}
+
+/// How `go mate` looks for the mate: alpha-beta alone, alpha-beta with proof-number search
+/// for when it comes up short, or proof-number search first (for long forced mates)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MateSearch {
+    AlphaBeta,
+    Fallback,
+    ProofNumber,
+}
+
+/// Search behaviour toggles set through UCI options, as opposed to the
+/// per-`go` limits in `SearchLimits` and the compile-time tunables in `params.rs`.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub underpromotion_pruning: bool, // skip rook/bishop (and quiet knight) promotions at low depths
+    pub quiescence_depth: usize,      // max plies searched in quiescence
+    pub quiescence_check_plies: usize, // plies of quiescence that also search quiet checks
+    pub avoid_repetition: bool,       // when winning, penalize root moves repeating a position
+    pub root_time_pruning: bool, // when short on time, finish iterations on the best root moves
+    pub verify_best_move: bool,  // re-search the best move shallowly before playing it
+    pub show_refutations: bool,  // print the best reply to each refuted root move
+    pub show_currline: bool,     // periodically print the line being searched
+    pub show_wdl: bool,          // print win/draw/loss permille along with the score
+    pub contempt: i32,           // centipawns repetition draws are scored against the side ahead
+    pub analyse_mode: bool,      // analysing rather than playing (no contempt)
+    pub verify_pruning_rate: f64, // fraction of pruned nodes re-searched without pruning
+    pub verify_pruning_tolerance: i32, // centipawns the unpruned value may be off by
+    pub thinking_format: ThinkingFormat, // set by the protocol the GUI speaks
+    pub mate_search: MateSearch, // how `go mate` searches
+    pub silent: bool,            // print nothing but bestmove, for mass self-play
+    pub multi_pv: usize,         // root moves reported each iteration, with a line each
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            underpromotion_pruning: true,
+            quiescence_depth: QUIESCENCE_DEPTH,
+            quiescence_check_plies: QUIESCENCE_CHECK_PLIES,
+            avoid_repetition: false,
+            root_time_pruning: false,
+            verify_best_move: false,
+            show_refutations: false,
+            show_currline: false,
+            show_wdl: false,
+            contempt: CONTEMPT,
+            analyse_mode: false,
+            verify_pruning_rate: 0.0,
+            verify_pruning_tolerance: 50,
+            thinking_format: ThinkingFormat::Uci,
+            mate_search: MateSearch::AlphaBeta,
+            silent: false,
+            multi_pv: 1,
+        }
+    }
+}
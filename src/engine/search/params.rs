@@ -6,19 +6,19 @@
 /// Format: NAME, current_value, min, max, description
 
 // Futility pruning margins (centipawns)
-pub const FUTILITY_MARGIN_1: f32 = 51.8; // min: 25, max: 100
-pub const FUTILITY_MARGIN_2: f32 = 218.9; // min: 150, max: 350
-pub const FUTILITY_MARGIN_3: f32 = 647.5; // min: 450, max: 800
+pub const FUTILITY_MARGIN_1: i32 = 52; // min: 25, max: 100
+pub const FUTILITY_MARGIN_2: i32 = 219; // min: 150, max: 350
+pub const FUTILITY_MARGIN_3: i32 = 648; // min: 450, max: 800
 
 // Reverse futility pruning margins (centipawns)
-pub const REVERSE_FUTILITY_MARGIN_1: f32 = 141.4; // min: 75, max: 225
-pub const REVERSE_FUTILITY_MARGIN_2: f32 = 116.1; // min: 50, max: 200
-pub const REVERSE_FUTILITY_MARGIN_3: f32 = 350.3; // min: 200, max: 520
+pub const REVERSE_FUTILITY_MARGIN_1: i32 = 141; // min: 75, max: 225
+pub const REVERSE_FUTILITY_MARGIN_2: i32 = 116; // min: 50, max: 200
+pub const REVERSE_FUTILITY_MARGIN_3: i32 = 350; // min: 200, max: 520
 
 // Razoring margins (centipawns)
-pub const RAZORING_MARGIN_1: f32 = 341.8; // min: 220, max: 460
-pub const RAZORING_MARGIN_2: f32 = 465.9; // min: 320, max: 600
-pub const RAZORING_MARGIN_3: f32 = 790.6; // min: 550, max: 950
+pub const RAZORING_MARGIN_1: i32 = 342; // min: 220, max: 460
+pub const RAZORING_MARGIN_2: i32 = 466; // min: 320, max: 600
+pub const RAZORING_MARGIN_3: i32 = 791; // min: 550, max: 950
 
 // Null move pruning
 pub const NULL_MOVE_REDUCTION: usize = 2; // min: 1, max: 4
@@ -30,22 +30,68 @@ pub const LMR_DIVISOR: f32 = 1.3; // min: 0.5, max: 2.5
 pub const LMR_MIN_DEPTH: usize = 3; // min: 2, max: 5
 pub const LMR_MOVE_INDEX: usize = 3; // min: 2, max: 6
 
+// History pruning: late quiet moves with a combined (butterfly + continuation) history score
+// below -margin * depth are skipped at depths up to this one
+pub const HISTORY_PRUNING_DEPTH: usize = 3; // min: 1, max: 5
+pub const HISTORY_PRUNING_MARGIN: i32 = 1500; // min: 500, max: 4000
+
 // Extended futility
 pub const EXT_FUTILITY_MULTIPLIER: f32 = 1.4; // min: 0.7, max: 1.8
 
 // Delta pruning (quiescence) - centipawns
-pub const DELTA_PRUNING_MARGIN: f32 = 75.3; // min: 40, max: 110
+pub const DELTA_PRUNING_MARGIN: i32 = 75; // min: 40, max: 110
+
+// Quiescence search limits (defaults of the QuiescenceDepth/QuiescenceChecks options)
+pub const QUIESCENCE_DEPTH: usize = 32; // min: 4, max: 64
+pub const QUIESCENCE_CHECK_PLIES: usize = 1; // min: 0, max: 3
+
+// Underpromotion pruning
+pub const UNDERPROMOTION_PRUNING_DEPTH: usize = 3; // min: 1, max: 6
+
+// Repetition avoidance (centipawns, used with the AvoidRepetition option)
+pub const AVOID_REPETITION_THRESHOLD: i32 = 150; // min: 50, max: 400
+pub const AVOID_REPETITION_PENALTY: i32 = 30; // min: 5, max: 100
+
+// Contempt (centipawns, default of the Contempt option): repetition draws are scored this much
+// against the side ahead in material, fading out towards the endgame
+pub const CONTEMPT: i32 = 20; // min: 0, max: 60
+
+// Easy move: the best root move leading the others by the margin (centipawns) for a couple of
+// iterations ends the search once this fraction of the allotted time is used
+pub const EASY_MOVE_MARGIN: i32 = 150; // min: 75, max: 300
+pub const EASY_MOVE_TIME_FRACTION: f32 = 0.3; // min: 0.1, max: 0.6
+
+// Root time pruning (RootTimePruning option): past this fraction of the hard time limit, an
+// iteration only finishes the root moves searched so far
+pub const ROOT_PRUNING_TIME_FRACTION: f32 = 0.85; // min: 0.6, max: 0.95
+
+// Score trend: an iteration scoring this many centipawns below the previous one multiplies
+// the time of the search by the factor (up to its hard limit)
+pub const SCORE_DROP_MARGIN: i32 = 40; // min: 15, max: 100
+pub const SCORE_DROP_TIME_FACTOR: f32 = 1.5; // min: 1.1, max: 3
+
+// Best move verification (VerifyBestMove option): the best move re-searched without pruning
+// failing low by this many centipawns below its score gets replaced by the second-best one
+pub const VERIFY_BEST_MOVE_MARGIN: i32 = 200; // min: 100, max: 400
 
 // Aspiration windows
-pub const ASPIRATION_INITIAL: f32 = 54.4; // min: 30, max: 85
-pub const ASPIRATION_MIN: f32 = 20.8; // min: 8, max: 30
+pub const ASPIRATION_INITIAL: i32 = 54; // min: 30, max: 85
+pub const ASPIRATION_MIN: i32 = 21; // min: 8, max: 30
 pub const ASPIRATION_EXPAND: f32 = 2.7; // min: 1.8, max: 3.6
 
+// Aspiration window adaptation over a game: after a search whose windows failed more (less)
+// often than these rates, the next one starts them wider (narrower) by the step, within limits
+pub const ASPIRATION_VOLATILE_RATE: f32 = 0.3; // min: 0.15, max: 0.5
+pub const ASPIRATION_STABLE_RATE: f32 = 0.1; // min: 0.0, max: 0.2
+pub const ASPIRATION_ADAPT_STEP: f32 = 1.2; // min: 1.05, max: 1.5
+pub const ASPIRATION_SCALE_MIN: f32 = 0.5; // min: 0.3, max: 1.0
+pub const ASPIRATION_SCALE_MAX: f32 = 2.5; // min: 1.0, max: 4.0
+
 /// Helper functions for depth-indexed lookups
 #[inline(always)]
-pub const fn futility_margin(depth: usize) -> f32 {
+pub const fn futility_margin(depth: usize) -> i32 {
     match depth {
-        0 => 0.0,
+        0 => 0,
         1 => FUTILITY_MARGIN_1,
         2 => FUTILITY_MARGIN_2,
         _ => FUTILITY_MARGIN_3,
@@ -53,9 +99,9 @@ pub const fn futility_margin(depth: usize) -> f32 {
 }
 
 #[inline(always)]
-pub const fn reverse_futility_margin(depth: usize) -> f32 {
+pub const fn reverse_futility_margin(depth: usize) -> i32 {
     match depth {
-        0 => 0.0,
+        0 => 0,
         1 => REVERSE_FUTILITY_MARGIN_1,
         2 => REVERSE_FUTILITY_MARGIN_2,
         _ => REVERSE_FUTILITY_MARGIN_3,
@@ -63,7 +109,7 @@ pub const fn reverse_futility_margin(depth: usize) -> f32 {
 }
 
 #[inline(always)]
-pub const fn razoring_margin(depth: usize) -> f32 {
+pub const fn razoring_margin(depth: usize) -> i32 {
     match depth {
         1 => RAZORING_MARGIN_1,
         2 => RAZORING_MARGIN_2,
@@ -0,0 +1,265 @@
+//! Proof-number search for `go mate`, an alternative to alpha-beta for long forced mates: the
+//! tree is grown towards the node that is cheapest to prove (or disprove) the mate in, rather
+//! than uniformly deepened, so narrow forcing lines with few defender replies go deep quickly.
+
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use crate::game::board::{BoardMove, Game};
+use crate::game::pieces::Color;
+
+use super::limits::SearchLimits;
+use super::results::SearchStats;
+
+// Proof and disproof numbers of a node that can't be proven (or disproven) anymore
+const INFINITY: u32 = u32::MAX;
+
+// Nodes the tree may grow to (16 bytes each), beyond which the search gives up
+const MAX_TREE_NODES: usize = 8_000_000;
+
+// Children a single expansion can add at most
+const MAX_MOVES: usize = 256;
+
+struct Node {
+    board_move: BoardMove, // move leading to the node
+    proof: u32,            // leaves to prove for the attacker to mate from here
+    disproof: u32,         // leaves to prove for the defender to escape from here
+    first_child: u32,      // children of a node are stored next to each other
+    child_count: u16,      // zero until expanded
+}
+
+impl Node {
+    fn new(board_move: BoardMove, proof: u32, disproof: u32) -> Self {
+        Self {
+            board_move,
+            proof,
+            disproof,
+            first_child: 0,
+            child_count: 0,
+        }
+    }
+
+    fn is_solved(&self) -> bool {
+        self.proof == 0 || self.disproof == 0
+    }
+
+    fn children(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + self.child_count as usize
+    }
+}
+
+/// Proof-number search for a mate by the side to move within the given number of plies. Nodes
+/// at even plies are OR nodes (the attacker needs one mating move), odd ones AND nodes (every
+/// defender reply has to be mated).
+pub struct ProofNumberSearch<'a> {
+    game: &'a mut Game,
+    attacker: Color,
+    max_plies: usize,
+    root_moves: Vec<BoardMove>, // when not empty, the root moves the mate is searched among
+    nodes: Vec<Node>,
+}
+
+impl<'a> ProofNumberSearch<'a> {
+    pub fn new(game: &'a mut Game, max_plies: usize) -> Self {
+        let attacker = game.side;
+
+        Self {
+            game,
+            attacker,
+            max_plies,
+            root_moves: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Only look for mates starting with the given root moves
+    pub fn with_root_moves(mut self, root_moves: Vec<BoardMove>) -> Self {
+        self.root_moves = root_moves;
+        self
+    }
+
+    /// Search until the mate is proven or disproven, or the limits run out; returns the mating
+    /// line (the defender resisting the longest) if one was proven
+    pub fn run(
+        &mut self,
+        stats: &mut SearchStats,
+        limits: &SearchLimits,
+        stop_flag: &Arc<AtomicBool>,
+    ) -> Option<Vec<BoardMove>> {
+        self.nodes.clear();
+        self.nodes.push(Node::new(BoardMove::empty(), 1, 1));
+
+        let mut path = Vec::with_capacity(self.max_plies + 1);
+
+        while !self.nodes[0].is_solved() {
+            if stats.should_stop_at_node(limits, stop_flag)
+                || self.nodes.len() + MAX_MOVES > MAX_TREE_NODES
+            {
+                return None;
+            }
+
+            // walk down to the most proving node, making the moves along the way
+            path.clear();
+            path.push(0);
+            let mut index = 0;
+            while self.nodes[index].child_count > 0 {
+                index = self.most_proving_child(index, path.len() - 1);
+                self.game.make_move(self.nodes[index].board_move);
+                path.push(index);
+            }
+
+            self.expand(index, path.len() - 1);
+            stats.increment_nodes();
+
+            // and back up, updating the numbers of its ancestors
+            for ply in (0..path.len()).rev() {
+                if ply < path.len() - 1 {
+                    self.update(path[ply], ply);
+                }
+                if ply > 0 {
+                    self.game.unmake_move();
+                }
+            }
+        }
+
+        (self.nodes[0].proof == 0).then(|| self.mate_line())
+    }
+
+    fn is_or_node(ply: usize) -> bool {
+        ply.is_multiple_of(2)
+    }
+
+    /// Child with the smallest proof number at OR nodes, disproof number at AND nodes
+    fn most_proving_child(&self, index: usize, ply: usize) -> usize {
+        let children = self.nodes[index].children();
+
+        if Self::is_or_node(ply) {
+            children.min_by_key(|&child| self.nodes[child].proof)
+        } else {
+            children.min_by_key(|&child| self.nodes[child].disproof)
+        }
+        .unwrap()
+    }
+
+    /// Add the children of a leaf, each scored by whether it ends the game, runs out of plies
+    /// or by its mobility (more defender replies are more work to prove the mate against)
+    fn expand(&mut self, index: usize, ply: usize) {
+        let (count, moves) = self.game.get_moves();
+        let child_ply = ply + 1;
+        let first_child = self.nodes.len();
+
+        for &board_move in &moves[..count] {
+            if ply == 0 && !self.root_moves.is_empty() && !self.root_moves.contains(&board_move) {
+                continue;
+            }
+
+            // the last move has to mate, which only a check can
+            if child_ply == self.max_plies && !self.game.is_check(board_move) {
+                self.nodes.push(Node::new(board_move, INFINITY, 0));
+                continue;
+            }
+
+            self.game.make_move(board_move);
+            let (reply_count, _) = self.game.get_moves();
+
+            let (proof, disproof) = if reply_count == 0 {
+                // mate (or a variant's win) is only good for the attacker, stalemate never is
+                if self.game.is_lost() && self.game.side != self.attacker {
+                    (0, INFINITY)
+                } else {
+                    (INFINITY, 0)
+                }
+            } else if child_ply == self.max_plies {
+                (INFINITY, 0)
+            } else if Self::is_or_node(child_ply) {
+                (1, reply_count as u32)
+            } else {
+                (reply_count as u32, 1)
+            };
+
+            self.game.unmake_move();
+            self.nodes.push(Node::new(board_move, proof, disproof));
+        }
+
+        let child_count = self.nodes.len() - first_child;
+        let node = &mut self.nodes[index];
+        node.first_child = first_child as u32;
+        node.child_count = child_count as u16;
+
+        // every root move excluded, nothing to prove the mate with
+        if node.child_count == 0 {
+            (node.proof, node.disproof) = (INFINITY, 0);
+            return;
+        }
+
+        self.update(index, ply);
+    }
+
+    /// Recompute the numbers of an expanded node from its children
+    fn update(&mut self, index: usize, ply: usize) {
+        let children = self.nodes[index].children();
+        let proofs = self.nodes[children.clone()].iter().map(|child| child.proof);
+        let disproofs = self.nodes[children.clone()]
+            .iter()
+            .map(|child| child.disproof);
+
+        let (proof, disproof) = if Self::is_or_node(ply) {
+            (
+                proofs.min().unwrap(),
+                disproofs.fold(0, u32::saturating_add),
+            )
+        } else {
+            (
+                proofs.fold(0, u32::saturating_add),
+                disproofs.min().unwrap(),
+            )
+        };
+
+        let node = &mut self.nodes[index];
+        node.proof = proof;
+        node.disproof = disproof;
+    }
+
+    /// Plies to mate from a proven node, with the attacker mating as fast as it can and the
+    /// defender holding out as long as it can
+    fn mate_distance(&self, index: usize, ply: usize) -> usize {
+        let children = self.nodes[index]
+            .children()
+            .filter(|&child| self.nodes[child].proof == 0)
+            .map(|child| self.mate_distance(child, ply + 1) + 1);
+
+        let distance = if Self::is_or_node(ply) {
+            children.min()
+        } else {
+            children.max()
+        };
+
+        // a leaf proven node is the mate itself
+        distance.unwrap_or(0)
+    }
+
+    /// The proven mating line from the root
+    fn mate_line(&self) -> Vec<BoardMove> {
+        let mut line = Vec::new();
+        let mut index = 0;
+
+        while self.nodes[index].child_count > 0 {
+            let ply = line.len();
+            let proven = self.nodes[index]
+                .children()
+                .filter(|&child| self.nodes[child].proof == 0);
+
+            index = if Self::is_or_node(ply) {
+                proven.min_by_key(|&child| self.mate_distance(child, ply + 1))
+            } else {
+                proven.max_by_key(|&child| self.mate_distance(child, ply + 1))
+            }
+            .unwrap();
+
+            line.push(self.nodes[index].board_move);
+        }
+
+        line
+    }
+}
@@ -1,17 +1,31 @@
 use super::limits::SearchLimits;
-use crate::game::board::{BoardMove, BoardMoveExt};
+use super::params::{
+    ASPIRATION_ADAPT_STEP, ASPIRATION_SCALE_MAX, ASPIRATION_SCALE_MIN, ASPIRATION_STABLE_RATE,
+    ASPIRATION_VOLATILE_RATE,
+};
+use crate::game::board::BoardMove;
 use std::fmt::{Display, Formatter, Result};
 use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, Ordering},
+    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
 };
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// How much of the tree below a search result was searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchStatus {
+    #[default]
+    Completed, // every move was searched (or pruned), the evaluation is exact or a bound
+    Partial,     // the root ran out of time after some of its moves, exact for those only
+    Interrupted, // stopped midway, neither the move nor the evaluation mean anything
+}
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub best_move: BoardMove,
-    pub evaluation: f32,
+    pub evaluation: i32,
     pub pv: Vec<BoardMove>, // Principal variation
+    pub status: SearchStatus,
 }
 
 impl Display for SearchResult {
@@ -21,76 +35,591 @@ impl Display for SearchResult {
 }
 
 impl SearchResult {
-    pub fn leaf(evaluation: f32) -> Self {
+    pub fn leaf(evaluation: i32) -> Self {
         Self {
             best_move: BoardMove::empty(),
             evaluation,
             pv: Vec::new(),
+            status: SearchStatus::Completed,
         }
     }
 
-    pub fn with_pv(best_move: BoardMove, evaluation: f32, mut pv: Vec<BoardMove>) -> Self {
+    pub fn with_pv(best_move: BoardMove, evaluation: i32, mut pv: Vec<BoardMove>) -> Self {
         let mut new_pv = vec![best_move];
         new_pv.append(&mut pv);
         Self {
             best_move,
             evaluation,
             pv: new_pv,
+            status: SearchStatus::Completed,
         }
     }
 
     pub fn interrupted() -> Self {
         Self {
             best_move: BoardMove::empty(),
-            evaluation: f32::NAN,
+            evaluation: 0,
             pv: Vec::new(),
+            status: SearchStatus::Interrupted,
+        }
+    }
+
+    /// Mark the result of a root search that only got through some of its moves
+    pub fn partial(self) -> Self {
+        Self {
+            status: SearchStatus::Partial,
+            ..self
         }
     }
 
+    /// Whether the result can be used at all: an empty PV (say, from a fail-low) is still valid,
+    /// only an interrupted search isn't
     pub fn is_valid(&self) -> bool {
-        !self.evaluation.is_nan()
+        self.status != SearchStatus::Interrupted
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.status == SearchStatus::Partial
+    }
+}
+
+/// Type of a node as predicted by its parent, before it is searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedNode {
+    Pv = 0,  // searched with an open window, exact score expected
+    Cut = 1, // expected to fail high
+    All = 2, // expected to fail low
+}
+
+impl ExpectedNode {
+    pub const ALL_TYPES: [ExpectedNode; 3] =
+        [ExpectedNode::Pv, ExpectedNode::Cut, ExpectedNode::All];
+
+    /// Expected type of the n-th searched child of a node of this type
+    pub fn child(self, moves_searched: usize) -> ExpectedNode {
+        match self {
+            ExpectedNode::Pv if moves_searched == 0 => ExpectedNode::Pv,
+            ExpectedNode::Pv => ExpectedNode::Cut,
+            // if the first move of a cut node doesn't cut, the node is really an all node
+            ExpectedNode::Cut if moves_searched == 0 => ExpectedNode::All,
+            ExpectedNode::Cut => ExpectedNode::Cut,
+            ExpectedNode::All => ExpectedNode::Cut,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ExpectedNode::Pv => "PV",
+            ExpectedNode::Cut => "Cut",
+            ExpectedNode::All => "All",
+        }
+    }
+}
+
+/// Counts of expected vs. actual node types, used to validate the node type predictions
+#[derive(Debug, Clone, Default)]
+pub struct NodeTypeStats {
+    pub counts: [[u64; 3]; 3], // [expected][actual]
+}
+
+impl NodeTypeStats {
+    pub fn record(&mut self, expected: ExpectedNode, actual: ExpectedNode) {
+        self.counts[expected as usize][actual as usize] += 1;
+    }
+
+    pub fn total(&self, expected: ExpectedNode) -> u64 {
+        self.counts[expected as usize].iter().sum()
+    }
+
+    /// Fraction of nodes of the given expected type that turned out to be of that type
+    pub fn accuracy(&self, expected: ExpectedNode) -> f64 {
+        let total = self.total(expected);
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.counts[expected as usize][expected as usize] as f64 / total as f64
+    }
+}
+
+impl Display for NodeTypeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "Node types (expected -> actual):")?;
+
+        for expected in ExpectedNode::ALL_TYPES {
+            let total = self.total(expected);
+            write!(f, "  {:<3} {:>10} nodes:", expected.name(), total)?;
+
+            for actual in ExpectedNode::ALL_TYPES {
+                let count = self.counts[expected as usize][actual as usize];
+                let pct = if total > 0 {
+                    count as f64 / total as f64 * 100.0
+                } else {
+                    0.0
+                };
+                write!(f, " {} {} ({:.1}%)", actual.name(), count, pct)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Nodes by the part of the search that visited them, since the total nps alone doesn't show
+/// where the time goes
+#[derive(Debug, Clone, Default)]
+pub struct NodeCounts {
+    pub main: u64,       // alpha-beta nodes
+    pub quiescence: u64, // quiescence search nodes
+    pub time_ms: u64,
+}
+
+impl NodeCounts {
+    pub fn total(&self) -> u64 {
+        self.main + self.quiescence
+    }
+
+    pub fn merge(&mut self, other: &NodeCounts) {
+        self.main += other.main;
+        self.quiescence += other.quiescence;
+        self.time_ms = self.time_ms.max(other.time_ms);
+    }
+
+    fn nps(&self, nodes: u64) -> u64 {
+        (nodes * 1000).checked_div(self.time_ms).unwrap_or(0)
+    }
+}
+
+impl Display for NodeCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "Nodes ({} ms):", self.time_ms)?;
+
+        let total = self.total();
+        for (name, nodes) in [
+            ("Total", total),
+            ("Main search", self.main),
+            ("Quiescence", self.quiescence),
+        ] {
+            let pct = if total > 0 {
+                nodes as f64 / total as f64 * 100.0
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "  {:<12} {:>10} ({:>5.1}%) {:>10} nps",
+                name,
+                nodes,
+                pct,
+                self.nps(nodes)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pruning techniques checked by the verify-pruning mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruningKind {
+    ReverseFutility = 0,
+    Razoring = 1,
+    NullMove = 2,
+    Futility = 3, // quiet moves skipped at frontier nodes
+    History = 4,  // late quiet moves skipped for their history scores
+}
+
+impl PruningKind {
+    pub const ALL_KINDS: [PruningKind; 5] = [
+        PruningKind::ReverseFutility,
+        PruningKind::Razoring,
+        PruningKind::NullMove,
+        PruningKind::Futility,
+        PruningKind::History,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            PruningKind::ReverseFutility => "Reverse futility",
+            PruningKind::Razoring => "Razoring",
+            PruningKind::NullMove => "Null move",
+            PruningKind::Futility => "Futility",
+            PruningKind::History => "History",
+        }
+    }
+}
+
+/// How often pruned nodes searched again without pruning came out differently
+#[derive(Debug, Clone, Default)]
+pub struct PruningStats {
+    pub checked: [u64; 5], // [kind]
+    pub changed: [u64; 5], // [kind], value beyond the tolerance on the other side of the bound
+}
+
+impl PruningStats {
+    pub fn record(&mut self, kind: PruningKind, changed: bool) {
+        self.checked[kind as usize] += 1;
+        self.changed[kind as usize] += changed as u64;
+    }
+
+    pub fn merge(&mut self, other: &PruningStats) {
+        for kind in PruningKind::ALL_KINDS {
+            self.checked[kind as usize] += other.checked[kind as usize];
+            self.changed[kind as usize] += other.changed[kind as usize];
+        }
+    }
+}
+
+impl Display for PruningStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "Pruning verification (changed / checked):")?;
+
+        for kind in PruningKind::ALL_KINDS {
+            let checked = self.checked[kind as usize];
+            let changed = self.changed[kind as usize];
+            let pct = if checked > 0 {
+                changed as f64 / checked as f64 * 100.0
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "  {:<16} {:>8} / {:<8} ({:.1}%)",
+                kind.name(),
+                changed,
+                checked,
+                pct
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How often the iterations searched with an aspiration window fell outside of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AspirationStats {
+    pub searches: u64, // iterations searched with a window
+    pub fail_highs: u64,
+    pub fail_lows: u64,
+}
+
+impl AspirationStats {
+    pub fn fails(&self) -> u64 {
+        self.fail_highs + self.fail_lows
+    }
+
+    pub fn fail_rate(&self) -> f64 {
+        match self.searches {
+            0 => 0.0,
+            searches => self.fails() as f64 / searches as f64,
+        }
+    }
+
+    pub fn merge(&mut self, other: &AspirationStats) {
+        self.searches += other.searches;
+        self.fail_highs += other.fail_highs;
+        self.fail_lows += other.fail_lows;
+    }
+}
+
+/// Aspiration windows over a game: when they keep failing (the evaluation is volatile), the
+/// following searches start them wider, and when they rarely do, narrower
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AspirationAdaptation {
+    pub last: AspirationStats, // of the last search
+    pub game: AspirationStats, // of all the searches of the game
+    pub scale: f32,            // of the initial window of the next search
+}
+
+impl Default for AspirationAdaptation {
+    fn default() -> Self {
+        Self {
+            last: AspirationStats::default(),
+            game: AspirationStats::default(),
+            scale: 1.0,
+        }
+    }
+}
+
+impl AspirationAdaptation {
+    /// Take a finished search into account, adapting the window of the next one
+    pub fn update(&mut self, search: &AspirationStats) {
+        self.last = *search;
+        self.game.merge(search);
+
+        if search.searches == 0 {
+            return;
+        }
+
+        let fail_rate = search.fail_rate() as f32;
+        if fail_rate > ASPIRATION_VOLATILE_RATE {
+            self.scale *= ASPIRATION_ADAPT_STEP;
+        } else if fail_rate < ASPIRATION_STABLE_RATE {
+            self.scale /= ASPIRATION_ADAPT_STEP;
+        }
+
+        self.scale = self.scale.clamp(ASPIRATION_SCALE_MIN, ASPIRATION_SCALE_MAX);
+    }
+}
+
+impl Display for AspirationAdaptation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "Aspiration windows (next window x{:.2}):", self.scale)?;
+
+        for (name, stats) in [("Last search", self.last), ("Game", self.game)] {
+            writeln!(
+                f,
+                "  {:<12} {:>6} searched {:>6} fail high {:>6} fail low ({:.1}%)",
+                name,
+                stats.searches,
+                stats.fail_highs,
+                stats.fail_lows,
+                stats.fail_rate() * 100.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The final evaluation of a move the engine played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendEntry {
+    pub ply: usize,      // halfmoves played before the move
+    pub evaluation: i32, // from the engine's point of view
+    pub depth: usize,    // last completed iteration
+    pub time_ms: u64,
+}
+
+impl TrendEntry {
+    pub fn move_number(&self) -> usize {
+        self.ply / 2 + 1
+    }
+}
+
+impl Display for TrendEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.move_number(),
+            self.evaluation,
+            self.depth,
+            self.time_ms
+        )
+    }
+}
+
+/// The evaluations of the engine's moves over a game, for plotting how it went
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EvalTrend {
+    pub entries: Vec<TrendEntry>,
+}
+
+impl EvalTrend {
+    /// Add the move of a finished search, replacing the moves from that ply on (taken back,
+    /// or searched again)
+    pub fn record(&mut self, entry: TrendEntry) {
+        self.entries.retain(|recorded| recorded.ply < entry.ply);
+        self.entries.push(entry);
+    }
+}
+
+impl Display for EvalTrend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "move,eval,depth,time")?;
+
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+// How often a thread publishes its node count for the other threads to see
+const NODE_PUBLISH_INTERVAL: u64 = 1024;
+
+// How often (in nodes) the clock is read when checking whether to stop
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// A node counter on its own cache line, so that threads publishing their counts
+/// don't invalidate each other's lines.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct PaddedCounter(AtomicU64);
+
+/// Node counts of all search threads. Each thread counts into its own `SearchStats`
+/// and only publishes the count here every so often, so that no shared atomic
+/// is written on every node; the total is aggregated on demand.
+#[derive(Debug, Clone)]
+pub struct ThreadNodes {
+    counts: Arc<Vec<PaddedCounter>>,
+    depth: Arc<AtomicU64>, // depth the main thread is searching
+}
+
+impl ThreadNodes {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            counts: Arc::new(
+                (0..threads.max(1))
+                    .map(|_| PaddedCounter::default())
+                    .collect(),
+            ),
+            depth: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn publish(&self, thread: usize, nodes: u64) {
+        self.counts[thread].0.store(nodes, Ordering::Relaxed);
+    }
+
+    /// Relaxed snapshot of the published node count of a thread
+    pub fn get(&self, thread: usize) -> u64 {
+        self.counts[thread].0.load(Ordering::Relaxed)
+    }
+
+    /// Relaxed snapshot of the node counts of all threads (may lag slightly behind)
+    pub fn total(&self) -> u64 {
+        (0..self.threads()).map(|thread| self.get(thread)).sum()
+    }
+
+    pub fn publish_depth(&self, depth: u64) {
+        self.depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Start of the current search on the monotonic clock, restarted by the controller on `go`
+/// and `ponderhit` and read by the search threads without locking. Being monotonic, the time
+/// is immune to the system clock changing mid-search.
+#[derive(Debug, Clone)]
+pub struct SearchClock {
+    epoch: Instant,           // reference point the start is measured from
+    start_ms: Arc<AtomicU64>, // milliseconds from the epoch to the start of the search
+}
+
+impl Default for SearchClock {
+    fn default() -> Self {
+        Self::started_ago(Duration::ZERO)
+    }
+}
+
+impl SearchClock {
+    /// A clock as if the search had started the given time ago
+    pub fn started_ago(elapsed: Duration) -> Self {
+        let now = Instant::now();
+
+        Self {
+            epoch: now.checked_sub(elapsed).unwrap_or(now),
+            start_ms: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn restart(&self) {
+        self.start_ms
+            .store(self.epoch.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn elapsed_ms(&self) -> u64 {
+        (self.epoch.elapsed().as_millis() as u64)
+            .saturating_sub(self.start_ms.load(Ordering::Relaxed))
     }
 }
 
 pub struct SearchStats {
-    pub nodes: u64,
-    pub search_start: Arc<Mutex<Instant>>,
+    pub nodes: u64,            // nodes searched by this thread
+    pub quiescence_nodes: u64, // of which in quiescence search
+    pub thread_index: usize,
+    pub thread_nodes: ThreadNodes,
+    pub node_types: NodeTypeStats,
+    pub pruning: PruningStats,
+    pub aspiration: AspirationStats,
+    pub clock: SearchClock,
     pub ponder_flag: Arc<AtomicBool>,
     pub current_depth: u64,
 }
 
 impl SearchStats {
-    pub fn new(search_start: Arc<Mutex<Instant>>, ponder_flag: Arc<AtomicBool>) -> Self {
+    pub fn new(clock: SearchClock, ponder_flag: Arc<AtomicBool>) -> Self {
         Self {
             nodes: 0,
-            search_start,
+            quiescence_nodes: 0,
+            thread_index: 0,
+            thread_nodes: ThreadNodes::new(1),
+            node_types: NodeTypeStats::default(),
+            pruning: PruningStats::default(),
+            aspiration: AspirationStats::default(),
+            clock,
             ponder_flag,
             current_depth: 0,
         }
     }
 
+    /// Count nodes as the given thread of a multi-threaded search
+    pub fn with_thread(mut self, thread_nodes: ThreadNodes, thread_index: usize) -> Self {
+        self.thread_nodes = thread_nodes;
+        self.thread_index = thread_index;
+        self
+    }
+
     pub fn increment_nodes(&mut self) {
         self.nodes += 1;
+
+        if self.nodes.is_multiple_of(NODE_PUBLISH_INTERVAL) {
+            self.publish_nodes();
+        }
     }
 
-    pub fn get_elapsed_ms(&self) -> u64 {
-        if let Ok(start) = self.search_start.lock() {
-            start.elapsed().as_millis() as u64
-        } else {
-            0
+    pub fn increment_quiescence_nodes(&mut self) {
+        self.quiescence_nodes += 1;
+        self.increment_nodes();
+    }
+
+    /// Nodes of this thread split by search part, with the time spent so far
+    pub fn node_counts(&self) -> NodeCounts {
+        NodeCounts {
+            main: self.nodes - self.quiescence_nodes,
+            quiescence: self.quiescence_nodes,
+            time_ms: self.get_elapsed_ms(),
         }
     }
 
+    pub fn publish_nodes(&self) {
+        self.thread_nodes.publish(self.thread_index, self.nodes);
+    }
+
+    /// Nodes searched by all threads: exact for this one, last published for the others
+    pub fn total_nodes(&self) -> u64 {
+        (0..self.thread_nodes.threads())
+            .map(|thread| {
+                if thread == self.thread_index {
+                    self.nodes
+                } else {
+                    self.thread_nodes.get(thread)
+                }
+            })
+            .sum()
+    }
+
+    pub fn get_elapsed_ms(&self) -> u64 {
+        self.clock.elapsed_ms()
+    }
+
     pub fn get_nps(&self) -> u64 {
-        if let Ok(start) = self.search_start.lock() {
-            let elapsed_secs = start.elapsed().as_secs_f64();
-            if elapsed_secs > 0.0 {
-                (self.nodes as f64 / elapsed_secs) as u64
-            } else {
-                0
-            }
-        } else {
-            0
+        match self.get_elapsed_ms() {
+            0 => 0,
+            elapsed_ms => self.total_nodes() * 1000 / elapsed_ms,
         }
     }
 
@@ -98,6 +627,24 @@ impl SearchStats {
         self.ponder_flag.load(Ordering::Relaxed)
     }
 
+    /// Cheaper version of `should_stop` for every node of the search: the node limit is
+    /// checked against the local counter and the clock is only read every so often.
+    pub fn should_stop_at_node(&self, limits: &SearchLimits, stop_flag: &Arc<AtomicBool>) -> bool {
+        if stop_flag.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        if let Some(max_nodes) = limits.max_nodes
+            && self.nodes >= max_nodes
+            && !self.is_pondering()
+            && !limits.infinite
+        {
+            return true;
+        }
+
+        self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) && self.should_stop(limits, stop_flag)
+    }
+
     pub fn should_stop(&self, limits: &SearchLimits, stop_flag: &Arc<AtomicBool>) -> bool {
         // Check external stop flag
         if stop_flag.load(Ordering::Relaxed) {
@@ -132,6 +679,33 @@ impl SearchStats {
         false
     }
 
+    /// Whether the search may end before its allotted time runs out (never when pondering,
+    /// searching infinitely or for an exact time)
+    pub fn can_end_early(&self, limits: &SearchLimits) -> bool {
+        !self.is_pondering() && !limits.infinite && !limits.exact && limits.max_time_ms.is_some()
+    }
+
+    /// Whether a search that can end early has used the given fraction of its time
+    pub fn past_soft_time(&self, limits: &SearchLimits, fraction: f32) -> bool {
+        match limits.max_time_ms {
+            Some(max_time_ms) if self.can_end_early(limits) => {
+                self.get_elapsed_ms() as f32 >= max_time_ms as f32 * fraction
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a timed search (not pondering or infinite) has used the given fraction of its
+    /// hard time limit
+    pub fn past_hard_time(&self, limits: &SearchLimits, fraction: f32) -> bool {
+        match limits.max_time_ms {
+            Some(max_time_ms) if !self.is_pondering() && !limits.infinite => {
+                self.get_elapsed_ms() as f32 >= max_time_ms as f32 * fraction
+            }
+            _ => false,
+        }
+    }
+
     pub fn has_time_for_iteration(&self, limits: &SearchLimits, last_iteration_ms: u64) -> bool {
         // While pondering, always continue
         if self.is_pondering() {
@@ -1,34 +1,88 @@
-use std::sync::{Arc, Mutex, atomic::AtomicBool};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
+use crate::engine::endgame::{mating_ending_attacker, mating_ending_move_score};
 use crate::engine::evaluate::{
-    CHECKMATE_SCORE, QUEEN_VALUE, calculate_game_phase, get_piece_value,
+    GAME_PHASE_MAX, INFINITE_SCORE, MATE_BOUND, PAWN_VALUE, QUEEN_VALUE, calculate_game_phase,
+    evaluate_material, get_piece_value, is_mate_score, mate_in, mated_in, moves_to_mate,
 };
 use crate::engine::killer::KillerMoves;
-use crate::engine::table::{NodeType, TranspositionTable};
-use crate::game::board::{BoardMove, BoardMoveExt, Game};
+use crate::engine::table::{
+    DEPTH_QS_CHECKS, DEPTH_QS_NO_CHECKS, NodeType, TTEntry, TranspositionTable, entry_depth,
+    score_from_tt, score_to_tt,
+};
+use crate::engine::wdl::WdlModel;
+use crate::game::board::{BoardMove, Game, MoveKind};
 use crate::game::pieces::{Color, Piece};
+use crate::game::square::BoardSquare;
 
-use super::history::History;
+use super::history::{History, PieceMove};
 use super::limits::SearchLimits;
+use super::options::{MateSearch, SearchOptions, ThinkingFormat};
 use super::params::{
-    ASPIRATION_EXPAND, ASPIRATION_INITIAL, ASPIRATION_MIN, DELTA_PRUNING_MARGIN,
-    EXT_FUTILITY_MULTIPLIER, LMR_DIVISOR, LMR_MIN_DEPTH, LMR_MOVE_INDEX, NULL_MOVE_DEPTH_THRESHOLD,
-    NULL_MOVE_MIN_DEPTH, NULL_MOVE_REDUCTION, futility_margin, razoring_margin,
+    ASPIRATION_EXPAND, ASPIRATION_INITIAL, ASPIRATION_MIN, AVOID_REPETITION_PENALTY,
+    AVOID_REPETITION_THRESHOLD, DELTA_PRUNING_MARGIN, EASY_MOVE_MARGIN, EASY_MOVE_TIME_FRACTION,
+    EXT_FUTILITY_MULTIPLIER, HISTORY_PRUNING_DEPTH, HISTORY_PRUNING_MARGIN, LMR_DIVISOR,
+    LMR_MIN_DEPTH, LMR_MOVE_INDEX, NULL_MOVE_DEPTH_THRESHOLD, NULL_MOVE_MIN_DEPTH,
+    NULL_MOVE_REDUCTION, ROOT_PRUNING_TIME_FRACTION, SCORE_DROP_MARGIN, SCORE_DROP_TIME_FACTOR,
+    UNDERPROMOTION_PRUNING_DEPTH, VERIFY_BEST_MOVE_MARGIN, futility_margin, razoring_margin,
     reverse_futility_margin,
 };
-use super::results::{SearchResult, SearchStats};
+use super::pns::ProofNumberSearch;
+use super::results::{
+    ExpectedNode, PruningKind, SearchClock, SearchResult, SearchStats, ThreadNodes,
+};
+
+// How much the root score has to change during an infinite search to be reported right away
+const LIVE_INFO_MARGIN: i32 = 20;
+
+// Easy move detection starts at this depth, and needs this many easy iterations in a row
+const EASY_MOVE_MIN_DEPTH: usize = 6;
+const EASY_MOVE_ITERATIONS: usize = 2;
+
+// Score drops of iterations from this depth on extend the time (earlier ones fluctuate a lot)
+const SCORE_DROP_MIN_DEPTH: usize = 5;
+
+// Depth the best move is re-searched to (at most) when verifying it
+const VERIFY_BEST_MOVE_DEPTH: usize = 4;
+
+/// What a search shares with the controller and the other search threads
+pub struct SearchContext<'a> {
+    pub options: SearchOptions,
+    pub tt: &'a TranspositionTable,
+    pub history: &'a mut History, // each thread's own copy
+    pub stop_flag: Arc<AtomicBool>,
+    pub ponder_flag: Arc<AtomicBool>, // set while pondering, when the clock doesn't run
+    pub clock: SearchClock,
+}
 
 /// Main search struct containing all search state
 pub struct Search<'a> {
     pub game: &'a mut Game,
     pub stats: SearchStats,
     pub limits: SearchLimits,
-    pub tt: &'a mut TranspositionTable,
+    pub options: SearchOptions,
+    pub tt: &'a TranspositionTable,
     pub history: &'a mut History,
     pub killer_moves: KillerMoves,
     pub stop_flag: Arc<AtomicBool>,
     pub uci_info: bool,
+    pub completed_depth: usize, // depth of the last fully searched iteration
+    pub second_best_score: Option<i32>, // upper bound on the best other root move's score
+    avoiding_repetition: bool,  // whether root moves into earlier positions are penalized
+    root_side: Color,
+    draw_score: i32, // score of a repetition draw for the side to move at the root
+    pruning_enabled: bool, // off while verifying a pruning decision
+    root_moves_searched: Vec<BoardMove>, // root moves searched in the last root search
+    root_moves: Vec<BoardMove>, // when not empty, the root moves this search is restricted to
+    initial_pv: Vec<BoardMove>, // expected line from an earlier search, for the first iterations
+    root_history_len: usize, // length of the game history at the root, for currline
+    last_currline_ms: u64,
+    last_root_info: Option<(BoardMove, i32)>, // root move and score last reported to the GUI
+    aspiration_scale: f32, // of the initial aspiration window, adapted over the game
+    pv_index: usize,       // of the MultiPV line being searched, 0 for the best one
+    pub lines: Vec<SearchResult>, // MultiPV lines of the last iteration, the best one first
 }
 
 impl<'a> Search<'a> {
@@ -36,50 +90,164 @@ impl<'a> Search<'a> {
     pub fn new(
         game: &'a mut Game,
         limits: SearchLimits,
-        stop_flag: Arc<AtomicBool>,
-        tt: &'a mut TranspositionTable,
-        history: &'a mut History,
+        context: SearchContext<'a>,
         uci_info: bool,
-        search_start: Arc<Mutex<Instant>>,
-        ponder_flag: Arc<AtomicBool>,
     ) -> Self {
+        let SearchContext {
+            options,
+            tt,
+            history,
+            stop_flag,
+            ponder_flag,
+            clock,
+        } = context;
+
+        // silent searches report nothing, whoever they're for
+        let uci_info = uci_info && !options.silent;
+
+        // the searchmoves of the limits (any illegal ones dropped), until told otherwise
+        let root_moves = limits
+            .moves
+            .iter()
+            .filter_map(|&board_move| game.legal_move(board_move))
+            .collect();
+
         Self {
             game,
-            stats: SearchStats::new(search_start, ponder_flag),
+            stats: SearchStats::new(clock, ponder_flag),
             limits,
+            options,
             tt,
             history,
             killer_moves: KillerMoves::new(256),
             stop_flag,
             uci_info,
+            completed_depth: 0,
+            second_best_score: None,
+            avoiding_repetition: false,
+            root_side: Color::White,
+            draw_score: 0,
+            pruning_enabled: true,
+            root_moves_searched: Vec::new(),
+            root_moves,
+            initial_pv: Vec::new(),
+            root_history_len: 0,
+            last_currline_ms: 0,
+            last_root_info: None,
+            aspiration_scale: 1.0,
+            pv_index: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Count nodes as the given thread of a multi-threaded search
+    pub fn with_thread(mut self, thread_nodes: ThreadNodes, thread_index: usize) -> Self {
+        self.stats = self.stats.with_thread(thread_nodes, thread_index);
+        self
+    }
+
+    /// Only search the given root moves (instead of those of the limits), leaving the others
+    /// to another search thread
+    pub fn with_root_moves(mut self, root_moves: Vec<BoardMove>) -> Self {
+        self.root_moves = root_moves;
+        self
+    }
+
+    /// Scale the initial aspiration window, as adapted from the earlier searches of the game
+    pub fn with_aspiration_scale(mut self, scale: f32) -> Self {
+        self.aspiration_scale = scale;
+        self
+    }
+
+    /// Order the first iterations by a line expected from an earlier search
+    pub fn with_previous_pv(mut self, pv: Vec<BoardMove>) -> Self {
+        self.initial_pv = pv;
+        self
+    }
+
+    /// Whether the move is excluded at the root (searched by another thread)
+    fn is_excluded_root_move(&self, ply: usize, board_move: BoardMove) -> bool {
+        ply == 1 && !self.root_moves.is_empty() && !self.root_moves.contains(&board_move)
+    }
+
+    /// Whether the remaining root moves should be skipped to finish the iteration in time
+    fn is_short_on_time(&self) -> bool {
+        self.options.root_time_pruning
+            && !self.stop_flag.load(Ordering::Relaxed)
+            && self
+                .stats
+                .past_hard_time(&self.limits, ROOT_PRUNING_TIME_FRACTION)
+    }
+
+    /// Whether a root search interrupted by the time limit can still return the root moves
+    /// it has finished, instead of the whole iteration being discarded
+    fn can_cut_root_short(&self, ply: usize, moves_searched: usize) -> bool {
+        ply == 1 && moves_searched > 0 && self.is_short_on_time()
+    }
+
+    /// Score of a repetition draw for the side to move at the root: when playing, the side
+    /// ahead in material shouldn't settle for a draw (and the side behind gladly takes one),
+    /// which matters less the fewer pieces are left to play for
+    fn contempt_draw_score(&self) -> i32 {
+        if self.options.analyse_mode {
+            return 0;
+        }
+
+        let side = self.game.side;
+        let balance = evaluate_material(self.game, side) - evaluate_material(self.game, !side);
+        let advantage = balance.clamp(-PAWN_VALUE, PAWN_VALUE);
+
+        -self.options.contempt * advantage * (GAME_PHASE_MAX - calculate_game_phase(self.game))
+            / (PAWN_VALUE * GAME_PHASE_MAX)
+    }
+
+    /// Whether nodes may be pruned by their static evaluation (reverse futility, razoring,
+    /// null move): a mate search has to see the quiet moves that don't win material too
+    fn static_pruning_enabled(&self) -> bool {
+        self.pruning_enabled && self.limits.mate.is_none()
+    }
+
+    /// Score of a repetition draw for the side to move
+    fn repetition_score(&self) -> i32 {
+        if self.game.side == self.root_side {
+            self.draw_score
+        } else {
+            -self.draw_score
         }
     }
 
     /// Run iterative deepening search
     pub fn run(&mut self) -> SearchResult {
-        let mut best_completed_result = SearchResult::leaf(0.0);
-        let mut previous_pv: Vec<BoardMove> = Vec::new();
+        let mut best_completed_result = SearchResult::leaf(0);
+        let mut previous_pv = std::mem::take(&mut self.initial_pv);
         let mut last_iteration_ms = 0u64;
+        let mut easy_iterations = 0;
 
         // If only one move is available, return it immediately
         let (count, moves) = self.game.get_moves();
 
-        if count == 1 && !self.limits.exact {
-            let best_move = moves[0];
-            let pv = vec![best_move];
+        if count == 1 && !self.limits.exact && self.limits.mate.is_none() {
+            let result = SearchResult::with_pv(moves[0], 0, Vec::new());
             if self.uci_info {
-                self.print_uci_info(1, 0.0, &pv);
+                self.print_uci_info(1, 0, &result.pv);
             }
 
-            return SearchResult {
-                best_move,
-                evaluation: 0.0,
-                pv,
-            };
+            return result;
         }
 
-        // Start new search generation
-        self.tt.new_search();
+        self.root_history_len = self.game.history.len();
+        self.root_side = self.game.side;
+        self.draw_score = self.contempt_draw_score();
+
+        // a mate in n moves is n attacker and n - 1 defender plies
+        let mate_plies = self.limits.mate.map(|moves| 2 * moves - 1);
+        if let Some(plies) = mate_plies
+            && self.options.mate_search == MateSearch::ProofNumber
+            && let Some(result) = self.prove_mate(plies)
+        {
+            self.stats.publish_nodes();
+            return result;
+        }
 
         for depth in 1..=self.limits.max_depth.unwrap_or(256) {
             // Check if we have enough time for this iteration (skip for first few depths)
@@ -100,29 +268,100 @@ impl<'a> Search<'a> {
 
             let iteration_start = Instant::now();
             self.stats.current_depth = depth as u64;
+            if self.stats.thread_index == 0 {
+                self.stats.thread_nodes.publish_depth(depth as u64);
+            }
+
+            // Only bother avoiding repetitions when clearly winning (by the last iteration)
+            let root_score = if best_completed_result.pv.is_empty() {
+                self.game.evaluate_stm()
+            } else {
+                best_completed_result.evaluation
+            };
+            self.avoiding_repetition =
+                self.options.avoid_repetition && root_score > AVOID_REPETITION_THRESHOLD;
 
             let result = if depth > 1 && !best_completed_result.pv.is_empty() {
-                self.aspiration_search(
+                self.aspiration_search(depth, best_completed_result.evaluation, &previous_pv)
+            } else {
+                self.alpha_beta(
                     depth,
-                    best_completed_result.evaluation,
+                    1,
+                    -INFINITE_SCORE,
+                    INFINITE_SCORE,
                     &previous_pv,
-                    best_completed_result.best_move,
+                    ExpectedNode::Pv,
                 )
-            } else {
-                self.alpha_beta(depth, 1, -f32::INFINITY, f32::INFINITY, &previous_pv)
             };
 
-            // Only accept the result if it's valid (not interrupted)
-            if result.is_valid() && !self.stats.should_stop(&self.limits, &self.stop_flag) {
-                if self.uci_info {
-                    self.print_uci_info(depth, result.evaluation, &result.pv);
+            // Only accept the result if the iteration wasn't interrupted; one completed just as
+            // the limits ran out (say, on the last node allowed) is as good as any other
+            let cut_short = result.is_partial();
+            if result.is_valid() {
+                // the MultiPV lines are reported once they're all searched, the best one first
+                let result = if self.options.multi_pv > 1 && !cut_short {
+                    self.search_other_lines(depth, result)
+                } else {
+                    if self.uci_info {
+                        self.print_uci_info(depth, result.evaluation, &result.pv);
+                    }
+                    self.lines = vec![result.clone()];
+                    result
+                };
+
+                if self.uci_info && self.options.show_refutations {
+                    self.print_refutations(result.best_move);
+                }
+
+                if self.is_easy_move(depth, &result, best_completed_result.best_move) {
+                    easy_iterations += 1;
+                } else {
+                    easy_iterations = 0;
+                }
+
+                if !best_completed_result.pv.is_empty() {
+                    self.extend_time_if_dropping(
+                        depth,
+                        best_completed_result.evaluation,
+                        result.evaluation,
+                    );
                 }
+
                 best_completed_result = result.clone();
+                if !cut_short {
+                    // an iteration cut short didn't search all of the root moves
+                    self.completed_depth = depth;
+                }
+                self.last_root_info = Some((result.best_move, result.evaluation));
                 previous_pv = result.pv;
                 last_iteration_ms = iteration_start.elapsed().as_millis() as u64;
 
-                // If we found a checkmate, stop searching deeper
-                if result.evaluation.abs() > CHECKMATE_SCORE - 1000.0 {
+                // No time left for another iteration, which would be cut short right away
+                if cut_short {
+                    if self.uci_info {
+                        println!(
+                            "info string Depth {} finished on the best root moves only",
+                            depth
+                        );
+                    }
+                    break;
+                }
+
+                // If we found a checkmate, stop searching deeper (unless there are other MultiPV
+                // lines to deepen)
+                if is_mate_score(result.evaluation) && self.options.multi_pv == 1 {
+                    break;
+                }
+
+                // A move clearly better than the rest doesn't need the full time
+                if easy_iterations >= EASY_MOVE_ITERATIONS
+                    && self
+                        .stats
+                        .past_soft_time(&self.limits, EASY_MOVE_TIME_FRACTION)
+                {
+                    if self.uci_info {
+                        println!("info string Easy move, stopping at depth {}", depth);
+                    }
                     break;
                 }
             } else {
@@ -134,72 +373,372 @@ impl<'a> Search<'a> {
             }
         }
 
+        // Proof-number search gets the rest of the time when alpha-beta didn't find the mate
+        if let Some(plies) = mate_plies
+            && self.options.mate_search == MateSearch::Fallback
+            && best_completed_result.evaluation < MATE_BOUND
+            && let Some(result) = self.prove_mate(plies)
+        {
+            best_completed_result = result;
+        }
+
         // Always return the best move from the last completed iteration
         if best_completed_result.best_move == BoardMove::empty() {
             // Emergency fallback: if we somehow have no completed iteration,
             // at least return the first legal move
             let (count, moves) = self.game.get_moves();
             if count > 0 {
-                best_completed_result = SearchResult {
-                    best_move: moves[0],
-                    evaluation: 0.0,
-                    pv: vec![moves[0]],
-                };
+                best_completed_result = SearchResult::with_pv(moves[0], 0, Vec::new());
             }
         }
 
+        if self.options.verify_best_move && self.completed_depth > 0 {
+            best_completed_result = self.verify_best_move(best_completed_result);
+        }
+
+        self.stats.publish_nodes();
+
         best_completed_result
     }
 
+    /// Give the search more time (up to its hard limit) when an iteration scored well below
+    /// the previous one, to look for something better before committing to a move that may be
+    /// losing. Only this search's limits are extended; a helper thread searching the other
+    /// root moves keeps its own.
+    pub(crate) fn extend_time_if_dropping(&mut self, depth: usize, previous: i32, score: i32) {
+        let (Some(max_time_ms), Some(extended_time_ms)) =
+            (self.limits.max_time_ms, self.limits.extended_time_ms)
+        else {
+            return;
+        };
+
+        if depth < SCORE_DROP_MIN_DEPTH
+            || previous - score < SCORE_DROP_MARGIN
+            || is_mate_score(previous)
+            || is_mate_score(score)
+            || !self.stats.can_end_early(&self.limits)
+        {
+            return;
+        }
+
+        let time_ms = ((max_time_ms as f32 * SCORE_DROP_TIME_FACTOR) as u64).min(extended_time_ms);
+        if time_ms <= max_time_ms {
+            return;
+        }
+
+        self.limits.max_time_ms = Some(time_ms);
+        if self.uci_info {
+            println!(
+                "info string Score dropped from {} to {} at depth {}, extending the search to {} ms",
+                previous, score, depth, time_ms
+            );
+        }
+    }
+
+    /// Prove a mate within the given plies by proof-number search, reporting its line like a
+    /// completed iteration of that depth
+    fn prove_mate(&mut self, plies: usize) -> Option<SearchResult> {
+        let pv = ProofNumberSearch::new(self.game, plies)
+            .with_root_moves(self.root_moves.clone())
+            .run(&mut self.stats, &self.limits, &self.stop_flag)?;
+
+        let evaluation = mate_in(pv.len() + 1);
+        if self.uci_info {
+            self.print_uci_info(pv.len(), evaluation, &pv);
+        }
+
+        self.completed_depth = pv.len();
+        Some(SearchResult::with_pv(pv[0], evaluation, pv[1..].to_vec()))
+    }
+
+    /// Search the next best root moves to the depth of the iteration for MultiPV, each line
+    /// among the root moves not in the lines before it, and report all the lines sorted by
+    /// their scores (which a line searched later can beat), returning the best one
+    fn search_other_lines(&mut self, depth: usize, best: SearchResult) -> SearchResult {
+        let candidates = match self.root_moves.is_empty() {
+            true => {
+                let (move_count, moves) = self.game.get_moves();
+                moves[..move_count].to_vec()
+            }
+            false => self.root_moves.clone(),
+        };
+
+        let root_moves = std::mem::take(&mut self.root_moves);
+        let previous_lines = std::mem::replace(&mut self.lines, vec![best]);
+
+        for pv_index in 1..self.options.multi_pv.min(candidates.len()) {
+            self.pv_index = pv_index;
+            self.root_moves = candidates
+                .iter()
+                .copied()
+                .filter(|&board_move| !self.lines.iter().any(|line| line.best_move == board_move))
+                .collect();
+
+            // ordered (and windowed) by the line of the same rank in the previous iteration
+            let result = match previous_lines.get(pv_index) {
+                Some(previous) => self.aspiration_search(depth, previous.evaluation, &previous.pv),
+                None => self.alpha_beta(
+                    depth,
+                    1,
+                    -INFINITE_SCORE,
+                    INFINITE_SCORE,
+                    &[],
+                    ExpectedNode::Pv,
+                ),
+            };
+
+            if !result.is_valid() {
+                break;
+            }
+
+            let cut_short = result.is_partial();
+            self.lines.push(result);
+            if cut_short {
+                break;
+            }
+        }
+
+        // stable, so that of equal scores the line searched first stays ahead
+        self.lines
+            .sort_by_key(|line| std::cmp::Reverse(line.evaluation));
+
+        if self.uci_info {
+            for pv_index in 0..self.lines.len() {
+                self.pv_index = pv_index;
+                let line = self.lines[pv_index].clone();
+                self.print_uci_info(depth, line.evaluation, &line.pv);
+            }
+        }
+
+        self.root_moves = root_moves;
+        self.pv_index = 0;
+        self.lines[0].clone()
+    }
+
+    /// Whether the best move of the iteration is an easy one: the same as in the previous
+    /// iteration and in the TT, and ahead of all other root moves by a large margin.
+    fn is_easy_move(
+        &mut self,
+        depth: usize,
+        result: &SearchResult,
+        previous_best: BoardMove,
+    ) -> bool {
+        self.second_best_score = None;
+
+        // only worth it (and only used) when the search could end before its time
+        if depth < EASY_MOVE_MIN_DEPTH
+            || result.best_move != previous_best
+            || !self.stats.can_end_early(&self.limits)
+            || is_mate_score(result.evaluation)
+        {
+            return false;
+        }
+
+        let tt_move = self
+            .tt
+            .probe(self.game.zobrist_key)
+            .map(|entry| entry.best_move);
+        if tt_move != Some(result.best_move) {
+            return false;
+        }
+
+        self.second_best_score = self.second_best_below(
+            depth / 2,
+            result.best_move,
+            result.evaluation - EASY_MOVE_MARGIN,
+        );
+        self.second_best_score.is_some()
+    }
+
+    /// Upper bound on the score of the best root move other than `best_move`, from reduced
+    /// depth null window searches against the threshold; None if some move reaches it.
+    fn second_best_below(
+        &mut self,
+        depth: usize,
+        best_move: BoardMove,
+        threshold: i32,
+    ) -> Option<i32> {
+        let (move_count, moves) = self.game.get_moves();
+        let mut second_best = -INFINITE_SCORE;
+
+        for &board_move in moves[0..move_count].iter().filter(|&&m| m != best_move) {
+            self.game.make_move(board_move);
+            self.history.push_position(self.game.zobrist_key);
+
+            let result =
+                self.alpha_beta(depth, 2, -threshold, -threshold + 1, &[], ExpectedNode::Cut);
+
+            self.history.pop_position();
+            self.game.unmake_move();
+
+            let value = -result.evaluation;
+            if !result.is_valid() || value >= threshold {
+                return None;
+            }
+
+            second_best = second_best.max(value);
+        }
+
+        Some(second_best)
+    }
+
+    /// Re-search the best move at a shallow depth without pruning or TT cutoffs, with a null
+    /// window below its score; if it fails low by far (a corrupted TT entry, a race between the
+    /// search threads), the best of the other root moves at that depth is played instead.
+    ///
+    /// Runs after the limits have run out, so it gets limits of its own (and a stop flag that
+    /// nothing sets); the depth is what keeps it short.
+    pub fn verify_best_move(&mut self, result: SearchResult) -> SearchResult {
+        if is_mate_score(result.evaluation) || self.limits.mate.is_some() {
+            return result;
+        }
+
+        let depth = VERIFY_BEST_MOVE_DEPTH.min(self.completed_depth);
+        let verify_limits = SearchLimits {
+            max_depth: Some(depth),
+            max_nodes: None,
+            max_time_ms: None,
+            extended_time_ms: None,
+            moves: vec![],
+            infinite: false,
+            exact: false,
+            mate: None,
+        };
+        let limits = std::mem::replace(&mut self.limits, verify_limits);
+        let stop_flag = std::mem::replace(&mut self.stop_flag, Arc::new(AtomicBool::new(false)));
+        let uci_info = std::mem::replace(&mut self.uci_info, false);
+
+        let threshold = result.evaluation - VERIFY_BEST_MOVE_MARGIN;
+
+        self.game.make_move(result.best_move);
+        self.history.push_position(self.game.zobrist_key);
+        let reply =
+            self.search_unpruned(depth - 1, 2, -threshold, -threshold + 1, ExpectedNode::Cut);
+        self.history.pop_position();
+        self.game.unmake_move();
+
+        let value = -reply.evaluation;
+        let mut verified = result.clone();
+
+        if value < threshold {
+            let candidates = match self.root_moves.is_empty() {
+                true => {
+                    let (move_count, moves) = self.game.get_moves();
+                    moves[..move_count].to_vec()
+                }
+                false => self.root_moves.clone(),
+            };
+            let others = candidates
+                .into_iter()
+                .filter(|&board_move| board_move != result.best_move)
+                .collect::<Vec<_>>();
+
+            if !others.is_empty() {
+                let root_moves = std::mem::replace(&mut self.root_moves, others);
+                let fallback = self.search_unpruned(
+                    depth,
+                    1,
+                    -INFINITE_SCORE,
+                    INFINITE_SCORE,
+                    ExpectedNode::Pv,
+                );
+                self.root_moves = root_moves;
+
+                if fallback.is_valid() && fallback.evaluation > value {
+                    verified = fallback;
+                }
+            }
+        }
+
+        self.limits = limits;
+        self.stop_flag = stop_flag;
+        self.uci_info = uci_info;
+
+        if self.uci_info && verified.best_move != result.best_move {
+            println!(
+                "info string Best move {} failed verification at depth {} (at most {} against {}), playing {} instead",
+                result.best_move.unparse(),
+                depth,
+                value,
+                result.evaluation,
+                verified.best_move.unparse()
+            );
+        }
+
+        verified
+    }
+
     /// Alpha-beta search with negamax
+    ///
+    /// The expected node type is predicted by the parent and used to steer reductions and
+    /// null move pruning; how well the predictions hold up is tracked in the search stats.
     fn alpha_beta(
         &mut self,
         depth: usize,
         ply: usize,
-        mut alpha: f32,
-        mut beta: f32,
+        mut alpha: i32,
+        mut beta: i32,
         previous_pv: &[BoardMove],
+        expected: ExpectedNode,
     ) -> SearchResult {
         self.stats.increment_nodes();
 
-        if self.stats.should_stop(&self.limits, &self.stop_flag) {
+        if self
+            .stats
+            .should_stop_at_node(&self.limits, &self.stop_flag)
+        {
             return SearchResult::interrupted();
         }
 
+        if ply == 1 {
+            self.root_moves_searched.clear();
+        }
+
+        if self.options.show_currline && self.uci_info && self.stats.nodes.is_multiple_of(4096) {
+            self.print_currline();
+        }
+
         // Threefold repetition checks (only for low depths since this one is costly)
         let zobrist_key = self.game.zobrist_key;
 
         if self.game.is_fifty_move_rule() {
-            return SearchResult::leaf(0.0);
+            return SearchResult::leaf(0);
+        }
+
+        // lost by a rule of the variant, scored like a mate (before anything prunes it away)
+        if self.game.variant.has_lost(self.game) {
+            return SearchResult::leaf(mated_in(ply));
         }
 
         if ply > 1 && ply <= 6 {
             if self.history.is_threefold_repetition(zobrist_key) {
-                return SearchResult::leaf(0.0);
+                return SearchResult::leaf(self.repetition_score());
             }
         }
 
         let original_alpha = alpha;
-        let is_pv_node = beta - alpha > 1.0; // PV nodes have open window
+        let is_pv_node = beta - alpha > 1; // PV nodes have open window
         let in_check = self.game.is_king_in_check(self.game.side);
 
         // Probe transposition table
         let mut tt_move = None;
-        if let Some(tt_entry) = self.tt.probe(zobrist_key) {
+        if let Some(tt_entry) = self.probe_tt(zobrist_key, ply) {
             tt_move = Some(tt_entry.best_move);
 
-            // Use TT value if depth is sufficient (but not in PV nodes for exact scores)
-            if tt_entry.depth >= depth as u8
+            // Use TT value if depth is sufficient (but not in PV nodes for exact scores);
+            // when verifying pruning, the stored values may come from pruned searches, and
+            // near the fifty-move rule the value depends on the halfmove clock
+            // a root restricted to some of the moves can't trust bounds on all of them
+            if self.pruning_enabled
+                && (ply > 1 || self.root_moves.is_empty())
+                && tt_entry.depth >= entry_depth(depth)
                 && (!is_pv_node || tt_entry.node_type != NodeType::Exact)
+                && tt_entry.is_valid_for_clock(self.game.get_halfmove_clock(), entry_depth(depth))
             {
                 match tt_entry.node_type {
                     NodeType::Exact => {
                         // Exact score - we can return immediately (only in non-PV nodes)
-                        return SearchResult::with_pv(
-                            tt_entry.best_move,
-                            tt_entry.evaluation,
-                            Vec::new(),
-                        );
+                        return tt_cutoff_result(&tt_entry);
                     }
                     NodeType::LowerBound => {
                         // Beta cutoff occurred, evaluation is a lower bound
@@ -213,62 +752,85 @@ impl<'a> Search<'a> {
 
                 // Check for alpha-beta cutoff after adjusting bounds
                 if alpha >= beta {
-                    return SearchResult::with_pv(
-                        tt_entry.best_move,
-                        tt_entry.evaluation,
-                        Vec::new(),
-                    );
+                    return tt_cutoff_result(&tt_entry);
                 }
             }
         }
 
         // Enter quiescence search to remove the horizon effect
         if depth == 0 {
-            return self.quiescence_search(ply, alpha, beta);
+            return self.quiescence_search(ply, 0, alpha, beta);
         }
 
         let static_eval = if !in_check {
-            self.game.evaluate() * self.game.side
+            self.game.evaluate_stm()
         } else {
-            -f32::INFINITY // Don't use static eval when in check
+            -INFINITE_SCORE // Don't use static eval when in check
         };
 
         // Reverse futility pruning (static eval pruning)
         // If our position is so good that even with a margin we're above beta, we can return
-        if !is_pv_node && !in_check && depth <= 3 && beta.abs() < CHECKMATE_SCORE - 1000.0 {
+        if self.static_pruning_enabled()
+            && !is_pv_node
+            && !in_check
+            && depth <= 3
+            && !is_mate_score(beta)
+        {
             let margin = reverse_futility_margin(depth);
             if static_eval - margin >= beta {
+                if self.should_verify_pruning() {
+                    let unpruned = self.search_unpruned(depth, ply, alpha, beta, expected);
+                    self.record_pruning(
+                        PruningKind::ReverseFutility,
+                        &unpruned,
+                        |value, tolerance| value < beta - tolerance,
+                    );
+                }
+
+                self.stats.node_types.record(expected, ExpectedNode::Cut);
                 return SearchResult::leaf(beta);
             }
         }
 
         // Razoring - drop into quiescence when evaluation is far below alpha at low depths
-        if !is_pv_node
+        if self.static_pruning_enabled()
+            && !is_pv_node
             && !in_check
             && depth <= 3
             && depth >= 1
-            && alpha.abs() < CHECKMATE_SCORE - 1000.0
+            && !is_mate_score(alpha)
         {
             let margin = razoring_margin(depth);
 
             if static_eval + margin < alpha {
                 // Do a quiescence search to verify the position is really bad
-                let q_result = self.quiescence_search(ply, alpha, beta);
+                let q_result = self.quiescence_search(ply, 0, alpha, beta);
 
                 // If quiescence confirms we're below alpha, return early
                 if q_result.evaluation < alpha {
+                    if self.should_verify_pruning() {
+                        let unpruned = self.search_unpruned(depth, ply, alpha, beta, expected);
+                        self.record_pruning(
+                            PruningKind::Razoring,
+                            &unpruned,
+                            |value, tolerance| value > alpha + tolerance,
+                        );
+                    }
+
                     return q_result;
                 }
             }
         }
 
-        // Null move pruning (skip in PV nodes)
+        // Null move pruning (skip in PV nodes and nodes expected to fail low)
         // Don't try null move if we're way below beta
         // Also don't do this in king and pawn endgames
-        if !is_pv_node
+        if self.static_pruning_enabled()
+            && !is_pv_node
+            && expected != ExpectedNode::All
             && depth >= NULL_MOVE_MIN_DEPTH
             && !in_check
-            && beta.abs() < CHECKMATE_SCORE - 1000.0
+            && !is_mate_score(beta)
             && static_eval >= beta
             && (self.game.color_bitboards[Color::White as usize]
                 | self.game.color_bitboards[Color::Black as usize])
@@ -282,25 +844,34 @@ impl<'a> Search<'a> {
                 depth.saturating_sub(1 + r),
                 ply + 1,
                 -beta,
-                -beta + 1.0, // Null window
+                -beta + 1, // Null window
                 &[],
+                ExpectedNode::All,
             );
 
             self.game.unmake_null_move();
 
             if -null_result.evaluation >= beta {
+                if self.should_verify_pruning() {
+                    let unpruned = self.search_unpruned(depth, ply, alpha, beta, expected);
+                    self.record_pruning(PruningKind::NullMove, &unpruned, |value, tolerance| {
+                        value < beta - tolerance
+                    });
+                }
+
+                self.stats.node_types.record(expected, ExpectedNode::Cut);
                 return SearchResult::leaf(beta); // Fail high
             }
         }
 
         // Check if futility pruning can be applied to this node
         let futility_pruning_enabled =
-            !is_pv_node && !in_check && depth <= 3 && alpha.abs() < CHECKMATE_SCORE - 1000.0;
+            self.pruning_enabled && !is_pv_node && !in_check && depth <= 3 && !is_mate_score(alpha);
 
         let fut_margin = if futility_pruning_enabled {
             futility_margin(depth)
         } else {
-            f32::INFINITY
+            INFINITE_SCORE
         };
 
         let can_prune_node = futility_pruning_enabled && static_eval + fut_margin <= alpha;
@@ -308,46 +879,86 @@ impl<'a> Search<'a> {
         let (move_count, mut moves) = self.game.get_moves();
 
         if move_count == 0 {
-            let eval = if in_check {
-                -CHECKMATE_SCORE + ply as f32
-            } else {
-                0.0
-            };
+            let eval = if in_check { mated_in(ply) } else { 0 };
 
             self.tt.store(
                 zobrist_key,
-                depth as u8,
-                eval,
+                entry_depth(depth),
+                score_to_tt(eval, ply),
                 BoardMove::empty(),
                 NodeType::Exact,
+                self.game.get_halfmove_clock(),
             );
 
             return SearchResult::leaf(eval);
         }
 
+        // History pruning: late quiet moves that keep failing (both in general and as replies
+        // to the previous move) are skipped at shallow depths
+        let history_pruning_enabled = self.pruning_enabled
+            && !is_pv_node
+            && !in_check
+            && depth <= HISTORY_PRUNING_DEPTH
+            && !is_mate_score(alpha);
+
+        let previous_move = self.previous_piece_move();
+        let counter_move =
+            previous_move.map(|previous| self.history.get_counter_move(previous, self.game.side));
+        let killers = self.killer_moves.get_killers(ply);
+
         let pv_move = previous_pv.get(0).copied();
-        self.order_moves(
-            &mut moves[0..move_count],
-            tt_move,
-            pv_move,
-            self.killer_moves.get_killers(ply),
-        );
+        if in_check {
+            self.order_evasions(&mut moves[0..move_count], tt_move, pv_move);
+        } else {
+            self.order_moves(
+                &mut moves[0..move_count],
+                tt_move,
+                pv_move,
+                killers,
+                counter_move,
+                previous_move,
+            );
+        }
 
         let mut best_move = BoardMove::empty();
-        let mut best_value = -f32::INFINITY;
+        let mut best_value = -INFINITE_SCORE;
         let mut best_pv = Vec::new();
         let mut moves_searched = 0;
         let mut quiet_moves_searched = 0;
+        let mut futility_pruned = false;
+        let mut history_pruned = false;
+        let mut root_interrupted = false;
 
         for (move_index, board_move) in moves[0..move_count].iter().enumerate() {
+            if self.is_excluded_root_move(ply, *board_move) {
+                continue;
+            }
+
+            // Root time pruning: close to the time limit, the moves searched so far are the
+            // best candidates, and the rest would only get the iteration discarded
+            if self.can_cut_root_short(ply, moves_searched) {
+                root_interrupted = true;
+                break;
+            }
+
             let is_capture = self.game.is_capture(*board_move);
             let is_promotion = board_move.get_promotion().is_some();
             let gives_check = self.game.is_check(*board_move);
 
             let is_quiet_move = !is_capture && !is_promotion && !gives_check;
+            let piece_move = self.piece_move(*board_move);
+
+            // Underpromotion pruning: at low depths only the queen promotion is worth searching,
+            // except for knight promotions giving check (which can mate where a queen can't)
+            if depth <= UNDERPROMOTION_PRUNING_DEPTH
+                && self.is_prunable_underpromotion(*board_move, gives_check)
+            {
+                continue;
+            }
 
             // Futility pruning: Skip quiet moves if position is hopeless
             if moves_searched > 0 && can_prune_node && is_quiet_move {
+                futility_pruned = true;
                 continue;
             }
 
@@ -355,18 +966,57 @@ impl<'a> Search<'a> {
             if futility_pruning_enabled && depth >= 2 && is_quiet_move && quiet_moves_searched >= 3
             {
                 // Use a more aggressive margin for individual move pruning
-                let move_fut_margin = fut_margin * EXT_FUTILITY_MULTIPLIER;
+                let move_fut_margin = (fut_margin as f32 * EXT_FUTILITY_MULTIPLIER) as i32;
                 if static_eval + move_fut_margin <= alpha {
                     quiet_moves_searched += 1;
+                    futility_pruned = true;
                     continue;
                 }
             }
 
+            // Killers and the countermove are exempt, since they did well in similar positions
+            if history_pruning_enabled
+                && is_quiet_move
+                && move_index >= LMR_MOVE_INDEX
+                && !killers.contains(board_move)
+                && counter_move != Some(*board_move)
+                && self.quiet_history_score(*board_move, previous_move)
+                    < -(HISTORY_PRUNING_MARGIN * depth as i32)
+            {
+                history_pruned = true;
+                continue;
+            }
+
             self.game.make_move(*board_move);
 
             let new_zobrist = self.game.zobrist_key;
             self.history.push_position(new_zobrist);
 
+            if ply == 1 {
+                self.root_moves_searched.push(*board_move);
+            }
+
+            // Walking back into an earlier position would be scored as a draw by the child,
+            // so when winning score it below that instead to prefer making progress
+            if ply == 1
+                && self.avoiding_repetition
+                && self.history.is_threefold_repetition(new_zobrist)
+            {
+                self.history.pop_position();
+                self.game.unmake_move();
+                moves_searched += 1;
+
+                let value = -AVOID_REPETITION_PENALTY;
+                if value > best_value {
+                    best_value = value;
+                    best_move = *board_move;
+                    best_pv = Vec::new();
+                }
+
+                alpha = alpha.max(best_value);
+                continue;
+            }
+
             // Pass the PV for the next ply
             let next_pv = if !previous_pv.is_empty() && *board_move == previous_pv[0] {
                 &previous_pv[1..]
@@ -375,16 +1025,22 @@ impl<'a> Search<'a> {
             };
 
             let mut value;
+            let child_expected = expected.child(moves_searched);
 
             // PVS: First move gets full window, others get null window first
             if moves_searched == 0 {
                 // Search the first move with full window
-                let result = self.alpha_beta(depth - 1, ply + 1, -beta, -alpha, next_pv);
+                let result =
+                    self.alpha_beta(depth - 1, ply + 1, -beta, -alpha, next_pv, child_expected);
                 value = -result.evaluation;
 
                 if !result.is_valid() {
                     self.history.pop_position();
                     self.game.unmake_move();
+                    if self.can_cut_root_short(ply, moves_searched) {
+                        root_interrupted = true;
+                        break;
+                    }
                     return SearchResult::interrupted();
                 }
 
@@ -404,6 +1060,12 @@ impl<'a> Search<'a> {
                     // More reduction for late moves and high depths
                     let mut reduction =
                         ((depth as f32).ln() * (move_index as f32).ln() / LMR_DIVISOR) as usize;
+
+                    // Reduce more where we expect a cutoff anyway
+                    if expected == ExpectedNode::Cut {
+                        reduction += 1;
+                    }
+
                     reduction = reduction.clamp(1, depth - 1);
 
                     // Reduce less in PV nodes (when window is wider)
@@ -413,12 +1075,22 @@ impl<'a> Search<'a> {
 
                     // Search with reduced depth first
                     let reduced_depth = depth.saturating_sub(1 + reduction);
-                    let reduced_result =
-                        self.alpha_beta(reduced_depth, ply + 1, -alpha - 1.0, -alpha, next_pv);
+                    let reduced_result = self.alpha_beta(
+                        reduced_depth,
+                        ply + 1,
+                        -alpha - 1,
+                        -alpha,
+                        next_pv,
+                        child_expected,
+                    );
 
                     if !reduced_result.is_valid() {
                         self.history.pop_position();
                         self.game.unmake_move();
+                        if self.can_cut_root_short(ply, moves_searched) {
+                            root_interrupted = true;
+                            break;
+                        }
                         return SearchResult::interrupted();
                     }
 
@@ -429,6 +1101,14 @@ impl<'a> Search<'a> {
                         // Penalize this move in history since it failed low
                         self.history
                             .add_history_penalty(*board_move, !self.game.side, depth);
+                        if let (Some(previous), Some(current)) = (previous_move, piece_move) {
+                            self.history.add_continuation_penalty(
+                                previous,
+                                current,
+                                !self.game.side,
+                                depth,
+                            );
+                        }
 
                         self.history.pop_position();
                         self.game.unmake_move();
@@ -442,12 +1122,22 @@ impl<'a> Search<'a> {
                 }
 
                 // PVS: Search with null window first
-                let null_window_result =
-                    self.alpha_beta(depth - 1, ply + 1, -alpha - 1.0, -alpha, next_pv);
+                let null_window_result = self.alpha_beta(
+                    depth - 1,
+                    ply + 1,
+                    -alpha - 1,
+                    -alpha,
+                    next_pv,
+                    child_expected,
+                );
 
                 if !null_window_result.is_valid() {
                     self.history.pop_position();
                     self.game.unmake_move();
+                    if self.can_cut_root_short(ply, moves_searched) {
+                        root_interrupted = true;
+                        break;
+                    }
                     return SearchResult::interrupted();
                 }
 
@@ -455,8 +1145,25 @@ impl<'a> Search<'a> {
 
                 // If the null window search fails high, re-search with full window
                 if value > alpha && value < beta {
-                    let full_window_result =
-                        self.alpha_beta(depth - 1, ply + 1, -beta, -alpha, next_pv);
+                    let full_window_result = self.alpha_beta(
+                        depth - 1,
+                        ply + 1,
+                        -beta,
+                        -alpha,
+                        next_pv,
+                        ExpectedNode::Pv,
+                    );
+
+                    if !full_window_result.is_valid() {
+                        self.history.pop_position();
+                        self.game.unmake_move();
+                        if self.can_cut_root_short(ply, moves_searched) {
+                            root_interrupted = true;
+                            break;
+                        }
+                        return SearchResult::interrupted();
+                    }
+
                     value = -full_window_result.evaluation;
 
                     if value > best_value {
@@ -479,12 +1186,25 @@ impl<'a> Search<'a> {
                 quiet_moves_searched += 1;
             }
 
+            // An exact new best root move during infinite analysis is reported right away,
+            // since a single iteration can take minutes at high depths
+            if ply == 1 && best_move == *board_move && best_value > alpha && best_value < beta {
+                self.report_root_change(depth, best_move, best_value, &best_pv);
+            }
+
             alpha = alpha.max(best_value);
             if alpha >= beta {
                 // This move caused a beta cutoff - it's a good move!
                 if !self.game.is_capture(*board_move) {
                     self.killer_moves.add_killer(ply, *board_move);
                     self.history.add_history(*board_move, self.game.side, depth);
+
+                    if let (Some(previous), Some(current)) = (previous_move, piece_move) {
+                        let side = self.game.side;
+                        self.history.set_counter_move(previous, side, *board_move);
+                        self.history
+                            .add_continuation_history(previous, current, side, depth);
+                    }
                 }
                 break;
             } else if value <= original_alpha {
@@ -492,6 +1212,37 @@ impl<'a> Search<'a> {
                 if !self.game.is_capture(*board_move) {
                     self.history
                         .add_history_penalty(*board_move, self.game.side, depth);
+
+                    if let (Some(previous), Some(current)) = (previous_move, piece_move) {
+                        self.history.add_continuation_penalty(
+                            previous,
+                            current,
+                            self.game.side,
+                            depth,
+                        );
+                    }
+                }
+            }
+        }
+
+        // Only an exact value of the root moves finished before the interruption is usable
+        if root_interrupted && (best_value <= original_alpha || best_value >= beta) {
+            return SearchResult::interrupted();
+        }
+
+        // The skipped quiet moves could only have raised the value of the node
+        if (futility_pruned || history_pruned) && self.should_verify_pruning() {
+            let unpruned = self.search_unpruned(depth, ply, original_alpha, beta, expected);
+            let pruned_value = best_value.max(original_alpha);
+
+            for (pruned, kind) in [
+                (futility_pruned, PruningKind::Futility),
+                (history_pruned, PruningKind::History),
+            ] {
+                if pruned {
+                    self.record_pruning(kind, &unpruned, |value, tolerance| {
+                        value > pruned_value + tolerance
+                    });
                 }
             }
         }
@@ -504,15 +1255,36 @@ impl<'a> Search<'a> {
             NodeType::Exact // Exact value
         };
 
-        self.tt
-            .store(zobrist_key, depth as u8, best_value, best_move, node_type);
+        // the value of a restricted (or cut short) root doesn't hold for the position itself
+        if ply > 1 || (self.root_moves.is_empty() && !root_interrupted) {
+            self.tt.store(
+                zobrist_key,
+                entry_depth(depth),
+                score_to_tt(best_value, ply),
+                best_move,
+                node_type,
+                self.game.get_halfmove_clock(),
+            );
+        }
+
+        let actual = match node_type {
+            NodeType::Exact => ExpectedNode::Pv,
+            NodeType::LowerBound => ExpectedNode::Cut,
+            NodeType::UpperBound => ExpectedNode::All,
+        };
+        self.stats.node_types.record(expected, actual);
 
         // Don't include empty PV moves
-        if best_move == BoardMove::empty() {
+        let result = if best_move == BoardMove::empty() {
             // If no move was selected (all pruned or failed), return leaf evaluation
             SearchResult::leaf(best_value)
         } else {
             SearchResult::with_pv(best_move, best_value, best_pv)
+        };
+
+        match root_interrupted {
+            true => result.partial(),
+            false => result,
         }
     }
 
@@ -520,24 +1292,40 @@ impl<'a> Search<'a> {
     fn aspiration_search(
         &mut self,
         depth: usize,
-        previous_score: f32,
+        previous_score: i32,
         previous_pv: &[BoardMove],
-        previous_best_move: BoardMove,
     ) -> SearchResult {
         // Don't use aspiration windows for checkmate scores
-        if previous_score.abs() > CHECKMATE_SCORE - 1000.0 {
-            return self.alpha_beta(depth, 1, -f32::INFINITY, f32::INFINITY, previous_pv);
+        if is_mate_score(previous_score) {
+            return self.alpha_beta(
+                depth,
+                1,
+                -INFINITE_SCORE,
+                INFINITE_SCORE,
+                previous_pv,
+                ExpectedNode::Pv,
+            );
         }
 
         // Skip aspiration windows for low depths (<=4)
         if depth <= 4 {
-            return self.alpha_beta(depth, 1, -f32::INFINITY, f32::INFINITY, previous_pv);
+            return self.alpha_beta(
+                depth,
+                1,
+                -INFINITE_SCORE,
+                INFINITE_SCORE,
+                previous_pv,
+                ExpectedNode::Pv,
+            );
         }
 
-        // Exponential narrowing: starting at initial and approaching min at higher depths
-        let initial_window = (ASPIRATION_INITIAL
-            * (ASPIRATION_MIN / ASPIRATION_INITIAL).powf((depth as f32 - 4.0) / 10.0))
-        .max(ASPIRATION_MIN);
+        // Exponential narrowing: starting at initial and approaching min at higher depths,
+        // rounded to whole centipawns
+        let (initial, min) = (ASPIRATION_INITIAL as f32, ASPIRATION_MIN as f32);
+        let initial_window = ((initial * (min / initial).powf((depth as f32 - 4.0) / 10.0))
+            .max(min)
+            * self.aspiration_scale)
+            .round() as i32;
 
         let mut alpha = previous_score - initial_window;
         let mut beta = previous_score + initial_window;
@@ -545,44 +1333,26 @@ impl<'a> Search<'a> {
         let mut fail_high_count = 0;
         let mut fail_low_count = 0;
 
-        loop {
-            let result = self.alpha_beta(depth, 1, alpha, beta, previous_pv);
+        self.stats.aspiration.searches += 1;
 
-            // If search was interrupted, return the previous best move
-            if !result.is_valid() {
-                return SearchResult {
-                    best_move: previous_best_move,
-                    evaluation: previous_score,
-                    pv: previous_pv.to_vec(),
-                };
-            }
+        loop {
+            let result = self.alpha_beta(depth, 1, alpha, beta, previous_pv, ExpectedNode::Pv);
 
-            // If search was interrupted and returned empty move, fall back to previous result
-            if result.best_move == BoardMove::empty() && previous_best_move != BoardMove::empty() {
-                return SearchResult {
-                    best_move: previous_best_move,
-                    evaluation: previous_score,
-                    pv: previous_pv.to_vec(),
-                };
+            // A result within the window is exact (as is a partial one, which only is when so);
+            // an interrupted one is passed on for the caller to keep the previous iteration
+            if !result.is_valid() || (result.evaluation > alpha && result.evaluation < beta) {
+                return result;
             }
 
-            // Check if we should stop before continuing
+            // A bound is no use as the result of the iteration, and there's no time to resolve it
             if self.stats.should_stop(&self.limits, &self.stop_flag) {
-                // Return the last valid result we have
-                if result.best_move != BoardMove::empty() {
-                    return result;
-                } else {
-                    return SearchResult {
-                        best_move: previous_best_move,
-                        evaluation: previous_score,
-                        pv: previous_pv.to_vec(),
-                    };
-                }
+                return SearchResult::interrupted();
             }
 
             if result.evaluation <= alpha {
                 fail_low_count += 1;
                 fail_high_count = 0;
+                self.stats.aspiration.fail_lows += 1;
 
                 if self.uci_info {
                     println!(
@@ -595,27 +1365,22 @@ impl<'a> Search<'a> {
                     if self.uci_info {
                         println!("info string Second fail low, switching to full window search");
                     }
-                    let fallback_result =
-                        self.alpha_beta(depth, 1, -f32::INFINITY, f32::INFINITY, previous_pv);
-
-                    if fallback_result.best_move == BoardMove::empty()
-                        && previous_best_move != BoardMove::empty()
-                    {
-                        return SearchResult {
-                            best_move: previous_best_move,
-                            evaluation: previous_score,
-                            pv: previous_pv.to_vec(),
-                        };
-                    }
-
-                    return fallback_result;
+                    return self.alpha_beta(
+                        depth,
+                        1,
+                        -INFINITE_SCORE,
+                        INFINITE_SCORE,
+                        previous_pv,
+                        ExpectedNode::Pv,
+                    );
                 }
 
                 let delta = previous_score - alpha;
-                alpha = previous_score - delta * ASPIRATION_EXPAND;
-            } else if result.evaluation >= beta {
+                alpha = previous_score - (delta as f32 * ASPIRATION_EXPAND) as i32;
+            } else {
                 fail_high_count += 1;
                 fail_low_count = 0;
+                self.stats.aspiration.fail_highs += 1;
 
                 if self.uci_info {
                     println!(
@@ -628,49 +1393,120 @@ impl<'a> Search<'a> {
                     if self.uci_info {
                         println!("info string Second fail high, switching to full window search");
                     }
-                    let fallback_result =
-                        self.alpha_beta(depth, 1, -f32::INFINITY, f32::INFINITY, previous_pv);
-
-                    if fallback_result.best_move == BoardMove::empty()
-                        && previous_best_move != BoardMove::empty()
-                    {
-                        return SearchResult {
-                            best_move: previous_best_move,
-                            evaluation: previous_score,
-                            pv: previous_pv.to_vec(),
-                        };
-                    }
-
-                    return fallback_result;
+                    return self.alpha_beta(
+                        depth,
+                        1,
+                        -INFINITE_SCORE,
+                        INFINITE_SCORE,
+                        previous_pv,
+                        ExpectedNode::Pv,
+                    );
                 }
 
                 let delta = beta - previous_score;
-                beta = previous_score + delta * ASPIRATION_EXPAND;
-            } else {
-                return result;
+                beta = previous_score + (delta as f32 * ASPIRATION_EXPAND) as i32;
             }
         }
     }
 
-    /// Quiescence search for tactical moves
-    fn quiescence_search(&mut self, ply: usize, mut alpha: f32, beta: f32) -> SearchResult {
-        self.stats.increment_nodes();
+    /// Quiescence search for tactical moves, `qs_ply` plies into the quiescence search
+    fn quiescence_search(
+        &mut self,
+        ply: usize,
+        qs_ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> SearchResult {
+        self.stats.increment_quiescence_nodes();
 
-        if self.stats.should_stop(&self.limits, &self.stop_flag) {
+        if self
+            .stats
+            .should_stop_at_node(&self.limits, &self.stop_flag)
+        {
             return SearchResult::interrupted();
         }
 
+        if self.game.variant.has_lost(self.game) {
+            return SearchResult::leaf(mated_in(ply));
+        }
+
         // Limit quiescence search depth to prevent explosion
-        const MAX_QUIESCENCE_PLY: usize = 32;
-        if ply > MAX_QUIESCENCE_PLY {
-            return SearchResult::leaf(self.game.evaluate() * self.game.side);
+        if qs_ply >= self.options.quiescence_depth {
+            return SearchResult::leaf(self.game.evaluate_stm());
         }
 
-        let stand_pat = self.game.evaluate() * self.game.side;
+        let zobrist_key = self.game.zobrist_key;
+        let original_alpha = alpha;
+        let searches_checks = qs_ply < self.options.quiescence_check_plies;
+        let tt_depth = match searches_checks {
+            true => DEPTH_QS_CHECKS,
+            false => DEPTH_QS_NO_CHECKS,
+        };
+
+        // The same capture sequences are reached in many orders, so their values are
+        // looked up like in the main search (an entry of any deeper search will do)
+        let tt_entry = self.probe_tt(zobrist_key, ply);
+        let mut tt_move = None;
+        if let Some(tt_entry) = tt_entry {
+            tt_move = Some(tt_entry.best_move);
+
+            let is_pv_node = beta - alpha > 1;
+            if self.pruning_enabled
+                && tt_entry.depth >= tt_depth
+                && (!is_pv_node || tt_entry.node_type != NodeType::Exact)
+                && tt_entry.is_valid_for_clock(self.game.get_halfmove_clock(), tt_depth)
+            {
+                let cutoff = match tt_entry.node_type {
+                    NodeType::Exact => true,
+                    NodeType::LowerBound => tt_entry.evaluation >= beta,
+                    NodeType::UpperBound => tt_entry.evaluation <= alpha,
+                };
+
+                if cutoff {
+                    return SearchResult::leaf(tt_entry.evaluation);
+                }
+            }
+        }
+
+        // Positions reached again aren't evaluated again, their evaluation is stored too
+        let static_eval = tt_entry
+            .and_then(|tt_entry| tt_entry.static_eval)
+            .unwrap_or_else(|| self.game.evaluate_stm());
+
+        // A stored value bounding the evaluation more tightly than it is a better guess at
+        // what standing pat is worth
+        let stand_pat = match tt_entry {
+            Some(tt_entry)
+                if self.pruning_enabled
+                    && tt_entry.is_valid_for_clock(self.game.get_halfmove_clock(), tt_depth) =>
+            {
+                match tt_entry.node_type {
+                    NodeType::Exact => tt_entry.evaluation,
+                    NodeType::LowerBound => static_eval.max(tt_entry.evaluation),
+                    NodeType::UpperBound => static_eval.min(tt_entry.evaluation),
+                }
+            }
+            _ => static_eval,
+        };
+
+        // Getting mated can't be stood pat on, so a check without a way out ends the line here
+        // (a mate on the last ply of the main search would go unseen otherwise)
+        let in_check = self.game.is_king_in_check(self.game.side);
+        if in_check && self.game.get_moves().0 == 0 {
+            return SearchResult::leaf(mated_in(ply));
+        }
 
         // If we're already doing well enough to cause a beta cutoff, we can return
         if stand_pat >= beta {
-            return SearchResult::leaf(beta);
+            self.store_quiescence(
+                tt_depth,
+                ply,
+                stand_pat,
+                BoardMove::empty(),
+                NodeType::LowerBound,
+                static_eval,
+            );
+            return SearchResult::leaf(stand_pat);
         }
 
         // Update alpha with standing pat score
@@ -681,13 +1517,9 @@ impl<'a> Search<'a> {
         // Get all moves
         let (move_count, moves) = self.game.get_moves();
 
-        // If no moves available, check for checkmate or stalemate
+        // No moves left is a stalemate here, as a mate was caught above
         if move_count == 0 {
-            if self.game.is_king_in_check(self.game.side) {
-                return SearchResult::leaf(-CHECKMATE_SCORE + ply as f32);
-            } else {
-                return SearchResult::leaf(0.0);
-            }
+            return SearchResult::leaf(0);
         }
 
         let game_phase = calculate_game_phase(self.game);
@@ -697,20 +1529,28 @@ impl<'a> Search<'a> {
         for i in 0..move_count {
             let board_move = moves[i];
 
+            // Underpromotions are skipped entirely, except for checking knight promotions
+            let knight_check =
+                board_move.get_promotion() == Some(Piece::Knight) && self.game.is_check(board_move);
+            if self.is_prunable_underpromotion(board_move, knight_check) {
+                continue;
+            }
+
             // SEE pruning: skip captures that lose material
             // Don't apply to checks since they might have tactical value
             if self.game.is_capture(board_move) {
-                let see_value = self.game.see(board_move.get_to());
-                if see_value < 0.0 {
+                if self.game.see_move(board_move) < 0 {
                     continue;
                 }
             }
 
-            // Only extend checks for the first ply, since the check is super expensive
-            if self.game.is_capture(board_move) || (ply <= 1 && self.game.is_check(board_move)) {
+            // Only extend checks for the first few plies, since the check is super expensive
+            if self.game.is_capture(board_move)
+                || (qs_ply < self.options.quiescence_check_plies && self.game.is_check(board_move))
+            {
                 // Apply delta pruning for captures only (not for checks)
                 // Don't do this for endgames though since we might miss stuff
-                if game_phase < 0.7 && self.game.is_capture(board_move) {
+                if game_phase < GAME_PHASE_MAX * 7 / 10 && self.game.is_capture(board_move) {
                     let max_gain = self.calculate_delta_margin(&board_move);
 
                     // Delta pruning: if even the best possible outcome can't improve alpha,
@@ -726,12 +1566,25 @@ impl<'a> Search<'a> {
 
         // If no captures/checks available, return the standing pat evaluation
         if capture_moves.is_empty() {
+            let node_type = match stand_pat > original_alpha {
+                true => NodeType::Exact,
+                false => NodeType::UpperBound,
+            };
+            self.store_quiescence(
+                tt_depth,
+                ply,
+                stand_pat,
+                BoardMove::empty(),
+                node_type,
+                static_eval,
+            );
             return SearchResult::leaf(stand_pat);
         }
 
+        // the move that was best before goes first, then the most valuable victims
         capture_moves.sort_unstable_by(|a, b| {
-            let score_a = self.mvv_lva_score(a);
-            let score_b = self.mvv_lva_score(b);
+            let score_a = (tt_move == Some(*a), self.mvv_lva_score(a));
+            let score_b = (tt_move == Some(*b), self.mvv_lva_score(b));
             score_b.cmp(&score_a)
         });
 
@@ -742,7 +1595,7 @@ impl<'a> Search<'a> {
         for board_move in capture_moves.iter() {
             self.game.make_move(*board_move);
 
-            let result = self.quiescence_search(ply + 1, -beta, -alpha);
+            let result = self.quiescence_search(ply + 1, qs_ply + 1, -beta, -alpha);
 
             if !result.is_valid() {
                 self.game.unmake_move();
@@ -765,6 +1618,15 @@ impl<'a> Search<'a> {
             }
         }
 
+        let node_type = if best_value >= beta {
+            NodeType::LowerBound
+        } else if best_value > original_alpha {
+            NodeType::Exact
+        } else {
+            NodeType::UpperBound
+        };
+        self.store_quiescence(tt_depth, ply, best_value, best_move, node_type, static_eval);
+
         // Return the best result found
         if best_move == BoardMove::empty() {
             SearchResult::leaf(best_value)
@@ -773,6 +1635,104 @@ impl<'a> Search<'a> {
         }
     }
 
+    /// Probe the transposition table, with the entry's mate scores counted from the root
+    fn probe_tt(&self, zobrist_key: u64, ply: usize) -> Option<TTEntry> {
+        self.tt.probe(zobrist_key).map(|entry| TTEntry {
+            evaluation: score_from_tt(entry.evaluation, ply),
+            ..entry
+        })
+    }
+
+    fn store_quiescence(
+        &self,
+        tt_depth: i8,
+        ply: usize,
+        value: i32,
+        best_move: BoardMove,
+        node_type: NodeType,
+        static_eval: i32,
+    ) {
+        self.tt.store_entry(TTEntry {
+            key: self.game.zobrist_key,
+            depth: tt_depth,
+            evaluation: score_to_tt(value, ply),
+            best_move,
+            node_type,
+            halfmove_clock: self.game.get_halfmove_clock(),
+            static_eval: Some(static_eval),
+            ..Default::default()
+        });
+    }
+
+    /// Whether to check this pruning decision against an unpruned search (verify-pruning mode)
+    fn should_verify_pruning(&self) -> bool {
+        self.pruning_enabled
+            && self.options.verify_pruning_rate > 0.0
+            && rand::random_bool(self.options.verify_pruning_rate.min(1.0))
+    }
+
+    /// Search a node again with all pruning (and TT cutoffs) disabled in its subtree
+    fn search_unpruned(
+        &mut self,
+        depth: usize,
+        ply: usize,
+        alpha: i32,
+        beta: i32,
+        expected: ExpectedNode,
+    ) -> SearchResult {
+        self.pruning_enabled = false;
+        let result = self.alpha_beta(depth, ply, alpha, beta, &[], expected);
+        self.pruning_enabled = true;
+        result
+    }
+
+    /// Record whether the unpruned value ended up where the pruning decision said it wouldn't
+    fn record_pruning(
+        &mut self,
+        kind: PruningKind,
+        unpruned: &SearchResult,
+        changed: impl Fn(i32, i32) -> bool,
+    ) {
+        if unpruned.is_valid() {
+            let tolerance = self.options.verify_pruning_tolerance;
+            self.stats
+                .pruning
+                .record(kind, changed(unpruned.evaluation, tolerance));
+        }
+    }
+
+    /// The previous move (by the opponent) with the piece that made it, if it wasn't a null move
+    fn previous_piece_move(&self) -> Option<PieceMove> {
+        let &(board_move, ..) = self.game.history.last()?;
+        if board_move == BoardMove::empty() {
+            return None;
+        }
+
+        let (piece, _) = self.game.pieces.get(board_move.get_to())?;
+        Some((piece, board_move))
+    }
+
+    /// A move of the side to move with the piece making it
+    fn piece_move(&self, board_move: BoardMove) -> Option<PieceMove> {
+        self.game
+            .pieces
+            .get(board_move.get_from())
+            .map(|(piece, _)| (piece, board_move))
+    }
+
+    /// History score of a quiet move, combined with how well it did as a reply to the previous move
+    fn quiet_history_score(&self, board_move: BoardMove, previous: Option<PieceMove>) -> i32 {
+        let mut score = self.history.get_history_score(&board_move, self.game.side);
+
+        if let (Some(previous), Some(current)) = (previous, self.piece_move(board_move)) {
+            score += self
+                .history
+                .get_continuation_score(previous, current, self.game.side);
+        }
+
+        score
+    }
+
     /// Order moves using various heuristics
     fn order_moves(
         &self,
@@ -780,16 +1740,18 @@ impl<'a> Search<'a> {
         tt_move: Option<BoardMove>,
         pv_move: Option<BoardMove>,
         killer_moves: [BoardMove; 2],
+        counter_move: Option<BoardMove>,
+        previous_move: Option<PieceMove>,
     ) {
-        moves.sort_unstable_by_key(|&mv| {
+        let mating_attacker = mating_ending_attacker(self.game);
+
+        sort_moves_by_score(moves, |mv| {
             if Some(mv) == pv_move {
                 -1_000_000
             } else if Some(mv) == tt_move {
                 -900_000
             } else if self.game.is_capture(mv) {
-                let see = self.game.see_sign(mv.get_to());
-
-                if see > 0 {
+                if self.game.see_move(mv) >= 0 {
                     -800_000 - self.mvv_lva_score(&mv)
                 } else {
                     -400_000 - self.mvv_lva_score(&mv)
@@ -798,32 +1760,92 @@ impl<'a> Search<'a> {
                 -700_000
             } else if mv == killer_moves[1] {
                 -600_000
+            } else if Some(mv) == counter_move {
+                -550_000
+            } else if let Some(attacker) = mating_attacker {
+                -500_000
+                    - mating_ending_move_score(self.game, mv, attacker)
+                    - self.quiet_history_score(mv, previous_move)
+            } else {
+                -500_000 - self.quiet_history_score(mv, previous_move)
+            }
+        });
+    }
+
+    /// Order check evasions: killers are about quiet moves in unrelated positions, so instead
+    /// captures of the checker come first (by SEE), then other captures (only the king can make
+    /// them), blocks (by history) and king moves last
+    pub(crate) fn order_evasions(
+        &self,
+        moves: &mut [BoardMove],
+        tt_move: Option<BoardMove>,
+        pv_move: Option<BoardMove>,
+    ) {
+        let checkers = self.game.get_checkers(self.game.side);
+
+        sort_moves_by_score(moves, |mv| {
+            if Some(mv) == pv_move {
+                -1_000_000
+            } else if Some(mv) == tt_move {
+                -900_000
+            } else if self.game.is_capture(mv) {
+                // en passant takes the pawn next to the moving one, not the one on the target
+                let captured = match mv.get_kind() {
+                    MoveKind::EnPassant => {
+                        BoardSquare::from_position(mv.get_to().get_x(), mv.get_from().get_y())
+                    }
+                    _ => mv.get_to(),
+                };
+
+                if checkers.is_set(captured) {
+                    -800_000 - self.game.see_move(mv)
+                } else {
+                    -600_000 - self.game.see_move(mv)
+                }
+            } else if self
+                .game
+                .pieces
+                .get(mv.get_from())
+                .is_some_and(|(piece, _)| piece == Piece::King)
+            {
+                -300_000 - self.history.get_history_score(&mv, self.game.side)
             } else {
                 -500_000 - self.history.get_history_score(&mv, self.game.side)
             }
         });
     }
 
+    /// Whether a move is an underpromotion that underpromotion pruning would skip
+    fn is_prunable_underpromotion(&self, board_move: BoardMove, gives_check: bool) -> bool {
+        if !self.options.underpromotion_pruning {
+            return false;
+        }
+
+        match board_move.get_promotion() {
+            Some(Piece::Queen) | None => false,
+            Some(Piece::Knight) => !gives_check,
+            Some(_) => true,
+        }
+    }
+
     /// Calculate MVV-LVA score for move ordering
     fn mvv_lva_score(&self, board_move: &BoardMove) -> i32 {
-        if let Some((victim_piece, _victim_color)) = self.game.pieces[board_move.get_to() as usize]
-        {
+        if let Some((victim_piece, _victim_color)) = self.game.pieces.get(board_move.get_to()) {
             if let Some((attacker_piece, _attacker_color)) =
-                self.game.pieces[board_move.get_from() as usize]
+                self.game.pieces.get(board_move.get_from())
             {
                 let victim_value = get_piece_value(victim_piece);
                 let attacker_value = get_piece_value(attacker_piece);
 
                 // Special case for king captures (since KING_VALUE is 0 in evaluate.rs)
                 let victim_score = if victim_piece == Piece::King {
-                    10000.0 // Very high value for capturing a king
+                    10000 // Very high value for capturing a king
                 } else {
                     victim_value
                 };
 
                 // MVV-LVA score: victim value * 100 - attacker value
-                // Convert to i32 for sorting
-                return (victim_score * 100.0 - attacker_value) as i32;
+                return victim_score * 100 - attacker_value;
             }
         }
 
@@ -831,24 +1853,22 @@ impl<'a> Search<'a> {
     }
 
     /// Calculate maximum possible gain from a capture move
-    fn calculate_delta_margin(&self, board_move: &BoardMove) -> f32 {
-        let mut max_gain = 0.0;
+    fn calculate_delta_margin(&self, board_move: &BoardMove) -> i32 {
+        let mut max_gain = 0;
 
         // Add value of captured piece
-        if let Some((victim_piece, _victim_color)) = self.game.pieces[board_move.get_to() as usize]
-        {
+        if let Some((victim_piece, _victim_color)) = self.game.pieces.get(board_move.get_to()) {
             max_gain += if victim_piece == Piece::King {
-                10000.0
+                10000
             } else {
                 get_piece_value(victim_piece)
             };
         }
 
-        if let Some((attacker_piece, _attacker_color)) =
-            self.game.pieces[board_move.get_from() as usize]
+        if let Some((attacker_piece, _attacker_color)) = self.game.pieces.get(board_move.get_from())
         {
             if attacker_piece == Piece::Pawn {
-                let to_rank = board_move.get_to() / 8;
+                let to_rank = board_move.get_to().get_y();
                 if to_rank == 0 || to_rank == 7 {
                     max_gain += QUEEN_VALUE - get_piece_value(Piece::Pawn);
                 }
@@ -858,38 +1878,113 @@ impl<'a> Search<'a> {
         max_gain
     }
 
-    /// Print UCI info string with search statistics
-    fn print_uci_info(&mut self, depth: usize, mut score: f32, pv: &[BoardMove]) {
-        let mut info = format!("info depth {}", depth);
+    /// Print the best reply (from the TT) to each root move that lost to the best move
+    fn print_refutations(&mut self, best_move: BoardMove) {
+        for board_move in self.root_moves_searched.clone() {
+            if board_move == best_move {
+                continue;
+            }
 
-        // Convert score to white's perspective for UCI output
-        score = score * self.game.side;
+            self.game.make_move(board_move);
 
-        // Check if this is a checkmate score
-        if score.abs() > CHECKMATE_SCORE - 1000.0 {
-            // Calculate moves to mate (converting from plies to moves)
-            let plies_to_mate = (CHECKMATE_SCORE - score.abs()) as i32;
+            let (move_count, moves) = self.game.get_moves();
+            let reply = self
+                .tt
+                .probe(self.game.zobrist_key)
+                .map(|entry| entry.best_move)
+                .filter(|reply| moves[0..move_count].contains(reply));
 
-            // Convert plies to moves (round up)
-            let moves_to_mate = (plies_to_mate + 1) / 2;
+            self.game.unmake_move();
 
-            // Handle the special case of already being in checkmate
-            if moves_to_mate == 0 {
-                info.push_str(" score mate 0");
-            } else if score > 0.0 {
-                // We're winning - delivering checkmate
-                info.push_str(&format!(" score mate {}", moves_to_mate));
-            } else {
-                // We're losing - being checkmated
-                info.push_str(&format!(" score mate -{}", moves_to_mate));
+            match reply {
+                Some(reply) => println!(
+                    "info refutation {} {}",
+                    board_move.unparse(),
+                    reply.unparse()
+                ),
+                None => println!("info refutation {}", board_move.unparse()),
+            }
+        }
+    }
+
+    /// Print the current root line if its move or score differs materially from the
+    /// last one reported (only in infinite mode, which has no natural reporting points).
+    fn report_root_change(
+        &mut self,
+        depth: usize,
+        best_move: BoardMove,
+        score: i32,
+        best_pv: &[BoardMove],
+    ) {
+        // only the best line is followed, the others are reported once they're done
+        if !self.uci_info || !self.limits.infinite || self.pv_index > 0 {
+            return;
+        }
+
+        let changed = match self.last_root_info {
+            Some((last_move, last_score)) => {
+                last_move != best_move || (score - last_score).abs() >= LIVE_INFO_MARGIN
             }
+            None => false, // the first iterations finish quickly anyway
+        };
+
+        if changed {
+            self.last_root_info = Some((best_move, score));
+
+            let mut pv = vec![best_move];
+            pv.extend_from_slice(best_pv);
+            self.print_uci_info(depth, score, &pv);
+        }
+    }
+
+    /// Print the line currently being searched, at most once a second
+    fn print_currline(&mut self) {
+        let elapsed = self.stats.get_elapsed_ms();
+        if elapsed < self.last_currline_ms + 1000 {
+            return;
+        }
+        self.last_currline_ms = elapsed;
+
+        let line = self.game.history[self.root_history_len..]
+            .iter()
+            .map(|&(board_move, ..)| match board_move == BoardMove::empty() {
+                true => "0000".to_string(), // null move
+                false => board_move.unparse(),
+            })
+            .collect::<Vec<_>>();
+
+        println!("info currline {}", line.join(" "));
+    }
+
+    /// Print UCI info string with search statistics
+    pub fn print_uci_info(&mut self, depth: usize, score: i32, pv: &[BoardMove]) {
+        if self.options.thinking_format == ThinkingFormat::XBoard {
+            self.print_xboard_thinking(depth, score, pv);
+            return;
+        }
+
+        let mut info = format!("info depth {}", depth);
+
+        if self.options.multi_pv > 1 {
+            info.push_str(&format!(" multipv {}", self.pv_index + 1));
+        }
+
+        // UCI scores are from the engine's (side to move's) point of view, which is
+        // exactly what negamax returns at the root, so no conversion is needed
+
+        if is_mate_score(score) {
+            info.push_str(&format!(" score mate {}", moves_to_mate(score)));
         } else {
-            // Regular centipawn score
-            info.push_str(&format!(" score cp {}", score as i32));
+            info.push_str(&format!(" score cp {}", score));
+        }
+
+        if self.options.show_wdl {
+            let (win, draw, loss) = WdlModel::default().permille(score, self.game);
+            info.push_str(&format!(" wdl {} {} {}", win, draw, loss));
         }
 
         // Add nodes
-        info.push_str(&format!(" nodes {}", self.stats.nodes));
+        info.push_str(&format!(" nodes {}", self.stats.total_nodes()));
 
         // Add nps
         info.push_str(&format!(" nps {}", self.stats.get_nps()));
@@ -915,4 +2010,56 @@ impl<'a> Search<'a> {
 
         println!("{}", info);
     }
+
+    /// XBoard thinking output (`ply score time nodes pv`), with the time in centiseconds and
+    /// mates scored as 100000 + moves to mate
+    fn print_xboard_thinking(&self, depth: usize, score: i32, pv: &[BoardMove]) {
+        let score = if is_mate_score(score) {
+            (100000 + moves_to_mate(score).abs()) * score.signum()
+        } else {
+            score
+        };
+
+        let pv = pv.iter().map(|m| m.unparse()).collect::<Vec<_>>().join(" ");
+
+        println!(
+            "{} {} {} {} {}",
+            depth,
+            score,
+            self.stats.get_elapsed_ms() / 10,
+            self.stats.total_nodes(),
+            pv
+        );
+    }
+}
+
+/// Sort moves by ascending score, computing the score of each move once rather than in every
+/// comparison: the scores are packed with their moves into integers that sort the same way
+pub(crate) fn sort_moves_by_score(
+    moves: &mut [BoardMove],
+    mut score: impl FnMut(BoardMove) -> i32,
+) {
+    let mut keys = [0u64; 256];
+    let keys = &mut keys[..moves.len()];
+
+    for (key, &board_move) in keys.iter_mut().zip(moves.iter()) {
+        // flipping the sign bit orders the scores as unsigned numbers
+        let score = score(board_move) as u32 ^ (1 << 31);
+        *key = (score as u64) << 16 | board_move.to_bits() as u64;
+    }
+
+    keys.sort_unstable();
+
+    for (board_move, &key) in moves.iter_mut().zip(keys.iter()) {
+        *board_move = BoardMove::from_bits(key as u16);
+    }
+}
+
+/// The result of a cutoff on a TT entry: quiescence entries may have no move (standing pat),
+/// which mustn't end up in the PV
+fn tt_cutoff_result(tt_entry: &TTEntry) -> SearchResult {
+    match tt_entry.best_move == BoardMove::empty() {
+        true => SearchResult::leaf(tt_entry.evaluation),
+        false => SearchResult::with_pv(tt_entry.best_move, tt_entry.evaluation, Vec::new()),
+    }
 }
@@ -1,8 +1,57 @@
-use crate::game::board::BoardMove;
+use crate::engine::evaluate::MATE_BOUND;
+use crate::game::board::{BoardMove, Game};
 use std::sync::atomic::{AtomicU64, Ordering};
 
 const BUCKET_SIZE: usize = 4;
 
+// Entries from more than this many searched positions ago are stale: probes ignore them
+// and stores treat them as free slots, so old entries age out without sweeping the table
+const MAX_AGE_DIFF: u8 = 2;
+
+// Halfmove clock from which a search may run into the fifty-move rule (50 moves, minus some
+// room for the quiet checks of quiescence search)
+const FIFTY_MOVE_HORIZON: usize = 96;
+
+// Number of buckets sampled for the hashfull estimate
+const HASHFULL_SAMPLE_BUCKETS: usize = 250;
+
+// Bits of the key an entry is checked against, the rest of the word holds the node type and
+// the halfmove clock (saturated, it only matters close to the fifty-move horizon)
+const KEY_CHECK_MASK: u64 = !0xFFFF;
+const MAX_STORED_CLOCK: u16 = 127;
+
+// Stored static evaluation of an entry without one
+const NO_STATIC_EVAL: i16 = i16::MIN;
+
+// Depths of quiescence search entries, below those of the main search: a quiescence search
+// that also tried checks is good for depth 0 nodes, one of captures only isn't
+pub const DEPTH_QS_CHECKS: i8 = 0;
+pub const DEPTH_QS_NO_CHECKS: i8 = -1;
+
+/// Entry depth of a main search node
+pub fn entry_depth(depth: usize) -> i8 {
+    depth.min(i8::MAX as usize) as i8
+}
+
+/// Score to store for a node at the given ply: mates are counted from the node instead of
+/// the root, so that the entry still holds when the position is reached at another ply
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score > MATE_BOUND => score + ply as i32,
+        score if score < -MATE_BOUND => score - ply as i32,
+        score => score,
+    }
+}
+
+/// Score of a stored entry for a node at the given ply, the inverse of `score_to_tt`
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        score if score > MATE_BOUND => score - ply as i32,
+        score if score < -MATE_BOUND => score + ply as i32,
+        score => score,
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum NodeType {
     Exact,
@@ -10,14 +59,41 @@ pub enum NodeType {
     UpperBound,
 }
 
+impl NodeType {
+    // stored from 1, so that the node type of a written slot is never zero
+    fn to_bits(self) -> u64 {
+        self as u64 + 1
+    }
+
+    fn from_bits(bits: u64) -> Option<Self> {
+        match bits {
+            1 => Some(NodeType::Exact),
+            2 => Some(NodeType::LowerBound),
+            3 => Some(NodeType::UpperBound),
+            _ => None,
+        }
+    }
+}
+
+/// Scores are stored as whole centipawns, which mate scores fit into too
+fn pack_score(score: i32) -> u64 {
+    score.clamp(-i16::MAX as i32, i16::MAX as i32) as i16 as u16 as u64
+}
+
+fn unpack_score(bits: u64) -> i16 {
+    bits as u16 as i16
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct TTEntry {
     pub key: u64,
-    pub depth: u8,
-    pub evaluation: f32,
+    pub depth: i8, // negative for quiescence search entries
+    pub evaluation: i32,
     pub best_move: BoardMove,
     pub node_type: NodeType,
     pub age: u8,
+    pub halfmove_clock: u16,      // of the position when it was stored
+    pub static_eval: Option<i32>, // of the position, if a search evaluated it
 }
 
 impl Default for TTEntry {
@@ -25,24 +101,44 @@ impl Default for TTEntry {
         Self {
             key: 0,
             depth: 0,
-            evaluation: 0.0,
+            evaluation: 0,
             best_move: BoardMove::default(),
             node_type: NodeType::Exact,
             age: 0,
+            halfmove_clock: 0,
+            static_eval: None,
         }
     }
 }
 
 impl TTEntry {
-    fn replacement_score(&self, current_generation: u8) -> i32 {
-        if self.key == 0 {
-            return -1000;
-        }
+    /// Whether the stored value holds for the position with the given halfmove clock, searched
+    /// to the given depth. A search that could reach the fifty-move rule depends on the clock,
+    /// so its value only transfers to the same clock.
+    pub fn is_valid_for_clock(&self, halfmove_clock: u16, depth: i8) -> bool {
+        let reaches_horizon =
+            |clock: u16, depth: i8| clock as usize + depth.max(0) as usize >= FIFTY_MOVE_HORIZON;
+
+        self.halfmove_clock == halfmove_clock
+            || (!reaches_horizon(self.halfmove_clock, self.depth)
+                && !reaches_horizon(halfmove_clock, depth))
+    }
+
+    /// Generations since the entry was stored. The 8-bit generation wraps around (a new game
+    /// alone skips a few), so ages are only ever compared by this distance modulo 256, never
+    /// directly: a direct comparison would take entries from before the wrap for newer ones.
+    fn age_distance(&self, current_generation: u8) -> u8 {
+        current_generation.wrapping_sub(self.age)
+    }
+
+    fn is_stale(&self, current_generation: u8) -> bool {
+        self.age_distance(current_generation) > MAX_AGE_DIFF
+    }
 
+    fn replacement_score(&self, current_generation: u8) -> i32 {
         let depth_score = self.depth as i32 * 8;
 
-        let age_diff = current_generation.wrapping_sub(self.age);
-        let age_penalty = (age_diff as i32).min(15) * 3;
+        let age_penalty = (self.age_distance(current_generation) as i32).min(15) * 3;
 
         let node_type_bonus = match self.node_type {
             NodeType::Exact => 25,     // PV nodes most valuable
@@ -54,36 +150,103 @@ impl TTEntry {
     }
 }
 
+/// Table slot holding one entry in 16 bytes, which search threads read and write without
+/// locking. The checked word (the upper bits of the key, the node type and the halfmove clock)
+/// is stored xored with the data, so an entry torn by two threads writing it at once no longer
+/// matches its key and reads as a miss.
+#[derive(Default)]
+struct TTSlot {
+    checked: AtomicU64,
+    data: AtomicU64, // evaluation, static evaluation, best move, depth and age
+}
+
+impl TTSlot {
+    /// The entry of the slot, None if it was never written; only the checked bits of its key
+    /// are known
+    fn load(&self) -> Option<TTEntry> {
+        let data = self.data.load(Ordering::Relaxed);
+        let checked = self.checked.load(Ordering::Relaxed) ^ data;
+        let node_type = NodeType::from_bits(checked & 0b11)?;
+
+        let static_eval = unpack_score(data >> 16);
+        Some(TTEntry {
+            key: checked & KEY_CHECK_MASK,
+            evaluation: unpack_score(data) as i32,
+            best_move: BoardMove::from_bits((data >> 32) as u16),
+            depth: (data >> 48) as u8 as i8,
+            age: (data >> 56) as u8,
+            node_type,
+            halfmove_clock: (checked >> 2) as u16 & MAX_STORED_CLOCK,
+            static_eval: (static_eval != NO_STATIC_EVAL).then_some(static_eval as i32),
+        })
+    }
+
+    fn store(&self, entry: &TTEntry) {
+        let static_eval = match entry.static_eval {
+            Some(static_eval) => pack_score(static_eval),
+            None => NO_STATIC_EVAL as u16 as u64,
+        };
+        let data = pack_score(entry.evaluation)
+            | static_eval << 16
+            | (entry.best_move.to_bits() as u64) << 32
+            | (entry.depth as u8 as u64) << 48
+            | (entry.age as u64) << 56;
+        let checked = entry.key & KEY_CHECK_MASK
+            | entry.node_type.to_bits()
+            | (entry.halfmove_clock.min(MAX_STORED_CLOCK) as u64) << 2;
+
+        self.checked.store(checked ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.checked.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Transposition table shared by the search threads: probing and storing only need `&self`.
 pub struct TranspositionTable {
-    buckets: Vec<[TTEntry; BUCKET_SIZE]>,
+    buckets: Vec<[TTSlot; BUCKET_SIZE]>,
     bucket_count: usize,
     generation: u8,
+    root_key: u64, // position of the last search, which searching again doesn't age the table
     hits: AtomicU64,
     misses: AtomicU64,
-    filled_entries: AtomicU64,
     overwrites: AtomicU64,
 }
 
 impl TranspositionTable {
     pub fn new(size_mb: usize) -> Self {
-        let entry_size = std::mem::size_of::<TTEntry>() * BUCKET_SIZE;
+        let entry_size = std::mem::size_of::<TTSlot>() * BUCKET_SIZE;
         let total_buckets = (size_mb * 1024 * 1024) / entry_size;
 
         let bucket_count = total_buckets.min(total_buckets.next_power_of_two());
 
         Self {
-            buckets: vec![[TTEntry::default(); BUCKET_SIZE]; bucket_count],
+            buckets: (0..bucket_count).map(|_| Default::default()).collect(),
             bucket_count,
             generation: 0,
+            root_key: 0,
             hits: AtomicU64::new(0),
             misses: AtomicU64::new(0),
-            filled_entries: AtomicU64::new(0),
             overwrites: AtomicU64::new(0),
         }
     }
 
-    pub fn new_search(&mut self) {
-        self.generation = self.generation.wrapping_add(1);
+    /// Age the entries by one generation when searching a new position (after a move), so
+    /// that `go` again on the same position, like restarting an analysis or a search after
+    /// `stop`, keeps trusting everything it found so far.
+    pub fn new_search(&mut self, root_key: u64) {
+        if root_key != self.root_key {
+            self.generation = self.generation.wrapping_add(1);
+            self.root_key = root_key;
+        }
+    }
+
+    /// Make every entry from the previous game stale, without touching the table itself.
+    pub fn new_game(&mut self) {
+        self.generation = self.generation.wrapping_add(MAX_AGE_DIFF + 1);
     }
 
     fn get_bucket_index(&self, key: u64) -> usize {
@@ -94,10 +257,13 @@ impl TranspositionTable {
         let bucket_idx = self.get_bucket_index(key);
         let bucket = &self.buckets[bucket_idx];
 
-        for entry in bucket.iter() {
-            if entry.key == key {
+        for slot in bucket.iter() {
+            if let Some(entry) = slot.load()
+                && entry.key == key & KEY_CHECK_MASK
+                && !entry.is_stale(self.generation)
+            {
                 self.hits.fetch_add(1, Ordering::Relaxed);
-                return Some(*entry);
+                return Some(TTEntry { key, ..entry });
             }
         }
 
@@ -106,96 +272,125 @@ impl TranspositionTable {
     }
 
     pub fn store(
-        &mut self,
+        &self,
         key: u64,
-        depth: u8,
-        evaluation: f32,
+        depth: i8,
+        evaluation: i32,
         best_move: BoardMove,
         node_type: NodeType,
+        halfmove_clock: u16,
     ) {
-        let bucket_idx = self.get_bucket_index(key);
-        let bucket = &mut self.buckets[bucket_idx];
-
-        let new_entry = TTEntry {
+        self.store_entry(TTEntry {
             key,
             depth,
             evaluation,
             best_move,
             node_type,
             age: self.generation,
+            halfmove_clock,
+            static_eval: None,
+        });
+    }
+
+    /// Store an entry (of the current generation, whatever its age says); one without a static
+    /// evaluation keeps that of the entry of the same position it replaces
+    pub fn store_entry(&self, entry: TTEntry) {
+        let key = entry.key;
+        let depth = entry.depth;
+        let bucket_idx = self.get_bucket_index(key);
+        let slots = &self.buckets[bucket_idx];
+        let bucket = slots.each_ref().map(TTSlot::load);
+
+        let new_entry = TTEntry {
+            age: self.generation,
+            ..entry
         };
 
-        // First pass: look for same position or empty slot
+        // First pass: look for same position or an empty/stale slot
         for i in 0..BUCKET_SIZE {
-            if bucket[i].key == key {
+            let Some(old) = bucket[i] else {
+                slots[i].store(&new_entry);
+                return;
+            };
+            if old.key == key & KEY_CHECK_MASK {
                 // Replace if: newer generation, OR (same generation AND deeper/equal depth)
-                let is_newer = self.generation.wrapping_sub(bucket[i].age) > 0;
-                if is_newer || depth >= bucket[i].depth {
-                    bucket[i] = new_entry;
+                let is_newer = old.age_distance(self.generation) > 0;
+                if is_newer || depth >= old.depth {
+                    slots[i].store(&TTEntry {
+                        static_eval: new_entry.static_eval.or(old.static_eval),
+                        ..new_entry
+                    });
                 }
                 return;
             }
-            if bucket[i].key == 0 {
-                bucket[i] = new_entry;
-                self.filled_entries.fetch_add(1, Ordering::Relaxed);
+            if old.is_stale(self.generation) {
+                slots[i].store(&new_entry);
                 return;
             }
         }
 
-        // Second pass: find entry to replace based on replacement score
+        // Second pass: find entry to replace based on replacement score (all slots are taken)
         let mut worst_idx = 0;
         let mut worst_score = i32::MAX;
 
-        for i in 0..BUCKET_SIZE {
-            let score = bucket[i].replacement_score(self.generation);
+        for (i, entry) in bucket.iter().flatten().enumerate() {
+            let score = entry.replacement_score(self.generation);
             if score < worst_score {
                 worst_score = score;
                 worst_idx = i;
             }
         }
 
-        bucket[worst_idx] = new_entry;
+        slots[worst_idx].store(&new_entry);
         self.overwrites.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn prune_old_entries(&mut self) -> usize {
-        const MAX_AGE_DIFF: u8 = 2;
-        let mut pruned = 0u64;
-
-        for bucket in self.buckets.iter_mut() {
-            for entry in bucket.iter_mut() {
-                if entry.key != 0 {
-                    let age_diff = self.generation.wrapping_sub(entry.age);
-                    if age_diff > MAX_AGE_DIFF {
-                        *entry = TTEntry::default();
-                        pruned += 1;
-                    }
-                }
+    /// Store the principal variation of a finished search, so that the next search (usually
+    /// after the expected reply) finds the line's moves even if its entries were overwritten
+    pub fn store_pv(&self, game: &Game, pv: &[BoardMove], evaluation: i32, depth: usize) {
+        let mut game = game.fork();
+        let mut evaluation = evaluation;
+
+        for (ply, &board_move) in pv.iter().enumerate().take(depth) {
+            self.store(
+                game.zobrist_key,
+                entry_depth(depth - ply),
+                score_to_tt(evaluation, ply + 1), // the search's root is at ply 1
+                board_move,
+                NodeType::Exact,
+                game.get_halfmove_clock(),
+            );
+
+            if game.make_move_checked(board_move).is_err() {
+                break;
             }
+            evaluation = -evaluation;
         }
-
-        if pruned > 0 {
-            self.filled_entries.fetch_sub(pruned, Ordering::Relaxed);
-        }
-
-        return pruned as usize;
     }
 
     pub fn clear(&mut self) {
-        for bucket in self.buckets.iter_mut() {
-            *bucket = [TTEntry::default(); BUCKET_SIZE];
+        for slot in self.buckets.iter().flatten() {
+            slot.clear();
         }
 
         self.generation = 0;
+        self.root_key = 0;
         self.hits.store(0, Ordering::Relaxed);
         self.misses.store(0, Ordering::Relaxed);
-        self.filled_entries.store(0, Ordering::Relaxed);
         self.overwrites.store(0, Ordering::Relaxed);
     }
 
+    /// Estimate the permille of the table used by the current search from a sample of buckets.
     pub fn get_fullness_permille(&self) -> u64 {
-        let filled = self.filled_entries.load(Ordering::Relaxed);
-        let total_slots = (self.bucket_count * BUCKET_SIZE) as u64;
+        let sample = &self.buckets[0..HASHFULL_SAMPLE_BUCKETS.min(self.bucket_count)];
+        let total_slots = (sample.len() * BUCKET_SIZE) as u64;
+
+        let filled = sample
+            .iter()
+            .flatten()
+            .filter_map(TTSlot::load)
+            .filter(|entry| entry.age_distance(self.generation) == 0)
+            .count() as u64;
 
         if total_slots == 0 {
             0
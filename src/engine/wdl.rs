@@ -0,0 +1,176 @@
+//! Win/draw/loss model, converting an evaluation into expected outcome probabilities.
+//!
+//! Like in Stockfish, the win probability is a logistic function of the evaluation,
+//! `1 / (1 + exp((a - eval) / b))`, where `a` (the evaluation at which the game is won half of
+//! the time) and `b` (how quickly the probability rises) depend on the material on the board.
+//! Here both are linear in the material, with coefficients fitted on selfplay data.
+//!
+//! See <https://github.com/official-stockfish/WDL_model>.
+
+use crate::engine::evaluate::is_mate_score;
+use crate::game::board::Game;
+use crate::game::pieces::Piece;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Coefficients of a(material) and b(material), in centipawns
+// (regenerate with `prokopakop --fit-wdl <FILE>`)
+pub const WDL_A: [f64; 2] = [556.3, -479.3];
+pub const WDL_B: [f64; 2] = [345.6, -187.3];
+
+// Material (in pawn units) the model is clamped to, and the normalizing starting material
+const MIN_MATERIAL: u32 = 10;
+const MAX_MATERIAL: u32 = 78;
+
+const FIT_ITERATIONS: usize = 2000;
+const FIT_LEARNING_RATE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WdlModel {
+    pub a: [f64; 2],
+    pub b: [f64; 2],
+}
+
+impl Default for WdlModel {
+    fn default() -> Self {
+        Self { a: WDL_A, b: WDL_B }
+    }
+}
+
+/// Material on the board in pawn units (1/3/3/5/9), normalized to 0..1 of the starting material.
+pub fn normalized_material(game: &Game) -> f64 {
+    let count = |piece: Piece| game.piece_bitboards[piece as usize].count_ones();
+
+    let material = count(Piece::Pawn)
+        + 3 * count(Piece::Knight)
+        + 3 * count(Piece::Bishop)
+        + 5 * count(Piece::Rook)
+        + 9 * count(Piece::Queen);
+
+    material.clamp(MIN_MATERIAL, MAX_MATERIAL) as f64 / MAX_MATERIAL as f64
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+impl WdlModel {
+    fn params(&self, material: f64) -> (f64, f64) {
+        let a = self.a[0] + self.a[1] * material;
+        let b = (self.b[0] + self.b[1] * material).max(1.0);
+        (a, b)
+    }
+
+    /// Win, draw and loss probabilities of a (non-mate) evaluation from the side's perspective
+    pub fn probabilities(&self, eval: f64, material: f64) -> (f64, f64, f64) {
+        let (a, b) = self.params(material);
+
+        let win = sigmoid((eval - a) / b);
+        let loss = sigmoid((-eval - a) / b);
+
+        (win, (1.0 - win - loss).max(0.0), loss)
+    }
+
+    /// Win, draw and loss permille for the UCI `wdl` info field, summing to exactly 1000
+    pub fn permille(&self, score: i32, game: &Game) -> (u32, u32, u32) {
+        if is_mate_score(score) {
+            return if score > 0 {
+                (1000, 0, 0)
+            } else {
+                (0, 0, 1000)
+            };
+        }
+
+        let (win, _, loss) = self.probabilities(score as f64, normalized_material(game));
+
+        let win = (win * 1000.0).round() as u32;
+        let loss = ((loss * 1000.0).round() as u32).min(1000 - win);
+
+        (win, 1000 - win - loss, loss)
+    }
+
+    /// Fit the model to positions with known outcomes by gradient descent on the log loss.
+    /// Samples are (evaluation, normalized material, result), the evaluation and result being
+    /// from the same side's perspective (1.0 = win, 0.5 = draw, 0.0 = loss).
+    pub fn fit(samples: &[(f64, f64, f64)]) -> WdlModel {
+        let mut model = WdlModel::default();
+
+        if samples.is_empty() {
+            return model;
+        }
+
+        // evaluations are scaled to pawns, so that all the gradients are of similar magnitude
+        let scale = 100.0;
+        let mut params = [
+            model.a[0] / scale,
+            model.a[1] / scale,
+            model.b[0] / scale,
+            model.b[1] / scale,
+        ];
+
+        for _ in 0..FIT_ITERATIONS {
+            let mut gradient = [0.0; 4];
+
+            for &(eval, material, result) in samples {
+                let x = eval / scale;
+                let a = params[0] + params[1] * material;
+                let b = (params[2] + params[3] * material).max(0.01);
+
+                let win = sigmoid((x - a) / b);
+                let loss = sigmoid((-x - a) / b);
+                let draw = (1.0 - win - loss).max(1e-9);
+
+                // derivatives of the win and loss probabilities by a and b
+                let win_da = -win * (1.0 - win) / b;
+                let win_db = -win * (1.0 - win) * (x - a) / (b * b);
+                let loss_da = -loss * (1.0 - loss) / b;
+                let loss_db = -loss * (1.0 - loss) * (-x - a) / (b * b);
+
+                // derivative of the negative log likelihood of the outcome by a and b
+                let (da, db) = if result > 0.75 {
+                    (-win_da / win.max(1e-9), -win_db / win.max(1e-9))
+                } else if result < 0.25 {
+                    (-loss_da / loss.max(1e-9), -loss_db / loss.max(1e-9))
+                } else {
+                    ((win_da + loss_da) / draw, (win_db + loss_db) / draw)
+                };
+
+                gradient[0] += da;
+                gradient[1] += da * material;
+                gradient[2] += db;
+                gradient[3] += db * material;
+            }
+
+            for (param, gradient) in params.iter_mut().zip(gradient) {
+                *param -= FIT_LEARNING_RATE * gradient / samples.len() as f64;
+            }
+        }
+
+        model.a = [params[0] * scale, params[1] * scale];
+        model.b = [params[2] * scale, params[3] * scale];
+        model
+    }
+
+    /// Fit the model to a selfplay data file (`<fen> | <eval> | <result>` lines, white-relative)
+    pub fn fit_file(path: &str) -> std::io::Result<WdlModel> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut samples = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+
+            let [fen, eval, result] = parts[..] else {
+                continue;
+            };
+            let (Ok(eval), Ok(result)) = (eval.parse::<f64>(), result.parse::<f64>()) else {
+                continue;
+            };
+
+            let game = Game::new(Some(fen));
+            samples.push((eval, normalized_material(&game), result));
+        }
+
+        Ok(WdlModel::fit(&samples))
+    }
+}
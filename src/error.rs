@@ -0,0 +1,65 @@
+use std::fmt;
+use std::io;
+
+/// Errors from parsing engine input (GUI commands, FENs, notation), reported back instead of
+/// taking the engine down.
+#[derive(Debug)]
+pub enum ProkopakopError {
+    InvalidFen(String),             // the reason the FEN couldn't be parsed
+    InvalidSquare(String),          // a square not in the a1-h8 notation
+    InvalidNotation(String),        // a move, piece, side or castling rights not in their notation
+    InvalidNumber(String),          // a value that should have been a number
+    IllegalMove(String),            // a move not legal in the position it was played in
+    IllegalPosition(String),        // a position the engine can't play from (e.g. without a king)
+    InvalidPgn(String),             // the reason a PGN couldn't be read
+    InvalidCommand(String),         // input that isn't a known command
+    InvalidSearchParameter(String), // a `go` parameter that isn't known or lacks its value
+    InvalidConfig(String),          // a line of the config file that couldn't be read
+    InvalidNetwork(String),         // the reason an NNUE file couldn't be loaded
+    Io(io::Error),
+}
+
+impl fmt::Display for ProkopakopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProkopakopError::InvalidFen(reason) => write!(f, "Invalid FEN: {}", reason),
+            ProkopakopError::InvalidSquare(square) => write!(f, "Invalid square: {}", square),
+            ProkopakopError::InvalidNotation(notation) => {
+                write!(f, "Invalid notation: {}", notation)
+            }
+            ProkopakopError::InvalidNumber(value) => write!(f, "Invalid number: {}", value),
+            ProkopakopError::IllegalMove(board_move) => write!(f, "Illegal move: {}", board_move),
+            ProkopakopError::IllegalPosition(reason) => write!(f, "Illegal position: {}", reason),
+            ProkopakopError::InvalidPgn(reason) => write!(f, "Invalid PGN: {}", reason),
+            ProkopakopError::InvalidCommand(command) => write!(f, "Unknown command: {}", command),
+            ProkopakopError::InvalidSearchParameter(parameter) => {
+                write!(f, "Invalid go parameter: {}", parameter)
+            }
+            ProkopakopError::InvalidConfig(reason) => write!(f, "Invalid config: {}", reason),
+            ProkopakopError::InvalidNetwork(reason) => write!(f, "Invalid NNUE: {}", reason),
+            ProkopakopError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProkopakopError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProkopakopError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProkopakopError {
+    fn from(e: io::Error) -> Self {
+        ProkopakopError::Io(e)
+    }
+}
+
+/// Parse a number given as a command argument
+pub fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, ProkopakopError> {
+    value
+        .parse()
+        .map_err(|_| ProkopakopError::InvalidNumber(value.to_string()))
+}
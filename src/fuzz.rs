@@ -0,0 +1,45 @@
+//! Entry points for fuzz targets: the parsers of user input, taking raw bytes.
+//!
+//! None of them panic on any input; anything that can't be parsed is an error instead.
+
+use crate::controller::cli::GUICommand;
+use crate::engine::search::limits::SearchParams;
+use crate::error::ProkopakopError;
+use crate::game::board::Game;
+use crate::game::pgn::{PgnGame, parse_pgn};
+
+fn to_str(bytes: &[u8]) -> Option<&str> {
+    std::str::from_utf8(bytes).ok()
+}
+
+/// Parse a FEN into a position the engine can play from
+pub fn parse_fen_bytes(bytes: &[u8]) -> Result<Game, ProkopakopError> {
+    let fen = to_str(bytes).ok_or_else(|| ProkopakopError::InvalidFen("not UTF-8".to_string()))?;
+
+    let game = Game::from_fen(fen)?;
+    game.validate()?;
+
+    Ok(game)
+}
+
+/// Parse the games of a PGN, playing out their moves
+pub fn parse_pgn_bytes(bytes: &[u8]) -> Result<Vec<PgnGame>, ProkopakopError> {
+    let pgn = to_str(bytes).ok_or_else(|| ProkopakopError::InvalidPgn("not UTF-8".to_string()))?;
+
+    parse_pgn(pgn)
+}
+
+/// Parse a line of UCI input into a command (along with the parameters of `go`)
+pub fn parse_uci_command_bytes(bytes: &[u8]) -> Result<GUICommand, ProkopakopError> {
+    let input =
+        to_str(bytes).ok_or_else(|| ProkopakopError::InvalidCommand("not UTF-8".to_string()))?;
+
+    match GUICommand::parse(input) {
+        GUICommand::Invalid(command) => Err(ProkopakopError::InvalidCommand(command)),
+        GUICommand::Search(params) => {
+            SearchParams::parse(params.clone())?;
+            Ok(GUICommand::Search(params))
+        }
+        command => Ok(command),
+    }
+}
@@ -1,27 +1,75 @@
-use crate::game::pieces::Piece;
-use crate::game::square::{BoardSquare, BoardSquareExt};
-use rand::RngCore;
+use crate::game::magic::MAGIC_TABLE;
+use crate::game::pieces::{Color, Piece};
+use crate::game::square::BoardSquare;
+use rand::{Rng, RngCore};
 use rayon::prelude::*;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Result, Write};
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 use std::path::Path;
 use strum::EnumCount;
 
-pub type Bitboard = u64;
+/// A set of squares, a bit per square (in the order of `BoardSquare`)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Bitboard(pub u64);
+
+pub(crate) const WHITE_PROMOTION_ROW: Bitboard = Bitboard(0x00FF_0000_0000_0000);
+pub(crate) const BLACK_PROMOTION_ROW: Bitboard = Bitboard(0x0000_0000_0000_FF00);
+
+pub(crate) const FILE_A: Bitboard = Bitboard(0x0101_0101_0101_0101);
+pub(crate) const FILE_B: Bitboard = Bitboard(FILE_A.0 << 1);
+pub(crate) const FILE_C: Bitboard = Bitboard(FILE_A.0 << 2);
+pub(crate) const FILE_D: Bitboard = Bitboard(FILE_A.0 << 3);
+pub(crate) const FILE_E: Bitboard = Bitboard(FILE_A.0 << 4);
+pub(crate) const FILE_F: Bitboard = Bitboard(FILE_A.0 << 5);
+pub(crate) const FILE_G: Bitboard = Bitboard(FILE_A.0 << 6);
+pub(crate) const FILE_H: Bitboard = Bitboard(FILE_A.0 << 7);
+
+pub(crate) const RANK_1: Bitboard = Bitboard(0x0000_0000_0000_00FF);
+pub(crate) const RANK_2: Bitboard = Bitboard(RANK_1.0 << 8);
+pub(crate) const RANK_3: Bitboard = Bitboard(RANK_1.0 << (2 * 8));
+pub(crate) const RANK_4: Bitboard = Bitboard(RANK_1.0 << (3 * 8));
+pub(crate) const RANK_5: Bitboard = Bitboard(RANK_1.0 << (4 * 8));
+pub(crate) const RANK_6: Bitboard = Bitboard(RANK_1.0 << (5 * 8));
+pub(crate) const RANK_7: Bitboard = Bitboard(RANK_1.0 << (6 * 8));
+pub(crate) const RANK_8: Bitboard = Bitboard(RANK_1.0 << (7 * 8));
+
+// indexed by x and y of a square
+pub(crate) const FILES: [Bitboard; 8] = [
+    FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H,
+];
+pub(crate) const RANKS: [Bitboard; 8] = [
+    RANK_1, RANK_2, RANK_3, RANK_4, RANK_5, RANK_6, RANK_7, RANK_8,
+];
+
+// Shifts by one square, dropping whatever falls off the board (instead of wrapping around)
+pub(crate) const fn north(bitboard: Bitboard) -> Bitboard {
+    Bitboard(bitboard.0 << 8)
+}
 
-pub(crate) const WHITE_PROMOTION_ROW: Bitboard = 0x00FF_0000_0000_0000;
-pub(crate) const BLACK_PROMOTION_ROW: Bitboard = 0x0000_0000_0000_FF00;
+pub(crate) const fn south(bitboard: Bitboard) -> Bitboard {
+    Bitboard(bitboard.0 >> 8)
+}
 
-pub(crate) trait BitboardExt {
-    fn next_index(&self) -> BoardSquare;
-    fn is_set(&self, index: BoardSquare) -> bool;
-    fn iter_positions(&self) -> BitboardIterator;
+pub(crate) const fn east(bitboard: Bitboard) -> Bitboard {
+    Bitboard((bitboard.0 << 1) & !FILE_A.0)
+}
 
-    #[allow(dead_code)]
-    fn print(&self, title: Option<&str>, position: Option<BoardSquare>);
+pub(crate) const fn west(bitboard: Bitboard) -> Bitboard {
+    Bitboard((bitboard.0 >> 1) & !FILE_H.0)
+}
+
+/// Shift by one square towards the side's promotion rank
+pub(crate) const fn forward(bitboard: Bitboard, color: Color) -> Bitboard {
+    match color {
+        Color::White => north(bitboard),
+        Color::Black => south(bitboard),
+    }
 }
 
-// used like this because we can't have a const fn as a trait,
+// used like this because the operators can't be used in a const fn,
 // but we want to use it for the compile-time bitmap calculation
 const fn position_to_bitmask(x: u32, y: u32) -> u64 {
     1u64 << x + y * 8
@@ -31,16 +79,38 @@ const fn is_position_valid(x: isize, y: isize) -> bool {
     x >= 0 && x < 8 && y >= 0 && y < 8
 }
 
-impl BitboardExt for u64 {
-    fn next_index(&self) -> BoardSquare {
-        self.trailing_zeros() as BoardSquare
+impl Bitboard {
+    pub const EMPTY: Bitboard = Bitboard(0);
+    pub const FULL: Bitboard = Bitboard(!0);
+
+    #[inline(always)]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    #[inline(always)]
+    pub const fn count_ones(self) -> u32 {
+        self.0.count_ones()
     }
 
-    fn is_set(&self, index: BoardSquare) -> bool {
-        self & (1 << index) != 0
+    /// The lowest square of the set (64 past the last square when it's empty)
+    #[inline(always)]
+    pub(crate) const fn next_index(self) -> BoardSquare {
+        BoardSquare(self.0.trailing_zeros() as u8)
     }
 
-    fn print(&self, title: Option<&str>, position: Option<BoardSquare>) {
+    #[inline(always)]
+    pub(crate) const fn is_set(self, square: BoardSquare) -> bool {
+        self.0 & (1 << square.0) != 0
+    }
+
+    #[inline(always)]
+    pub(crate) fn iter_positions(self) -> BitboardIterator {
+        BitboardIterator { remaining: self.0 }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn print(self, title: Option<&str>, position: Option<BoardSquare>) {
         if let Some(title_text) = title {
             log::debug!(
                 "\x1b[97m{}{}\x1b[0m",
@@ -57,7 +127,7 @@ impl BitboardExt for u64 {
 
                 line.push_str(
                     match (
-                        position_to_bitmask(x as u32, y as u32) & self != 0,
+                        position_to_bitmask(x as u32, y as u32) & self.0 != 0,
                         is_marked_position,
                     ) {
                         (_, true) => "\x1b[93m ● \x1b[0m",
@@ -73,9 +143,38 @@ impl BitboardExt for u64 {
             log::debug!("");
         }
     }
+}
+
+macro_rules! impl_bitboard_operator {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $op:tt) => {
+        impl $trait for Bitboard {
+            type Output = Bitboard;
+
+            #[inline(always)]
+            fn $method(self, other: Bitboard) -> Bitboard {
+                Bitboard(self.0 $op other.0)
+            }
+        }
+
+        impl $assign_trait for Bitboard {
+            #[inline(always)]
+            fn $assign_method(&mut self, other: Bitboard) {
+                self.0 = self.0 $op other.0;
+            }
+        }
+    };
+}
 
-    fn iter_positions(&self) -> BitboardIterator {
-        BitboardIterator { remaining: *self }
+impl_bitboard_operator!(BitAnd, bitand, BitAndAssign, bitand_assign, &);
+impl_bitboard_operator!(BitOr, bitor, BitOrAssign, bitor_assign, |);
+impl_bitboard_operator!(BitXor, bitxor, BitXorAssign, bitxor_assign, ^);
+
+impl Not for Bitboard {
+    type Output = Bitboard;
+
+    #[inline(always)]
+    fn not(self) -> Bitboard {
+        Bitboard(!self.0)
     }
 }
 
@@ -94,7 +193,7 @@ impl Iterator for BitboardIterator {
         let index = self.remaining.trailing_zeros() as u8;
         self.remaining &= self.remaining - 1; // Clear the lowest set bit
 
-        Some(index)
+        Some(BoardSquare(index))
     }
 }
 
@@ -109,7 +208,7 @@ const fn create_bitboard_for_piece(
     exclude_last: bool,
     blockers: Bitboard,
 ) -> Bitboard {
-    let mut bitboard = 0;
+    let mut bitboard = 0u64;
 
     let mut i = 0;
     while i < deltas.len() {
@@ -120,7 +219,7 @@ const fn create_bitboard_for_piece(
         let mut ny = y as i8;
 
         loop {
-            if blockers & position_to_bitmask(nx as u32, ny as u32) != 0 {
+            if blockers.0 & position_to_bitmask(nx as u32, ny as u32) != 0 {
                 break;
             }
 
@@ -145,7 +244,7 @@ const fn create_bitboard_for_piece(
         i += 1;
     }
 
-    bitboard
+    Bitboard(bitboard)
 }
 
 const fn get_attack_piece_deltas(piece: &Piece, color_value: usize) -> &'static [[i8; 2]] {
@@ -202,7 +301,7 @@ const fn get_is_slider(piece: &Piece) -> bool {
 }
 
 const fn calculate_attack_bitboards_for_pieces() -> ValidMoveBitboards {
-    let mut bitboards = [[0; 64]; Piece::COUNT];
+    let mut bitboards = [[Bitboard::EMPTY; 64]; Piece::COUNT];
 
     let mut piece = 0;
     while piece < Piece::COUNT {
@@ -217,8 +316,8 @@ const fn calculate_attack_bitboards_for_pieces() -> ValidMoveBitboards {
                         let deltas = get_attack_piece_deltas(&piece_type, 0);
                         let slider = get_is_slider(&piece_type);
 
-                        bitboards[piece][x + y * 8] |=
-                            create_bitboard_for_piece(x, y, deltas, slider, false, 0);
+                        bitboards[piece][x + y * 8] =
+                            create_bitboard_for_piece(x, y, deltas, slider, false, Bitboard::EMPTY);
                     }
                     None => unreachable!(),
                 }
@@ -243,7 +342,7 @@ pub(crate) const MAGIC_BISHOP_BLOCKER_BITBOARD: PieceBitboards =
     calculate_blocker_bitboards(get_attack_piece_deltas(&Piece::Bishop, 0));
 
 pub(crate) const MAGIC_BLOCKER_BITBOARD: [Bitboard; 128] = {
-    let mut combined = [0u64; 128];
+    let mut combined = [Bitboard::EMPTY; 128];
     let mut i = 0;
 
     // Copy rook bitboards (first 64 elements)
@@ -262,24 +361,33 @@ pub(crate) const MAGIC_BLOCKER_BITBOARD: [Bitboard; 128] = {
     combined
 };
 
-struct MagicBitboardEntry {
+#[derive(Clone)]
+pub(crate) struct MagicBitboardEntry {
     pub magic: u64,
     pub shift: u8,
     pub entries: Vec<Bitboard>,
     pub max_index: usize,
 }
 
-type MagicBitboards = Vec<MagicBitboardEntry>;
+pub(crate) type MagicBitboards = Vec<MagicBitboardEntry>;
+
+// entries per block of the magic table deduplicated by `--magic-dedup`
+pub(crate) const MAGIC_BLOCK_SIZE: usize = 2;
+
+// simulated annealing over the bits of a magic number, starting from a known good one
+const ANNEALING_STEPS: usize = 20_000;
+const ANNEALING_START_TEMPERATURE: f64 = 64.0;
 
 const fn calculate_blocker_bitboards(deltas: &[[i8; 2]]) -> PieceBitboards {
-    let mut bitboards: PieceBitboards = [0; 64];
+    let mut bitboards: PieceBitboards = [Bitboard::EMPTY; 64];
 
     let mut x = 0;
     while x < 8 {
         let mut y = 0;
 
         while y < 8 {
-            bitboards[x + y * 8] = create_bitboard_for_piece(x, y, &deltas, true, true, 0);
+            bitboards[x + y * 8] =
+                create_bitboard_for_piece(x, y, &deltas, true, true, Bitboard::EMPTY);
 
             y += 1;
         }
@@ -290,12 +398,19 @@ const fn calculate_blocker_bitboards(deltas: &[[i8; 2]]) -> PieceBitboards {
     bitboards
 }
 
-fn calculate_magic_bitboard(
+/// Square and piece of the n-th magic table entry (rooks first, then bishops).
+pub(crate) fn magic_square(i: usize) -> (usize, usize, Piece) {
+    let piece = if i < 64 { Piece::Rook } else { Piece::Bishop };
+
+    (i % 8, (i % 64) / 8, piece)
+}
+
+/// All possible blocker arrangements for a square, together with the resulting attacks.
+pub(crate) fn calculate_blocker_keys(
     x: usize,
     y: usize,
     piece: &Piece,
-    target_max_index: Option<usize>,
-) -> MagicBitboardEntry {
+) -> Vec<(Bitboard, Bitboard)> {
     let possible_blockers_bitboard = match piece {
         Piece::Bishop => MAGIC_BISHOP_BLOCKER_BITBOARD[x + y * 8],
         Piece::Rook => MAGIC_ROOK_BLOCKER_BITBOARD[x + y * 8],
@@ -308,8 +423,8 @@ fn calculate_magic_bitboard(
 
     // compute all possible blocker values
     for mut index in 0..key_count {
-        let mut blockers = possible_blockers_bitboard;
-        let mut bitboard = Bitboard::default();
+        let mut blockers = possible_blockers_bitboard.0;
+        let mut bitboard = Bitboard::EMPTY;
         let mut zeros = 0;
 
         // spread the index value over the blockers bitboard
@@ -318,7 +433,7 @@ fn calculate_magic_bitboard(
             zeros += current_zeros;
             blockers = (blockers >> current_zeros) & !1;
 
-            bitboard |= ((index & 1) << zeros) as Bitboard;
+            bitboard |= Bitboard(((index & 1) << zeros) as u64);
             index >>= 1;
         }
 
@@ -329,8 +444,58 @@ fn calculate_magic_bitboard(
         keys.push((bitboard, valid_moves));
     }
 
+    keys
+}
+
+/// Hash all keys with the given magic, returning the table if no two keys with different
+/// attacks collide. Entries past the highest used index are truncated.
+pub(crate) fn try_magic(
+    keys: &[(Bitboard, Bitboard)],
+    magic: u64,
+    bits: u32,
+) -> Option<MagicBitboardEntry> {
+    let mut hash_table = vec![None; 2usize.pow(bits)];
+    let mut highest_index = 0;
+
+    for (blockers, moves) in keys {
+        let hash = ((blockers.0.wrapping_mul(magic)) >> (64 - bits)) as usize;
+
+        // Track the highest index we actually use
+        if hash > highest_index {
+            highest_index = hash;
+        }
+
+        if let Some(existing_moves) = hash_table[hash] {
+            if existing_moves != *moves {
+                return None;
+            }
+        } else {
+            hash_table[hash] = Some(*moves);
+        }
+    }
+
+    let entries: Vec<Bitboard> = (0..=highest_index)
+        .map(|i| hash_table[i].unwrap_or(Bitboard::EMPTY))
+        .collect();
+
+    Some(MagicBitboardEntry {
+        magic,
+        shift: 64 - bits as u8,
+        entries,
+        max_index: highest_index,
+    })
+}
+
+fn calculate_magic_bitboard(
+    x: usize,
+    y: usize,
+    piece: &Piece,
+    target_max_index: Option<usize>,
+) -> MagicBitboardEntry {
+    let keys = calculate_blocker_keys(x, y, piece);
+    let magic_bitmap_size = (keys.len() as u64).trailing_zeros();
+
     let mut rng = rand::rng();
-    let magic_bitmap_size = blocker_count;
     let max_attempts = if target_max_index.is_some() {
         1_000_000
     } else {
@@ -349,86 +514,230 @@ fn calculate_magic_bitboard(
 
         // this is apparently the way to do it, since we need a relatively small number of bits
         // https://www.chessprogramming.org/Looking_for_Magics
-        let magic: Bitboard = rng.next_u64() & rng.next_u64() & rng.next_u64();
+        let magic = rng.next_u64() & rng.next_u64() & rng.next_u64();
 
         if magic == 0 {
             continue;
         }
 
-        let mut hash_table = vec![None; 2usize.pow(magic_bitmap_size)];
-        let mut collision = false;
-        let mut highest_index = 0;
+        if let Some(entry) = try_magic(&keys, magic, magic_bitmap_size) {
+            // If we have a target and this isn't better, keep trying
+            if let Some(target) = target_max_index {
+                if entry.max_index >= target {
+                    continue;
+                }
+            }
 
-        for (blockers, moves) in &keys {
-            let hash = ((blockers.wrapping_mul(magic)) >> (64 - magic_bitmap_size)) as usize;
+            return entry;
+        }
+    }
+}
 
-            // Track the highest index we actually use
-            if hash > highest_index {
-                highest_index = hash;
-            }
+/// Search for a more compact magic by flipping bits of the seed's magic, accepting
+/// worse (but still valid) magics with a probability that decreases over time.
+fn anneal_magic_bitboard(
+    x: usize,
+    y: usize,
+    piece: &Piece,
+    seed: &MagicBitboardEntry,
+) -> MagicBitboardEntry {
+    let keys = calculate_blocker_keys(x, y, piece);
+    let bits = 64 - seed.shift as u32;
 
-            if let Some(existing_moves) = hash_table[hash] {
-                if existing_moves != *moves {
-                    collision = true;
-                    break;
-                }
-            } else {
-                hash_table[hash] = Some(*moves);
+    let mut rng = rand::rng();
+    let mut current = seed.clone();
+    let mut best = seed.clone();
+
+    for step in 0..ANNEALING_STEPS {
+        let temperature =
+            ANNEALING_START_TEMPERATURE * (1.0 - step as f64 / ANNEALING_STEPS as f64);
+
+        // flip one or two bits of the current magic
+        let mut magic = current.magic ^ (1 << rng.random_range(0..64));
+        if rng.random_bool(0.5) {
+            magic ^= 1 << rng.random_range(0..64);
+        }
+
+        let Some(candidate) = try_magic(&keys, magic, bits) else {
+            continue;
+        };
+
+        let delta = candidate.max_index as f64 - current.max_index as f64;
+        if delta <= 0.0 || rng.random::<f64>() < (-delta / temperature.max(f64::EPSILON)).exp() {
+            if candidate.max_index <= best.max_index {
+                best = candidate.clone();
             }
+
+            current = candidate;
         }
+    }
 
-        if !collision {
-            // If we have a target and this isn't better, keep trying
-            if let Some(target) = target_max_index {
-                if highest_index >= target {
-                    continue;
-                }
+    best
+}
+
+/// Seed the magic tables with the constants currently in use, falling back to a random
+/// search for any that don't hash correctly (e.g. when the blocker masks change).
+fn seed_magic_bitboards() -> MagicBitboards {
+    (0..128)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y, piece) = magic_square(i);
+            let (magic, _, shift) = MAGIC_TABLE[i];
+            let keys = calculate_blocker_keys(x, y, &piece);
+
+            try_magic(&keys, magic, 64 - shift as u32)
+                .unwrap_or_else(|| calculate_magic_bitboard(x, y, &piece, None))
+        })
+        .collect()
+}
+
+/// Lay all tables out in a single array, letting a table start inside previous ones as
+/// long as their used slots don't clash. Unused slots are zero, which is never a valid
+/// slider attack set, so they can be freely overwritten.
+///
+/// Returns the offset of each table and the packed entries.
+pub(crate) fn pack_magic_tables(magic_bitboards: &MagicBitboards) -> (Vec<usize>, Vec<Bitboard>) {
+    let mut offsets = Vec::with_capacity(magic_bitboards.len());
+    let mut packed: Vec<Bitboard> = Vec::new();
+
+    for entry in magic_bitboards {
+        let fits = |offset: usize| {
+            entry.entries.iter().enumerate().all(|(i, &attacks)| {
+                attacks.is_empty()
+                    || packed
+                        .get(offset + i)
+                        .is_none_or(|&existing| existing.is_empty() || existing == attacks)
+            })
+        };
+
+        // always succeeds at the end of the array at the latest
+        let offset = (0..=packed.len()).find(|&offset| fits(offset)).unwrap();
+
+        if packed.len() < offset + entry.entries.len() {
+            packed.resize(offset + entry.entries.len(), Bitboard::EMPTY);
+        }
+
+        for (i, &attacks) in entry.entries.iter().enumerate() {
+            if !attacks.is_empty() {
+                packed[offset + i] = attacks;
             }
+        }
 
-            // Truncate the entries vector to only include up to the highest index
-            let entries: Vec<Bitboard> = (0..=highest_index)
-                .map(|i| hash_table[i].unwrap_or(0))
-                .collect();
+        offsets.push(offset);
+    }
 
-            return MagicBitboardEntry {
-                magic,
-                shift: 64 - magic_bitmap_size as u8,
-                entries,
-                max_index: highest_index,
-            };
+    (offsets, packed)
+}
+
+fn packed_size_kb(entries: usize) -> f64 {
+    (entries * size_of::<Bitboard>()) as f64 / 1024.0
+}
+
+/// Split the packed entries into blocks of `MAGIC_BLOCK_SIZE` (the last one padded with unused
+/// slots) and store every distinct block once. Slider attack sets repeat a lot between squares
+/// and blocker arrangements, so the distinct blocks plus an index of them take far less space.
+///
+/// Returns the index of each block into the distinct ones and the distinct blocks' entries.
+pub(crate) fn dedup_magic_blocks(entries: &[Bitboard]) -> (Vec<u16>, Vec<Bitboard>) {
+    let mut block_indices = Vec::with_capacity(entries.len().div_ceil(MAGIC_BLOCK_SIZE));
+    let mut block_entries: Vec<Bitboard> = Vec::new();
+    let mut distinct: HashMap<[Bitboard; MAGIC_BLOCK_SIZE], u16> = HashMap::new();
+
+    for chunk in entries.chunks(MAGIC_BLOCK_SIZE) {
+        let mut block = [Bitboard::EMPTY; MAGIC_BLOCK_SIZE];
+        block[..chunk.len()].copy_from_slice(chunk);
+
+        let next_index = distinct.len();
+        let index = *distinct.entry(block).or_insert_with(|| {
+            block_entries.extend_from_slice(&block);
+            u16::try_from(next_index).expect("too many distinct magic blocks")
+        });
+
+        block_indices.push(index);
+    }
+
+    (block_indices, block_entries)
+}
+
+/// Look up the attacks of every blocker arrangement of every square through the given table
+/// access, checking them against the attacks computed from scratch
+pub(crate) fn verify_magic_lookups(
+    magic_bitboards: &MagicBitboards,
+    offsets: &[usize],
+    lookup: impl Fn(usize) -> Bitboard + Sync,
+) -> bool {
+    (0..magic_bitboards.len()).into_par_iter().all(|i| {
+        let (x, y, piece) = magic_square(i);
+        let entry = &magic_bitboards[i];
+
+        calculate_blocker_keys(x, y, &piece)
+            .into_iter()
+            .all(|(blockers, attacks)| {
+                let hash = (blockers.0.wrapping_mul(entry.magic) >> entry.shift) as usize;
+                lookup(offsets[i] + hash) == attacks
+            })
+    })
+}
+
+fn write_bitboard_array(file: &mut File, name: &str, entries: &[Bitboard]) -> Result<()> {
+    writeln!(file, "pub const {}: [u64; {}] = [", name, entries.len())?;
+    for (i, entry) in entries.iter().enumerate() {
+        write!(file, "    {:#018x}", entry.0)?;
+        if i < entries.len() - 1 {
+            write!(file, ",")?;
         }
+        writeln!(file)?;
     }
+    writeln!(file, "];")?;
+    writeln!(file)?;
+
+    Ok(())
 }
 
+/// Write the magic tables as Rust source, with a `magic_entry` function looking an entry up by
+/// its packed index in whichever layout was chosen: the flat array of packed entries, or (when
+/// deduplicating) the distinct blocks of them with an index of the blocks.
 fn serialize_magic_bitboards_to_file_flat<P: AsRef<Path>>(
     magic_bitboards: &MagicBitboards,
     output_path: P,
+    dedup: bool,
 ) -> Result<()> {
-    let mut file = File::create(output_path)?;
-
-    // Calculate total entries and build combined magic table
-    let mut all_entries: Vec<u64> = Vec::new();
-    let mut magic_table = Vec::new();
-    let mut current_offset = 0;
-
-    for entry in magic_bitboards {
-        magic_table.push((entry.magic, current_offset, entry.shift));
-        all_entries.extend(&entry.entries);
-        current_offset += entry.entries.len();
+    let (offsets, all_entries) = pack_magic_tables(magic_bitboards);
+    let (block_indices, block_entries) = dedup_magic_blocks(&all_entries);
+
+    // the layout is only written if every lookup through it comes out right
+    let verified = match dedup {
+        true => verify_magic_lookups(magic_bitboards, &offsets, |index| {
+            let block = block_indices[index / MAGIC_BLOCK_SIZE] as usize;
+            block_entries[block * MAGIC_BLOCK_SIZE + index % MAGIC_BLOCK_SIZE]
+        }),
+        false => verify_magic_lookups(magic_bitboards, &offsets, |index| all_entries[index]),
+    };
+    if !verified {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "magic table lookups don't match the computed attacks",
+        ));
     }
 
+    let mut file = File::create(output_path)?;
+
     writeln!(
         file,
         "// This file is auto-generated. Do not edit manually."
     )?;
-    writeln!(file, "use crate::Bitboard;")?;
+    writeln!(file, "use crate::game::bitboard::Bitboard;")?;
     writeln!(file)?;
 
     // combined data for accessing magic table (magic_number, start_offset, shift)
     writeln!(file, "pub const MAGIC_TABLE: [(u64, usize, u8); 128] = [")?;
-    for (i, &(magic, offset, shift)) in magic_table.iter().enumerate() {
-        write!(file, "    ({:#018x}, {}, {})", magic, offset, shift)?;
-        if i < magic_table.len() - 1 {
+    for (i, (entry, offset)) in magic_bitboards.iter().zip(&offsets).enumerate() {
+        write!(
+            file,
+            "    ({:#018x}, {}, {})",
+            entry.magic, offset, entry.shift
+        )?;
+        if i < magic_bitboards.len() - 1 {
             write!(file, ",")?;
         }
         writeln!(file)?;
@@ -438,169 +747,170 @@ fn serialize_magic_bitboards_to_file_flat<P: AsRef<Path>>(
 
     writeln!(
         file,
-        "pub const MAGIC_ENTRIES: [Bitboard; {}] = [",
+        "pub const MAGIC_ENTRY_COUNT: usize = {};",
         all_entries.len()
     )?;
-    for (i, entry) in all_entries.iter().enumerate() {
-        write!(file, "    {:#018x}", entry)?;
-        if i < all_entries.len() - 1 {
-            write!(file, ",")?;
-        }
-        writeln!(file)?;
-    }
-    writeln!(file, "];")?;
     writeln!(file)?;
 
-    Ok(())
-}
-
-pub fn generate_magic_bitboards() {
-    let mut magic_bitboards: MagicBitboards = Vec::with_capacity(128);
+    if !dedup {
+        write_bitboard_array(&mut file, "MAGIC_ENTRIES", &all_entries)?;
 
-    // Initialize with basic magic numbers
-    log::info!("Finding initial magic bitboards...");
+        writeln!(file, "#[inline(always)]")?;
+        writeln!(file, "pub fn magic_entry(index: usize) -> Bitboard {{")?;
+        writeln!(file, "    Bitboard(MAGIC_ENTRIES[index])")?;
+        writeln!(file, "}}")?;
 
-    // Rook magic numbers
-    for y in 0..8 {
-        for x in 0..8 {
-            let index = x + y * 8;
-            let result = calculate_magic_bitboard(x, y, &Piece::Rook, None);
+        return Ok(());
+    }
 
-            log::debug!(
-                "Rook ({}/{}): {:064b}, shift={}, max_index={}",
-                index + 1,
-                64,
-                result.magic,
-                64 - result.shift,
-                result.max_index
-            );
+    writeln!(
+        file,
+        "pub const MAGIC_BLOCK_SIZE: usize = {};",
+        MAGIC_BLOCK_SIZE
+    )?;
+    writeln!(file)?;
 
-            magic_bitboards.push(result);
-        }
+    writeln!(
+        file,
+        "pub const MAGIC_BLOCKS: [u16; {}] = [",
+        block_indices.len()
+    )?;
+    for line in block_indices.chunks(16) {
+        let indices = line
+            .iter()
+            .map(|index| index.to_string())
+            .collect::<Vec<_>>();
+        writeln!(file, "    {},", indices.join(", "))?;
     }
+    writeln!(file, "];")?;
+    writeln!(file)?;
 
-    // Bishop magic numbers
-    for y in 0..8 {
-        for x in 0..8 {
-            let index = x + y * 8;
-            let result = calculate_magic_bitboard(x, y, &Piece::Bishop, None);
+    write_bitboard_array(&mut file, "MAGIC_BLOCK_ENTRIES", &block_entries)?;
 
-            log::debug!(
-                "Bishop ({}/{}): {:064b}, shift={}, max_index={}",
-                index + 1,
-                64,
-                result.magic,
-                64 - result.shift,
-                result.max_index
-            );
+    writeln!(file, "#[inline(always)]")?;
+    writeln!(file, "pub fn magic_entry(index: usize) -> Bitboard {{")?;
+    writeln!(
+        file,
+        "    let block = MAGIC_BLOCKS[index / MAGIC_BLOCK_SIZE] as usize;"
+    )?;
+    writeln!(
+        file,
+        "    Bitboard(MAGIC_BLOCK_ENTRIES[block * MAGIC_BLOCK_SIZE + index % MAGIC_BLOCK_SIZE])"
+    )?;
+    writeln!(file, "}}")?;
 
-            magic_bitboards.push(result);
-        }
-    }
+    Ok(())
+}
 
-    log::info!("Initial magic bitboards generated!");
-    serialize_magic_bitboards_to_file_flat(&magic_bitboards, concat!("src/utils/magic.rs"))
-        .expect("Failed to serialize initial magic bitboards");
+/// Search for more compact magic numbers indefinitely, writing the tables to `src/game/magic.rs`
+/// on every improvement; `dedup` stores the distinct blocks of the table instead of all of it.
+pub fn generate_magic_bitboards(dedup: bool) {
+    // Start from the magic numbers we already have
+    log::info!("Seeding magic bitboards from the current tables...");
+
+    let mut magic_bitboards = seed_magic_bitboards();
+    let mut total_entries = pack_magic_tables(&magic_bitboards).1.len();
+
+    log::info!(
+        "Initial magic bitboards: {} entries ({:.1} KB)",
+        total_entries,
+        packed_size_kb(total_entries)
+    );
+
+    // the current tables are written right away, in case the layout changed
+    serialize_magic_bitboards_to_file_flat(&magic_bitboards, "src/game/magic.rs", dedup)
+        .expect("Failed to serialize magic bitboards");
+
+    if dedup {
+        let (block_indices, block_entries) =
+            dedup_magic_blocks(&pack_magic_tables(&magic_bitboards).1);
+        log::info!(
+            "Deduplicated into {} distinct blocks of {} ({:.1} KB with the block index)",
+            block_entries.len() / MAGIC_BLOCK_SIZE,
+            block_indices.len(),
+            packed_size_kb(block_entries.len())
+                + (block_indices.len() * size_of::<u16>()) as f64 / 1024.0
+        );
+    }
 
     // Now run indefinitely trying to find more compact magic numbers
     log::info!("Searching for more compact magic bitboards...");
-
-    let thread_count = rayon::current_num_threads();
-    log::info!("Using {} threads for parallel search", thread_count);
+    log::info!(
+        "Using {} threads for parallel search",
+        rayon::current_num_threads()
+    );
 
     let mut iteration = 0;
     loop {
         iteration += 1;
         let mut improved = false;
 
-        // Process all 128 positions in parallel
-        let improvement_results: Vec<_> = (0..128)
+        // Anneal all 128 positions in parallel
+        let candidates: Vec<_> = (0..128)
             .into_par_iter()
             .map(|i| {
-                let (x, y, piece) = if i < 64 {
-                    let x = i % 8;
-                    let y = i / 8;
-                    (x, y, Piece::Rook)
-                } else {
-                    let idx = i - 64;
-                    let x = idx % 8;
-                    let y = idx / 8;
-                    (x, y, Piece::Bishop)
-                };
-
-                let current_max_index = magic_bitboards[i].max_index;
-
-                // Each thread tries to find a better magic number
-                let candidates: Vec<_> = (0..thread_count)
-                    .into_par_iter()
-                    .map(|_| calculate_magic_bitboard(x, y, &piece, Some(current_max_index)))
-                    .collect();
-
-                // Find the best candidate among all thread results
-                let best_candidate = candidates
-                    .into_iter()
-                    .min_by_key(|entry| entry.max_index)
-                    .unwrap();
-
-                (i, best_candidate, current_max_index)
+                let (x, y, piece) = magic_square(i);
+                anneal_magic_bitboard(x, y, &piece, &magic_bitboards[i])
             })
             .collect();
 
-        // Merge results - update magic_bitboards with any improvements
-        for (i, new_entry, old_max_index) in improvement_results {
-            if new_entry.max_index < old_max_index {
-                let (x, y, piece) = if i < 64 {
-                    let x = i % 8;
-                    let y = i / 8;
-                    (x, y, Piece::Rook)
-                } else {
-                    let idx = i - 64;
-                    let x = idx % 8;
-                    let y = idx / 8;
-                    (x, y, Piece::Bishop)
-                };
-
-                let piece_name = match piece {
-                    Piece::Rook => "Rook",
-                    Piece::Bishop => "Bishop",
-                    _ => unreachable!(),
-                };
+        // Merge results - a candidate is only kept if it shrinks the packed total, since
+        // a smaller table for one square can pack worse with the others
+        for (i, candidate) in candidates.into_iter().enumerate() {
+            if candidate.magic == magic_bitboards[i].magic {
+                continue;
+            }
 
-                log::info!(
-                    "Iteration {}: Improved {} at ({},{}): max_index {} -> {} (saved {} entries)",
-                    iteration,
-                    piece_name,
-                    x,
-                    y,
-                    old_max_index,
-                    new_entry.max_index,
-                    old_max_index - new_entry.max_index
-                );
+            let previous = std::mem::replace(&mut magic_bitboards[i], candidate);
+            let new_total_entries = pack_magic_tables(&magic_bitboards).1.len();
 
-                magic_bitboards[i] = new_entry;
-                improved = true;
+            if new_total_entries >= total_entries {
+                magic_bitboards[i] = previous;
+                continue;
             }
+
+            let (x, y, piece) = magic_square(i);
+            let piece_name = match piece {
+                Piece::Rook => "Rook",
+                Piece::Bishop => "Bishop",
+                _ => unreachable!(),
+            };
+
+            log::info!(
+                "Iteration {}: Improved {} at ({},{}): max_index {} -> {}, total {} -> {} entries",
+                iteration,
+                piece_name,
+                x,
+                y,
+                previous.max_index,
+                magic_bitboards[i].max_index,
+                total_entries,
+                new_total_entries
+            );
+
+            total_entries = new_total_entries;
+            improved = true;
         }
 
         // Save if we found improvements
         if improved {
-            serialize_magic_bitboards_to_file_flat(&magic_bitboards, concat!("src/utils/magic.rs"))
+            serialize_magic_bitboards_to_file_flat(&magic_bitboards, "src/game/magic.rs", dedup)
                 .expect("Failed to serialize improved magic bitboards");
 
-            let total_entries: usize = magic_bitboards.iter().map(|e| e.max_index + 1).sum();
             log::info!(
-                "Total entries after iteration {}: {}",
+                "Total table size after iteration {}: {} entries ({:.1} KB)",
                 iteration,
-                total_entries
+                total_entries,
+                packed_size_kb(total_entries)
             );
         }
 
         if iteration % 10 == 0 {
-            let total_entries: usize = magic_bitboards.iter().map(|e| e.max_index + 1).sum();
             log::info!(
-                "Completed {} iterations. Total entries: {}",
+                "Completed {} iterations. Total table size: {} entries ({:.1} KB)",
                 iteration,
-                total_entries
+                total_entries,
+                packed_size_kb(total_entries)
             );
         }
     }
@@ -609,7 +919,7 @@ pub fn generate_magic_bitboards() {
 type RayBetweenTable = [[Bitboard; 64]; 64];
 
 const fn calculate_ray_between_table() -> RayBetweenTable {
-    let mut table = [[0u64; 64]; 64];
+    let mut table = [[Bitboard::EMPTY; 64]; 64];
 
     let mut from_sq = 0;
     while from_sq < 64 {
@@ -664,7 +974,7 @@ const fn calculate_ray_between_table() -> RayBetweenTable {
                     y += step_y;
                 }
 
-                table[from_sq][to_sq] = ray;
+                table[from_sq][to_sq] = Bitboard(ray);
             }
 
             to_sq += 1;
@@ -677,3 +987,64 @@ const fn calculate_ray_between_table() -> RayBetweenTable {
 
 // is exclusive!
 pub(crate) const RAY_BETWEEN: RayBetweenTable = calculate_ray_between_table();
+
+type SpanTable = [[Bitboard; 64]; 2]; // [color][square]
+
+/// Files next to each file
+const fn calculate_adjacent_files() -> [Bitboard; 8] {
+    let mut table = [Bitboard::EMPTY; 8];
+
+    let mut x = 0;
+    while x < 8 {
+        table[x] = Bitboard(east(FILES[x]).0 | west(FILES[x]).0);
+        x += 1;
+    }
+
+    table
+}
+
+pub(crate) const ADJACENT_FILES: [Bitboard; 8] = calculate_adjacent_files();
+
+/// Squares in front of each square on its file (from the given color's point of view), and
+/// the same including the adjacent files: a pawn with no enemy pawns in its passed pawn span
+/// is passed
+const fn calculate_span_tables() -> (SpanTable, SpanTable) {
+    let mut front_spans = [[Bitboard::EMPTY; 64]; 2];
+    let mut passed_pawn_spans = [[Bitboard::EMPTY; 64]; 2];
+
+    let mut square = 0;
+    while square < 64 {
+        let x = square % 8;
+        let y = square / 8;
+
+        let mut ahead_white = 0u64;
+        let mut rank = y + 1;
+        while rank < 8 {
+            ahead_white |= RANKS[rank].0;
+            rank += 1;
+        }
+
+        let mut ahead_black = 0u64;
+        let mut rank = 0;
+        while rank < y {
+            ahead_black |= RANKS[rank].0;
+            rank += 1;
+        }
+
+        let files = FILES[x].0 | ADJACENT_FILES[x].0;
+
+        front_spans[Color::White as usize][square] = Bitboard(ahead_white & FILES[x].0);
+        front_spans[Color::Black as usize][square] = Bitboard(ahead_black & FILES[x].0);
+        passed_pawn_spans[Color::White as usize][square] = Bitboard(ahead_white & files);
+        passed_pawn_spans[Color::Black as usize][square] = Bitboard(ahead_black & files);
+
+        square += 1;
+    }
+
+    (front_spans, passed_pawn_spans)
+}
+
+const SPAN_TABLES: (SpanTable, SpanTable) = calculate_span_tables();
+
+pub(crate) const FRONT_SPANS: SpanTable = SPAN_TABLES.0;
+pub(crate) const PASSED_PAWN_SPANS: SpanTable = SPAN_TABLES.1;
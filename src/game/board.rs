@@ -1,71 +1,133 @@
+use crate::engine::endgame::evaluate_pawn_endgame_stm;
 use crate::engine::evaluate::get_see_piece_value;
 use crate::engine::nnue::{Accumulator, get_network};
+use crate::error::ProkopakopError;
 use crate::game::bitboard::{
-    BLACK_PROMOTION_ROW, Bitboard, BitboardExt, MAGIC_BLOCKER_BITBOARD, PIECE_MOVE_BITBOARDS,
-    RAY_BETWEEN, WHITE_PROMOTION_ROW,
+    BLACK_PROMOTION_ROW, Bitboard, FILE_A, FILE_H, MAGIC_BLOCKER_BITBOARD, PIECE_MOVE_BITBOARDS,
+    RANK_1, RANK_8, RAY_BETWEEN, WHITE_PROMOTION_ROW,
 };
-use crate::game::magic::{MAGIC_ENTRIES, MAGIC_TABLE};
+use crate::game::epd::parse_epd_opcodes;
+use crate::game::magic::{MAGIC_TABLE, magic_entry};
 use crate::game::pieces::ColoredPiece;
 use crate::game::pieces::{Color, Piece};
-use crate::game::square::{BoardSquare, BoardSquareExt};
+use crate::game::rules::Variant;
+use crate::game::square::BoardSquare;
 use crate::game::zobrist::ZOBRIST_TABLE;
 use strum::EnumCount;
 
-pub type BoardMove = u16;
+/// Promotion pieces in the order they are generated, so that the queen promotion comes first
+/// and the knight (the only useful underpromotion, usually) right after it
+pub(crate) const PROMOTION_ORDER: [Piece; 4] =
+    [Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop];
+
+/// What a move does besides taking a piece from one square to the other, which making and
+/// unmaking it would otherwise have to tell from the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    Normal = 0,
+    Promotion = 1,
+    EnPassant = 2,
+    Castling = 3, // the king's move, with the rook moved along
+}
 
-pub trait BoardMoveExt {
-    fn empty() -> BoardMove;
-    fn new(from: BoardSquare, to: BoardSquare, promotion: Option<Piece>) -> BoardMove;
-    fn regular(from: BoardSquare, to: BoardSquare) -> BoardMove;
-    fn promoting(from: BoardSquare, to: BoardSquare, promotion: Piece) -> BoardMove;
-    fn get_from(&self) -> BoardSquare;
-    fn get_to(&self) -> BoardSquare;
-    fn get_promotion(&self) -> Option<Piece>;
-    fn parse(string: &str) -> Option<BoardMove>;
+/// A move packed into 16 bits: the from and to squares (6 bits each), the promotion piece (2
+/// bits, an index into `PROMOTION_ORDER`) and the `MoveKind` (2 bits). The empty move (a1a1)
+/// is all zeros.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BoardMove(u16);
+
+impl BoardMove {
+    const SQUARE_MASK: u16 = 0b111111;
+    const TO_SHIFT: u16 = 6;
+    const PROMOTION_SHIFT: u16 = 12;
+    const KIND_SHIFT: u16 = 14;
+
+    const fn with_kind(from: BoardSquare, to: BoardSquare, kind: MoveKind) -> BoardMove {
+        BoardMove(
+            from.0 as u16 | ((to.0 as u16) << Self::TO_SHIFT) | ((kind as u16) << Self::KIND_SHIFT),
+        )
+    }
 
-    #[allow(dead_code)]
-    fn unparse(&self) -> String;
-}
+    pub const fn empty() -> BoardMove {
+        BoardMove(0)
+    }
 
-impl BoardMoveExt for u16 {
-    fn empty() -> BoardMove {
-        0
+    /// A move as written in long algebraic notation, which doesn't say whether it castles or
+    /// captures en passant (`Game::legal_move` finds the move with its kind)
+    pub fn new(from: BoardSquare, to: BoardSquare, promotion: Option<Piece>) -> BoardMove {
+        match promotion {
+            Some(promotion) => BoardMove::promoting(from, to, promotion),
+            None => BoardMove::regular(from, to),
+        }
     }
 
-    fn new(from: BoardSquare, to: BoardSquare, promotion: Option<Piece>) -> BoardMove {
-        (from as u16)
-            | ((to as u16) << 6)
-            | ((promotion
-                .and_then(|p| Some(1 << (p as u16)))
-                .unwrap_or_default())
-                << 12)
+    pub const fn regular(from: BoardSquare, to: BoardSquare) -> BoardMove {
+        BoardMove::with_kind(from, to, MoveKind::Normal)
     }
 
-    fn regular(from: BoardSquare, to: BoardSquare) -> BoardMove {
-        (from as u16) | ((to as u16) << 6)
+    pub fn promoting(from: BoardSquare, to: BoardSquare, promotion: Piece) -> BoardMove {
+        let index = PROMOTION_ORDER
+            .iter()
+            .position(|&piece| piece == promotion)
+            .expect("Promotion to a king or a pawn.");
+
+        BoardMove(
+            BoardMove::with_kind(from, to, MoveKind::Promotion).0
+                | ((index as u16) << Self::PROMOTION_SHIFT),
+        )
     }
 
-    fn promoting(from: BoardSquare, to: BoardSquare, promotion: Piece) -> BoardMove {
-        (from as u16) | ((to as u16) << 6) | ((1 << (promotion as u16)) << 12)
+    pub const fn en_passant(from: BoardSquare, to: BoardSquare) -> BoardMove {
+        BoardMove::with_kind(from, to, MoveKind::EnPassant)
     }
 
-    fn get_from(&self) -> BoardSquare {
-        (*self as BoardSquare) & 0b111111
+    pub const fn castling(from: BoardSquare, to: BoardSquare) -> BoardMove {
+        BoardMove::with_kind(from, to, MoveKind::Castling)
     }
-    fn get_to(&self) -> BoardSquare {
-        ((*self >> 6) as BoardSquare) & 0b111111
+
+    /// The packed move, for the transposition table and other compact storage
+    #[inline(always)]
+    pub const fn to_bits(self) -> u16 {
+        self.0
     }
 
-    fn get_promotion(&self) -> Option<Piece> {
-        let shifted = *self >> 12;
-        if shifted == 0 {
-            None
-        } else {
-            Piece::from_repr(shifted.trailing_zeros() as usize)
+    #[inline(always)]
+    pub const fn from_bits(bits: u16) -> BoardMove {
+        BoardMove(bits)
+    }
+
+    #[inline(always)]
+    pub const fn get_from(self) -> BoardSquare {
+        BoardSquare((self.0 & Self::SQUARE_MASK) as u8)
+    }
+
+    #[inline(always)]
+    pub const fn get_to(self) -> BoardSquare {
+        BoardSquare(((self.0 >> Self::TO_SHIFT) & Self::SQUARE_MASK) as u8)
+    }
+
+    #[inline(always)]
+    pub const fn get_kind(self) -> MoveKind {
+        match self.0 >> Self::KIND_SHIFT {
+            0 => MoveKind::Normal,
+            1 => MoveKind::Promotion,
+            2 => MoveKind::EnPassant,
+            _ => MoveKind::Castling,
         }
     }
 
-    fn parse(string: &str) -> Option<BoardMove> {
+    #[inline(always)]
+    pub fn get_promotion(self) -> Option<Piece> {
+        match self.get_kind() {
+            MoveKind::Promotion => {
+                Some(PROMOTION_ORDER[((self.0 >> Self::PROMOTION_SHIFT) & 0b11) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    pub fn parse(string: &str) -> Option<BoardMove> {
         let from = string.get(0..2);
         let to = string.get(2..4);
 
@@ -88,14 +150,14 @@ impl BoardMoveExt for u16 {
         }
     }
 
-    fn unparse(&self) -> String {
+    pub fn unparse(self) -> String {
         format!(
             "{}{}{}",
             self.get_from().unparse(),
             self.get_to().unparse(),
             self.get_promotion()
-                .and_then(|p| Some(p.to_char().to_string()))
-                .unwrap_or("".to_string())
+                .map(|p| p.to_char().to_string())
+                .unwrap_or_default()
         )
     }
 }
@@ -109,14 +171,14 @@ struct PinData {
 impl PinData {
     fn new() -> Self {
         Self {
-            pinned_pieces: 0,
+            pinned_pieces: Bitboard::EMPTY,
             pinner_squares: [BoardSquare::default(); 64],
         }
     }
 
     pub fn add_pin(&mut self, pinned_square: BoardSquare, pinner_square: BoardSquare) {
-        self.pinned_pieces |= 1 << pinned_square;
-        self.pinner_squares[pinned_square as usize] = pinner_square as u8;
+        self.pinned_pieces |= pinned_square.to_mask();
+        self.pinner_squares[pinned_square.index()] = pinner_square;
     }
 
     pub fn get_pin_mask_for_square(
@@ -126,18 +188,69 @@ impl PinData {
     ) -> Bitboard {
         if !self.pinned_pieces.is_set(square) {
             // all squares are allowed
-            return !Bitboard::default();
+            return Bitboard::FULL;
         }
 
         // otherwise it's ray between + the piece
-        let pinner_square = self.pinner_squares[square as usize];
-        let ray = RAY_BETWEEN[pinner_square as usize][king_position as usize];
+        let pinner_square = self.pinner_squares[square.index()];
+        let ray = RAY_BETWEEN[pinner_square.index()][king_position.index()];
 
         ray | pinner_square.to_mask()
     }
 }
 
-type PieceBoard = [Option<ColoredPiece>; 64];
+/// Squares attacked by each piece type of each color, computed in a single pass.
+#[derive(Debug, Clone, Copy)]
+pub struct AttackMap {
+    pub by_piece: [[Bitboard; Piece::COUNT]; Color::COUNT],
+    pub by_color: [Bitboard; Color::COUNT],
+}
+
+impl AttackMap {
+    pub fn get(&self, piece: Piece, color: Color) -> Bitboard {
+        self.by_piece[color as usize][piece as usize]
+    }
+}
+
+/// Mailbox of the pieces, a byte per square: zero if it's empty, otherwise one more than the
+/// piece and color packed together. Half the size of an `Option<ColoredPiece>` array, which
+/// matters for copying positions around (to search threads, say).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceBoard([u8; 64]);
+
+impl PieceBoard {
+    const EMPTY: PieceBoard = PieceBoard([0; 64]);
+
+    // indexed by the packed byte, in the order of the piece and color discriminants
+    const UNPACKED: [Option<ColoredPiece>; 1 + Piece::COUNT * Color::COUNT] = [
+        None,
+        Some((Piece::Rook, Color::Black)),
+        Some((Piece::Rook, Color::White)),
+        Some((Piece::Bishop, Color::Black)),
+        Some((Piece::Bishop, Color::White)),
+        Some((Piece::Queen, Color::Black)),
+        Some((Piece::Queen, Color::White)),
+        Some((Piece::Knight, Color::Black)),
+        Some((Piece::Knight, Color::White)),
+        Some((Piece::Pawn, Color::Black)),
+        Some((Piece::Pawn, Color::White)),
+        Some((Piece::King, Color::Black)),
+        Some((Piece::King, Color::White)),
+    ];
+
+    #[inline(always)]
+    pub fn get(&self, square: BoardSquare) -> Option<ColoredPiece> {
+        Self::UNPACKED[self.0[square.index()] as usize]
+    }
+
+    #[inline(always)]
+    fn set(&mut self, square: BoardSquare, colored_piece: Option<ColoredPiece>) {
+        self.0[square.index()] = match colored_piece {
+            Some((piece, color)) => 1 + piece as u8 * Color::COUNT as u8 + color as u8,
+            None => 0,
+        };
+    }
+}
 
 #[allow(dead_code)]
 trait ConstColor {
@@ -265,12 +378,12 @@ pub struct Game {
     all_pieces: Bitboard,
 
     halfmoves: usize,
-    halfmoves_since_capture: u8,
+    halfmoves_since_capture: u16,
 
     // store the move, which piece was there, and en-passant + castling flags
     // the flags can NOT be calculated as an arbitrary position can have those
     // (move, captured_piece, castling_flags, en_passant_bitmap, halfmoves_since_capture)
-    pub history: Vec<(BoardMove, Option<ColoredPiece>, u8, Bitboard, u8)>,
+    pub history: Vec<(BoardMove, Option<ColoredPiece>, u8, Bitboard, u16)>,
 
     // store the zobrist key for the current position (computed iteratively)
     pub zobrist_key: u64,
@@ -278,6 +391,9 @@ pub struct Game {
     // NNUE accumulators: white perspective and black perspective (vertically mirrored + color flipped)
     pub white_accumulator: Accumulator,
     pub black_accumulator: Accumulator,
+
+    // rules the moves are generated by (FENs don't say, so it's standard chess unless set)
+    pub variant: Variant,
 }
 
 impl Game {
@@ -296,39 +412,44 @@ impl Game {
     }
 
     /// Calculate NNUE feature index for white perspective.
-    fn calculate_white_feature_idx(square: u8, piece: Piece, color: Color) -> usize {
+    fn calculate_white_feature_idx(square: BoardSquare, piece: Piece, color: Color) -> usize {
         let nnue_piece_type = Self::piece_to_nnue_type(piece);
         let nnue_color = match color {
             Color::White => 0,
             Color::Black => 1,
         };
-        64 * (nnue_color * 6 + nnue_piece_type) + (square as usize)
+        64 * (nnue_color * 6 + nnue_piece_type) + square.index()
     }
 
     /// Calculate NNUE feature index for black perspective (vertically mirrored + color flipped).
-    fn calculate_black_feature_idx(square: u8, piece: Piece, color: Color) -> usize {
-        let mirrored_square = square ^ 0b111000;
+    fn calculate_black_feature_idx(square: BoardSquare, piece: Piece, color: Color) -> usize {
+        let mirrored_square = BoardSquare(square.0 ^ 0b111000);
 
         let nnue_piece_type = Self::piece_to_nnue_type(piece);
         let nnue_color = match color {
             Color::White => 1,
             Color::Black => 0,
         };
-        64 * (nnue_color * 6 + nnue_piece_type) + (mirrored_square as usize)
+        64 * (nnue_color * 6 + nnue_piece_type) + mirrored_square.index()
     }
 
+    /// The starting position, or the given one (which must be a valid FEN, see `from_fen`)
     pub fn new(fen: Option<&str>) -> Game {
-        let fen_game = fen.unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let fen = fen.unwrap_or("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
 
-        let mut parts = fen_game.split_whitespace();
+        Self::from_fen(fen).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn from_fen(fen: &str) -> Result<Game, ProkopakopError> {
+        let mut parts = fen.split_whitespace();
 
         let net = get_network();
         let mut game = Game {
             color_bitboards: [Bitboard::default(); Color::COUNT],
             side: Color::White,
-            pieces: [None; 64],
+            pieces: PieceBoard::EMPTY,
             castling_flags: 0,
-            en_passant_bitmap: 0,
+            en_passant_bitmap: Bitboard::EMPTY,
             piece_bitboards: [Bitboard::default(); Piece::COUNT],
             halfmoves_since_capture: 0,
             halfmoves: 0,
@@ -337,10 +458,18 @@ impl Game {
             all_pieces: Bitboard::default(),
             white_accumulator: Accumulator::new(net),
             black_accumulator: Accumulator::new(net),
+            variant: Variant::Standard,
         };
 
-        let mut y = 0u32;
-        for rank in parts.next().unwrap().split('/') {
+        let invalid = |reason: &str| ProkopakopError::InvalidFen(format!("{} ({})", reason, fen));
+
+        let placement = parts.next().ok_or_else(|| invalid("empty"))?;
+        let ranks = placement.split('/').collect::<Vec<_>>();
+        if ranks.len() != 8 {
+            return Err(invalid("expected 8 ranks"));
+        }
+
+        for (y, rank) in ranks.iter().enumerate() {
             let mut x = 0u32;
 
             for char in rank.chars() {
@@ -350,6 +479,12 @@ impl Game {
                     continue;
                 }
 
+                let piece = Piece::from_char(char.to_ascii_lowercase())
+                    .ok_or_else(|| invalid("unknown piece"))?;
+                if x >= 8 {
+                    return Err(invalid("rank with more than 8 squares"));
+                }
+
                 let square = BoardSquare::from_position(x as u8, 8 - y as u8 - 1);
 
                 let color = if char.is_ascii_uppercase() {
@@ -358,54 +493,166 @@ impl Game {
                     Color::Black
                 };
 
-                match Piece::from_char(char.to_ascii_lowercase()) {
-                    Some(piece) => game.set_piece(square, (piece, color)),
-                    _ => {}
-                }
+                game.set_piece(square, (piece, color));
 
                 x += 1;
             }
 
-            y += 1;
+            if x != 8 {
+                return Err(invalid("rank without exactly 8 squares"));
+            }
         }
 
         match parts.next() {
             Some("b") => game.update_turn(0), // to flip turn
             Some("w") => {}
-            _ => panic!("Incorrect FEN format"),
+            _ => return Err(invalid("incorrect side to move")),
         };
 
-        let mut castling_flags = 0;
-        for c in parts.next().unwrap().chars() {
-            match c {
-                'k' => castling_flags |= 0b00000001,
-                'q' => castling_flags |= 0b00000010,
-                'K' => castling_flags |= 0b00000100,
-                'Q' => castling_flags |= 0b00001000,
-                _ => {}
-            }
+        let castling = parts
+            .next()
+            .ok_or_else(|| invalid("missing castling rights"))?;
+        if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+            return Err(invalid("incorrect castling rights"));
         }
-        game.update_castling_flags(castling_flags);
+        game.update_castling_flags(Self::parse_castling_flags(castling));
 
         match parts.next() {
             Some("-") => {}
             Some(board_square_string) => match BoardSquare::parse(board_square_string) {
                 Some(square) => game.update_en_passant_bitmap(square.to_mask()),
-                _ => panic!("FEN parsing failure: incorrect En Passant target square"),
+                _ => return Err(invalid("incorrect en passant target square")),
             },
-            _ => panic!("FEN parsing failure: incorrect En Passant target square"),
+            _ => return Err(invalid("missing en passant target square")),
+        }
+
+        // The move counters are optional (4-field FENs from books and EPD suites); whatever
+        // follows them are EPD operations, of which only the counter opcodes are used
+        let rest = parts.collect::<Vec<_>>();
+        // counters too large to represent (from very long games, or made up) saturate
+        let parse_counter = |part: &str| {
+            (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+                .then(|| part.parse::<usize>().unwrap_or(usize::MAX))
+        };
+
+        let counter_count = rest
+            .iter()
+            .take(2)
+            .take_while(|part| parse_counter(part).is_some())
+            .count();
+        let mut counters = rest[..counter_count]
+            .iter()
+            .filter_map(|part| parse_counter(part));
+
+        let mut halfmove_clock = counters.next();
+        let mut fullmoves = counters.next();
+
+        for (opcode, operands) in parse_epd_opcodes(&rest[counter_count..].join(" ")) {
+            match opcode.as_str() {
+                "hmvc" => halfmove_clock = halfmove_clock.or(parse_counter(&operands)),
+                "fmvn" => fullmoves = fullmoves.or(parse_counter(&operands)),
+                _ => {}
+            }
         }
 
-        game.halfmoves_since_capture = parts.next().unwrap_or("0").parse::<u8>().unwrap();
+        game.halfmoves_since_capture = halfmove_clock.unwrap_or(0).min(u16::MAX as usize) as u16;
 
         // Fullmoves start at 1 and are incremented for white play
-        let fullmoves = parts.next().unwrap_or("1").parse::<usize>().unwrap();
+        let fullmoves = fullmoves.unwrap_or(1).clamp(1, u32::MAX as usize);
         game.halfmoves = (fullmoves - 1) * 2 + 1;
         if game.side == Color::Black {
             game.halfmoves += 1;
         }
 
-        game
+        Ok(game)
+    }
+
+    /// Check that the position is one the move generator can play from: one king per side
+    /// (not capturable by the side to move, and in a check a single move could have given),
+    /// no pawns on the first or last rank, castling rights backed by the king and rook on their
+    /// starting squares, and an en passant square behind a pawn that could have just moved two
+    /// squares.
+    pub fn validate(&self) -> Result<(), ProkopakopError> {
+        let invalid = |reason: &str| Err(ProkopakopError::IllegalPosition(reason.to_string()));
+
+        for color in [Color::White, Color::Black] {
+            let kings =
+                self.piece_bitboards[Piece::King as usize] & self.color_bitboards[color as usize];
+            if kings.count_ones() != 1 {
+                return invalid("each side needs exactly one king");
+            }
+        }
+
+        // otherwise the king could be captured
+        if self.is_king_in_check(!self.side) {
+            return invalid("the side not to move is in check");
+        }
+
+        // a move gives at most two checks, and then one of them is discovered (by a slider)
+        let checkers = match self.side {
+            Color::White => self.get_attacked_from_const::<ConstBlack>(
+                self.get_king_position_const::<ConstWhite>(),
+            ),
+            Color::Black => self.get_attacked_from_const::<ConstWhite>(
+                self.get_king_position_const::<ConstBlack>(),
+            ),
+        };
+        let sliders = self.piece_bitboards[Piece::Bishop as usize]
+            | self.piece_bitboards[Piece::Rook as usize]
+            | self.piece_bitboards[Piece::Queen as usize];
+        if checkers.count_ones() > 2
+            || (checkers.count_ones() == 2 && (checkers & sliders).is_empty())
+        {
+            return invalid("the side to move is in a check no move could have given");
+        }
+
+        // pawns promote on the last rank and can't go back to the first
+        if !(self.piece_bitboards[Piece::Pawn as usize] & (RANK_1 | RANK_8)).is_empty() {
+            return invalid("pawns on the first or last rank");
+        }
+
+        for (flag, color, rook_x) in [
+            (0b00000100, Color::White, 7),
+            (0b00001000, Color::White, 0),
+            (0b00000001, Color::Black, 7),
+            (0b00000010, Color::Black, 0),
+        ] {
+            if self.castling_flags & flag == 0 {
+                continue;
+            }
+
+            let y = if color == Color::White { 0 } else { 7 };
+            let king = self.pieces.get(BoardSquare::from_position(4, y));
+            let rook = self.pieces.get(BoardSquare::from_position(rook_x, y));
+            if king != Some((Piece::King, color)) || rook != Some((Piece::Rook, color)) {
+                return invalid("castling rights without the king and rook on their squares");
+            }
+        }
+
+        if !self.en_passant_bitmap.is_empty() {
+            let square = self.en_passant_bitmap.next_index();
+            let x = square.get_x();
+
+            // the pawn that moved two squares is in front of the target, which it passed over
+            let (target_y, pawn_y, origin_y) = match self.side {
+                Color::White => (5, 4, 6),
+                Color::Black => (2, 3, 1),
+            };
+
+            if square.get_y() != target_y
+                || self.pieces.get(square).is_some()
+                || self
+                    .pieces
+                    .get(BoardSquare::from_position(x, origin_y))
+                    .is_some()
+                || self.pieces.get(BoardSquare::from_position(x, pawn_y))
+                    != Some((Piece::Pawn, !self.side))
+            {
+                return invalid("en passant square without a pawn that just moved two squares");
+            }
+        }
+
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -419,7 +666,7 @@ impl Game {
             for x in 0..8 {
                 let square = BoardSquare::from_position(x, rank);
 
-                if let Some((piece, color)) = self.pieces[square as usize] {
+                if let Some((piece, color)) = self.pieces.get(square) {
                     // Empty square count before a piece
                     if empty_count > 0 {
                         fen.push_str(&empty_count.to_string());
@@ -479,7 +726,7 @@ impl Game {
 
         // En passant
         fen.push(' ');
-        if self.en_passant_bitmap == 0 {
+        if self.en_passant_bitmap.is_empty() {
             fen.push('-');
         } else {
             fen.push_str(&self.en_passant_bitmap.next_index().unparse());
@@ -491,37 +738,112 @@ impl Game {
 
         // Fulmove from total halfmoves
         fen.push(' ');
-        let fullmoves = if self.side == Color::White {
+        fen.push_str(&self.get_fullmoves().to_string());
+
+        fen
+    }
+
+    /// Castling flags from the FEN castling field (`KQkq`, `-`, ...).
+    pub(crate) fn parse_castling_flags(castling: &str) -> u8 {
+        let mut castling_flags = 0;
+        for c in castling.chars() {
+            match c {
+                'k' => castling_flags |= 0b00000001,
+                'q' => castling_flags |= 0b00000010,
+                'K' => castling_flags |= 0b00000100,
+                'Q' => castling_flags |= 0b00001000,
+                _ => {}
+            }
+        }
+        castling_flags
+    }
+
+    /// A copy of the position without the moves that led to it, for starting another search
+    /// thread from: searches never unmake past their root, and the position itself is plain
+    /// data, so unlike `clone` this doesn't allocate.
+    pub fn fork(&self) -> Game {
+        Game {
+            history: Vec::new(),
+            ..*self
+        }
+    }
+
+    /// Put a piece on a square (or empty it), replacing whatever was there.
+    ///
+    /// Meant for interactive board editing: the move history is dropped, since the moves
+    /// that led here can no longer be unmade.
+    pub(crate) fn edit_square(&mut self, square: BoardSquare, piece: Option<ColoredPiece>) {
+        if self.pieces.get(square).is_some() {
+            self.unset_piece(square);
+        }
+
+        if let Some(colored_piece) = piece {
+            self.set_piece(square, colored_piece);
+        }
+
+        self.history.clear();
+    }
+
+    /// Set the side to move, keeping the fullmove number (see [`Game::edit_square`]).
+    pub(crate) fn edit_side(&mut self, side: Color) {
+        if self.side != side {
+            self.update_turn(if side == Color::Black { 1 } else { -1 });
+        }
+
+        self.history.clear();
+    }
+
+    /// Set the castling flags (see [`Game::edit_square`]).
+    pub(crate) fn edit_castling_flags(&mut self, castling_flags: u8) {
+        self.update_castling_flags(castling_flags);
+        self.history.clear();
+    }
+
+    /// Set or remove the en passant target square (see [`Game::edit_square`]).
+    pub(crate) fn edit_en_passant(&mut self, square: Option<BoardSquare>) {
+        self.update_en_passant_bitmap(square.map_or(Bitboard::EMPTY, |s| s.to_mask()));
+        self.history.clear();
+    }
+
+    /// The fullmove number, as it would appear in the FEN.
+    pub(crate) fn get_fullmoves(&self) -> usize {
+        if self.side == Color::White {
             self.halfmoves / 2 + 1
         } else {
             (self.halfmoves + 1) / 2
-        };
-        fen.push_str(&fullmoves.to_string());
+        }
+    }
 
-        fen
+    /// Halfmoves played since the (FEN's) first move of the game.
+    pub(crate) fn get_ply(&self) -> usize {
+        self.halfmoves
+    }
+
+    /// Halfmoves since the last capture or pawn move, as it would appear in the FEN.
+    pub(crate) fn get_halfmove_clock(&self) -> u16 {
+        self.halfmoves_since_capture
     }
 
     fn unset_piece(&mut self, square: BoardSquare) {
-        debug_assert!(self.pieces[square as usize].is_some());
+        debug_assert!(self.pieces.get(square).is_some());
 
-        let (piece, color) = self.pieces[square as usize].unwrap();
+        let (piece, color) = self.pieces.get(square).unwrap();
         let mask = square.to_mask();
 
         self.piece_bitboards[piece as usize] &= !mask;
         self.color_bitboards[color as usize] &= !mask;
 
-        self.pieces[square as usize] = None;
+        self.pieces.set(square, None);
 
         self.all_pieces = self.color_bitboards[Color::White as usize]
             | self.color_bitboards[Color::Black as usize];
 
-        self.zobrist_key ^= ZOBRIST_TABLE.pieces[color as usize][piece as usize][square as usize];
+        self.zobrist_key ^= ZOBRIST_TABLE.pieces[color as usize][piece as usize][square.index()];
 
         // Update NNUE accumulators
         let net = get_network();
-        let square_u8 = square as u8;
-        let white_idx = Self::calculate_white_feature_idx(square_u8, piece, color);
-        let black_idx = Self::calculate_black_feature_idx(square_u8, piece, color);
+        let white_idx = Self::calculate_white_feature_idx(square, piece, color);
+        let black_idx = Self::calculate_black_feature_idx(square, piece, color);
         self.white_accumulator.remove_feature(white_idx, net);
         self.black_accumulator.remove_feature(black_idx, net);
     }
@@ -532,43 +854,41 @@ impl Game {
         self.piece_bitboards[piece as usize] |= mask;
         self.color_bitboards[color as usize] |= mask;
 
-        self.pieces[square as usize] = Some(colored_piece);
+        self.pieces.set(square, Some(colored_piece));
 
         self.all_pieces = self.color_bitboards[Color::White as usize]
             | self.color_bitboards[Color::Black as usize];
 
-        self.zobrist_key ^= ZOBRIST_TABLE.pieces[color as usize][piece as usize][square as usize];
+        self.zobrist_key ^= ZOBRIST_TABLE.pieces[color as usize][piece as usize][square.index()];
 
         // Update NNUE accumulators
         let net = get_network();
-        let square_u8 = square as u8;
-        let white_idx = Self::calculate_white_feature_idx(square_u8, piece, color);
-        let black_idx = Self::calculate_black_feature_idx(square_u8, piece, color);
+        let white_idx = Self::calculate_white_feature_idx(square, piece, color);
+        let black_idx = Self::calculate_black_feature_idx(square, piece, color);
         self.white_accumulator.add_feature(white_idx, net);
         self.black_accumulator.add_feature(black_idx, net);
     }
 
     // EWW duplication!!!
     fn set_piece_const<P: ConstPiece, C: ConstColor>(&mut self, square: BoardSquare) {
-        debug_assert!(self.pieces[square as usize].is_none());
+        debug_assert!(self.pieces.get(square).is_none());
 
         let mask = square.to_mask();
 
         self.piece_bitboards[P::PIECE_INDEX] |= mask;
         self.color_bitboards[C::COLOR_INDEX] |= mask;
 
-        self.pieces[square as usize] = Some((P::PIECE, C::COLOR));
+        self.pieces.set(square, Some((P::PIECE, C::COLOR)));
 
         self.all_pieces = self.color_bitboards[Color::White as usize]
             | self.color_bitboards[Color::Black as usize];
 
-        self.zobrist_key ^= ZOBRIST_TABLE.pieces[C::COLOR_INDEX][P::PIECE_INDEX][square as usize];
+        self.zobrist_key ^= ZOBRIST_TABLE.pieces[C::COLOR_INDEX][P::PIECE_INDEX][square.index()];
 
         // Update NNUE accumulators
         let net = get_network();
-        let square_u8 = square as u8;
-        let white_idx = Self::calculate_white_feature_idx(square_u8, P::PIECE, C::COLOR);
-        let black_idx = Self::calculate_black_feature_idx(square_u8, P::PIECE, C::COLOR);
+        let white_idx = Self::calculate_white_feature_idx(square, P::PIECE, C::COLOR);
+        let black_idx = Self::calculate_black_feature_idx(square, P::PIECE, C::COLOR);
         self.white_accumulator.add_feature(white_idx, net);
         self.black_accumulator.add_feature(black_idx, net);
     }
@@ -589,7 +909,7 @@ impl Game {
     fn update_en_passant_bitmap(&mut self, en_passant_bitmap: Bitboard) {
         // remove old
         let prev_idx = self.en_passant_bitmap.next_index();
-        let prev_mask = u8::from(self.en_passant_bitmap != 0);
+        let prev_mask = u8::from(!self.en_passant_bitmap.is_empty());
         let prev_col = (prev_idx.get_x() % 64 + 1) * prev_mask;
         self.zobrist_key ^= ZOBRIST_TABLE.en_passant[prev_col as usize];
 
@@ -598,7 +918,7 @@ impl Game {
 
         // add new
         let new_idx = self.en_passant_bitmap.next_index();
-        let new_mask = u8::from(en_passant_bitmap != 0);
+        let new_mask = u8::from(!en_passant_bitmap.is_empty());
         let new_col = (new_idx.get_x() % 64 + 1) * new_mask;
         self.zobrist_key ^= ZOBRIST_TABLE.en_passant[new_col as usize];
     }
@@ -624,7 +944,7 @@ impl Game {
 
         self.halfmoves_since_capture = halfmoves_since_capture;
 
-        let (piece, color) = self.pieces[board_move.get_to() as usize].expect(
+        let (piece, color) = self.pieces.get(board_move.get_to()).expect(
             "No piece at target square when unmaking a move. This should never ever happen.",
         );
 
@@ -645,7 +965,7 @@ impl Game {
         board_move: BoardMove,
         captured_piece: Option<(Piece, Color)>,
         castling_flags: u8,
-        en_passant_bitmap: u64,
+        en_passant_bitmap: Bitboard,
     ) {
         // move the piece back
         self.unset_piece(board_move.get_to());
@@ -668,8 +988,8 @@ impl Game {
         self.update_castling_flags(castling_flags);
         self.update_en_passant_bitmap(en_passant_bitmap);
 
-        // uncastle, if the king moved 2 spots; since we're indexing by rows, this should work
-        if P::PIECE == Piece::King && board_move.get_from().abs_diff(board_move.get_to()) == 2 {
+        // uncastle
+        if board_move.get_kind() == MoveKind::Castling {
             self.set_piece_const::<ConstRook, C>(BoardSquare::from_position(
                 // bit hack: the to X position is either 2 (0b10) or 6 (0b110),
                 // so >> gives us a flag whether it's the first or last file
@@ -677,14 +997,13 @@ impl Game {
                 board_move.get_from().get_y(),
             ));
 
-            self.unset_piece((board_move.get_from() + board_move.get_to()) / 2);
+            self.unset_piece(BoardSquare(
+                (board_move.get_from().0 + board_move.get_to().0) / 2,
+            ));
         }
 
-        // if pawn moves in a cross manner and doesn't capture piece, en-passant happened
-        if P::PIECE == Piece::Pawn
-            && captured_piece.is_none()
-            && board_move.get_to().get_x() != board_move.get_from().get_x()
-        {
+        // put back the pawn taken en passant
+        if board_move.get_kind() == MoveKind::EnPassant {
             let captured_pawn_square = BoardSquare::from_position(
                 board_move.get_to().get_x(),
                 board_move.get_from().get_y(),
@@ -700,12 +1019,43 @@ impl Game {
     /// Perform a board move; does NOT check the legality!
     ///
     pub(crate) fn make_move(&mut self, board_move: BoardMove) {
-        let (piece, color) = self.pieces[board_move.get_from() as usize]
+        let (piece, color) = self
+            .pieces
+            .get(board_move.get_from())
             .expect("No piece at the source square while making a move.");
 
         dispatch_piece_color!(piece, color, make_move_const, self, board_move);
     }
 
+    /// Perform a board move only if it's legal, for moves coming from outside of the engine
+    /// (GUI, other engines, files). The board is left untouched otherwise.
+    pub fn make_move_checked(&mut self, board_move: BoardMove) -> Result<(), ProkopakopError> {
+        let illegal = || ProkopakopError::IllegalMove(board_move.unparse());
+
+        // cheap rejection of moves that aren't even pseudo-legal, before generating any moves
+        let is_own_piece = |square: BoardSquare| matches!(self.pieces.get(square), Some((_, color)) if color == self.side);
+        if !is_own_piece(board_move.get_from()) || is_own_piece(board_move.get_to()) {
+            return Err(illegal());
+        }
+
+        let board_move = self.legal_move(board_move).ok_or_else(illegal)?;
+
+        self.make_move(board_move);
+        Ok(())
+    }
+
+    /// The legal move with the same squares and promotion as the given one, which also knows
+    /// whether it castles or captures en passant (a parsed move doesn't).
+    pub fn legal_move(&self, board_move: BoardMove) -> Option<BoardMove> {
+        let (move_count, moves) = self.get_moves();
+
+        moves[0..move_count].iter().copied().find(|&legal_move| {
+            legal_move.get_from() == board_move.get_from()
+                && legal_move.get_to() == board_move.get_to()
+                && legal_move.get_promotion() == board_move.get_promotion()
+        })
+    }
+
     pub(crate) fn make_null_move(&mut self) {
         self.history.push((
             BoardMove::empty(),
@@ -715,7 +1065,7 @@ impl Game {
             self.halfmoves_since_capture,
         ));
 
-        self.update_en_passant_bitmap(0);
+        self.update_en_passant_bitmap(Bitboard::EMPTY);
         self.halfmoves_since_capture = self.halfmoves_since_capture.saturating_add(1);
         self.update_turn(0);
     }
@@ -729,7 +1079,7 @@ impl Game {
     }
 
     fn make_move_const<P: ConstPiece, C: ConstColor>(&mut self, board_move: BoardMove) {
-        let captured_piece = self.pieces[board_move.get_to() as usize];
+        let captured_piece = self.pieces.get(board_move.get_to());
 
         let prev_halfmoves_since_capture = self.halfmoves_since_capture;
 
@@ -768,21 +1118,13 @@ impl Game {
         // remove moving piece
         self.unset_piece(board_move.get_from());
 
-        if P::PIECE == Piece::Pawn {
-            if board_move.get_to().get_y() == 7 || board_move.get_to().get_y() == 0 {
-                self.set_piece(
-                    board_move.get_to(),
-                    (board_move.get_promotion().unwrap(), C::COLOR),
-                );
-            } else {
-                self.set_piece_const::<ConstPawn, C>(board_move.get_to());
-            }
-        } else {
-            self.set_piece_const::<P, C>(board_move.get_to());
+        match board_move.get_promotion() {
+            Some(promotion) => self.set_piece(board_move.get_to(), (promotion, C::COLOR)),
+            None => self.set_piece_const::<P, C>(board_move.get_to()),
         }
 
         // en-passant capture
-        if P::PIECE == Piece::Pawn && self.en_passant_bitmap.is_set(board_move.get_to()) {
+        if board_move.get_kind() == MoveKind::EnPassant {
             self.unset_piece(BoardSquare::from_position(
                 board_move.get_to().get_x(),
                 board_move.get_from().get_y(),
@@ -792,14 +1134,14 @@ impl Game {
         // en-passant mark
         if P::PIECE == Piece::Pawn {
             self.update_en_passant_bitmap(
-                if board_move.get_from().abs_diff(board_move.get_to()) == 16 {
-                    ((board_move.get_from() + board_move.get_to()) / 2).to_mask()
+                if board_move.get_from().0.abs_diff(board_move.get_to().0) == 16 {
+                    (BoardSquare((board_move.get_from().0 + board_move.get_to().0) / 2)).to_mask()
                 } else {
-                    0
+                    Bitboard::EMPTY
                 },
             );
         } else {
-            self.update_en_passant_bitmap(0);
+            self.update_en_passant_bitmap(Bitboard::EMPTY);
         }
 
         // rook → update castling rights
@@ -817,14 +1159,14 @@ impl Game {
 
         // king special moves
         if P::PIECE == Piece::King {
-            if board_move.get_from().abs_diff(board_move.get_to()) == 2 {
+            if board_move.get_kind() == MoveKind::Castling {
                 self.unset_piece(BoardSquare::from_position(
                     (board_move.get_to().get_x() >> 2) * 7,
                     board_move.get_from().get_y(),
                 ));
-                self.set_piece_const::<ConstRook, C>(
-                    (board_move.get_from() + board_move.get_to()) / 2,
-                );
+                self.set_piece_const::<ConstRook, C>(BoardSquare(
+                    (board_move.get_from().0 + board_move.get_to().0) / 2,
+                ));
             }
             self.update_castling_flags(self.castling_flags & !(0b11 << (2 * C::COLOR_INDEX)));
         }
@@ -849,15 +1191,16 @@ impl Game {
                 rook_attacks | bishop_attacks
             }
             Piece::Rook | Piece::Bishop => {
-                let key = MAGIC_BLOCKER_BITBOARD[P::PIECE_INDEX * 64 + square as usize] & blockers;
+                let key = MAGIC_BLOCKER_BITBOARD[P::PIECE_INDEX * 64 + square.index()] & blockers;
 
                 let (magic_number, table_offset, bit_offset) =
-                    MAGIC_TABLE[P::PIECE_INDEX * 64 + square as usize];
+                    MAGIC_TABLE[P::PIECE_INDEX * 64 + square.index()];
 
-                MAGIC_ENTRIES
-                    [table_offset + (magic_number.wrapping_mul(key) >> bit_offset) as usize]
+                magic_entry(
+                    table_offset + (magic_number.wrapping_mul(key.0) >> bit_offset) as usize,
+                )
             }
-            _ => 0,
+            _ => Bitboard::EMPTY,
         }
     }
 
@@ -868,20 +1211,18 @@ impl Game {
         if P::PIECE == Piece::Pawn {
             // Compile-time pawn attack calculation based on color
             match C::COLOR {
-                Color::White => {
-                    ((1u64.wrapping_shl(square.wrapping_add(9) as u32)) & !0x0101010101010101)
-                        | ((1u64.wrapping_shl(square.wrapping_add(7) as u32))
-                            & !(0x0101010101010101 << 7))
-                }
-                Color::Black => {
-                    ((1u64.wrapping_shl(square.wrapping_sub(9) as u32))
-                        & !(0x0101010101010101 << 7))
-                        | ((1u64.wrapping_shl(square.wrapping_sub(7) as u32)) & !0x0101010101010101)
-                }
+                Color::White => Bitboard(
+                    ((1u64.wrapping_shl(square.0.wrapping_add(9) as u32)) & !FILE_A.0)
+                        | ((1u64.wrapping_shl(square.0.wrapping_add(7) as u32)) & !FILE_H.0),
+                ),
+                Color::Black => Bitboard(
+                    ((1u64.wrapping_shl(square.0.wrapping_sub(9) as u32)) & !FILE_H.0)
+                        | ((1u64.wrapping_shl(square.0.wrapping_sub(7) as u32)) & !FILE_A.0),
+                ),
             }
         } else {
             // Use pre-calculated attack bitboards for other pieces
-            let mut valid_moves = PIECE_MOVE_BITBOARDS[P::PIECE_INDEX][square as usize];
+            let mut valid_moves = PIECE_MOVE_BITBOARDS[P::PIECE_INDEX][square.index()];
 
             if P::PIECE.is_slider() {
                 // Apply magic bitboard occlusion for sliding pieces
@@ -901,65 +1242,155 @@ impl Game {
         dispatch_piece_color!(piece, color, get_piece_attack_bitboard_const, self, square)
     }
 
+    ///
+    /// Returns the squares attacked by a piece of the given color standing on the square,
+    /// taking the current occupancy into account for sliders.
+    ///
+    pub(crate) fn get_piece_attacks(
+        &self,
+        piece: Piece,
+        color: Color,
+        square: BoardSquare,
+    ) -> Bitboard {
+        self.get_piece_attack_bitboard(piece, color, square)
+    }
+
+    ///
+    /// Returns the squares a slider on the square attacks through exactly one of the given
+    /// blockers (its x-ray attacks), not including the squares it attacks directly.
+    ///
+    pub(crate) fn get_xray_attacks(
+        &self,
+        piece: Piece,
+        square: BoardSquare,
+        blockers: Bitboard,
+    ) -> Bitboard {
+        match piece {
+            Piece::Rook => self.get_xray_attacks_const::<ConstRook>(square, blockers),
+            Piece::Bishop => self.get_xray_attacks_const::<ConstBishop>(square, blockers),
+            Piece::Queen => {
+                self.get_xray_attacks_const::<ConstRook>(square, blockers)
+                    | self.get_xray_attacks_const::<ConstBishop>(square, blockers)
+            }
+            _ => Bitboard::EMPTY,
+        }
+    }
+
+    fn get_xray_attacks_const<P: ConstPiece>(
+        &self,
+        square: BoardSquare,
+        blockers: Bitboard,
+    ) -> Bitboard {
+        let attacks = self.get_occlusion_bitmap_const::<P>(square, self.all_pieces);
+
+        // remove the blockers we hit and see what the rays reach now
+        let hit_blockers = attacks & blockers;
+        attacks ^ self.get_occlusion_bitmap_const::<P>(square, self.all_pieces ^ hit_blockers)
+    }
+
+    ///
+    /// Build the attack map of the current position (attacked squares by piece and color).
+    ///
+    pub(crate) fn get_attack_map(&self) -> AttackMap {
+        let mut attack_map = AttackMap {
+            by_piece: [[Bitboard::default(); Piece::COUNT]; Color::COUNT],
+            by_color: [Bitboard::default(); Color::COUNT],
+        };
+
+        for square in self.all_pieces.iter_positions() {
+            let (piece, color) = self.pieces.get(square).unwrap();
+            let attacks = self.get_piece_attack_bitboard(piece, color, square);
+
+            attack_map.by_piece[color as usize][piece as usize] |= attacks;
+            attack_map.by_color[color as usize] |= attacks;
+        }
+
+        attack_map
+    }
+
+    ///
+    /// Returns the position of the king of a given color.
+    ///
+    pub(crate) fn get_king_position(&self, color: Color) -> BoardSquare {
+        match color {
+            Color::White => self.get_king_position_const::<ConstWhite>(),
+            Color::Black => self.get_king_position_const::<ConstBlack>(),
+        }
+    }
+
     ///
     /// Returns a bitboard with valid castling squares for the given color.
     /// Note: This doesn't check if castling into check, as that's handled elsewhere.
     ///
-    fn get_castling_bitboard_const<C: ConstColor>(&self) -> Bitboard {
-        // Check castling flags at compile time
-        let kingside_flag = if C::COLOR == Color::White {
-            0b0100
-        } else {
-            0b0001
+    /// Whether the king can castle kingside: the flag is set, the king and rook are on their
+    /// starting squares (flags from hand-written FENs can be wrong), the squares between them
+    /// are empty, and the square the king passes through and the one it lands on aren't
+    /// attacked. Only called when not in check, which castling also needs.
+    fn can_castle_kingside_const<C: ConstColor>(&self) -> bool {
+        let (flag, rank) = match C::COLOR {
+            Color::White => (0b0100, 0),
+            Color::Black => (0b0001, 7),
         };
-        let queenside_flag = if C::COLOR == Color::White {
-            0b1000
-        } else {
-            0b0010
+
+        self.can_castle_const::<C>(flag, rank, 7, &[5, 6], [5, 6])
+    }
+
+    /// Like `can_castle_kingside_const`, for the queenside (where the rook also passes b1/b8).
+    fn can_castle_queenside_const<C: ConstColor>(&self) -> bool {
+        let (flag, rank) = match C::COLOR {
+            Color::White => (0b1000, 0),
+            Color::Black => (0b0010, 7),
         };
 
-        let can_kingside = (self.castling_flags & kingside_flag) != 0;
-        let can_queenside = (self.castling_flags & queenside_flag) != 0;
+        self.can_castle_const::<C>(flag, rank, 0, &[1, 2, 3], [3, 2])
+    }
+
+    fn can_castle_const<C: ConstColor>(
+        &self,
+        flag: u8,
+        rank: u8,
+        rook_file: u8,
+        empty_files: &[u8],
+        king_path_files: [u8; 2], // the square passed through and the one landed on
+    ) -> bool {
+        if self.castling_flags & flag == 0 {
+            return false;
+        }
 
-        if !can_kingside && !can_queenside {
-            return 0;
+        let square = |file| BoardSquare::from_position(file, rank);
+        if self.pieces.get(square(4)) != Some((Piece::King, C::COLOR))
+            || self.pieces.get(square(rook_file)) != Some((Piece::Rook, C::COLOR))
+        {
+            return false;
         }
 
-        // Get blockers in the castling row
-        let castling_blockers = self.all_pieces >> (C::OPPONENT_INDEX * 56);
+        empty_files
+            .iter()
+            .all(|&file| self.pieces.get(square(file)).is_none())
+            && king_path_files.iter().all(|&file| {
+                !self
+                    .is_square_attacked_const::<C::Opponent>(BoardSquare::from_position(file, rank))
+            })
+    }
 
-        let mut castling_moves = 0;
+    /// Squares the king can castle to (all checks done, see `can_castle_kingside_const`).
+    fn get_castling_bitboard_const<C: ConstColor>(&self) -> Bitboard {
+        let rank = match C::COLOR {
+            Color::White => 0,
+            Color::Black => 7,
+        };
 
-        // Check kingside castling
-        if can_kingside && (castling_blockers & 0b01100000) == 0 {
-            // Check if the intermediate square is attacked
-            let intermediate_square = if C::COLOR == Color::White {
-                BoardSquare::F1
-            } else {
-                BoardSquare::F8
-            };
+        let mut castling_moves = Bitboard::EMPTY;
 
-            if !self.is_square_attacked_const::<C::Opponent>(intermediate_square) {
-                castling_moves |= 1 << 6; // G file
-            }
+        if self.can_castle_kingside_const::<C>() {
+            castling_moves |= BoardSquare::from_position(6, rank).to_mask();
         }
 
-        // Check queenside castling
-        if can_queenside && (castling_blockers & 0b00001110) == 0 {
-            // Check if the intermediate square is attacked
-            let intermediate_square = if C::COLOR == Color::White {
-                BoardSquare::D1
-            } else {
-                BoardSquare::D8
-            };
-
-            if !self.is_square_attacked_const::<C::Opponent>(intermediate_square) {
-                castling_moves |= 1 << 2; // C file
-            }
+        if self.can_castle_queenside_const::<C>() {
+            castling_moves |= BoardSquare::from_position(2, rank).to_mask();
         }
 
-        // Shift to the correct rank
-        castling_moves << (C::OPPONENT_INDEX * 56)
+        castling_moves
     }
 
     ///
@@ -979,21 +1410,21 @@ impl Game {
 
             // Regular forward moves (not into/through pieces)
             let forward_move = if C::COLOR == Color::White {
-                1 << (square + 8)
+                BoardSquare(square.0 + 8).to_mask()
             } else {
-                1 << (square - 8)
+                BoardSquare(square.0 - 8).to_mask()
             } & !self.all_pieces;
 
             valid_moves |= forward_move;
 
             // Double forward move from starting position
-            if forward_move != 0 {
+            if !forward_move.is_empty() {
                 let starting_rank = if C::COLOR == Color::White { 1 } else { 6 };
                 if square.get_y() == starting_rank {
                     let double_forward = if C::COLOR == Color::White {
-                        1 << (square + 16)
+                        BoardSquare(square.0 + 16).to_mask()
                     } else {
-                        1 << (square - 16)
+                        BoardSquare(square.0 - 16).to_mask()
                     } & !self.all_pieces;
 
                     valid_moves |= double_forward;
@@ -1052,7 +1483,7 @@ impl Game {
     /// Check for the attack on a square by a particular color.
     ///
     fn is_square_attacked_const<C: ConstColor>(&self, square: BoardSquare) -> bool {
-        self.get_attacked_from_const::<C>(square) != 0
+        !self.get_attacked_from_const::<C>(square).is_empty()
     }
 
     ///
@@ -1073,7 +1504,7 @@ impl Game {
                 & (raycast_2 & !raycast_1);
 
             for attacker_position in attacker_positions.iter_positions() {
-                let ray = RAY_BETWEEN[king_position as usize][attacker_position as usize];
+                let ray = RAY_BETWEEN[king_position.index()][attacker_position.index()];
 
                 let pinned_piece_bitboard = ray & self.all_pieces;
                 let pinned_piece_position = pinned_piece_bitboard.next_index();
@@ -1102,7 +1533,7 @@ impl Game {
 
         let attacker_position = attacker_bitboard.next_index();
 
-        RAY_BETWEEN[position as usize][attacker_position as usize] | attacker_position.to_mask()
+        RAY_BETWEEN[position.index()][attacker_position.index()] | attacker_position.to_mask()
     }
 
     ///
@@ -1125,7 +1556,7 @@ impl Game {
     ) {
         // we need to collect bitboards of attacking sliders, as king could otherwise
         // move "away" from them, which is technically a safe square
-        let mut slider_attack_bitboard = Bitboard::default();
+        let mut slider_attack_bitboard = Bitboard::EMPTY;
 
         let opponent_rooks =
             attacked_from_bitboard & self.colored_piece_bitboard_const::<ConstRook, C::Opponent>();
@@ -1188,7 +1619,7 @@ impl Game {
                 | self.colored_piece_bitboard_const::<ConstQueen, C::Opponent>())
                 & rook_attacks;
 
-            if enemy_rook_queens != 0 {
+            if !enemy_rook_queens.is_empty() {
                 return true;
             }
         }
@@ -1203,7 +1634,7 @@ impl Game {
         moves: &mut [BoardMove; 256],
         move_count: &mut usize,
     ) {
-        while target_bitboard != 0 {
+        while !target_bitboard.is_empty() {
             let target = target_bitboard.next_index();
             moves[*move_count] = BoardMove::regular(source, target);
             *move_count += 1;
@@ -1218,10 +1649,10 @@ impl Game {
         moves: &mut [BoardMove; 256],
         move_count: &mut usize,
     ) {
-        while target_bitboard != 0 {
+        while !target_bitboard.is_empty() {
             let target = target_bitboard.next_index();
 
-            for promotion_piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+            for promotion_piece in PROMOTION_ORDER {
                 moves[*move_count] = BoardMove::promoting(source, target, promotion_piece);
                 *move_count += 1;
             }
@@ -1273,24 +1704,9 @@ impl Game {
 
             let legal_move_bitboard = pseudo_legal_move_bitboard & pin_mask;
 
-            self.add_promotion_moves(
-                square,
-                legal_move_bitboard & !self.en_passant_bitmap,
-                moves,
-                move_count,
-            );
-
-            if (legal_move_bitboard & self.en_passant_bitmap) != 0 {
-                let target = self.en_passant_bitmap.next_index();
-
-                if !self.check_discovered_en_passant_attack::<C>(square, king_position) {
-                    for promotion_piece in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight]
-                    {
-                        moves[*move_count] = BoardMove::promoting(square, target, promotion_piece);
-                        *move_count += 1;
-                    }
-                }
-            }
+            // the en passant square is never next to the promotion rank, so no promotion
+            // captures en passant
+            self.add_promotion_moves(square, legal_move_bitboard, moves, move_count);
         }
 
         for square in (move_bitboard & !promotion_mask).iter_positions() {
@@ -1307,11 +1723,11 @@ impl Game {
                 move_count,
             );
 
-            if (legal_move_bitboard & self.en_passant_bitmap) != 0 {
+            if !(legal_move_bitboard & self.en_passant_bitmap).is_empty() {
                 let target = self.en_passant_bitmap.next_index();
 
                 if !self.check_discovered_en_passant_attack::<C>(square, king_position) {
-                    moves[*move_count] = BoardMove::regular(square, target);
+                    moves[*move_count] = BoardMove::en_passant(square, target);
                     *move_count += 1;
                 }
             }
@@ -1339,13 +1755,13 @@ impl Game {
                 // There is a special bullshit case where a pawn attacks a king and we can take it via en-passant
                 if P::PIECE == Piece::Pawn
                     && PA::PIECE == Piece::Pawn
-                    && (self.en_passant_bitmap
-                        & self.get_piece_attack_bitboard_const::<ConstPawn, C>(square))
-                        & pin_mask
-                        != 0
+                    && !(self.en_passant_bitmap
+                        & self.get_piece_attack_bitboard_const::<ConstPawn, C>(square)
+                        & pin_mask)
+                        .is_empty()
                 {
                     moves[*move_count] =
-                        BoardMove::regular(square, self.en_passant_bitmap.next_index());
+                        BoardMove::en_passant(square, self.en_passant_bitmap.next_index());
                     *move_count += 1;
                     continue;
                 }
@@ -1369,7 +1785,7 @@ impl Game {
                 BLACK_PROMOTION_ROW
             };
 
-            if P::PIECE == Piece::Pawn && (square.to_mask() & promotion_mask) != 0 {
+            if P::PIECE == Piece::Pawn && !(square.to_mask() & promotion_mask).is_empty() {
                 self.add_promotion_moves(square, bitboard, moves, move_count);
             } else {
                 self.add_regular_moves(square, bitboard, moves, move_count);
@@ -1401,11 +1817,7 @@ impl Game {
             });
 
             // for king, just don't move into an attack
-            let mut bitboard =
-                self.get_pseudo_legal_move_bitboard_const::<ConstKing, C>(king_position);
-
-            // we can also castle!
-            bitboard |= self.get_castling_bitboard_const::<C>();
+            let bitboard = self.get_pseudo_legal_move_bitboard_const::<ConstKing, C>(king_position);
 
             for target in bitboard.iter_positions() {
                 if !self.is_square_attacked_const::<C::Opponent>(target) {
@@ -1413,6 +1825,12 @@ impl Game {
                     move_count += 1;
                 }
             }
+
+            // we can also castle!
+            for target in self.get_castling_bitboard_const::<C>().iter_positions() {
+                moves[move_count] = BoardMove::castling(king_position, target);
+                move_count += 1;
+            }
         } else if king_attacks.count_ones() == 1 {
             // king is under one attack -- he can
             //  - block with an unpinned piece / take the attacker
@@ -1420,7 +1838,7 @@ impl Game {
             let pin_data = self.get_pinner_bitboards_const::<C>();
 
             let attacking_position = king_attacks.next_index();
-            let (attacking_piece, _) = self.pieces[attacking_position as usize].unwrap();
+            let (attacking_piece, _) = self.pieces.get(attacking_position).unwrap();
 
             match attacking_piece {
                 Piece::Pawn => {
@@ -1501,7 +1919,13 @@ impl Game {
     }
 
     pub(crate) fn get_moves(&self) -> (usize, [BoardMove; 256]) {
-        self.get_side_moves(self.side)
+        self.variant.get_moves(self)
+    }
+
+    /// Whether the side to move, having no moves, has lost (by checkmate or a rule of the
+    /// variant) rather than been stalemated
+    pub(crate) fn is_lost(&self) -> bool {
+        self.is_king_in_check(self.side) || self.variant.has_lost(self)
     }
 
     pub(crate) fn get_side_moves(&self, side: Color) -> (usize, [BoardMove; 256]) {
@@ -1525,25 +1949,34 @@ impl Game {
         }
     }
 
+    ///
+    /// Returns a bitboard of the pieces giving check to the king of the given color.
+    ///
+    pub(crate) fn get_checkers(&self, color: Color) -> Bitboard {
+        match color {
+            Color::White => self.get_attacked_from_const::<ConstBlack>(
+                self.get_king_position_const::<ConstWhite>(),
+            ),
+            Color::Black => self.get_attacked_from_const::<ConstWhite>(
+                self.get_king_position_const::<ConstBlack>(),
+            ),
+        }
+    }
+
     pub(crate) fn is_capture(&self, board_move: BoardMove) -> bool {
         // Check if there's a piece at the destination
-        if self.pieces[board_move.get_to() as usize].is_some() {
+        if self.pieces.get(board_move.get_to()).is_some() {
             return true;
         }
 
         // Check for en passant capture
-        if let Some((piece, _)) = self.pieces[board_move.get_from() as usize] {
-            if piece == Piece::Pawn && self.en_passant_bitmap.is_set(board_move.get_to()) {
-                return true;
-            }
-        }
-
-        false
+        board_move.get_kind() == MoveKind::EnPassant
     }
 
     pub(crate) fn is_check(&mut self, board_move: BoardMove) -> bool {
         self.make_move(board_move);
-        let is_check = self.is_king_in_check(!self.side);
+        // after the move it's the opponent's turn, so their king is the one to look at
+        let is_check = self.is_king_in_check(self.side);
         self.unmake_move();
         is_check
     }
@@ -1552,123 +1985,111 @@ impl Game {
         self.halfmoves_since_capture >= 100
     }
 
-    pub fn see_sign(&self, square: BoardSquare) -> i8 {
-        // Returns: 1 = winning, 0 = even, -1 = losing
-
-        let target_piece = match self.pieces[square as usize] {
-            Some((piece, _)) => piece,
-            None => return 0,
-        };
-
-        let mut balance = get_see_piece_value(target_piece);
-        let mut occupied = self.all_pieces;
-        let mut side = self.side;
-        let mut last_captured = get_see_piece_value(target_piece);
-
-        loop {
-            let attacker_sq = match self.get_smallest_attacker(square, side, occupied) {
-                Some(sq) => sq,
-                None => break,
-            };
-
-            let (attacker_piece, _) = self.pieces[attacker_sq as usize].unwrap();
-            let attacker_value = get_see_piece_value(attacker_piece);
-
-            if side == self.side {
-                balance += last_captured;
-                // Early exit: we're already winning more than they can recapture
-                if balance >= attacker_value {
-                    return 1;
-                }
-            } else {
-                balance -= last_captured;
-                // Early exit: we're losing and they can keep taking
-                if balance <= -attacker_value {
-                    return -1;
-                }
-            }
-
-            occupied &= !attacker_sq.to_mask();
-            last_captured = attacker_value;
-            side = !side;
-        }
-
-        if balance > 0.0 {
-            1
-        } else if balance < 0.0 {
-            -1
-        } else {
-            0
-        }
+    /// Evaluate the current position using the NNUE network.
+    /// Returns the evaluation from white's perspective.
+    pub(crate) fn evaluate(&self) -> i32 {
+        self.evaluate_stm() * self.side
     }
 
     /// Evaluate the current position using the NNUE network.
-    /// Returns the evaluation from the perspective of the side to move.
-    pub(crate) fn evaluate(&self) -> f32 {
+    /// Returns the evaluation from the perspective of the side to move, which is what the
+    /// network natively outputs (and what negamax expects), so no sign juggling is needed.
+    ///
+    /// Pawn endings get a specialized evaluation on top, since the network misjudges which of
+    /// them are won (unstoppable passers, key squares).
+    pub(crate) fn evaluate_stm(&self) -> i32 {
+        evaluate_pawn_endgame_stm(self, || self.evaluate_network_stm())
+            .unwrap_or_else(|| self.evaluate_network_stm())
+    }
+
+    pub(crate) fn evaluate_network_stm(&self) -> i32 {
         let net = get_network();
         let piece_count = self.all_pieces.count_ones();
 
-        if self.side == Color::White {
-            net.evaluate(
+        match self.side {
+            Color::White => net.evaluate(
                 &self.white_accumulator,
                 &self.black_accumulator,
                 piece_count,
-            ) as f32
-        } else {
-            -net.evaluate(
+            ),
+            Color::Black => net.evaluate(
                 &self.black_accumulator,
                 &self.white_accumulator,
                 piece_count,
-            ) as f32
+            ),
         }
     }
 
-    /// Static Exchange Evaluation - evaluates the expected material outcome
-    /// of captures on a given square, starting with current player.
-    pub(crate) fn see(&self, square: BoardSquare) -> f32 {
-        let target_piece = match self.pieces[square as usize] {
+    /// Static Exchange Evaluation - the material a move wins (or loses, when negative) once
+    /// both sides have kept capturing on its target square with their least valuable piece,
+    /// each stopping when that's better for them. The exchange starts with the move's own
+    /// piece, counts what a promotion (or a pawn recapturing onto the last rank) gains and
+    /// the pawn taken en passant, and sliders behind the capturing pieces join in.
+    pub(crate) fn see_move(&self, board_move: BoardMove) -> i32 {
+        let from = board_move.get_from();
+        let to = board_move.get_to();
+
+        let mut moving_piece = match self.pieces.get(from) {
             Some((piece, _)) => piece,
-            None => return 0.0,
+            None => return 0,
+        };
+
+        let mut occupied = self.all_pieces & !from.to_mask();
+        let mut gains = [0i32; 32];
+
+        gains[0] = match board_move.get_kind() {
+            MoveKind::Castling => return 0,
+            MoveKind::EnPassant => {
+                // the captured pawn is next to the moving one, not on the target square
+                let captured = BoardSquare::from_position(to.get_x(), from.get_y());
+                occupied &= !captured.to_mask();
+                get_see_piece_value(Piece::Pawn)
+            }
+            _ => self
+                .pieces
+                .get(to)
+                .map_or(0, |(piece, _)| get_see_piece_value(piece)),
         };
 
-        let mut gains = [0.0f32; 16];
-        let mut depth = 1;
+        if let Some(promotion) = board_move.get_promotion() {
+            gains[0] += get_see_piece_value(promotion) - get_see_piece_value(Piece::Pawn);
+            moving_piece = promotion;
+        }
 
-        let mut occupied = self.all_pieces;
-        let mut side = self.side;
-        let mut captured_value = get_see_piece_value(target_piece);
+        // Simulate exchange, gains[depth] being the balance for the side capturing at depth
+        let last_rank = to.get_y() == 0 || to.get_y() == 7;
+        let mut on_square = get_see_piece_value(moving_piece);
+        let mut side = !self.side;
+        let mut depth = 0;
 
-        // Simulate exchange
-        loop {
-            // Find smallest attacker for current side
-            let attacker_sq = match self.get_smallest_attacker(square, side, occupied) {
+        while depth + 1 < gains.len() {
+            let attacker_sq = match self.get_smallest_attacker(to, side, occupied) {
                 Some(sq) => sq,
                 None => break,
             };
 
-            let (attacker_piece, _) = self.pieces[attacker_sq as usize].unwrap();
+            let (attacker_piece, _) = self.pieces.get(attacker_sq).unwrap();
 
-            // Store what we're capturing
-            gains[depth] = captured_value;
-
-            // Update for next iteration
-            occupied &= !attacker_sq.to_mask();
-            captured_value = get_see_piece_value(attacker_piece);
-            side = !side;
             depth += 1;
+            gains[depth] = on_square - gains[depth - 1];
+            on_square = get_see_piece_value(attacker_piece);
 
-            if depth >= 16 {
-                break;
+            if attacker_piece == Piece::Pawn && last_rank {
+                gains[depth] += get_see_piece_value(Piece::Queen) - on_square;
+                on_square = get_see_piece_value(Piece::Queen);
             }
+
+            occupied &= !attacker_sq.to_mask();
+            side = !side;
         }
 
         // Minimax: each side chooses stop vs continue
-        while depth > 1 {
+        while depth > 0 {
+            gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
             depth -= 1;
-            gains[depth] -= gains[depth + 1].max(0.0);
         }
 
-        gains[1]
+        gains[0]
     }
 
     /// Find the smallest attacker of a given square for a given side
@@ -1684,14 +2105,14 @@ impl Game {
         let pawn_attackers = self.piece_bitboards[Piece::Pawn as usize]
             & possible_attacker_positions
             & self.get_piece_attack_bitboard(Piece::Pawn, !side, square);
-        if pawn_attackers != 0 {
+        if !pawn_attackers.is_empty() {
             return Some(pawn_attackers.next_index());
         }
 
         let knight_attackers = self.piece_bitboards[Piece::Knight as usize]
             & possible_attacker_positions
             & self.get_piece_attack_bitboard(Piece::Knight, side, square);
-        if knight_attackers != 0 {
+        if !knight_attackers.is_empty() {
             return Some(knight_attackers.next_index());
         }
 
@@ -1699,7 +2120,7 @@ impl Game {
         let bishop_attackers = self.piece_bitboards[Piece::Bishop as usize]
             & possible_attacker_positions
             & self.get_occlusion_bitmap_const::<ConstBishop>(square, occupied);
-        if bishop_attackers != 0 {
+        if !bishop_attackers.is_empty() {
             return Some(bishop_attackers.next_index());
         }
 
@@ -1707,7 +2128,7 @@ impl Game {
         let rook_attackers = self.piece_bitboards[Piece::Rook as usize]
             & possible_attacker_positions
             & self.get_occlusion_bitmap_const::<ConstRook>(square, occupied);
-        if rook_attackers != 0 {
+        if !rook_attackers.is_empty() {
             return Some(rook_attackers.next_index());
         }
 
@@ -1715,14 +2136,14 @@ impl Game {
         let queen_attackers = self.piece_bitboards[Piece::Queen as usize]
             & possible_attacker_positions
             & self.get_occlusion_bitmap_const::<ConstQueen>(square, occupied);
-        if queen_attackers != 0 {
+        if !queen_attackers.is_empty() {
             return Some(queen_attackers.next_index());
         }
 
         let king_attackers = self.piece_bitboards[Piece::King as usize]
             & possible_attacker_positions
             & self.get_piece_attack_bitboard(Piece::King, side, square);
-        if king_attackers != 0 {
+        if !king_attackers.is_empty() {
             return Some(king_attackers.next_index());
         }
 
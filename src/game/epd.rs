@@ -0,0 +1,41 @@
+/// Split the operations of an EPD record (everything after the first four FEN fields) into
+/// `(opcode, operands)` pairs, e.g. `bm Nf3; id "WAC.001";` into `[("bm", "Nf3"), ("id", "\"WAC.001\"")]`.
+/// Semicolons inside quoted operands don't end the operation.
+pub(crate) fn parse_epd_opcodes(operations: &str) -> Vec<(String, String)> {
+    let mut opcodes = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    let mut push_operation = |operation: &str| {
+        let operation = operation.trim();
+        if operation.is_empty() {
+            return;
+        }
+
+        match operation.split_once(char::is_whitespace) {
+            Some((opcode, operands)) => {
+                opcodes.push((opcode.to_string(), operands.trim().to_string()))
+            }
+            None => opcodes.push((operation.to_string(), String::new())),
+        }
+    };
+
+    for char in operations.chars() {
+        match char {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(char);
+            }
+            ';' if !in_quotes => {
+                push_operation(&current);
+                current.clear();
+            }
+            _ => current.push(char),
+        }
+    }
+
+    // the last operation doesn't need to be terminated
+    push_operation(&current);
+
+    opcodes
+}
@@ -51,88 +51,90 @@ pub const MAGIC_TABLE: [(u64, usize, u8); 128] = [
     (0x0101020510040048, 68606, 54),
     (0x8800640092420001, 69630, 53),
     (0x48fffe99fecfaa00, 71678, 53),
-    (0x48fffe99fecfaa00, 73726, 54),
-    (0x48fffe99fecfaa00, 74750, 54),
-    (0x613fffddffce9200, 75774, 54),
-    (0x10010a00610c1a00, 76798, 54),
-    (0x2000040003030500, 77821, 54),
-    (0x0003ff95e5e6a4c0, 78844, 54),
-    (0x800800a400c1a200, 79868, 53),
-    (0xebffffb9ff9fc526, 81915, 52),
-    (0xebffffb9ff9fc526, 86011, 53),
-    (0x53bfffedffdeb1a2, 88059, 53),
-    (0x0840259000230105, 90107, 53),
-    (0x411fffddffdbf4d6, 92154, 53),
-    (0x411fffddffdbf4d6, 94202, 53),
-    (0x0003ffef27eebe74, 96250, 53),
-    (0x7645fffecbfea79e, 98298, 52),
-    (0x0803200303006028, 102394, 58),
-    (0x001308d101003020, 102452, 59),
-    (0xb010044180200808, 102478, 59),
-    (0x1414404281100400, 102510, 59),
-    (0x8044042005070001, 102542, 59),
-    (0x00120131e6200a00, 102574, 59),
-    (0x001380c305180800, 102605, 59),
-    (0x00010a3062080410, 102633, 58),
-    (0x0204060c03014304, 102683, 59),
-    (0x000812a82200803c, 102709, 59),
-    (0x0700088202420020, 102735, 59),
-    (0x0000044142800000, 102767, 59),
-    (0x8000140420050401, 102799, 59),
-    (0x802021c289140040, 102831, 59),
-    (0x0001026444440611, 102861, 59),
-    (0x000059322101880e, 102887, 59),
-    (0x1040108505832300, 102913, 59),
-    (0x80201043029300c0, 102943, 59),
-    (0x0808000400240e09, 102973, 57),
-    (0x21240008c4008020, 103100, 57),
-    (0x0914004294200112, 103228, 57),
-    (0x0001020200820100, 103356, 57),
-    (0x310400016221c450, 103484, 59),
-    (0x904200c030a18600, 103514, 59),
-    (0x10284016051308c0, 103544, 59),
-    (0x100a0802c3980801, 103575, 59),
-    (0x44404808100160c0, 103607, 57),
-    (0x1104040040401080, 103735, 55),
-    (0x082101004210c002, 104247, 55),
-    (0x6010008400c80401, 104759, 57),
-    (0x8204040801014540, 104887, 59),
-    (0x0000808001005808, 104919, 59),
-    (0x0004244620401003, 104951, 59),
-    (0x3008080a00084202, 104983, 59),
-    (0x0404020100580844, 105015, 57),
-    (0x0040880800a60a00, 105143, 55),
-    (0x00280e0020280480, 105655, 55),
-    (0x2111020200088808, 106167, 57),
-    (0x42030141a5040c10, 106295, 59),
-    (0x0048c4342a090100, 106326, 59),
-    (0x20810708b2c0c00a, 106357, 59),
-    (0x0000b18e50002020, 106388, 59),
-    (0x00101828d00c2800, 106419, 57),
-    (0x2503411148000400, 106546, 57),
-    (0x5110098500b06400, 106674, 57),
-    (0x0810095001000020, 106801, 57),
-    (0xa00c031168500400, 106929, 59),
-    (0x08032400a4d22a01, 106959, 59),
-    (0x1881814319061800, 106990, 59),
-    (0x0010340105890050, 107016, 59),
-    (0x200008c0c1418091, 107046, 59),
-    (0x2004000160880022, 107076, 59),
-    (0x3001001002120000, 107108, 59),
-    (0xc00014490281c000, 107140, 59),
-    (0x1130060601406400, 107170, 59),
-    (0x2003020202651200, 107196, 59),
-    (0x8020403085e01100, 107222, 58),
-    (0x20b7008061099120, 107282, 59),
-    (0x100089a060a34188, 107308, 59),
-    (0x00a0004404420880, 107338, 59),
-    (0x0800200090202210, 107370, 59),
-    (0x0202000448500110, 107402, 59),
-    (0x00800b8c0c018050, 107434, 59),
-    (0x0143206104009058, 107460, 58),
+    (0x48fffe99fecfaa00, 73724, 54),
+    (0x48fffe99fecfaa00, 74748, 54),
+    (0x613fffddffce9200, 75767, 54),
+    (0x10010a00610c1a00, 76791, 54),
+    (0x2000040003030500, 77814, 54),
+    (0x0003ff95e5e6a4c0, 78837, 54),
+    (0x800800a400c1a200, 79861, 53),
+    (0xebffffb9ff9fc526, 81908, 52),
+    (0xebffffb9ff9fc526, 85998, 53),
+    (0x53bfffedffdeb1a2, 88040, 53),
+    (0x0840259000230105, 90088, 53),
+    (0x411fffddffdbf4d6, 92135, 53),
+    (0x411fffddffdbf4d6, 94180, 53),
+    (0x0003ffef27eebe74, 96226, 53),
+    (0x7645fffecbfea79e, 98272, 52),
+    (0x0803200303006028, 102368, 58),
+    (0x001308d101003020, 102426, 59),
+    (0xb010044180200808, 102452, 59),
+    (0x1414404281100400, 102484, 59),
+    (0x8044042005070001, 102516, 59),
+    (0x00120131e6200a00, 102548, 59),
+    (0x001380c305180800, 102579, 59),
+    (0x00010a3062080410, 102607, 58),
+    (0x0204060c03014304, 102657, 59),
+    (0x000812a82200803c, 102683, 59),
+    (0x0700088202420020, 102709, 59),
+    (0x0000044142800000, 102741, 59),
+    (0x8000140420050401, 102773, 59),
+    (0x802021c289140040, 102805, 59),
+    (0x0001026444440611, 102835, 59),
+    (0x000059322101880e, 102861, 59),
+    (0x1040108505832300, 102887, 59),
+    (0x80201043029300c0, 102917, 59),
+    (0x0808000400240e09, 102947, 57),
+    (0x21240008c4008020, 103074, 57),
+    (0x0914004294200112, 103202, 57),
+    (0x0001020200820100, 103330, 57),
+    (0x310400016221c450, 103458, 59),
+    (0x904200c030a18600, 103488, 59),
+    (0x10284016051308c0, 103518, 59),
+    (0x100a0802c3980801, 103549, 59),
+    (0x44404808100160c0, 103581, 57),
+    (0x1104040040401080, 103709, 55),
+    (0x082101004210c002, 104221, 55),
+    (0x6010008400c80401, 104733, 57),
+    (0x8204040801014540, 104861, 59),
+    (0x0000808001005808, 104893, 59),
+    (0x0004244620401003, 104925, 59),
+    (0x3008080a00084202, 104957, 59),
+    (0x0404020100580844, 104989, 57),
+    (0x0040880800a60a00, 105117, 55),
+    (0x00280e0020280480, 105629, 55),
+    (0x2111020200088808, 106141, 57),
+    (0x42030141a5040c10, 106269, 59),
+    (0x0048c4342a090100, 106300, 59),
+    (0x20810708b2c0c00a, 106331, 59),
+    (0x0000b18e50002020, 106362, 59),
+    (0x00101828d00c2800, 106393, 57),
+    (0x2503411148000400, 106520, 57),
+    (0x5110098500b06400, 106648, 57),
+    (0x0810095001000020, 106775, 57),
+    (0xa00c031168500400, 106903, 59),
+    (0x08032400a4d22a01, 106933, 59),
+    (0x1881814319061800, 106964, 59),
+    (0x0010340105890050, 106990, 59),
+    (0x200008c0c1418091, 107020, 59),
+    (0x2004000160880022, 107050, 59),
+    (0x3001001002120000, 107082, 59),
+    (0xc00014490281c000, 107114, 59),
+    (0x1130060601406400, 107144, 59),
+    (0x2003020202651200, 107170, 59),
+    (0x8020403085e01100, 107196, 58),
+    (0x20b7008061099120, 107256, 59),
+    (0x100089a060a34188, 107282, 59),
+    (0x00a0004404420880, 107312, 59),
+    (0x0800200090202210, 107344, 59),
+    (0x0202000448500110, 107376, 59),
+    (0x00800b8c0c018050, 107408, 59),
+    (0x0143206104009058, 107434, 58),
 ];
 
-pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
+pub const MAGIC_ENTRY_COUNT: usize = 107496;
+
+pub const MAGIC_ENTRIES: [u64; 107496] = [
     0x01010101010101fe,
     0x000000000000010e,
     0x0000000001010102,
@@ -73857,10 +73859,8 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x0106010100000000,
     0x0000000000000000,
     0x010e010000000000,
-    0x0000000000000000,
-    0x01fe010101010100,
     0x02fd020202020202,
-    0x0000000000000000,
+    0x01fe010101010100,
     0x020d020000000000,
     0x020d020000000000,
     0x020d020202000000,
@@ -75902,16 +75902,11 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x0000000000000000,
     0x040a040404040000,
     0x043b040000000000,
-    0x0000000000000000,
-    0x040a040400000000,
-    0x0000000000000000,
-    0x0000000000000000,
-    0x04fb040404040400,
     0x08f7080808080808,
-    0x0000000000000000,
+    0x040a040400000000,
     0x0814080000000000,
     0x0000000000000000,
-    0x0000000000000000,
+    0x04fb040404040400,
     0x0816080800000000,
     0x0000000000000000,
     0x0817080000000000,
@@ -86138,18 +86133,12 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x1e01010000000000,
     0x0000000000000000,
     0xfe01010100000000,
-    0x0000000000000000,
+    0xfd02020202020202,
     0xfe01010101010000,
     0x1e01010000000000,
     0x0000000000000000,
-    0x0000000000000000,
-    0xfe01010101010100,
-    0xfd02020202020202,
-    0x0000000000000000,
-    0x0000000000000000,
-    0x0000000000000000,
     0x0502000000000000,
-    0x0000000000000000,
+    0xfe01010101010100,
     0x0000000000000000,
     0x0502000000000000,
     0x0502000000000000,
@@ -88186,18 +88175,12 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x3d02020000000000,
     0x0000000000000000,
     0xfd02020200000000,
-    0x0000000000000000,
+    0xfb04040404040404,
     0xfd02020202020000,
     0x3d02020000000000,
     0x0000000000000000,
-    0x0000000000000000,
-    0xfd02020202020200,
-    0xfb04040404040404,
-    0x0000000000000000,
-    0x0000000000000000,
-    0x0000000000000000,
     0x3b04000000000000,
-    0x0000000000000000,
+    0xfd02020202020200,
     0x3b04000000000000,
     0x0000000000000000,
     0x3b04000000000000,
@@ -94332,12 +94315,9 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x2f10100000000000,
     0x0000000000000000,
     0xec10101000000000,
-    0x0000000000000000,
-    0x0000000000000000,
-    0xef10101010101000,
     0xdf20202020202020,
     0x0000000000000000,
-    0x0000000000000000,
+    0xef10101010101000,
     0x0000000000000000,
     0x5820000000000000,
     0x0000000000000000,
@@ -96381,10 +96361,8 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0xdc20202000000000,
     0x0000000000000000,
     0xdc20202020200000,
-    0x0000000000000000,
-    0xdf20202020202000,
     0xbf40404040404040,
-    0x0000000000000000,
+    0xdf20202020202000,
     0xbc40000000000000,
     0xbc40000000000000,
     0x0000000000000000,
@@ -98429,10 +98407,8 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0xa040404000000000,
     0xa040400000000000,
     0x0000000000000000,
-    0x0000000000000000,
-    0xbf40404040404000,
     0x7f80808080808080,
-    0x0000000000000000,
+    0xbf40404040404000,
     0x7c80000000000000,
     0x7c80000000000000,
     0x0000000000000000,
@@ -107656,3 +107632,8 @@ pub const MAGIC_ENTRIES: [Bitboard; 107522] = [
     0x0040000000000000,
     0x0040000000000000,
 ];
+
+#[inline(always)]
+pub fn magic_entry(index: usize) -> Bitboard {
+    Bitboard(MAGIC_ENTRIES[index])
+}
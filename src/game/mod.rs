@@ -1,6 +1,10 @@
 pub mod bitboard;
 pub mod board;
+pub mod epd;
 pub mod magic;
+pub mod pgn;
 pub mod pieces;
+pub mod rules;
+pub mod san;
 pub mod square;
 pub mod zobrist;
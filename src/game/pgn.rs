@@ -0,0 +1,270 @@
+//! Reading games from PGN files, e.g. for building opening books.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::error::ProkopakopError;
+use crate::game::board::{BoardMove, Game};
+
+/// A game read from a PGN file: its tag pairs and the moves played from its starting position
+#[derive(Debug, Clone, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<BoardMove>,
+    pub result: Option<String>, // "1-0", "0-1", "1/2-1/2" or "*", if the movetext ends with it
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The position the game starts from (the FEN tag, or the starting position)
+    pub fn starting_game(&self) -> Result<Game, ProkopakopError> {
+        let game = match self.tag("FEN") {
+            Some(fen) => Game::from_fen(fen)?,
+            None => Game::new(None),
+        };
+        game.validate()?;
+
+        Ok(game)
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Whether the text ends inside a tag pair (one spanning several lines)
+fn has_open_tag(text: &str) -> bool {
+    let mut in_tag = false;
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for char in text.chars() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' if in_tag => in_quotes = !in_quotes,
+            '[' if !in_quotes => in_tag = true,
+            ']' if !in_quotes => in_tag = false,
+            _ => {}
+        }
+    }
+
+    in_tag
+}
+
+/// Parse the `[Name "Value"]` tag pairs of a game, any number of them per line
+fn parse_tags(text: &str) -> Result<Vec<(String, String)>, ProkopakopError> {
+    let invalid = || ProkopakopError::InvalidPgn(format!("malformed tag pairs {}", text.trim()));
+
+    let mut tags = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.next() {
+            None => return Ok(tags),
+            Some('[') => {}
+            Some(_) => return Err(invalid()),
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+            name.push(c);
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if name.is_empty() || chars.next() != Some('"') {
+            return Err(invalid());
+        }
+
+        let mut value = String::new();
+        loop {
+            match chars.next().ok_or_else(invalid)? {
+                '"' => break,
+                '\\' => value.push(chars.next().ok_or_else(invalid)?),
+                c => value.push(c),
+            }
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next() != Some(']') {
+            return Err(invalid());
+        }
+
+        tags.push((name, value));
+    }
+}
+
+/// Strip `{...}` and `;` comments and `(...)` variations (which can be nested) from the
+/// movetext, keeping its tokens apart
+fn strip_movetext(movetext: &str) -> Result<String, ProkopakopError> {
+    let mut stripped = String::new();
+    let mut in_brace_comment = false;
+    let mut in_line_comment = false;
+    let mut variation_depth = 0;
+
+    for char in movetext.chars() {
+        match char {
+            '}' if in_brace_comment => in_brace_comment = false,
+            '\n' if in_line_comment => in_line_comment = false,
+            _ if in_brace_comment || in_line_comment => continue,
+            '{' => in_brace_comment = true,
+            ';' => in_line_comment = true,
+            '(' => variation_depth += 1,
+            ')' if variation_depth == 0 => {
+                return Err(ProkopakopError::InvalidPgn(
+                    "unmatched variation end".to_string(),
+                ));
+            }
+            ')' => variation_depth -= 1,
+            _ if variation_depth > 0 => continue,
+            _ => {
+                stripped.push(char);
+                continue;
+            }
+        }
+
+        stripped.push(' ');
+    }
+
+    if in_brace_comment {
+        return Err(ProkopakopError::InvalidPgn(
+            "unterminated comment".to_string(),
+        ));
+    }
+
+    if variation_depth > 0 {
+        return Err(ProkopakopError::InvalidPgn(
+            "unterminated variation".to_string(),
+        ));
+    }
+
+    Ok(stripped)
+}
+
+/// Play the SAN moves of the movetext from the game's starting position
+fn parse_movetext(game: &mut PgnGame, movetext: &str) -> Result<(), ProkopakopError> {
+    let mut position = game.starting_game()?;
+
+    for token in strip_movetext(movetext)?.split_whitespace() {
+        if RESULTS.contains(&token) {
+            game.result = Some(token.to_string());
+            break;
+        }
+
+        // numeric annotation glyphs ($1 for a good move, ...)
+        if token.starts_with('$') {
+            continue;
+        }
+
+        // move numbers ("12.", "12...") may be glued to the move that follows
+        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if san.is_empty() {
+            continue;
+        }
+
+        let board_move = position.san_to_move(san)?;
+        position.make_move(board_move);
+        game.moves.push(board_move);
+    }
+
+    Ok(())
+}
+
+/// Reads the games of a PGN one at a time, so that even large database dumps don't need to
+/// fit in memory. A game that can't be parsed is an error, after which the next one is read.
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    pending_line: Option<String>, // first line of the next game, read while ending the last
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open(path: &str) -> Result<Self, ProkopakopError> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending_line: None,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, ProkopakopError> {
+        if let Some(line) = self.pending_line.take() {
+            return Ok(Some(line));
+        }
+
+        // names in older PGNs are often Latin-1, which doesn't matter for the moves
+        let mut line = Vec::new();
+        match self.reader.read_until(b'\n', &mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(String::from_utf8_lossy(&line).into_owned())),
+        }
+    }
+
+    /// The next game of the PGN, or None once all of them were read
+    pub fn next_game(&mut self) -> Result<Option<PgnGame>, ProkopakopError> {
+        let mut tag_text = String::new();
+        let mut movetext = String::new();
+
+        while let Some(line) = self.read_line()? {
+            let trimmed = line.trim_start();
+
+            // tags after movetext start the next game
+            if trimmed.starts_with('[') && !movetext.trim().is_empty() {
+                self.pending_line = Some(line);
+                break;
+            }
+
+            if (trimmed.starts_with('[') && movetext.trim().is_empty()) || has_open_tag(&tag_text) {
+                tag_text.push_str(&line);
+                continue;
+            }
+
+            // lines escaped from PGN processing
+            if line.starts_with('%') {
+                continue;
+            }
+
+            movetext.push_str(&line);
+        }
+
+        if tag_text.trim().is_empty() && movetext.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let mut game = PgnGame {
+            tags: parse_tags(&tag_text)?,
+            ..Default::default()
+        };
+        parse_movetext(&mut game, &movetext)?;
+
+        Ok(Some(game))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, ProkopakopError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_game().transpose()
+    }
+}
+
+/// Parse all games of a PGN
+pub fn parse_pgn(pgn: &str) -> Result<Vec<PgnGame>, ProkopakopError> {
+    PgnReader::new(pgn.as_bytes()).collect()
+}
+
+pub fn parse_pgn_file(path: &str) -> Result<Vec<PgnGame>, ProkopakopError> {
+    PgnReader::open(path)?.collect()
+}
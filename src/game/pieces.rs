@@ -32,8 +32,8 @@ impl Not for Color {
     }
 }
 
-impl Mul<Color> for f32 {
-    type Output = f32;
+impl Mul<Color> for i32 {
+    type Output = i32;
 
     fn mul(self, rhs: Color) -> Self::Output {
         match rhs {
@@ -0,0 +1,97 @@
+use crate::game::bitboard::Bitboard;
+use crate::game::board::{BoardMove, Game};
+use crate::game::pieces::Piece;
+use crate::game::square::BoardSquare;
+
+// d4, e4, d5 and e5, which a king reaching wins King of the Hill
+const HILL: Bitboard = Bitboard(
+    BoardSquare::D4.to_mask().0
+        | BoardSquare::E4.to_mask().0
+        | BoardSquare::D5.to_mask().0
+        | BoardSquare::E5.to_mask().0,
+);
+
+/// Rules of a chess variant, on top of the standard move generation.
+///
+/// Variants that only add ways to win (like King of the Hill) just implement `has_lost`;
+/// ones that change how moves are made (atomic explosions, crazyhouse drops) will need
+/// hooks into `make_move` too, which the board doesn't have yet.
+pub trait Rules {
+    /// Name of the variant as given to `UCI_Variant`
+    const NAME: &'static str;
+
+    /// Whether the side to move has already lost by a rule of the variant (besides checkmate)
+    fn has_lost(game: &Game) -> bool;
+
+    /// Legal moves of the side to move, none once the game is over
+    fn get_moves(game: &Game) -> (usize, [BoardMove; 256]) {
+        if Self::has_lost(game) {
+            return (0, [BoardMove::default(); 256]);
+        }
+
+        game.get_side_moves(game.side)
+    }
+}
+
+pub struct Standard;
+
+impl Rules for Standard {
+    const NAME: &'static str = "chess";
+
+    fn has_lost(_game: &Game) -> bool {
+        false
+    }
+}
+
+/// Standard chess, but getting the king to one of the four center squares also wins
+pub struct KingOfTheHill;
+
+impl Rules for KingOfTheHill {
+    const NAME: &'static str = "kingofthehill";
+
+    fn has_lost(game: &Game) -> bool {
+        let opponent_king =
+            game.piece_bitboards[Piece::King as usize] & game.color_bitboards[!game.side as usize];
+
+        !(opponent_king & HILL).is_empty()
+    }
+}
+
+/// The variant a game is played by, selecting its `Rules` implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    KingOfTheHill,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 2] = [Variant::Standard, Variant::KingOfTheHill];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => Standard::NAME,
+            Variant::KingOfTheHill => KingOfTheHill::NAME,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Variant> {
+        Self::ALL
+            .into_iter()
+            .find(|variant| variant.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn has_lost(self, game: &Game) -> bool {
+        match self {
+            Variant::Standard => Standard::has_lost(game),
+            Variant::KingOfTheHill => KingOfTheHill::has_lost(game),
+        }
+    }
+
+    pub fn get_moves(self, game: &Game) -> (usize, [BoardMove; 256]) {
+        match self {
+            Variant::Standard => Standard::get_moves(game),
+            Variant::KingOfTheHill => KingOfTheHill::get_moves(game),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::error::ProkopakopError;
+use crate::game::board::{BoardMove, Game, MoveKind};
+use crate::game::pieces::{Color, Piece};
+use crate::game::square::BoardSquare;
+
+impl Game {
+    /// Find the legal move given in standard algebraic notation. Check marks, annotations
+    /// (`!`, `?`) and the `=` of promotions are optional, and castling may use zeros.
+    pub(crate) fn san_to_move(&self, san: &str) -> Result<BoardMove, ProkopakopError> {
+        let illegal = || ProkopakopError::IllegalMove(san.to_string());
+
+        let (move_count, moves) = self.get_moves();
+        let moves = &moves[0..move_count];
+
+        let stripped = san.trim_end_matches(['+', '#', '!', '?']);
+
+        let castling_x = match stripped {
+            "O-O" | "0-0" => Some(6),
+            "O-O-O" | "0-0-0" => Some(2),
+            _ => None,
+        };
+        if let Some(to_x) = castling_x {
+            return moves
+                .iter()
+                .copied()
+                .find(|m| m.get_kind() == MoveKind::Castling && m.get_to().get_x() == to_x)
+                .ok_or_else(illegal);
+        }
+
+        let mut chars = stripped.chars().collect::<Vec<_>>();
+
+        // promotions are the only moves ending in an (uppercase) piece
+        let mut promotion = None;
+        if chars.len() > 2 && matches!(chars.last(), Some('Q' | 'R' | 'B' | 'N')) {
+            promotion = chars
+                .pop()
+                .and_then(|c| Piece::from_char(c.to_ascii_lowercase()));
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        let piece = match chars.first() {
+            Some(c) if c.is_ascii_uppercase() => {
+                let piece = Piece::from_char(c.to_ascii_lowercase()).ok_or_else(illegal)?;
+                chars.remove(0);
+                piece
+            }
+            _ => Piece::Pawn,
+        };
+
+        if chars.len() < 2 {
+            return Err(illegal());
+        }
+        let target = chars
+            .split_off(chars.len() - 2)
+            .into_iter()
+            .collect::<String>();
+        let to = BoardSquare::parse(&target).ok_or_else(illegal)?;
+
+        // what remains is the capture mark and the file and/or rank of the moving piece
+        let mut from_x = None;
+        let mut from_y = None;
+        for c in chars.into_iter().filter(|&c| c != 'x') {
+            match c {
+                'a'..='h' => from_x = Some(c as u8 - b'a'),
+                '1'..='8' => from_y = Some(c as u8 - b'1'),
+                _ => return Err(illegal()),
+            }
+        }
+
+        let mut candidates = moves.iter().copied().filter(|m| {
+            let from = m.get_from();
+            m.get_to() == to
+                && m.get_promotion() == promotion
+                && self.pieces.get(from).map(|(p, _)| p) == Some(piece)
+                && from_x.is_none_or(|x| from.get_x() == x)
+                && from_y.is_none_or(|y| from.get_y() == y)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(board_move), None) => Ok(board_move),
+            _ => Err(illegal()),
+        }
+    }
+
+    /// Format a legal move in standard algebraic notation (e.g. `Nbd7`, `exd6`, `e8=Q+`, `O-O`).
+    pub(crate) fn move_to_san(&mut self, board_move: BoardMove) -> String {
+        let from = board_move.get_from();
+        let to = board_move.get_to();
+
+        let Some((piece, _)) = self.pieces.get(from) else {
+            return board_move.unparse();
+        };
+
+        let mut san = String::new();
+
+        if board_move.get_kind() == MoveKind::Castling {
+            san.push_str(if to.get_x() == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            let is_capture = self.is_capture(board_move);
+
+            if piece == Piece::Pawn {
+                if is_capture {
+                    san.push((b'a' + from.get_x()) as char);
+                }
+            } else {
+                san.push(piece.to_char().to_ascii_uppercase());
+
+                // other pieces of the same type that can also go to the target square
+                let (move_count, moves) = self.get_moves();
+                let others: Vec<_> = moves[0..move_count]
+                    .iter()
+                    .filter(|m| {
+                        m.get_to() == to
+                            && m.get_from() != from
+                            && self.pieces.get(m.get_from()).map(|(p, _)| p) == Some(piece)
+                    })
+                    .map(|m| m.get_from())
+                    .collect();
+
+                if !others.is_empty() {
+                    let same_file = others.iter().any(|sq| sq.get_x() == from.get_x());
+                    let same_rank = others.iter().any(|sq| sq.get_y() == from.get_y());
+
+                    let square = from.unparse();
+                    if !same_file {
+                        san.push_str(&square[0..1]);
+                    } else if !same_rank {
+                        san.push_str(&square[1..2]);
+                    } else {
+                        san.push_str(&square);
+                    }
+                }
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+
+            san.push_str(&to.unparse());
+
+            if let Some(promotion) = board_move.get_promotion() {
+                san.push('=');
+                san.push(promotion.to_char().to_ascii_uppercase());
+            }
+        }
+
+        self.make_move(board_move);
+        if self.is_king_in_check(self.side) {
+            let (move_count, _) = self.get_moves();
+            san.push(if move_count == 0 { '#' } else { '+' });
+        }
+        self.unmake_move();
+
+        san
+    }
+
+    /// Format a line of moves played from the current position as numbered SAN,
+    /// e.g. `12... Nf6 13. e5 Nd5`. Stops early at the first illegal move.
+    pub(crate) fn line_to_san(&mut self, moves: &[BoardMove]) -> String {
+        let mut parts = Vec::new();
+        let mut played = 0;
+
+        for board_move in moves {
+            let (move_count, legal_moves) = self.get_moves();
+            if !legal_moves[0..move_count].contains(board_move) {
+                break;
+            }
+
+            let fullmoves = self.get_fullmoves();
+            match self.side {
+                Color::White => parts.push(format!("{}.", fullmoves)),
+                Color::Black if played == 0 => parts.push(format!("{}...", fullmoves)),
+                Color::Black => {}
+            }
+
+            parts.push(self.move_to_san(*board_move));
+
+            self.make_move(*board_move);
+            played += 1;
+        }
+
+        for _ in 0..played {
+            self.unmake_move();
+        }
+
+        parts.join(" ")
+    }
+}
@@ -1,122 +1,127 @@
 use crate::game::bitboard::Bitboard;
 
-pub type BoardSquare = u8;
+/// A square of the board, numbered by ranks from a1 (0) to h8 (63)
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BoardSquare(pub u8);
 
 #[allow(dead_code)]
-pub trait BoardSquareExt {
-    fn get_x(&self) -> u8;
-    fn get_y(&self) -> u8;
-    fn parse(string: &str) -> Option<BoardSquare>;
-    fn unparse(&self) -> String;
-    fn from_position(x: u8, y: u8) -> BoardSquare;
-    fn to_mask(&self) -> Bitboard;
-
-    const A1: BoardSquare = 0;
-    const A2: BoardSquare = 8;
-    const A3: BoardSquare = 16;
-    const A4: BoardSquare = 24;
-    const A5: BoardSquare = 32;
-    const A6: BoardSquare = 40;
-    const A7: BoardSquare = 48;
-    const A8: BoardSquare = 56;
-
-    const B1: BoardSquare = 1;
-    const B2: BoardSquare = 9;
-    const B3: BoardSquare = 17;
-    const B4: BoardSquare = 25;
-    const B5: BoardSquare = 33;
-    const B6: BoardSquare = 41;
-    const B7: BoardSquare = 49;
-    const B8: BoardSquare = 57;
-
-    const C1: BoardSquare = 2;
-    const C2: BoardSquare = 10;
-    const C3: BoardSquare = 18;
-    const C4: BoardSquare = 26;
-    const C5: BoardSquare = 34;
-    const C6: BoardSquare = 42;
-    const C7: BoardSquare = 50;
-    const C8: BoardSquare = 58;
-
-    const D1: BoardSquare = 3;
-    const D2: BoardSquare = 11;
-    const D3: BoardSquare = 19;
-    const D4: BoardSquare = 27;
-    const D5: BoardSquare = 35;
-    const D6: BoardSquare = 43;
-    const D7: BoardSquare = 51;
-    const D8: BoardSquare = 59;
-
-    const E1: BoardSquare = 4;
-    const E2: BoardSquare = 12;
-    const E3: BoardSquare = 20;
-    const E4: BoardSquare = 28;
-    const E5: BoardSquare = 36;
-    const E6: BoardSquare = 44;
-    const E7: BoardSquare = 52;
-    const E8: BoardSquare = 60;
-
-    const F1: BoardSquare = 5;
-    const F2: BoardSquare = 13;
-    const F3: BoardSquare = 21;
-    const F4: BoardSquare = 29;
-    const F5: BoardSquare = 37;
-    const F6: BoardSquare = 45;
-    const F7: BoardSquare = 53;
-    const F8: BoardSquare = 61;
-
-    const G1: BoardSquare = 6;
-    const G2: BoardSquare = 14;
-    const G3: BoardSquare = 22;
-    const G4: BoardSquare = 30;
-    const G5: BoardSquare = 38;
-    const G6: BoardSquare = 46;
-    const G7: BoardSquare = 54;
-    const G8: BoardSquare = 62;
-
-    const H1: BoardSquare = 7;
-    const H2: BoardSquare = 15;
-    const H3: BoardSquare = 23;
-    const H4: BoardSquare = 31;
-    const H5: BoardSquare = 39;
-    const H6: BoardSquare = 47;
-    const H7: BoardSquare = 55;
-    const H8: BoardSquare = 63;
-}
+impl BoardSquare {
+    pub const A1: BoardSquare = BoardSquare(0);
+    pub const A2: BoardSquare = BoardSquare(8);
+    pub const A3: BoardSquare = BoardSquare(16);
+    pub const A4: BoardSquare = BoardSquare(24);
+    pub const A5: BoardSquare = BoardSquare(32);
+    pub const A6: BoardSquare = BoardSquare(40);
+    pub const A7: BoardSquare = BoardSquare(48);
+    pub const A8: BoardSquare = BoardSquare(56);
+
+    pub const B1: BoardSquare = BoardSquare(1);
+    pub const B2: BoardSquare = BoardSquare(9);
+    pub const B3: BoardSquare = BoardSquare(17);
+    pub const B4: BoardSquare = BoardSquare(25);
+    pub const B5: BoardSquare = BoardSquare(33);
+    pub const B6: BoardSquare = BoardSquare(41);
+    pub const B7: BoardSquare = BoardSquare(49);
+    pub const B8: BoardSquare = BoardSquare(57);
+
+    pub const C1: BoardSquare = BoardSquare(2);
+    pub const C2: BoardSquare = BoardSquare(10);
+    pub const C3: BoardSquare = BoardSquare(18);
+    pub const C4: BoardSquare = BoardSquare(26);
+    pub const C5: BoardSquare = BoardSquare(34);
+    pub const C6: BoardSquare = BoardSquare(42);
+    pub const C7: BoardSquare = BoardSquare(50);
+    pub const C8: BoardSquare = BoardSquare(58);
+
+    pub const D1: BoardSquare = BoardSquare(3);
+    pub const D2: BoardSquare = BoardSquare(11);
+    pub const D3: BoardSquare = BoardSquare(19);
+    pub const D4: BoardSquare = BoardSquare(27);
+    pub const D5: BoardSquare = BoardSquare(35);
+    pub const D6: BoardSquare = BoardSquare(43);
+    pub const D7: BoardSquare = BoardSquare(51);
+    pub const D8: BoardSquare = BoardSquare(59);
+
+    pub const E1: BoardSquare = BoardSquare(4);
+    pub const E2: BoardSquare = BoardSquare(12);
+    pub const E3: BoardSquare = BoardSquare(20);
+    pub const E4: BoardSquare = BoardSquare(28);
+    pub const E5: BoardSquare = BoardSquare(36);
+    pub const E6: BoardSquare = BoardSquare(44);
+    pub const E7: BoardSquare = BoardSquare(52);
+    pub const E8: BoardSquare = BoardSquare(60);
+
+    pub const F1: BoardSquare = BoardSquare(5);
+    pub const F2: BoardSquare = BoardSquare(13);
+    pub const F3: BoardSquare = BoardSquare(21);
+    pub const F4: BoardSquare = BoardSquare(29);
+    pub const F5: BoardSquare = BoardSquare(37);
+    pub const F6: BoardSquare = BoardSquare(45);
+    pub const F7: BoardSquare = BoardSquare(53);
+    pub const F8: BoardSquare = BoardSquare(61);
+
+    pub const G1: BoardSquare = BoardSquare(6);
+    pub const G2: BoardSquare = BoardSquare(14);
+    pub const G3: BoardSquare = BoardSquare(22);
+    pub const G4: BoardSquare = BoardSquare(30);
+    pub const G5: BoardSquare = BoardSquare(38);
+    pub const G6: BoardSquare = BoardSquare(46);
+    pub const G7: BoardSquare = BoardSquare(54);
+    pub const G8: BoardSquare = BoardSquare(62);
+
+    pub const H1: BoardSquare = BoardSquare(7);
+    pub const H2: BoardSquare = BoardSquare(15);
+    pub const H3: BoardSquare = BoardSquare(23);
+    pub const H4: BoardSquare = BoardSquare(31);
+    pub const H5: BoardSquare = BoardSquare(39);
+    pub const H6: BoardSquare = BoardSquare(47);
+    pub const H7: BoardSquare = BoardSquare(55);
+    pub const H8: BoardSquare = BoardSquare(63);
+
+    #[inline(always)]
+    pub const fn get_x(self) -> u8 {
+        self.0 % 8
+    }
 
-impl BoardSquareExt for u8 {
-    fn get_x(&self) -> u8 {
-        self % 8
+    #[inline(always)]
+    pub const fn get_y(self) -> u8 {
+        self.0 / 8
     }
 
-    fn get_y(&self) -> u8 {
-        self / 8
+    /// For indexing the tables that have an entry per square
+    #[inline(always)]
+    pub const fn index(self) -> usize {
+        self.0 as usize
     }
 
-    fn parse(string: &str) -> Option<BoardSquare> {
+    pub fn parse(string: &str) -> Option<BoardSquare> {
         let mut chars = string.chars();
 
         match (chars.next(), chars.next()) {
-            (Some(file), Some(rank)) if file.is_alphabetic() && rank.is_numeric() => Some(
-                BoardSquare::from_position(file as u8 - b'a' as u8, rank as u8 - b'1' as u8),
-            ),
+            (Some(file @ 'a'..='h'), Some(rank @ '1'..='8')) => Some(BoardSquare::from_position(
+                file as u8 - b'a',
+                rank as u8 - b'1',
+            )),
             (_, _) => None,
         }
     }
 
-    fn unparse(&self) -> String {
+    pub fn unparse(self) -> String {
         format!(
             "{}{}",
-            (self.get_x() + b'a' as u8) as char,
-            (self.get_y() + b'1' as u8) as char
+            (self.get_x() + b'a') as char,
+            (self.get_y() + b'1') as char
         )
     }
 
-    fn from_position(x: u8, y: u8) -> BoardSquare {
-        x + y * 8
+    #[inline(always)]
+    pub const fn from_position(x: u8, y: u8) -> BoardSquare {
+        BoardSquare(x + y * 8)
     }
 
-    fn to_mask(&self) -> Bitboard {
-        1 << self
+    #[inline(always)]
+    pub const fn to_mask(self) -> Bitboard {
+        Bitboard(1 << self.0)
     }
 }
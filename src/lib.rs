@@ -1,4 +1,36 @@
 pub mod controller;
 pub mod engine;
+pub mod error;
+pub mod fuzz;
 pub mod game;
 pub mod test;
+
+// the one controller behind the UCI, XBoard and CLI front ends
+pub use controller::controller::GameController;
+pub use error::ProkopakopError;
+use game::board::Game;
+
+/// Number of positions `depth` plies from the given FEN (perft).
+///
+/// A stable entry point for scripts and fuzzers that don't need the controller API.
+pub fn perft(fen: &str, depth: usize) -> Result<usize, ProkopakopError> {
+    if depth == 0 {
+        Game::from_fen(fen)?.validate()?;
+        return Ok(1);
+    }
+
+    let mut controller = GameController::new();
+    controller.set_board_from_fen(fen)?;
+
+    Ok(controller.perft(depth).iter().map(|(_, count)| count).sum())
+}
+
+/// Static evaluation of the given FEN in centipawns, from white's perspective.
+///
+/// A stable entry point for scripts and fuzzers that don't need the controller API.
+pub fn evaluate(fen: &str) -> Result<i32, ProkopakopError> {
+    let game = Game::from_fen(fen)?;
+    game.validate()?;
+
+    Ok(game.evaluate())
+}
@@ -1,10 +1,27 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
+use prokopakop::controller::batch::{BatchConfig, run_batch_analysis};
+use prokopakop::controller::bench::{BenchConfig, movegen_checksum, run_bench};
+use prokopakop::controller::bot::{BotConfig, LICHESS_URL, TOKEN_VARIABLE, run_bot};
 use prokopakop::controller::cli::GUICommand;
-use prokopakop::controller::controller::{GameController, MoveResultType};
+use prokopakop::controller::config::EngineConfig;
+use prokopakop::controller::controller::{GameController, PerftCategories};
+use prokopakop::controller::eval_diff::{EvalDiffConfig, run_eval_diff};
+use prokopakop::controller::kibitz::run_kibitz;
+use prokopakop::controller::match_runner::{MatchConfig, MaterialRule, run_match};
 use prokopakop::controller::optimize::{OptimizeConfig, run_optimizer};
-use prokopakop::controller::training::{TrainingConfig, TrainingDataGenerator};
+use prokopakop::controller::selftest::fen_selftest;
+use prokopakop::controller::training::{
+    Dedupe, TrainingConfig, TrainingDataGenerator, install_interrupt_handler,
+};
+use prokopakop::controller::verify::{VerifyPruningConfig, run_pruning_verification};
+use prokopakop::controller::xboard::run_xboard;
+use prokopakop::engine::book::OpeningBook;
+use prokopakop::engine::wdl::WdlModel;
+use prokopakop::error::{ProkopakopError, parse_number};
 use prokopakop::game::bitboard::generate_magic_bitboards;
-use prokopakop::game::board::BoardMoveExt;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 fn main() {
     env_logger::init();
@@ -19,6 +36,12 @@ fn main() {
                 .help("Generate magic bitboards")
                 .num_args(0),
         )
+        .arg(
+            Arg::new("magic-dedup")
+                .long("magic-dedup")
+                .help("With --magic, store each distinct block of the attack table only once")
+                .num_args(0),
+        )
         .arg(
             Arg::new("selfplay")
                 .long("selfplay")
@@ -31,6 +54,63 @@ fn main() {
                 .help("Run SPSA parameter optimization")
                 .num_args(0),
         )
+        .arg(
+            Arg::new("verify-pruning")
+                .long("verify-pruning")
+                .help(
+                    "Re-search a sample of pruned nodes without pruning and report the differences",
+                )
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("match")
+                .long("match")
+                .help("Play a match between two UCI engines and write the games as PGN")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("bot")
+                .long("bot")
+                .help("Play on Lichess as a bot (token in the LICHESS_BOT_TOKEN variable)")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("analyze-batch")
+                .long("analyze-batch")
+                .help("Analyse the positions of --input to --depth, keeping the TT between them")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("bench-internal")
+                .long("bench-internal")
+                .help("Time move generation, make/unmake and evaluation on a fixed set of positions")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("build-book")
+                .long("build-book")
+                .value_name("FILE")
+                .help("Build a book file (which book learning can write to) from the games of a PGN"),
+        )
+        .arg(
+            Arg::new("eval-diff")
+                .long("eval-diff")
+                .value_name("FILE")
+                .help("Write the positions of a PGN the NNUE and the classical evaluation disagree on most"),
+        )
+        .arg(
+            Arg::new("top")
+                .long("top")
+                .value_name("NUM")
+                .help("Number of positions written by --eval-diff (default: 50)")
+                .default_value("50"),
+        )
+        .arg(
+            Arg::new("fit-wdl")
+                .long("fit-wdl")
+                .value_name("FILE")
+                .help("Fit the win/draw/loss model to selfplay data"),
+        )
         .arg(
             Arg::new("games")
                 .short('g')
@@ -104,11 +184,148 @@ fn main() {
                 .help("SPSA perturbation size (default: 0.05)")
                 .default_value("0.05"),
         )
+        // Pruning verification arguments
+        .arg(
+            Arg::new("positions")
+                .long("positions")
+                .value_name("NUM")
+                .help("Number of random positions to verify pruning on (default: 100)")
+                .default_value("100"),
+        )
+        .arg(
+            Arg::new("sample-rate")
+                .long("sample-rate")
+                .value_name("FLOAT")
+                .help("Fraction of pruned nodes to re-search (default: 0.05)")
+                .default_value("0.05"),
+        )
+        .arg(
+            Arg::new("tolerance")
+                .long("tolerance")
+                .value_name("CP")
+                .help("Centipawns the unpruned value may differ by (default: 50)")
+                .default_value("50"),
+        )
+        // Selfplay sampling arguments
+        .arg(
+            Arg::new("disagreement-margin")
+                .long("disagreement-margin")
+                .value_name("CP")
+                .help("Keep positions where NNUE and HCE disagree by at least this much"),
+        )
+        .arg(
+            Arg::new("keep-fraction")
+                .long("keep-fraction")
+                .value_name("FLOAT")
+                .help("Fraction of the other positions kept with --disagreement-margin (default: 0.1)")
+                .default_value("0.1"),
+        )
+        .arg(
+            Arg::new("max-plies")
+                .long("max-plies")
+                .value_name("NUM")
+                .help("Cut off selfplay games after this many plies, discarding their positions (default: 400)")
+                .default_value("400"),
+        )
+        .arg(
+            Arg::new("pv-leaf-eval")
+                .long("pv-leaf-eval")
+                .help("Label selfplay positions with the evaluation of the quiet PV leaf instead of the search score")
+                .num_args(0),
+        )
+        .arg(
+            Arg::new("material-rule")
+                .long("material-rule")
+                .value_name("RULE")
+                .help("Draw selfplay and match games without the material to mate, by the fide (no possible mate) or uscf (no forced mate) rule (default: fide)")
+                .default_value("fide"),
+        )
+        .arg(
+            Arg::new("dedupe")
+                .long("dedupe")
+                .value_name("MODE")
+                .help("Write each selfplay position once, remembering them exactly or in a bloom filter that drops a few new ones too (exact or bloom, default: exact)")
+                .num_args(0..=1)
+                .default_missing_value("exact"),
+        )
+        .arg(
+            Arg::new("dedupe-memory")
+                .long("dedupe-memory")
+                .value_name("MB")
+                .help("Size of the bloom filter of --dedupe bloom (default: 256)")
+                .default_value("256"),
+        )
+        // Match arguments
+        .arg(
+            Arg::new("engine-a")
+                .long("engine-a")
+                .value_name("PATH")
+                .help("First engine of the match"),
+        )
+        .arg(
+            Arg::new("engine-b")
+                .long("engine-b")
+                .value_name("PATH")
+                .help("Second engine of the match"),
+        )
+        // Batch analysis arguments
+        .arg(
+            Arg::new("input")
+                .long("input")
+                .value_name("FILE")
+                .help("Positions to analyse with --analyze-batch, a FEN or EPD record per line"),
+        )
+        // Opening book arguments (match and bot)
+        .arg(
+            Arg::new("book")
+                .long("book")
+                .value_name("FILE")
+                .help("Opening book to play from, a book file or a PGN"),
+        )
+        .arg(
+            Arg::new("book-learning")
+                .long("book-learning")
+                .help("Adjust the weights of the book moves played from the game results")
+                .num_args(0),
+        )
+        // Bot arguments
+        .arg(
+            Arg::new("bot-games")
+                .long("bot-games")
+                .value_name("NUM")
+                .help("Number of games to play as a bot before stopping (default: no limit)"),
+        )
+        // Benchmark arguments
+        .arg(
+            Arg::new("bench-time")
+                .long("bench-time")
+                .value_name("MS")
+                .help("Time of each of the 10 samples of a benchmark (default: 200)")
+                .default_value("200"),
+        )
+        .arg(
+            Arg::new("bench-save")
+                .long("bench-save")
+                .value_name("FILE")
+                .help("Save the benchmark results, e.g. to compare a later commit against"),
+        )
+        .arg(
+            Arg::new("bench-baseline")
+                .long("bench-baseline")
+                .value_name("FILE")
+                .help("Compare the benchmark results against ones saved with --bench-save"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Default option values (default: prokopakop.toml next to the binary, if there is one)"),
+        )
         .get_matches();
 
     // Handle magic flag
     if matches.get_flag("magic") {
-        generate_magic_bitboards();
+        generate_magic_bitboards(matches.get_flag("magic-dedup"));
         return;
     }
 
@@ -160,6 +377,339 @@ fn main() {
         return;
     }
 
+    // Handle verify-pruning flag
+    if matches.get_flag("verify-pruning") {
+        let num_positions = matches
+            .get_one::<String>("positions")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let search_depth = matches
+            .get_one::<String>("depth")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap();
+
+        let start_moves_min = matches
+            .get_one::<String>("start-moves-min")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let start_moves_max = matches
+            .get_one::<String>("start-moves-max")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let sample_rate = matches
+            .get_one::<String>("sample-rate")
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap();
+
+        let tolerance = matches
+            .get_one::<String>("tolerance")
+            .and_then(|s| s.parse::<i32>().ok())
+            .unwrap();
+
+        if start_moves_min > start_moves_max {
+            eprintln!("Error: start-moves-min must be <= start-moves-max");
+            std::process::exit(1);
+        }
+
+        eprintln!("=== Pruning Verification ===");
+        eprintln!("Positions: {}", num_positions);
+        eprintln!("Search depth: {}", search_depth);
+        eprintln!("Starting moves: {} - {}", start_moves_min, start_moves_max);
+        eprintln!("Sample rate: {}", sample_rate);
+        eprintln!("Tolerance: {}", tolerance);
+        eprintln!();
+
+        let config = VerifyPruningConfig {
+            num_positions,
+            search_depth,
+            start_moves_min,
+            start_moves_max,
+            sample_rate,
+            tolerance,
+        };
+
+        print!("{}", run_pruning_verification(&config));
+        return;
+    }
+
+    // Handle match flag
+    if matches.get_flag("match") {
+        let (Some(engine_a), Some(engine_b)) = (
+            matches.get_one::<String>("engine-a"),
+            matches.get_one::<String>("engine-b"),
+        ) else {
+            eprintln!("Error: --match requires --engine-a and --engine-b");
+            std::process::exit(1);
+        };
+
+        let games = matches
+            .get_one::<String>("games")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let tc = matches
+            .get_one::<String>("tc")
+            .map(|s| s.to_string())
+            .unwrap();
+
+        let concurrency = matches
+            .get_one::<String>("concurrency")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let output_file = matches
+            .get_one::<String>("output")
+            .map(|s| s.as_str())
+            .unwrap_or("data/match.pgn");
+
+        let start_moves_min = matches
+            .get_one::<String>("start-moves-min")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        let start_moves_max = matches
+            .get_one::<String>("start-moves-max")
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap();
+
+        if start_moves_min > start_moves_max {
+            eprintln!("Error: start-moves-min must be <= start-moves-max");
+            std::process::exit(1);
+        }
+
+        let book = matches.get_one::<String>("book").cloned();
+        let book_learning = matches.get_flag("book-learning");
+        let material_rule = parse_material_rule(&matches);
+
+        eprintln!("=== Engine Match ===");
+        eprintln!("Engine A: {}", engine_a);
+        eprintln!("Engine B: {}", engine_b);
+        eprintln!("Games: {}", games);
+        eprintln!("Time control: {}", tc);
+        eprintln!("Concurrency: {}", concurrency);
+        match &book {
+            Some(book) => eprintln!("Book: {} (learning: {})", book, book_learning),
+            None => eprintln!("Starting moves: {} - {}", start_moves_min, start_moves_max),
+        }
+        eprintln!("Material rule: {}", material_rule.name());
+        eprintln!("Output file: {}", output_file);
+        eprintln!();
+
+        let config = MatchConfig {
+            engine_a: engine_a.clone(),
+            engine_b: engine_b.clone(),
+            games,
+            time_control: tc,
+            concurrency,
+            start_moves_min,
+            start_moves_max,
+            pgn_path: output_file.to_string(),
+            book,
+            book_learning,
+            material_rule,
+        };
+
+        if let Err(e) = run_match(config) {
+            eprintln!("Error during match: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Handle analyze-batch flag
+    if matches.get_flag("analyze-batch") {
+        let Some(input) = matches.get_one::<String>("input") else {
+            eprintln!("Error: --analyze-batch requires --input");
+            std::process::exit(1);
+        };
+
+        let depth = matches
+            .get_one::<String>("depth")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap();
+
+        let output = matches.get_one::<String>("output").cloned();
+
+        eprintln!("=== Batch Analysis ===");
+        eprintln!("Input: {}", input);
+        eprintln!("Search depth: {}", depth);
+        if let Some(output) = &output {
+            eprintln!("Output file: {}", output);
+        }
+        eprintln!();
+
+        let config = BatchConfig {
+            input: input.clone(),
+            depth,
+            output,
+        };
+
+        if let Err(e) = run_batch_analysis(&config) {
+            eprintln!("Error during batch analysis: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Handle bench-internal flag
+    if matches.get_flag("bench-internal") {
+        let sample_time = matches
+            .get_one::<String>("bench-time")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap();
+
+        let save = matches.get_one::<String>("bench-save").cloned();
+        let baseline = matches.get_one::<String>("bench-baseline").cloned();
+
+        eprintln!("=== Internal Benchmarks ===");
+        eprintln!("Sample time: {} ms", sample_time);
+        if let Some(path) = &baseline {
+            eprintln!("Baseline: {}", path);
+        }
+        eprintln!();
+
+        let config = BenchConfig {
+            sample_time: Duration::from_millis(sample_time),
+            save,
+            baseline,
+        };
+
+        if let Err(e) = run_bench(config) {
+            eprintln!("Error while benchmarking: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Handle bot flag
+    if matches.get_flag("bot") {
+        let Ok(token) = std::env::var(TOKEN_VARIABLE) else {
+            eprintln!(
+                "Error: --bot requires a Lichess API token in {}",
+                TOKEN_VARIABLE
+            );
+            std::process::exit(1);
+        };
+
+        let max_games = matches
+            .get_one::<String>("bot-games")
+            .and_then(|s| s.parse::<u32>().ok());
+
+        let book = matches.get_one::<String>("book").cloned();
+        let book_learning = matches.get_flag("book-learning");
+
+        eprintln!("=== Lichess Bot ===");
+        eprintln!("Server: {}", LICHESS_URL);
+        match max_games {
+            Some(games) => eprintln!("Games: {}", games),
+            None => eprintln!("Games: no limit"),
+        }
+        if let Some(book) = &book {
+            eprintln!("Book: {} (learning: {})", book, book_learning);
+        }
+        eprintln!();
+
+        let config = BotConfig {
+            token,
+            url: LICHESS_URL.to_string(),
+            max_games,
+            book,
+            book_learning,
+        };
+
+        if let Err(e) = run_bot(config) {
+            eprintln!("Error while playing as a bot: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Handle build-book flag
+    if let Some(path) = matches.get_one::<String>("build-book") {
+        let output_file = matches
+            .get_one::<String>("output")
+            .map(|s| s.as_str())
+            .unwrap_or("data/opening.book");
+
+        eprintln!("=== Opening Book ===");
+        eprintln!("Games: {}", path);
+        eprintln!("Output file: {}", output_file);
+        eprintln!();
+
+        let book = OpeningBook::open(Path::new(path))
+            .and_then(|book| book.save_as(Path::new(output_file)));
+
+        match book {
+            Ok(book) => eprintln!("Book positions: {}", book.position_count()),
+            Err(e) => {
+                eprintln!("Error building the book: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Handle eval-diff flag
+    if let Some(path) = matches.get_one::<String>("eval-diff") {
+        let count = matches
+            .get_one::<String>("top")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(50);
+
+        let output = matches.get_one::<String>("output").cloned();
+
+        eprintln!("=== Evaluation Diff ===");
+        eprintln!("Games: {}", path);
+        eprintln!("Positions written: {}", count);
+        if let Some(output) = &output {
+            eprintln!("Output file: {}", output);
+        }
+        eprintln!();
+
+        let config = EvalDiffConfig {
+            input: path.clone(),
+            output,
+            count,
+        };
+
+        match run_eval_diff(&config) {
+            Ok(summary) => eprintln!("{}", summary),
+            Err(e) => {
+                eprintln!("Error comparing the evaluations: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Handle fit-wdl flag
+    if let Some(path) = matches.get_one::<String>("fit-wdl") {
+        eprintln!("=== WDL Model Fit ===");
+        eprintln!("Data: {}", path);
+        eprintln!();
+
+        match WdlModel::fit_file(path) {
+            Ok(model) => {
+                println!(
+                    "pub const WDL_A: [f64; 2] = [{:.1}, {:.1}];",
+                    model.a[0], model.a[1]
+                );
+                println!(
+                    "pub const WDL_B: [f64; 2] = [{:.1}, {:.1}];",
+                    model.b[0], model.b[1]
+                );
+            }
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     // Handle selfplay flag
     if matches.get_flag("selfplay") {
         let num_games = matches
@@ -192,15 +742,64 @@ fn main() {
             std::process::exit(1);
         }
 
+        let disagreement_margin = matches
+            .get_one::<String>("disagreement-margin")
+            .and_then(|s| s.parse::<i32>().ok());
+
+        let keep_fraction = matches
+            .get_one::<String>("keep-fraction")
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap();
+
+        let max_plies = matches
+            .get_one::<String>("max-plies")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap();
+
+        let pv_leaf_eval = matches.get_flag("pv-leaf-eval");
+        let material_rule = parse_material_rule(&matches);
+        let dedupe = parse_dedupe(&matches);
+
         eprintln!("=== NNUE Training Data Generator ===");
         eprintln!("Games: {}", num_games);
         eprintln!("Search depth: {}", search_depth);
         eprintln!("Starting moves: {} - {}", start_moves_min, start_moves_max);
+        eprintln!("Max plies: {}", max_plies);
+        eprintln!("Material rule: {}", material_rule.name());
+        eprintln!(
+            "Labels: {}",
+            match pv_leaf_eval {
+                true => "PV leaf evaluation",
+                false => "search score",
+            }
+        );
+        if let Some(margin) = disagreement_margin {
+            eprintln!(
+                "Sampling: NNUE/HCE disagreement >= {} (keeping {} of the rest)",
+                margin, keep_fraction
+            );
+        }
+        match dedupe {
+            Dedupe::Off => {}
+            Dedupe::Exact => eprintln!("Dedupe: exact"),
+            Dedupe::Approximate(memory_mb) => eprintln!("Dedupe: bloom filter ({} MB)", memory_mb),
+        }
         eprintln!("Output file: {}", output_file);
         eprintln!();
 
-        let config = TrainingConfig::new(num_games, search_depth, start_moves_min, start_moves_max);
+        let mut config =
+            TrainingConfig::new(num_games, search_depth, start_moves_min, start_moves_max)
+                .with_max_plies(max_plies)
+                .with_material_rule(material_rule)
+                .with_dedupe(dedupe);
+        if pv_leaf_eval {
+            config = config.with_pv_leaf_eval();
+        }
+        if let Some(margin) = disagreement_margin {
+            config = config.with_disagreement_sampling(margin, keep_fraction);
+        }
         let generator = TrainingDataGenerator::new(config);
+        install_interrupt_handler();
 
         // Generate training data in parallel and write immediately to file
         match generator.generate_parallel_to_file(output_file) {
@@ -218,15 +817,19 @@ fn main() {
     }
 
     let mut controller = GameController::new();
+    apply_config(&mut controller, &matches);
 
     // Interactive UCI mode
     loop {
         let input = GUICommand::receive();
 
+        // The search may have finished (and printed bestmove) since the last command
+        controller.apply_deferred_options();
+
         match input {
             GUICommand::Quit => {
                 // Make sure to stop any ongoing search before quitting
-                controller.stop_search();
+                controller.stop_search_for_quit();
                 break;
             }
             GUICommand::UCI => {
@@ -244,53 +847,178 @@ fn main() {
 
                 controller.initialize();
             }
+            GUICommand::XBoard if !controller.is_initialized() => {
+                run_xboard(controller);
+                return;
+            }
+            GUICommand::Kibitz(delay) => {
+                match parse_number::<u64>(delay.as_deref().unwrap_or("1000")) {
+                    Ok(delay) => {
+                        run_kibitz(controller, Duration::from_millis(delay));
+                        return;
+                    }
+                    Err(e) => println!("info string {}", e),
+                }
+            }
             _ if !controller.is_initialized() => {
                 // Ignore commands until UCI initialization
                 continue;
             }
-            GUICommand::FenPosition(fen) => controller.set_board_from_fen(fen.as_str()),
+            GUICommand::FenPosition(fen) => {
+                if let Err(e) = controller.set_board_from_fen(fen.as_str()) {
+                    println!("info string {}", e);
+                }
+            }
             GUICommand::MovePosition(moves) => {
-                controller.reset_board();
-
                 if let Some(moves_strings) = moves {
-                    for notation in moves_strings {
-                        match controller.try_move_piece(&notation) {
-                            MoveResultType::Success => (),
-                            MoveResultType::InvalidMove => {
-                                eprintln!("Invalid move: {}", notation);
-                            }
-                            MoveResultType::InvalidNotation => {
-                                eprintln!("Invalid notation format: {}", notation);
-                            }
-                        }
+                    if let Err(e) = controller.set_board_from_moves(&moves_strings) {
+                        println!("info string {}", e);
                     }
                 } else {
-                    controller.reset_transposition_table();
+                    controller.reset_board();
                 }
             }
+            GUICommand::UciNewGame => {
+                // only here, since GUIs also send a bare `position startpos` before searches
+                controller.reset_board();
+                controller.new_game();
+            }
             GUICommand::SetOption(name, value) => {
-                controller.set_option(name.as_str(), value.as_str())
+                if let Err(e) = controller.set_option(name.as_str(), value.as_str()) {
+                    println!("info string Failed to set option {}: {}", name, e);
+                }
             }
             GUICommand::IsReady => println!("readyok"),
+            GUICommand::Debug(debug) => controller.debug = debug,
             GUICommand::Search(params) => controller.search(params, true),
             GUICommand::Perft(depth_string) => {
-                let moves = controller.perft(depth_string.parse::<usize>().unwrap());
+                let depth = match parse_number::<usize>(&depth_string) {
+                    Ok(depth) => depth,
+                    Err(e) => {
+                        println!("info string {}", e);
+                        continue;
+                    }
+                };
+                controller.print_perft(depth);
+            }
+            GUICommand::PerftStats(depth_string) => {
+                let depth = match parse_number::<usize>(&depth_string) {
+                    Ok(depth) => depth,
+                    Err(e) => {
+                        println!("info string {}", e);
+                        continue;
+                    }
+                };
+                let moves = controller.perft_categories(depth);
 
-                let mut total = 0;
-                for (m, c) in &moves {
-                    println!("{}: {}", m.unparse(), c);
-                    total += c;
+                let mut total = PerftCategories::default();
+                for (m, categories) in &moves {
+                    println!("{}: {}", m.unparse(), categories.nodes);
+                    total += *categories;
                 }
 
-                println!("\nNodes: {}", total);
+                println!("\n{}", total);
+            }
+            GUICommand::Checksum(depth_string) => match parse_number::<usize>(&depth_string) {
+                Ok(depth) => println!("{}", movegen_checksum(depth)),
+                Err(e) => println!("info string {}", e),
+            },
+            GUICommand::Estimate(depth_string) => match parse_number::<usize>(&depth_string) {
+                Ok(depth) => println!("{}", controller.estimate_tree(depth)),
+                Err(e) => println!("info string {}", e),
+            },
+            GUICommand::SelftestFen(games) => {
+                match parse_number::<u32>(games.as_deref().unwrap_or("100")) {
+                    Ok(games) => println!("{}", fen_selftest(games)),
+                    Err(e) => println!("info string {}", e),
+                }
             }
             GUICommand::PonderHit => controller.ponderhit(),
             GUICommand::Stop => {
                 let _ = controller.stop_search();
             }
+            GUICommand::LegalMoves(with_san) => controller.print_legal_moves(with_san),
             GUICommand::Eval => controller.print_evaluation(),
+            GUICommand::Stats => controller.print_search_stats(),
+            GUICommand::PvPgn(with_fen) => controller.print_pv_pgn(with_fen),
+            GUICommand::Trend => controller.print_eval_trend(),
             GUICommand::Joke => controller.tell_joke(),
-            GUICommand::Invalid(command) => eprintln!("Invalid command: {}", command),
+            GUICommand::SetPiece(square, piece) => controller.edit_set_piece(&square, &piece),
+            GUICommand::ClearSquare(square) => controller.edit_clear_square(&square),
+            GUICommand::SideTo(side) => controller.edit_side_to_move(&side),
+            GUICommand::Castling(castling) => controller.edit_castling(&castling),
+            GUICommand::EnPassant(square) => controller.edit_en_passant(&square),
+            GUICommand::Exclude(moves) => controller.exclude_moves(&moves),
+            GUICommand::Include(moves) => controller.include_moves(&moves),
+            GUICommand::XBoard => println!(
+                "info string {}",
+                ProkopakopError::InvalidCommand("xboard (already speaking UCI)".to_string())
+            ),
+            GUICommand::Invalid(command) => {
+                println!("info string {}", ProkopakopError::InvalidCommand(command))
+            }
         }
     }
 }
+
+fn parse_material_rule(matches: &ArgMatches) -> MaterialRule {
+    let rule = matches.get_one::<String>("material-rule").unwrap();
+
+    MaterialRule::parse(rule).unwrap_or_else(|| {
+        eprintln!("Error: material-rule must be fide or uscf, not {}", rule);
+        std::process::exit(1);
+    })
+}
+
+/// Set the options of the config file, before the GUI sets its own
+fn apply_config(controller: &mut GameController, matches: &ArgMatches) {
+    let path = match matches.get_one::<String>("config") {
+        Some(path) => PathBuf::from(path),
+        None => match EngineConfig::default_path() {
+            Some(path) => path,
+            None => return,
+        },
+    };
+
+    match EngineConfig::load(&path) {
+        Ok(config) => {
+            // a stale entry (say, a net that was moved) is left out rather than keeping the
+            // engine from starting
+            for (name, value) in &config.options {
+                if let Err(e) = controller.set_option(name, value) {
+                    println!(
+                        "info string Skipping {} = {} from {}: {}",
+                        name,
+                        value,
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_dedupe(matches: &ArgMatches) -> Dedupe {
+    let Some(mode) = matches.get_one::<String>("dedupe") else {
+        return Dedupe::Off;
+    };
+
+    let memory_mb = matches
+        .get_one::<String>("dedupe-memory")
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&mb| mb > 0)
+        .unwrap_or_else(|| {
+            eprintln!("Error: dedupe-memory must be a positive number of megabytes");
+            std::process::exit(1);
+        });
+
+    Dedupe::parse(mode, memory_mb).unwrap_or_else(|| {
+        eprintln!("Error: dedupe must be exact or bloom, not {}", mode);
+        std::process::exit(1);
+    })
+}
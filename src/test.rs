@@ -11,9 +11,11 @@ mod tests {
     fn test_null_move() {
         let mut controller = GameController::new();
 
-        controller.set_board_from_fen(
-            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
-        );
+        controller
+            .set_board_from_fen(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            )
+            .unwrap();
 
         let original_hash = controller.game.zobrist_key;
 
@@ -40,7 +42,7 @@ mod tests {
             println!("Testing Zobrist consistency for: {}", position);
 
             controller.reset_board();
-            controller.set_board_from_fen(position);
+            controller.set_board_from_fen(position).unwrap();
 
             let mut zobrist_position_map: HashMap<u64, String> = HashMap::new();
             let mut path = Vec::new();
@@ -240,7 +242,7 @@ mod tests {
             "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
             "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
         ] {
-            controller.set_board_from_fen(position);
+            controller.set_board_from_fen(position).unwrap();
 
             // Get the FEN back from the game
             let generated_fen = controller.game.get_fen();
@@ -301,7 +303,7 @@ mod tests {
 
         // Test with a complete standard starting position
         let starting_fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-        controller.set_board_from_fen(starting_fen);
+        controller.set_board_from_fen(starting_fen).unwrap();
         let generated = controller.game.get_fen();
         assert_eq!(starting_fen, generated, "Starting position FEN mismatch");
     }
@@ -311,6 +313,55 @@ mod tests {
         test_perft_positions_from_file("data/small.txt", 1, 3);
     }
 
+    #[test]
+    fn test_perft_categories() {
+        use crate::controller::controller::PerftCategories;
+
+        // reference numbers from the chessprogramming wiki perft tables
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                3,
+                [8902, 34, 0, 0, 0, 12, 0],
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2,
+                [2039, 351, 1, 91, 0, 3, 0],
+            ),
+            (
+                "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+                3,
+                [2812, 209, 2, 0, 0, 267, 0],
+            ),
+        ];
+
+        for (fen, depth, expected) in cases {
+            let mut controller = GameController::new();
+            controller.set_board_from_fen(fen).unwrap();
+
+            let mut total = PerftCategories::default();
+            for (_, categories) in controller.perft_categories(depth) {
+                total += categories;
+            }
+
+            assert_eq!(
+                [
+                    total.nodes,
+                    total.captures,
+                    total.en_passants,
+                    total.castles,
+                    total.promotions,
+                    total.checks,
+                    total.checkmates
+                ],
+                expected,
+                "{}",
+                fen
+            );
+        }
+    }
+
     #[test]
     #[cfg(not(debug_assertions))]
     fn test_perft_positions_medium() {
@@ -363,7 +414,7 @@ mod tests {
             .par_iter()
             .map(|(fen, depth, expected_count)| {
                 let mut controller = GameController::new();
-                controller.set_board_from_fen(fen);
+                controller.set_board_from_fen(fen).unwrap();
 
                 let moves = controller.perft(*depth);
 
@@ -423,58 +474,435 @@ mod tests {
 
         Ok(positions)
     }
+
+    #[test]
+    fn test_malformed_input_is_an_error() {
+        use crate::error::{ProkopakopError, parse_number};
+        use crate::game::board::Game;
+        use crate::game::square::BoardSquare;
+
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+        ] {
+            assert!(matches!(
+                Game::from_fen(fen),
+                Err(ProkopakopError::InvalidFen(_))
+            ));
+        }
+
+        // the current position is kept when the new one can't be parsed
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1")
+            .unwrap();
+        assert!(controller.set_board_from_fen("4k3/8/8 w - - 0 1").is_err());
+        assert_eq!(controller.game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+
+        for square in ["i1", "a9", "A1", "a0", "1a", ""] {
+            assert_eq!(BoardSquare::parse(square), None);
+        }
+        assert_eq!(BoardSquare::parse("h8"), Some(BoardSquare::H8));
+
+        assert_eq!(parse_number::<usize>("5").unwrap(), 5);
+        assert!(matches!(
+            parse_number::<usize>("-1"),
+            Err(ProkopakopError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_nnue_file() {
+        use crate::engine::nnue::load_nnue_from_file;
+        use crate::error::ProkopakopError;
+
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("prokopakop-missing-{}.nnue", std::process::id()));
+        assert!(matches!(
+            load_nnue_from_file(&missing),
+            Err(ProkopakopError::Io(_))
+        ));
+
+        let truncated = dir.join(format!("prokopakop-truncated-{}.nnue", std::process::id()));
+        std::fs::write(&truncated, [0u8; 1024]).unwrap();
+        assert!(matches!(
+            load_nnue_from_file(&truncated),
+            Err(ProkopakopError::InvalidNetwork(_))
+        ));
+        std::fs::remove_file(&truncated).unwrap();
+
+        // reported rather than taking the engine down
+        let mut controller = GameController::new();
+        controller.initialize();
+        assert!(
+            controller
+                .set_option("NNUE", missing.to_str().unwrap())
+                .is_err()
+        );
+        assert!(controller.set_option("NNUE", "<none>").is_ok());
+    }
+
+    #[test]
+    fn test_illegal_positions_are_rejected() {
+        use crate::error::ProkopakopError;
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")
+            .unwrap();
+
+        for fen in [
+            // two white kings
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            // pawns on the back ranks
+            "4k2P/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/p3K3 w - - 0 1",
+            // the side not to move in check
+            "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+            // checks no move could have given: triple, and a double check without a slider
+            "4k3/8/8/8/8/8/3p1p2/r3K3 w - - 0 1",
+            "4k3/8/8/8/8/3n4/5p2/4K3 w - - 0 1",
+        ] {
+            assert!(
+                matches!(
+                    controller.set_board_from_fen(fen),
+                    Err(ProkopakopError::IllegalPosition(_))
+                ),
+                "{} should be illegal",
+                fen
+            );
+        }
+
+        assert_eq!(controller.game.get_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+
+        // a discovered double check is fine
+        controller
+            .set_board_from_fen("4k3/8/8/8/8/5n2/8/r3K3 w - - 0 1")
+            .unwrap();
+
+        // an illegal position made by editing the board isn't searched
+        controller.edit_clear_square("e8");
+        controller.search(vec!["depth".to_string(), "1".to_string()], false);
+        assert!(!controller.is_searching());
+    }
+
+    #[test]
+    fn test_make_move_checked() {
+        use crate::error::ProkopakopError;
+        use crate::game::board::{BoardMove, Game};
+
+        // the white king is in check from the e8 rook
+        let fen = "4r1k1/8/8/8/8/8/3P4/R3K3 w Q - 0 1";
+        let mut game = Game::new(Some(fen));
+
+        // empty source square, opponent's piece, friendly target, moves leaving the king in check
+        for notation in ["e2e4", "e8e7", "a1e1", "a1a8", "d2d4", "e1e2"] {
+            let board_move = BoardMove::parse(notation).unwrap();
+            assert!(matches!(
+                game.make_move_checked(board_move),
+                Err(ProkopakopError::IllegalMove(_))
+            ));
+            assert_eq!(game.get_fen(), fen);
+        }
+
+        game.make_move_checked(BoardMove::parse("e1d1").unwrap())
+            .unwrap();
+        assert_eq!(game.get_fen(), "4r1k1/8/8/8/8/8/3P4/R2K4 b - - 1 1");
+    }
+
+    #[test]
+    fn test_move_kinds() {
+        use crate::game::board::{BoardMove, Game, MoveKind};
+        use crate::game::pieces::Piece;
+
+        let game = Game::new(Some("r3k2r/8/8/3pP3/8/1p6/8/R3K2R w KQkq d6 0 1"));
+        let kind = |notation: &str| {
+            game.legal_move(BoardMove::parse(notation).unwrap())
+                .map(|board_move| board_move.get_kind())
+        };
+
+        // parsed moves don't know their kind until matched against the legal ones
+        assert_eq!(
+            BoardMove::parse("e1g1").unwrap().get_kind(),
+            MoveKind::Normal
+        );
+        assert_eq!(kind("e1g1"), Some(MoveKind::Castling));
+        assert_eq!(kind("e1c1"), Some(MoveKind::Castling));
+        assert_eq!(kind("e5d6"), Some(MoveKind::EnPassant));
+        assert_eq!(kind("e5e6"), Some(MoveKind::Normal));
+        assert_eq!(kind("e1e3"), None);
+
+        let promotion = BoardMove::parse("b7b8n").unwrap();
+        assert_eq!(promotion.get_kind(), MoveKind::Promotion);
+        assert_eq!(promotion.get_promotion(), Some(Piece::Knight));
+        assert_eq!(BoardMove::from_bits(promotion.to_bits()), promotion);
+        assert_eq!(promotion.unparse(), "b7b8n");
+    }
+
+    #[test]
+    fn test_castling_legality() {
+        use crate::game::board::Game;
+
+        let castles = |fen: &str| {
+            let game = Game::from_fen(fen).unwrap();
+            let (move_count, moves) = game.get_moves();
+            let mut castles = moves[..move_count]
+                .iter()
+                .map(|board_move| board_move.unparse())
+                .filter(|notation| ["e1g1", "e1c1", "e8g8", "e8c8"].contains(&notation.as_str()))
+                .collect::<Vec<_>>();
+            castles.sort();
+            castles
+        };
+
+        assert_eq!(
+            castles("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"),
+            ["e1c1", "e1g1"]
+        );
+        assert_eq!(
+            castles("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"),
+            ["e8c8", "e8g8"]
+        );
+
+        // the square the king passes through is attacked
+        assert_eq!(castles("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1"), ["e1c1"]);
+        assert_eq!(castles("4k3/8/8/8/8/8/3r4/R3K2R w KQ - 0 1"), ["e1g1"]);
+        assert_eq!(castles("r3k2r/8/8/8/8/8/8/3RK3 b kq - 0 1"), ["e8g8"]);
+
+        // the square the king lands on is attacked
+        assert_eq!(castles("4k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1"), ["e1c1"]);
+        assert_eq!(castles("4k3/8/8/8/8/8/2r5/R3K2R w KQ - 0 1"), ["e1g1"]);
+
+        // only the rook passes b1, so it may be attacked
+        assert_eq!(castles("4k3/8/8/8/8/8/1r6/R3K3 w Q - 0 1"), ["e1c1"]);
+
+        // in check, or with something in the way
+        assert!(castles("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").is_empty());
+        assert_eq!(
+            castles("4k3/8/8/8/8/8/8/RN2K1NR w KQ - 0 1"),
+            Vec::<String>::new()
+        );
+
+        // flags a hand-written FEN got wrong: no rook, or the king not on its square
+        assert_eq!(castles("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1"), ["e1g1"]);
+        assert!(castles("4k3/8/8/8/8/8/8/R4K1R w KQ - 0 1").is_empty());
+        assert!(castles("4k3/8/8/8/8/8/8/R3K2B w K - 0 1").is_empty());
+    }
+
+    #[test]
+    fn test_fen_roundtrip() {
+        use crate::controller::selftest::{check_fen_roundtrip, fen_selftest};
+        use crate::game::board::{BoardMove, Game};
+
+        // double pushes (capturable en passant or not), quiet moves and captures, which
+        // the en passant square and the move counters depend on
+        let mut game = Game::new(None);
+        for notation in [
+            "e2e4", "g8f6", "e4e5", "d7d5", "e5d6", "e7d6", "g1f3", "b8c6", "f1b5", "a7a6",
+        ] {
+            game.make_move_checked(BoardMove::parse(notation).unwrap())
+                .unwrap();
+            check_fen_roundtrip(&game).unwrap();
+        }
+        assert_eq!(
+            game.get_fen(),
+            "r1bqkb1r/1pp2ppp/p1np1n2/1B6/8/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 6"
+        );
+
+        let result = fen_selftest(1);
+        assert_eq!(result.games, 1);
+        assert!(result.positions > 1);
+        assert!(result.mismatch.is_none(), "{}", result);
+    }
+
+    #[test]
+    fn test_fork() {
+        use crate::game::board::{BoardMove, Game, PieceBoard};
+
+        // a byte per square
+        assert_eq!(std::mem::size_of::<PieceBoard>(), 64);
+
+        let mut game = Game::new(None);
+        for notation in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"] {
+            game.make_move(BoardMove::parse(notation).unwrap());
+        }
+
+        let fork = game.fork();
+        assert!(fork.history.is_empty());
+        assert_eq!(fork.get_fen(), game.get_fen());
+        assert_eq!(fork.zobrist_key, game.zobrist_key);
+        assert_eq!(fork.pieces, game.pieces);
+        assert_eq!(fork.evaluate_network_stm(), game.evaluate_network_stm());
+
+        // moves made from the fork can be unmade back to it
+        let mut fork = fork;
+        let (move_count, moves) = fork.get_moves();
+        for &board_move in &moves[..move_count] {
+            fork.make_move(board_move);
+            fork.unmake_move();
+        }
+        assert_eq!(fork.get_fen(), game.get_fen());
+        assert_eq!(fork.zobrist_key, game.zobrist_key);
+    }
+
+    #[test]
+    fn test_go_perft() {
+        use crate::engine::search::limits::SearchParams;
+
+        let parse = |params: &str| {
+            SearchParams::parse(params.split_whitespace().map(|s| s.to_string()).collect()).unwrap()
+        };
+        assert_eq!(parse("perft 5").perft, Some(5));
+        assert_eq!(parse("searchmoves e2e4 perft 3").perft, Some(3));
+        assert_eq!(parse("searchmoves e2e4 perft 3").searchmoves.len(), 1);
+        assert_eq!(parse("depth 5").perft, None);
+
+        // counted right away instead of starting a search
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.search(vec!["perft".to_string(), "2".to_string()], true);
+        assert!(!controller.is_searching());
+    }
+
+    #[test]
+    fn test_go_parse_errors() {
+        use crate::engine::search::limits::SearchParams;
+        use crate::error::ProkopakopError;
+
+        let parse = |params: &str| {
+            SearchParams::parse(params.split_whitespace().map(|s| s.to_string()).collect())
+        };
+
+        assert!(parse("").is_ok());
+        assert!(parse("wtime 1000 btime -20 winc 10 binc 10 movestogo 5").is_ok());
+        assert_eq!(parse("mate 3").unwrap().mate, Some(3));
+        assert!(parse("ponder wtime 100").unwrap().ponder);
+
+        for (params, message) in [
+            ("nonsense 12", "Invalid go parameter: nonsense"),
+            ("depth", "Invalid go parameter: depth without a value"),
+            ("depth x", "Invalid number: x"),
+            ("nodes -5", "Invalid number: -5"),
+            ("wtime 1.5", "Invalid number: 1.5"),
+            ("mate 0", "Invalid go parameter: mate 0"),
+            ("searchmoves e2e4 e9e5", "Illegal move: e9e5 in searchmoves"),
+        ] {
+            let error = parse(params).unwrap_err();
+            assert_eq!(error.to_string(), message, "go {}", params);
+        }
+
+        assert!(matches!(
+            parse("perft"),
+            Err(ProkopakopError::InvalidSearchParameter(_))
+        ));
+
+        // a GUI sending something wrong gets an answer rather than nothing
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.search(vec!["depth".to_string(), "x".to_string()], false);
+        assert!(!controller.is_searching());
+    }
+
+    #[test]
+    fn test_library_helpers() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+        assert_eq!(crate::perft(start, 0).unwrap(), 1);
+        assert_eq!(crate::perft(start, 3).unwrap(), 8902);
+        assert_eq!(crate::perft(kiwipete, 2).unwrap(), 2039);
+        assert!(crate::perft("not a fen", 1).is_err());
+
+        // the same position with colors swapped evaluates to the opposite score
+        let white = crate::evaluate("4k3/8/8/8/8/8/3QP3/4K3 w - - 0 1").unwrap();
+        let black = crate::evaluate("4k3/3qp3/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert!(white > 0);
+        assert_eq!(white, -black);
+        assert!(crate::evaluate("not a fen").is_err());
+
+        // parsable but illegal positions are errors too, at any depth
+        let kingless = "8/8/8/4P3/8/8/8/8 w - - 0 1";
+        assert!(crate::perft(kingless, 0).is_err());
+        assert!(crate::evaluate(kingless).is_err());
+    }
+
+    #[test]
+    fn test_king_of_the_hill() {
+        use crate::game::board::BoardMove;
+        use crate::game::rules::Variant;
+
+        let mut controller = GameController::new();
+        controller
+            .set_option("UCI_Variant", "kingofthehill")
+            .unwrap();
+        controller
+            .set_board_from_fen("4k3/8/8/8/8/2K5/8/8 w - - 0 1")
+            .unwrap();
+        assert_eq!(controller.game.variant, Variant::KingOfTheHill);
+
+        // standard moves until a king reaches the center, after which the game is over
+        let (move_count, _) = controller.game.get_moves();
+        assert_eq!(move_count, 8);
+        assert!(!controller.game.is_lost());
+
+        controller.game.make_move(BoardMove::parse("c3d4").unwrap());
+        assert_eq!(controller.game.get_moves().0, 0);
+        assert!(controller.game.is_lost());
+
+        // a standard game goes on from there
+        controller.set_option("UCI_Variant", "chess").unwrap();
+        assert!(controller.game.get_moves().0 > 0);
+        assert!(!controller.game.is_lost());
+
+        assert_eq!(
+            Variant::parse("KingOfTheHill"),
+            Some(Variant::KingOfTheHill)
+        );
+        assert_eq!(Variant::parse("atomic"), None);
+    }
 }
 
 #[cfg(test)]
 mod see_tests {
     use crate::{
-        controller::controller::GameController,
         engine::evaluate::{BISHOP_VALUE, KNIGHT_VALUE, PAWN_VALUE, QUEEN_VALUE, ROOK_VALUE},
-        game::square::BoardSquare,
+        game::board::{BoardMove, Game},
     };
 
-    fn test_see_position(fen: &str, square: &str, expected_score: f32) {
-        let mut controller = GameController::new();
-        controller.set_board_from_fen(fen);
+    fn test_see_position(fen: &str, move_string: &str, expected_score: i32) {
+        // some of the positions are made up around the exchange, without a black king
+        let game = Game::from_fen(fen).unwrap();
 
-        let square_idx = parse_square(square);
-        let actual_score = controller.game.see(square_idx);
-        let actual_sign = controller.game.see_sign(square_idx);
+        let parsed = BoardMove::parse(move_string).unwrap();
+        let board_move = game.legal_move(parsed).unwrap_or(parsed);
+        let actual_score = game.see_move(board_move);
 
         assert_eq!(
             actual_score, expected_score,
-            "SEE failed for position '{}' on square {}: expected {}, got {}",
-            fen, square, expected_score, actual_score
-        );
-
-        assert_eq!(
-            actual_sign,
-            expected_score.signum() as i8,
-            "SEE sign failed for position '{}' on square {}: expected {}, got {}",
-            fen,
-            square,
-            expected_score.signum(),
-            actual_sign
+            "SEE failed for position '{}' and move {}: expected {}, got {}",
+            fen, move_string, expected_score, actual_score
         );
     }
 
-    fn parse_square(square: &str) -> BoardSquare {
-        let bytes = square.as_bytes();
-        let file = (bytes[0] - b'a') as usize;
-        let rank = (bytes[1] - b'1') as usize;
-        (rank * 8 + file) as BoardSquare
-    }
-
     #[test]
     fn test_see_simple_winning_captures() {
         // Pawn takes queen
-        test_see_position("8/1n5q/8/4q3/3P4/6PP/8/4K2k w - - 0 1", "e5", QUEEN_VALUE);
+        test_see_position("8/1n5q/8/4q3/3P4/6PP/8/4K2k w - - 0 1", "d4e5", QUEEN_VALUE);
 
         // Knight takes rook
-        test_see_position("8/8/8/4r3/8/5N2/8/4K2k w - - 0 1", "e5", ROOK_VALUE);
+        test_see_position("8/8/8/4r3/8/5N2/8/4K2k w - - 0 1", "f3e5", ROOK_VALUE);
 
         // Bishop takes knight
-        test_see_position("8/8/8/4n3/8/2B5/8/4K2k w - - 0 1", "e5", KNIGHT_VALUE);
+        test_see_position("8/8/8/4n3/8/2B5/8/4K2k w - - 0 1", "c3e5", KNIGHT_VALUE);
     }
 
     #[test]
@@ -482,7 +910,7 @@ mod see_tests {
         // Pawn takes rook, pawn takes pawn
         test_see_position(
             "8/8/3p1p2/4n3/3P4/8/8/4K2k w - - 0 1",
-            "e5",
+            "d4e5",
             KNIGHT_VALUE - PAWN_VALUE,
         );
     }
@@ -490,10 +918,10 @@ mod see_tests {
     #[test]
     fn test_see_equal_trades() {
         // Queen-queen-knight
-        test_see_position("8/5n2/8/4q3/8/2Q5/8/4K2k w - - 0 1", "e5", 0.0);
+        test_see_position("8/5n2/8/4q3/8/2Q5/8/4K2k w - - 0 1", "c3e5", 0);
 
         // Rook-rook-pawn
-        test_see_position("8/8/5p2/R3r3/8/8/8/4K2k w - - 0 1", "e5", 0.0);
+        test_see_position("8/8/5p2/R3r3/8/8/8/4K2k w - - 0 1", "a5e5", 0);
     }
 
     #[test]
@@ -501,21 +929,21 @@ mod see_tests {
         // Queen takes pawn (defended by pawn)
         test_see_position(
             "8/8/3p4/4p3/8/8/4Q3/4K2k w - - 0 1",
-            "e5",
+            "e2e5",
             -QUEEN_VALUE + PAWN_VALUE,
         );
 
         // Rook takes pawn (defended by knight)
         test_see_position(
             "8/8/4p3/6n1/8/8/4R3/4K2k w - - 0 1",
-            "e6",
+            "e2e6",
             -ROOK_VALUE + PAWN_VALUE,
         );
 
         // Knight takes pawn (defended by pawn)
         test_see_position(
             "8/8/3p4/4p3/8/5N2/8/4K2k w - - 0 1",
-            "e5",
+            "f3e5",
             -KNIGHT_VALUE + PAWN_VALUE,
         );
     }
@@ -524,7 +952,7 @@ mod see_tests {
         // Pawn should take knight and that's it
         test_see_position(
             "4r3/3p4/4n3/3P4/8/4Q3/8/4K3 w - - 0 1",
-            "e6",
+            "d5e6",
             KNIGHT_VALUE - PAWN_VALUE,
         );
 
@@ -532,7 +960,7 @@ mod see_tests {
         // they would lose more, so they don't even recapture
         test_see_position(
             "4r3/3p4/4n3/3P2N1/8/4Q3/8/4K3 w - - 0 1",
-            "e6",
+            "d5e6",
             KNIGHT_VALUE,
         );
     }
@@ -541,7 +969,7 @@ mod see_tests {
     fn test_see_xray_attacks() {
         test_see_position(
             "8/3p4/4r3/8/2B5/1B6/8/4K2k w - - 0 1",
-            "e6",
+            "c4e6",
             ROOK_VALUE + PAWN_VALUE - BISHOP_VALUE,
         );
     }
@@ -550,17 +978,3549 @@ mod see_tests {
     fn test_king_recapture() {
         test_see_position(
             "8/4r3/8/8/1R2q3/4K3/8/7k w - - 0 1",
-            "e4",
+            "b4e4",
             ROOK_VALUE + QUEEN_VALUE - ROOK_VALUE,
         );
     }
 
+    #[test]
+    fn test_see_moving_piece() {
+        // The queen taking a defended pawn loses it, even though the pawn could take safely
+        test_see_position(
+            "8/8/3p4/4p3/3P4/8/4Q3/4K2k w - - 0 1",
+            "e2e5",
+            2 * PAWN_VALUE - QUEEN_VALUE,
+        );
+        test_see_position("8/8/3p4/4p3/3P4/8/4Q3/4K2k w - - 0 1", "d4e5", PAWN_VALUE);
+
+        // A quiet move to an attacked square hangs the piece
+        test_see_position("8/8/8/4p3/8/8/4N3/4K2k w - - 0 1", "e2d4", -KNIGHT_VALUE);
+    }
+
+    #[test]
+    fn test_see_promotions() {
+        // Promoting where the rook takes the queen at once only loses the pawn
+        test_see_position("3r4/4P3/8/8/8/8/8/4K2k w - - 0 1", "e7e8q", -PAWN_VALUE);
+
+        // Promoting by taking the rook wins it on top of the promotion
+        test_see_position(
+            "3r4/4P3/8/8/8/8/8/4K2k w - - 0 1",
+            "e7d8q",
+            ROOK_VALUE + QUEEN_VALUE - PAWN_VALUE,
+        );
+    }
+
+    #[test]
+    fn test_see_en_passant() {
+        // The captured pawn isn't on the target square
+        test_see_position("8/8/8/3pP3/8/8/8/4K2k w - d6 0 1", "e5d6", PAWN_VALUE);
+
+        // and taking it opens the file for the rook recapturing from behind it
+        test_see_position("3r4/8/8/3pP3/8/8/8/3RK2k w - d6 0 1", "e5d6", PAWN_VALUE);
+    }
+
     #[test]
     fn test_king_no_recapture() {
         test_see_position(
             "8/8/3p1p2/4n3/3P1K2/8/8/7k w - - 0 1",
-            "e5",
+            "d4e5",
             KNIGHT_VALUE - PAWN_VALUE,
         );
     }
 }
+
+#[cfg(test)]
+mod eval_tests {
+    use crate::engine::evaluate::{
+        evaluate_classical, evaluate_classical_stm, evaluate_king_safety, evaluate_mobility,
+        evaluate_pins, get_mobility_area,
+    };
+    use crate::game::board::Game;
+    use crate::game::pieces::{Color, Piece};
+    use crate::game::square::BoardSquare;
+
+    #[test]
+    fn test_pawn_endgame_rule_of_the_square() {
+        use crate::engine::endgame::is_unstoppable_passer;
+
+        // the black king on f4 catches the a-pawn only if it moves first
+        let white_to_move = Game::new(Some("4K3/8/8/8/P4k2/8/8/8 w - - 0 1"));
+        let black_to_move = Game::new(Some("4K3/8/8/8/P4k2/8/8/8 b - - 0 1"));
+
+        assert!(is_unstoppable_passer(
+            &white_to_move,
+            BoardSquare::A4,
+            Color::White
+        ));
+        assert!(!is_unstoppable_passer(
+            &black_to_move,
+            BoardSquare::A4,
+            Color::White
+        ));
+        assert!(white_to_move.evaluate_stm() > 1000);
+    }
+
+    #[test]
+    fn test_pawn_endgame_key_squares() {
+        // the white king on a key square wins even with black to move and in front of the pawn
+        let won = Game::new(Some("3k4/8/3K4/8/3P4/8/8/8 b - - 0 1"));
+        assert!(won.evaluate_stm() <= -1000);
+
+        // the black king in front of the pawn with the white king behind holds the draw
+        let drawn = Game::new(Some("3k4/8/8/8/3P4/3K4/8/8 w - - 0 1"));
+        assert_eq!(drawn.evaluate_stm(), 0);
+
+        // a rook pawn is drawn with the defending king in the corner
+        let corner = Game::new(Some("k7/8/8/P7/8/8/2K5/8 w - - 0 1"));
+        assert_eq!(corner.evaluate_stm(), 0);
+    }
+
+    #[test]
+    fn test_pawn_endgame_only_for_pawn_endings() {
+        use crate::engine::endgame::evaluate_pawn_endgame_stm;
+
+        let game = Game::new(Some("4k3/8/8/8/3P4/8/8/3QK3 w - - 0 1"));
+        assert_eq!(evaluate_pawn_endgame_stm(&game, || 0), None);
+    }
+
+    #[test]
+    fn test_mating_ending_move_ordering() {
+        use crate::engine::endgame::{mating_ending_attacker, mating_ending_move_score};
+        use crate::game::board::BoardMove;
+
+        let krk = Game::new(Some("8/8/8/4k3/8/8/8/R3K3 w - - 0 1"));
+        assert_eq!(mating_ending_attacker(&krk), Some(Color::White));
+
+        // a check beats a quiet rook move, and the king approaching beats it retreating
+        let score =
+            |from, to| mating_ending_move_score(&krk, BoardMove::regular(from, to), Color::White);
+        assert!(score(BoardSquare::A1, BoardSquare::A5) > score(BoardSquare::A1, BoardSquare::A2));
+        assert!(score(BoardSquare::E1, BoardSquare::E2) > score(BoardSquare::E1, BoardSquare::F1));
+
+        // equal material or pawns on the board don't count
+        let krkr = Game::new(Some("7r/8/8/4k3/8/8/8/R3K3 w - - 0 1"));
+        let kpk = Game::new(Some("8/8/8/4k3/8/8/P7/4K3 w - - 0 1"));
+        assert_eq!(mating_ending_attacker(&krkr), None);
+        assert_eq!(mating_ending_attacker(&kpk), None);
+    }
+
+    #[test]
+    fn test_nnue_batch_matches_single_evaluations() {
+        use crate::engine::nnue::get_network;
+
+        // the piece count only selects the output bucket, so any positions can share one here
+        let positions = [
+            "r1bqkbnr/pppp1ppp/2n5/4N3/4P3/8/PPPP1PPP/RNBQKB1R b KQkq - 0 3",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4",
+        ]
+        .map(|fen| Game::new(Some(fen)));
+
+        // the output layer relies on the weights being clipped like the default network's
+        let net = get_network();
+        assert!(net.has_valid_output_weights());
+
+        let inputs = positions.each_ref().map(|game| match game.side {
+            Color::White => (&game.white_accumulator, &game.black_accumulator),
+            Color::Black => (&game.black_accumulator, &game.white_accumulator),
+        });
+
+        let batch = net.evaluate_batch(inputs, 31);
+        for ((us, them), output) in inputs.into_iter().zip(batch) {
+            assert_eq!(net.evaluate(us, them, 31), output);
+        }
+    }
+
+    #[test]
+    fn test_king_safety_attacked_king() {
+        // Queen and rook swarming the castled king versus the same pieces far away
+        let attacked = Game::new(Some("6k1/5ppp/8/8/8/5Q2/5PPP/3R2K1 b - - 0 1"));
+        let safe = Game::new(Some("6k1/5ppp/8/8/8/8/Q4PPP/R5K1 b - - 0 1"));
+
+        assert!(
+            evaluate_king_safety(&attacked, Color::Black)
+                < evaluate_king_safety(&safe, Color::Black)
+        );
+    }
+
+    #[test]
+    fn test_king_safety_symmetry() {
+        let game = Game::new(Some(
+            "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1",
+        ));
+
+        assert_eq!(
+            evaluate_king_safety(&game, Color::White),
+            evaluate_king_safety(&game, Color::Black)
+        );
+    }
+
+    #[test]
+    fn test_mobility_area_excludes_pawn_controlled_squares() {
+        // Black pawn on d5 controls c4 and e4, white pawn on a2 is blocked by a3
+        let game = Game::new(Some("4k3/8/8/3p4/8/p7/P7/4K3 w - - 0 1"));
+        let area = get_mobility_area(&game, Color::White);
+
+        assert!(!area.is_set(BoardSquare::C4));
+        assert!(!area.is_set(BoardSquare::E4));
+        assert!(!area.is_set(BoardSquare::A2)); // blocked pawn
+        assert!(!area.is_set(BoardSquare::E1)); // own king
+        assert!(area.is_set(BoardSquare::D4));
+    }
+
+    #[test]
+    fn test_mobility_prefers_active_pieces() {
+        let active = Game::new(Some("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1"));
+        let cornered = Game::new(Some("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"));
+
+        assert!(
+            evaluate_mobility(&active, Color::White) > evaluate_mobility(&cornered, Color::White)
+        );
+    }
+
+    /// Flip the board vertically and swap the colors of all pieces and the side to move.
+    fn mirror_fen(fen: &str) -> String {
+        let parts: Vec<&str> = fen.split_whitespace().collect();
+
+        let swap_case = |s: &str| {
+            s.chars()
+                .map(|c| {
+                    if c.is_ascii_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
+        };
+
+        let board = parts[0]
+            .split('/')
+            .rev()
+            .map(swap_case)
+            .collect::<Vec<_>>()
+            .join("/");
+        let side = if parts[1] == "w" { "b" } else { "w" };
+
+        let mut castling: Vec<char> = swap_case(parts[2]).chars().collect();
+        castling.sort_by_key(|c| "KQkq-".find(*c));
+
+        let en_passant = match parts[3] {
+            "-" => "-".to_string(),
+            square => square
+                .chars()
+                .map(|c| match c {
+                    '3' => '6',
+                    '6' => '3',
+                    c => c,
+                })
+                .collect(),
+        };
+
+        format!(
+            "{} {} {} {} {}",
+            board,
+            side,
+            castling.into_iter().collect::<String>(),
+            en_passant,
+            parts[4..].join(" ")
+        )
+    }
+
+    #[test]
+    fn test_classical_eval_symmetry() {
+        for fen in [
+            "r1bq1rk1/pppp1ppp/2n2n2/2b1p3/4P3/3P1N2/PPP2PPP/RNBQKB1R w KQ - 0 1",
+            "8/5pk1/6p1/8/3N4/8/1Q3PPP/6K1 b - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ] {
+            let game = Game::new(Some(fen));
+            let mirrored = Game::new(Some(&mirror_fen(fen)));
+
+            // White-relative scores flip sign, side-to-move-relative scores stay the same
+            assert_eq!(evaluate_classical(&game), -evaluate_classical(&mirrored));
+            assert_eq!(
+                evaluate_classical_stm(&game),
+                evaluate_classical_stm(&mirrored)
+            );
+
+            assert_eq!(game.evaluate_stm(), game.evaluate() * game.side);
+        }
+    }
+
+    #[test]
+    fn test_xray_attacks() {
+        // rook on a1 looks through the white knight on a3 and the black pawn on d1
+        let game = Game::new(Some("4k3/8/p7/8/8/N7/8/R2pK3 w - - 0 1"));
+        let own = game.color_bitboards[Color::White as usize];
+        let all = own | game.color_bitboards[Color::Black as usize];
+
+        let xray = game.get_xray_attacks(Piece::Rook, BoardSquare::A1, own);
+        assert_eq!(
+            xray,
+            BoardSquare::A4.to_mask() | BoardSquare::A5.to_mask() | BoardSquare::A6.to_mask()
+        );
+
+        let xray = game.get_xray_attacks(Piece::Rook, BoardSquare::A1, all);
+        assert!(xray.is_set(BoardSquare::E1));
+        assert!(!xray.is_set(BoardSquare::F1));
+        assert!(!xray.is_set(BoardSquare::A2));
+    }
+
+    #[test]
+    fn test_pinned_piece_penalized() {
+        // knight on d2 pinned to the king by the bishop on b4
+        let pinned = Game::new(Some("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1"));
+        // same knight, bishop no longer on the diagonal
+        let free = Game::new(Some("4k3/8/8/8/8/1b6/3N4/4K3 w - - 0 1"));
+
+        assert!(evaluate_pins(&pinned, Color::White) < 0);
+        assert_eq!(evaluate_pins(&free, Color::White), 0);
+
+        // black's pieces aren't affected
+        assert_eq!(evaluate_pins(&pinned, Color::Black), 0);
+    }
+
+    #[test]
+    fn test_skewer_penalized() {
+        // king on e4 checked by the rook on a4, with the white rook on h4 behind it
+        let skewered = Game::new(Some("4k3/8/8/8/r3K2R/8/8/8 w - - 0 1"));
+        // a pawn behind the king isn't worth counting
+        let pawn_behind = Game::new(Some("4k3/8/8/8/r3K2P/8/8/8 w - - 0 1"));
+
+        assert!(evaluate_pins(&skewered, Color::White) < 0);
+        assert_eq!(evaluate_pins(&pawn_behind, Color::White), 0);
+    }
+
+    #[test]
+    fn test_wdl_permille() {
+        use crate::engine::evaluate::CHECKMATE_SCORE;
+        use crate::engine::wdl::WdlModel;
+
+        let model = WdlModel::default();
+        let game = Game::new(None);
+
+        for score in [-900, -150, 0, 35, 400] {
+            let (win, draw, loss) = model.permille(score, &game);
+            assert_eq!(win + draw + loss, 1000);
+
+            // the model is symmetric in the score
+            assert_eq!(model.permille(-score, &game), (loss, draw, win));
+        }
+
+        let (win, _, loss) = model.permille(0, &game);
+        assert_eq!(win, loss);
+        assert!(model.permille(400, &game).0 > model.permille(100, &game).0);
+
+        assert_eq!(model.permille(CHECKMATE_SCORE - 3, &game), (1000, 0, 0));
+        assert_eq!(model.permille(-CHECKMATE_SCORE + 4, &game), (0, 0, 1000));
+    }
+
+    #[test]
+    fn test_wdl_fit() {
+        use crate::engine::wdl::WdlModel;
+
+        let target = WdlModel {
+            a: [250.0, -100.0],
+            b: [90.0, 20.0],
+        };
+
+        // outcomes in proportion to the target model's probabilities
+        let mut samples = Vec::new();
+        for material in [0.2, 0.6, 1.0] {
+            for eval in (-400..=400).step_by(50) {
+                let (win, draw, loss) = target.probabilities(eval as f64, material);
+
+                for (result, probability) in [(1.0, win), (0.5, draw), (0.0, loss)] {
+                    for _ in 0..(probability * 50.0).round() as usize {
+                        samples.push((eval as f64, material, result));
+                    }
+                }
+            }
+        }
+
+        let fitted = WdlModel::fit(&samples);
+        for material in [0.2, 0.6, 1.0] {
+            for eval in [-200.0, 0.0, 100.0, 300.0] {
+                let (win, draw, _) = fitted.probabilities(eval, material);
+                let (target_win, target_draw, _) = target.probabilities(eval, material);
+
+                assert!((win - target_win).abs() < 0.05);
+                assert!((draw - target_draw).abs() < 0.05);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use crate::controller::controller::GameController;
+    use crate::engine::search::params::{ASPIRATION_SCALE_MAX, ASPIRATION_SCALE_MIN};
+    use crate::engine::search::results::{
+        AspirationAdaptation, AspirationStats, SearchResult, SearchStatus,
+    };
+    use crate::game::board::{BoardMove, Game};
+    use crate::game::pieces::Piece;
+
+    #[cfg(not(debug_assertions))]
+    use crate::engine::search::results::ExpectedNode;
+
+    #[test]
+    fn test_sort_moves_by_score() {
+        use crate::engine::search::searcher::sort_moves_by_score;
+
+        let (move_count, mut moves) = Game::new(None).get_moves();
+        let moves = &mut moves[..move_count];
+
+        // scores of both signs and extremes, each computed once per move
+        let score = |board_move: BoardMove| match board_move.unparse().as_str() {
+            "e2e4" => i32::MIN,
+            "d2d4" => -1_000_000,
+            "g1f3" => -1,
+            "b1c3" => i32::MAX,
+            _ => 0,
+        };
+        let mut calls = 0;
+        sort_moves_by_score(moves, |board_move| {
+            calls += 1;
+            score(board_move)
+        });
+
+        assert_eq!(calls, move_count);
+        assert_eq!(moves[0].unparse(), "e2e4");
+        assert_eq!(moves[1].unparse(), "d2d4");
+        assert_eq!(moves[2].unparse(), "g1f3");
+        assert_eq!(moves[move_count - 1].unparse(), "b1c3");
+        assert!(
+            moves
+                .windows(2)
+                .all(|pair| score(pair[0]) <= score(pair[1]))
+        );
+    }
+
+    #[test]
+    fn test_continuation_history() {
+        use crate::engine::search::history::History;
+        use crate::game::pieces::Color;
+
+        let mut history = History::new();
+        let previous = (Piece::Pawn, BoardMove::parse("e7e5").unwrap());
+        let other_previous = (Piece::Knight, BoardMove::parse("g8f6").unwrap());
+        let reply = (Piece::Knight, BoardMove::parse("g1f3").unwrap());
+
+        history.add_continuation_history(previous, reply, Color::White, 4);
+        assert_eq!(
+            history.get_continuation_score(previous, reply, Color::White),
+            16
+        );
+
+        // scores are kept per previous move and side
+        assert_eq!(
+            history.get_continuation_score(other_previous, reply, Color::White),
+            0
+        );
+        assert_eq!(
+            history.get_continuation_score(previous, reply, Color::Black),
+            0
+        );
+
+        for _ in 0..1000 {
+            history.add_continuation_penalty(previous, reply, Color::White, 10);
+        }
+        assert_eq!(
+            history.get_continuation_score(previous, reply, Color::White),
+            -8192
+        );
+
+        assert_eq!(
+            history.get_counter_move(previous, Color::White),
+            BoardMove::empty()
+        );
+        history.set_counter_move(previous, Color::White, reply.1);
+        assert_eq!(history.get_counter_move(previous, Color::White), reply.1);
+    }
+
+    #[test]
+    fn test_thread_node_counts() {
+        use crate::engine::search::results::{SearchClock, SearchStats, ThreadNodes};
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let thread_nodes = ThreadNodes::new(2);
+        let mut stats = SearchStats::new(SearchClock::default(), Arc::new(AtomicBool::new(false)))
+            .with_thread(thread_nodes.clone(), 1);
+
+        for _ in 0..1500 {
+            stats.increment_nodes();
+        }
+
+        // only every so often is the count published, but locally it's always exact
+        assert_eq!(thread_nodes.get(1), 1024);
+        assert_eq!(stats.total_nodes(), 1500);
+
+        thread_nodes.publish(0, 700);
+        assert_eq!(stats.total_nodes(), 2200);
+
+        stats.publish_nodes();
+        assert_eq!(thread_nodes.total(), 2200);
+    }
+
+    #[test]
+    fn test_search_clock() {
+        use crate::engine::search::results::SearchClock;
+        use std::time::Duration;
+
+        let clock = SearchClock::started_ago(Duration::from_millis(300));
+        assert!(clock.elapsed_ms() >= 300);
+
+        // the search threads see a restart through their clones
+        let search_clock = clock.clone();
+        clock.restart();
+        assert!(search_clock.elapsed_ms() < 300);
+    }
+
+    #[test]
+    fn test_easy_move_soft_time() {
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::results::{SearchClock, SearchStats};
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::time::Duration;
+
+        let stats = SearchStats::new(
+            SearchClock::started_ago(Duration::from_millis(500)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        let mut limits = SearchLimits {
+            max_depth: None,
+            max_nodes: None,
+            max_time_ms: Some(1000),
+            extended_time_ms: Some(1000),
+            moves: vec![],
+            infinite: false,
+            exact: false,
+            mate: None,
+        };
+
+        assert!(stats.can_end_early(&limits));
+        assert!(stats.past_soft_time(&limits, 0.3));
+        assert!(!stats.past_soft_time(&limits, 0.9));
+
+        // an exact movetime is always used up
+        limits.exact = true;
+        assert!(!stats.can_end_early(&limits));
+        assert!(!stats.past_soft_time(&limits, 0.3));
+
+        limits.exact = false;
+        limits.max_time_ms = None;
+        assert!(!stats.can_end_early(&limits));
+    }
+
+    #[test]
+    fn test_past_hard_time() {
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::results::{SearchClock, SearchStats};
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::time::Duration;
+
+        let stats = SearchStats::new(
+            SearchClock::started_ago(Duration::from_millis(900)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        let mut limits = SearchLimits {
+            max_depth: None,
+            max_nodes: None,
+            max_time_ms: Some(1000),
+            extended_time_ms: Some(1000),
+            moves: vec![],
+            infinite: false,
+            exact: true,
+            mate: None,
+        };
+
+        // unlike the soft time, an exact movetime has a hard limit to run into
+        assert!(stats.past_hard_time(&limits, 0.85));
+        assert!(!stats.past_hard_time(&limits, 0.95));
+
+        limits.infinite = true;
+        assert!(!stats.past_hard_time(&limits, 0.85));
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_root_time_pruning() {
+        use crate::engine::evaluate::INFINITE_SCORE;
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.set_option("RootTimePruning", "true").unwrap();
+        controller
+            .set_board_from_fen(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            )
+            .unwrap();
+
+        controller.search(vec!["movetime".to_string(), "300".to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+
+        // the last iteration may be cut short, but always ends with an exact legal move
+        let (count, moves) = controller.game.get_moves();
+        assert!(moves[0..count].contains(&result.best_move));
+        assert!(result.evaluation.abs() < INFINITE_SCORE);
+        assert_eq!(result.pv.first(), Some(&result.best_move));
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn search_best_move(fen: &str, depth: usize, underpromotion_pruning: bool) -> String {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.set_board_from_fen(fen).unwrap();
+        controller.search_options.underpromotion_pruning = underpromotion_pruning;
+
+        controller.search(vec!["depth".to_string(), depth.to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+
+        result.best_move.unparse()
+    }
+
+    #[test]
+    fn test_promotion_encoding_round_trip() {
+        for (suffix, piece) in [
+            ("q", Piece::Queen),
+            ("n", Piece::Knight),
+            ("r", Piece::Rook),
+            ("b", Piece::Bishop),
+        ] {
+            let notation = format!("e7e8{}", suffix);
+            let board_move = BoardMove::parse(&notation).unwrap();
+
+            assert_eq!(board_move.get_promotion(), Some(piece));
+            assert_eq!(board_move.unparse(), notation);
+        }
+    }
+
+    #[test]
+    fn test_queen_promotion_generated_first() {
+        let game = Game::new(Some("8/4P3/8/8/8/8/k7/7K w - - 0 1"));
+        let (move_count, moves) = game.get_moves();
+
+        let promotions: Vec<Piece> = moves[0..move_count]
+            .iter()
+            .filter_map(|m| m.get_promotion())
+            .collect();
+
+        assert_eq!(
+            promotions,
+            vec![Piece::Queen, Piece::Knight, Piece::Rook, Piece::Bishop]
+        );
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_knight_underpromotion_mate_found() {
+        // f8=N# is the only mate, the queen promotion doesn't even give check
+        let fen = "6bn/5Ppk/6pp/8/8/8/8/K7 w - - 0 1";
+
+        assert_eq!(search_best_move(fen, 3, true), "f7f8n");
+        assert_eq!(search_best_move(fen, 3, false), "f7f8n");
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_mate_distance_through_kept_table() {
+        use crate::engine::evaluate::moves_to_mate;
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen("r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w - - 0 1")
+            .unwrap();
+
+        controller.search(vec!["depth".to_string(), "6".to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+        assert_eq!(moves_to_mate(result.evaluation), 2);
+
+        // two plies later, with the table of the first search kept, the mate is a move closer
+        for board_move in &result.pv[..2] {
+            controller.try_move_piece(&board_move.unparse());
+        }
+        controller.search(vec!["depth".to_string(), "6".to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+        assert_eq!(moves_to_mate(result.evaluation), 1);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_go_mate_with_alpha_beta() {
+        use crate::engine::evaluate::moves_to_mate;
+
+        for (fen, moves, best_move) in [
+            ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 1, "d1d8"),
+            (
+                "r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w - - 0 1",
+                2,
+                "d2h6",
+            ),
+        ] {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_board_from_fen(fen).unwrap();
+
+            controller.search(vec!["mate".to_string(), moves.to_string()], false);
+            let result = controller.wait_for_search().unwrap();
+
+            // the mate is found within the moves asked for, and reported as a mate in them
+            assert_eq!(result.best_move.unparse(), best_move);
+            assert_eq!(moves_to_mate(result.evaluation), moves as i32);
+        }
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_go_searchmoves() {
+        let go = |params: &str, threads: &str| {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_option("Threads", threads).unwrap();
+            controller
+                .set_board_from_fen("3r2k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1")
+                .unwrap();
+
+            let params = params.split_whitespace().map(String::from).collect();
+            controller.search(params, false);
+            controller.wait_for_search().unwrap().best_move.unparse()
+        };
+
+        // the back-rank mate isn't among the moves to search, with one thread or split over two
+        for threads in ["1", "2"] {
+            assert_eq!(go("depth 6", threads), "d1d8");
+            assert_eq!(go("depth 6 searchmoves g1f1 h2h3", threads), "h2h3");
+            assert_eq!(go("depth 6 searchmoves g2g3", threads), "g2g3");
+        }
+
+        // no legal move to search among searches all of them
+        assert_eq!(go("depth 6 searchmoves e1e2", "1"), "d1d8");
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_proof_number_mate_search() {
+        use crate::engine::evaluate::is_mate_score;
+
+        let search_mate = |fen: &str, moves: usize| {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_board_from_fen(fen).unwrap();
+            controller.set_option("MateSearch", "ProofNumber").unwrap();
+
+            controller.search(vec!["mate".to_string(), moves.to_string()], false);
+            (controller.wait_for_search().unwrap(), controller.game)
+        };
+
+        for (fen, moves) in [
+            ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 1),
+            ("1k6/8/2K5/8/8/8/8/7R w - - 0 1", 2),
+            (
+                "r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w - - 0 1",
+                2,
+            ),
+        ] {
+            let (result, mut game) = search_mate(fen, moves);
+            assert!(is_mate_score(result.evaluation) && result.evaluation > 0);
+            assert_eq!(result.pv.len(), 2 * moves - 1);
+
+            // the proven line ends in mate
+            for &board_move in &result.pv {
+                game.make_move(board_move);
+            }
+            assert_eq!(game.get_moves().0, 0);
+            assert!(game.is_king_in_check(game.side));
+        }
+
+        // without a mate to prove, alpha-beta still picks a move
+        let (result, game) = search_mate("1k6/8/2K5/8/8/8/8/7R w - - 0 1", 1);
+        let (count, moves) = game.get_moves();
+        assert!(moves[..count].contains(&result.best_move));
+        assert!(!is_mate_score(result.evaluation));
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_node_type_predictions() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            )
+            .unwrap();
+
+        controller.search(vec!["depth".to_string(), "8".to_string()], false);
+        controller.wait_for_search().unwrap();
+
+        let stats = controller.last_node_stats();
+
+        for expected in ExpectedNode::ALL_TYPES {
+            assert!(stats.total(expected) > 0);
+        }
+
+        // the bulk of the tree is cut/all nodes, which should mostly be predicted correctly
+        assert!(stats.accuracy(ExpectedNode::Cut) > 0.5);
+        assert!(stats.accuracy(ExpectedNode::All) > 0.5);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_setoption_deferred_during_search() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        controller.search(vec!["infinite".to_string()], false);
+        controller.set_option("Hash", "1").unwrap();
+
+        // the search is still using the old table
+        assert!(controller.is_searching());
+        assert_eq!(controller.hash_table_size, 128);
+
+        controller.stop_search();
+        assert_eq!(controller.hash_table_size, 1);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_stop_search_within() {
+        use std::time::Duration;
+
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        assert!(matches!(
+            controller.stop_search_within(Duration::ZERO),
+            Ok(None)
+        ));
+
+        controller.search(vec!["infinite".to_string()], false);
+        std::thread::sleep(Duration::from_millis(100));
+
+        // a search that stops is joined as usual
+        let result = controller.stop_search_within(Duration::from_secs(5));
+        assert!(matches!(result, Ok(Some(_))));
+        assert!(!controller.is_searching());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_ponder_waits_for_ponderhit() {
+        use crate::game::board::BoardMove;
+        use std::time::Duration;
+
+        let ponder = |controller: &mut GameController| {
+            controller.initialize();
+            controller
+                .set_board_from_fen("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1")
+                .unwrap();
+
+            let params = ["ponder", "wtime", "10000", "btime", "10000"];
+            controller.search(params.iter().map(|p| p.to_string()).collect(), false);
+            std::thread::sleep(Duration::from_millis(200));
+
+            // the mate is found right away, but the move waits for the GUI
+            assert!(controller.is_searching());
+        };
+
+        let mut controller = GameController::new();
+        ponder(&mut controller);
+        controller.ponderhit();
+        let result = controller.wait_for_search().unwrap();
+        assert_eq!(result.best_move, BoardMove::parse("a1a8").unwrap());
+        assert_eq!(controller.eval_trend().entries.len(), 1);
+
+        // a ponder miss stops the search, which still answers with its move
+        let mut controller = GameController::new();
+        ponder(&mut controller);
+        let result = controller.stop_search().unwrap();
+        assert_eq!(result.best_move, BoardMove::parse("a1a8").unwrap());
+        assert!(controller.eval_trend().entries.is_empty());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_avoid_repetition() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.set_option("AvoidRepetition", "true").unwrap();
+
+        // a queen up, but the queen has been shuffling back and forth
+        controller
+            .set_board_from_fen("7k/8/8/8/8/8/8/3QK3 w - - 0 1")
+            .unwrap();
+        for notation in ["d1d2", "h8g8", "d2d1", "g8h8"] {
+            controller.try_move_piece(notation);
+        }
+
+        controller.search(vec!["depth".to_string(), "6".to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+
+        assert_ne!(result.best_move, BoardMove::parse("d1d2").unwrap());
+        assert!(result.evaluation > 0);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_two_thread_root_split() {
+        // the knight wins the queen, whichever thread gets to search the move
+        for threads in ["1", "2"] {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_option("Threads", threads).unwrap();
+            controller
+                .set_board_from_fen("4k3/ppp5/8/8/7q/8/PPP3N1/4K3 w - - 0 1")
+                .unwrap();
+
+            controller.search(vec!["depth".to_string(), "6".to_string()], false);
+            let result = controller.wait_for_search().unwrap();
+
+            assert_eq!(result.best_move, BoardMove::parse("g2h4").unwrap());
+            assert!(result.evaluation > 500);
+        }
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_thread_placement_options() {
+        use crate::engine::affinity::NumaPolicy;
+
+        // pinned threads and a (re)placed TT search just the same, with or without the feature
+        for policy in ["Interleave", "local"] {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_option("Threads", "2").unwrap();
+            controller.set_option("ThreadAffinity", "true").unwrap();
+            controller.set_option("NUMAPolicy", policy).unwrap();
+            controller.set_option("NUMAPolicy", "Everywhere").unwrap();
+            assert!(controller.thread_affinity);
+            assert_eq!(Some(controller.numa_policy), NumaPolicy::parse(policy));
+
+            controller
+                .set_board_from_fen("4k3/ppp5/8/8/7q/8/PPP3N1/4K3 w - - 0 1")
+                .unwrap();
+            controller.search(vec!["depth".to_string(), "6".to_string()], false);
+            let result = controller.wait_for_search().unwrap();
+
+            assert_eq!(result.best_move, BoardMove::parse("g2h4").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_option_ranges() {
+        let mut controller = GameController::new();
+
+        // an empty table (or no search thread) would take the next search down
+        for (name, value) in [("Hash", "0"), ("Threads", "0"), ("Hash", "33554433")] {
+            controller.set_option(name, value).unwrap();
+        }
+        assert_eq!((controller.hash_table_size, controller.threads), (128, 1));
+
+        controller.set_option("Threads", "1024").unwrap();
+        assert_eq!(controller.threads, 1024);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_contempt_for_repetition() {
+        use crate::engine::search::params::CONTEMPT;
+
+        // a rook down, white can repeat the position by shuffling the knights
+        let search = |analyse_mode: bool| {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller
+                .set_option("UCI_AnalyseMode", &analyse_mode.to_string())
+                .unwrap();
+            controller
+                .set_board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1")
+                .unwrap();
+            for notation in [
+                "g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8",
+            ] {
+                controller.try_move_piece(notation);
+            }
+
+            controller.search(vec!["depth".to_string(), "6".to_string()], false);
+            controller.wait_for_search().unwrap()
+        };
+
+        // in analysis, the draw is worth exactly nothing
+        let analysed = search(true);
+        assert_eq!(analysed.best_move, BoardMove::parse("g1f3").unwrap());
+        assert_eq!(analysed.evaluation, 0);
+
+        // when playing, the side behind in material is glad to take the draw
+        let played = search(false);
+        assert_eq!(played.best_move, BoardMove::parse("g1f3").unwrap());
+        assert_eq!(played.evaluation, CONTEMPT);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_pv_continues_into_next_search() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        controller.search(vec!["depth".to_string(), "6".to_string()], false);
+        let pv = controller.wait_for_search().unwrap().pv;
+        assert!(pv.len() >= 3);
+
+        // nothing's been played yet, so the whole line is still expected
+        assert_eq!(controller.continued_pv(), pv);
+
+        for board_move in &pv[0..2] {
+            controller.try_move_piece(&board_move.unparse());
+        }
+        assert_eq!(controller.continued_pv(), pv[2..]);
+
+        // a different reply leaves nothing to continue
+        controller.reset_board();
+        controller.try_move_piece(&pv[0].unparse());
+        controller.try_move_piece(if pv[1].unparse() == "a7a6" {
+            "h7h6"
+        } else {
+            "a7a6"
+        });
+        assert!(controller.continued_pv().is_empty());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_quiescence_node_counts() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            )
+            .unwrap();
+
+        controller.search(vec!["depth".to_string(), "5".to_string()], false);
+        controller.wait_for_search().unwrap();
+
+        // a tactical position spends plenty of nodes in both
+        let counts = controller.last_node_counts();
+        assert!(counts.main > 0);
+        assert!(counts.quiescence > 0);
+        assert_eq!(counts.total(), counts.main + counts.quiescence);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_verify_pruning() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            )
+            .unwrap();
+
+        // without verification, nothing is recorded
+        controller.search(vec!["depth".to_string(), "5".to_string()], false);
+        controller.wait_for_search().unwrap();
+        assert_eq!(controller.last_pruning_stats().checked, [0; 5]);
+
+        controller.search_options.verify_pruning_rate = 1.0;
+        controller.search(vec!["depth".to_string(), "5".to_string()], false);
+        controller.wait_for_search().unwrap();
+
+        let stats = controller.last_pruning_stats();
+        assert!(stats.checked.iter().sum::<u64>() > 0);
+        for kind in 0..5 {
+            assert!(stats.changed[kind] <= stats.checked[kind]);
+        }
+    }
+
+    #[test]
+    fn test_search_status() {
+        let e2e4 = BoardMove::parse("e2e4").unwrap();
+
+        // a fail-low with nothing to show for it is still a proper result
+        let fail_low = SearchResult::leaf(-50);
+        assert!(fail_low.is_valid() && !fail_low.is_partial());
+        assert_eq!(fail_low.status, SearchStatus::Completed);
+
+        let interrupted = SearchResult::interrupted();
+        assert!(!interrupted.is_valid() && !interrupted.is_partial());
+
+        // a partial root keeps its move and evaluation
+        let partial = SearchResult::with_pv(e2e4, 30, Vec::new()).partial();
+        assert!(partial.is_valid() && partial.is_partial());
+        assert_eq!((partial.best_move, partial.evaluation), (e2e4, 30));
+        assert_eq!(partial.pv, vec![e2e4]);
+    }
+
+    #[test]
+    fn test_score_drop_extends_time() {
+        use crate::engine::evaluate::CHECKMATE_SCORE;
+        use crate::engine::search::history::History;
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::options::SearchOptions;
+        use crate::engine::search::results::SearchClock;
+        use crate::engine::search::searcher::{Search, SearchContext};
+        use crate::engine::table::TranspositionTable;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let mut game = Game::new(None);
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = SearchLimits {
+            max_depth: None,
+            max_nodes: None,
+            max_time_ms: Some(1000),
+            extended_time_ms: Some(2000),
+            moves: vec![],
+            infinite: false,
+            exact: false,
+            mate: None,
+        };
+        let context = SearchContext {
+            options: SearchOptions::default(),
+            tt: &tt,
+            history: &mut history,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            ponder_flag: Arc::new(AtomicBool::new(false)),
+            clock: SearchClock::default(),
+        };
+        let mut search = Search::new(&mut game, limits, context, false);
+
+        // small drops, shallow iterations and mate scores leave the time alone
+        search.extend_time_if_dropping(10, 30, 10);
+        search.extend_time_if_dropping(3, 30, -200);
+        search.extend_time_if_dropping(10, CHECKMATE_SCORE - 5, 200);
+        assert_eq!(search.limits.max_time_ms, Some(1000));
+
+        // a real drop extends it, but never past the hard limit
+        search.extend_time_if_dropping(10, 30, -50);
+        assert_eq!(search.limits.max_time_ms, Some(1500));
+        search.extend_time_if_dropping(11, -50, -150);
+        search.extend_time_if_dropping(12, -150, -250);
+        assert_eq!(search.limits.max_time_ms, Some(2000));
+
+        // an exact movetime is never extended
+        search.limits.max_time_ms = Some(1000);
+        search.limits.exact = true;
+        search.extend_time_if_dropping(10, 30, -50);
+        assert_eq!(search.limits.max_time_ms, Some(1000));
+    }
+
+    #[test]
+    fn test_evasions_capture_checker_first() {
+        use crate::engine::search::history::History;
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::options::SearchOptions;
+        use crate::engine::search::results::SearchClock;
+        use crate::engine::search::searcher::{Search, SearchContext};
+        use crate::engine::table::TranspositionTable;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        // the pawn on d2 gives check, the king could also take the (more valuable) bishop on f1
+        let mut game = Game::new(Some("7k/8/8/8/8/1N6/3p4/4Kb2 w - - 0 1"));
+        let (move_count, mut moves) = game.get_moves();
+
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = SearchLimits {
+            max_depth: Some(1),
+            max_nodes: None,
+            max_time_ms: None,
+            extended_time_ms: None,
+            moves: vec![],
+            infinite: false,
+            exact: false,
+            mate: None,
+        };
+        let context = SearchContext {
+            options: SearchOptions::default(),
+            tt: &tt,
+            history: &mut history,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            ponder_flag: Arc::new(AtomicBool::new(false)),
+            clock: SearchClock::default(),
+        };
+        let search = Search::new(&mut game, limits, context, false);
+        search.order_evasions(&mut moves[0..move_count], None, None);
+
+        let position = |uci: &str| {
+            let board_move = BoardMove::parse(uci).unwrap();
+            moves[0..move_count]
+                .iter()
+                .position(|&m| m == board_move)
+                .unwrap()
+        };
+        assert!(position("b3d2") < position("e1f1"));
+        assert!(position("e1d2") < position("e1f1"));
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_verify_best_move() {
+        use crate::engine::search::history::History;
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::options::SearchOptions;
+        use crate::engine::search::results::SearchClock;
+        use crate::engine::search::searcher::{Search, SearchContext};
+        use crate::engine::table::TranspositionTable;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        // the white queen on d4 can take the defended pawn on d5 or retreat
+        let mut game = Game::new(Some("4k3/8/4p3/3p4/3Q4/8/8/4K3 w - - 0 1"));
+        let tt = TranspositionTable::new(1);
+        let mut history = History::new();
+        let limits = SearchLimits {
+            max_depth: Some(6),
+            max_nodes: None,
+            max_time_ms: None,
+            extended_time_ms: None,
+            moves: vec![],
+            infinite: false,
+            exact: false,
+            mate: None,
+        };
+        let context = SearchContext {
+            options: SearchOptions::default(),
+            tt: &tt,
+            history: &mut history,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            ponder_flag: Arc::new(AtomicBool::new(false)),
+            clock: SearchClock::default(),
+        };
+        let mut search = Search::new(&mut game, limits, context, false);
+        search.completed_depth = 6;
+
+        // a sound move keeps its place
+        let retreat = BoardMove::parse("d4d3").unwrap();
+        let verified = search.verify_best_move(SearchResult::with_pv(retreat, 700, Vec::new()));
+        assert_eq!(verified.best_move, retreat);
+
+        // a queen blunder scored like a sound move (say, from a corrupted entry) is replaced
+        let blunder = BoardMove::parse("d4d5").unwrap();
+        let verified = search.verify_best_move(SearchResult::with_pv(blunder, 700, Vec::new()));
+        assert_ne!(verified.best_move, blunder);
+        assert!(verified.is_valid() && verified.evaluation > 300);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_multi_pv() {
+        use crate::engine::evaluate::is_mate_score;
+        use crate::engine::search::history::History;
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::options::SearchOptions;
+        use crate::engine::search::results::SearchClock;
+        use crate::engine::search::searcher::{Search, SearchContext};
+        use crate::engine::table::TranspositionTable;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let search_lines = |multi_pv: usize, searchmoves: &[&str]| {
+            let mut game = Game::new(Some("3r2k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1"));
+            let tt = TranspositionTable::new(1);
+            let mut history = History::new();
+            let limits = SearchLimits {
+                max_depth: Some(5),
+                max_nodes: None,
+                max_time_ms: None,
+                extended_time_ms: None,
+                moves: searchmoves
+                    .iter()
+                    .map(|m| BoardMove::parse(m).unwrap())
+                    .collect(),
+                infinite: false,
+                exact: false,
+                mate: None,
+            };
+            let options = SearchOptions {
+                multi_pv,
+                ..SearchOptions::default()
+            };
+            let context = SearchContext {
+                options,
+                tt: &tt,
+                history: &mut history,
+                stop_flag: Arc::new(AtomicBool::new(false)),
+                ponder_flag: Arc::new(AtomicBool::new(false)),
+                clock: SearchClock::default(),
+            };
+            let mut search = Search::new(&mut game, limits, context, false);
+
+            let result = search.run();
+            assert_eq!(search.lines[0].best_move, result.best_move);
+            (search.completed_depth, search.lines.clone())
+        };
+
+        // the mate first, then the best of the other moves, each a different one (and deepened
+        // past the mate, which alone would end the search)
+        let (depth, lines) = search_lines(3, &[]);
+        assert_eq!((depth, lines.len()), (5, 3));
+        assert_eq!(lines[0].best_move.unparse(), "d1d8");
+        assert!(is_mate_score(lines[0].evaluation));
+        assert!(
+            lines
+                .windows(2)
+                .all(|pair| pair[0].evaluation >= pair[1].evaluation)
+        );
+        assert_ne!(lines[1].best_move, lines[2].best_move);
+        assert!(lines.iter().all(|line| line.pv[0] == line.best_move));
+
+        // no more lines than the root moves to search
+        let (_, lines) = search_lines(3, &["g1f1", "h2h3"]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| !is_mate_score(line.evaluation)));
+
+        let (_, lines) = search_lines(1, &[]);
+        assert_eq!(lines.len(), 1);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_tt_cutoffs_keep_pv_moves() {
+        use crate::engine::search::history::History;
+        use crate::engine::search::limits::SearchLimits;
+        use crate::engine::search::options::SearchOptions;
+        use crate::engine::search::results::SearchClock;
+        use crate::engine::search::searcher::{Search, SearchContext};
+        use crate::engine::table::TranspositionTable;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        // the second search cuts off on quiescence entries (which have no move) of the first
+        let tt = TranspositionTable::new(16);
+        let search_lines = |depth: usize, searchmoves: &[&str]| {
+            let mut game = Game::new(None);
+            let mut history = History::new();
+            let limits = SearchLimits {
+                max_depth: Some(depth),
+                max_nodes: None,
+                max_time_ms: None,
+                extended_time_ms: None,
+                moves: searchmoves
+                    .iter()
+                    .map(|m| BoardMove::parse(m).unwrap())
+                    .collect(),
+                infinite: false,
+                exact: false,
+                mate: None,
+            };
+            let context = SearchContext {
+                options: SearchOptions {
+                    multi_pv: 3,
+                    ..SearchOptions::default()
+                },
+                tt: &tt,
+                history: &mut history,
+                stop_flag: Arc::new(AtomicBool::new(false)),
+                ponder_flag: Arc::new(AtomicBool::new(false)),
+                clock: SearchClock::default(),
+            };
+            let mut search = Search::new(&mut game, limits, context, false);
+            let result = search.run();
+            (result, search.lines)
+        };
+
+        // a line searched later can score better than the first one, and is reported first
+        let (result, lines) = search_lines(6, &["e2e4", "d2d4"]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].evaluation >= lines[1].evaluation);
+        assert_eq!(result.best_move, lines[0].best_move);
+
+        let (_, lines) = search_lines(6, &[]);
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines
+                .iter()
+                .all(|line| line.pv.iter().all(|&m| m != BoardMove::empty()))
+        );
+    }
+
+    #[test]
+    fn test_aspiration_adaptation() {
+        let mut aspiration = AspirationAdaptation::default();
+
+        // volatile searches widen the window, up to a limit
+        let volatile = AspirationStats {
+            searches: 4,
+            fail_highs: 1,
+            fail_lows: 1,
+        };
+        aspiration.update(&volatile);
+        assert!(aspiration.scale > 1.0);
+        for _ in 0..50 {
+            aspiration.update(&volatile);
+        }
+        assert_eq!(aspiration.scale, ASPIRATION_SCALE_MAX);
+        assert_eq!(aspiration.last, volatile);
+        assert_eq!(aspiration.game.searches, 51 * 4);
+
+        // stable ones narrow it, down to a limit
+        let stable = AspirationStats {
+            searches: 20,
+            ..Default::default()
+        };
+        for _ in 0..50 {
+            aspiration.update(&stable);
+        }
+        assert_eq!(aspiration.scale, ASPIRATION_SCALE_MIN);
+
+        // searches too shallow for windows change nothing
+        aspiration.update(&AspirationStats::default());
+        assert_eq!(aspiration.scale, ASPIRATION_SCALE_MIN);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_aspiration_reset_on_new_game() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_fen(
+                "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            )
+            .unwrap();
+
+        controller.search(vec!["depth".to_string(), "8".to_string()], false);
+        controller.wait_for_search().unwrap();
+        assert!(controller.aspiration().game.searches > 0);
+
+        controller.new_game();
+        assert_eq!(controller.aspiration(), AspirationAdaptation::default());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_eval_trend() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        let search = |controller: &mut GameController, params: &[&str]| {
+            controller.search(params.iter().map(|p| p.to_string()).collect(), false);
+            if params.contains(&"ponder") {
+                controller.stop_search();
+            } else {
+                controller.wait_for_search().unwrap();
+            }
+        };
+
+        search(&mut controller, &["depth", "3"]);
+        controller.try_move_piece("e2e4");
+        controller.try_move_piece("e7e5");
+        search(&mut controller, &["depth", "2"]);
+
+        // a ponder search stopped without a ponderhit isn't one of the game's moves
+        search(&mut controller, &["ponder", "depth", "2"]);
+
+        let trend = controller.eval_trend();
+        let moves = trend
+            .entries
+            .iter()
+            .map(|entry| (entry.move_number(), entry.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(moves, [(1, 3), (2, 2)]);
+
+        let csv = trend.to_string();
+        assert_eq!(csv.lines().next(), Some("move,eval,depth,time"));
+        assert!(csv.lines().nth(2).unwrap().starts_with("2,"));
+
+        // searching a taken back move again replaces it and what followed
+        controller.undo_move();
+        controller.undo_move();
+        search(&mut controller, &["depth", "1"]);
+        let trend = controller.eval_trend();
+        assert_eq!(trend.entries.len(), 1);
+        assert_eq!(trend.entries[0].depth, 1);
+
+        controller.new_game();
+        assert!(controller.eval_trend().entries.is_empty());
+    }
+
+    #[test]
+    fn test_search_options() {
+        let mut controller = GameController::new();
+
+        controller.set_option("QuiescenceDepth", "8").unwrap();
+        controller.set_option("QuiescenceChecks", "0").unwrap();
+        assert_eq!(controller.search_options.quiescence_depth, 8);
+        assert_eq!(controller.search_options.quiescence_check_plies, 0);
+
+        // out of range values are rejected
+        controller.set_option("QuiescenceDepth", "1000").unwrap();
+        assert_eq!(controller.search_options.quiescence_depth, 8);
+
+        controller.set_option("AvoidRepetition", "true").unwrap();
+        assert!(controller.search_options.avoid_repetition);
+
+        controller
+            .set_option("UCI_ShowRefutations", "true")
+            .unwrap();
+        controller.set_option("UCI_ShowCurrLine", "true").unwrap();
+        assert!(controller.search_options.show_refutations);
+        assert!(controller.search_options.show_currline);
+
+        controller.set_option("Contempt", "35").unwrap();
+        controller.set_option("UCI_AnalyseMode", "true").unwrap();
+        assert_eq!(controller.search_options.contempt, 35);
+        assert!(controller.search_options.analyse_mode);
+
+        controller.set_option("Contempt", "-10").unwrap();
+        assert_eq!(controller.search_options.contempt, 35);
+
+        controller.set_option("Silent", "true").unwrap();
+        assert!(controller.search_options.silent);
+        controller.set_option("Silent", "maybe").unwrap();
+        assert!(controller.search_options.silent);
+
+        // names are case insensitive, with any spacing between words
+        controller.set_option("move   OVERHEAD", "50").unwrap();
+        assert_eq!(controller.move_overhead, 50);
+
+        // unknown options and valueless buttons are fine
+        controller.set_option("NoSuchOption", "1").unwrap();
+        controller.set_option("Clear Hash", "").unwrap();
+        assert_eq!(controller.move_overhead, 50);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_quiescence_depth_limits_horizon() {
+        let fen = "4k3/8/4p3/3r4/8/8/8/3QK3 w - - 0 1";
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.set_board_from_fen(fen).unwrap();
+
+        // without quiescence, a depth 1 search is just the best static eval after our move
+        let mut game = controller.game.clone();
+        let (move_count, moves) = game.get_moves();
+        let best_static_eval = moves[0..move_count]
+            .iter()
+            .map(|m| {
+                game.make_move(*m);
+                let eval = -game.evaluate_stm();
+                game.unmake_move();
+                eval
+            })
+            .max()
+            .unwrap();
+
+        controller.search_options.quiescence_depth = 0;
+        controller.search(vec!["depth".to_string(), "1".to_string()], false);
+        let result = controller.wait_for_search().unwrap();
+        assert_eq!(result.evaluation, best_static_eval);
+    }
+}
+
+#[cfg(test)]
+mod magic_tests {
+    use crate::game::bitboard::{
+        Bitboard, MAGIC_BLOCK_SIZE, MagicBitboards, calculate_blocker_keys, dedup_magic_blocks,
+        magic_square, pack_magic_tables, try_magic, verify_magic_lookups,
+    };
+    use crate::game::magic::{MAGIC_ENTRY_COUNT, MAGIC_TABLE, magic_entry};
+    use std::sync::OnceLock;
+
+    /// The magic bitboards of the current magic numbers, computed once for all the tests
+    fn current_magic_bitboards() -> &'static MagicBitboards {
+        static MAGIC_BITBOARDS: OnceLock<MagicBitboards> = OnceLock::new();
+
+        MAGIC_BITBOARDS.get_or_init(|| {
+            (0..128)
+                .map(|i| {
+                    let (x, y, piece) = magic_square(i);
+                    let (magic, _, shift) = MAGIC_TABLE[i];
+                    let keys = calculate_blocker_keys(x, y, &piece);
+
+                    try_magic(&keys, magic, 64 - shift as u32).expect("current magic doesn't hash")
+                })
+                .collect()
+        })
+    }
+
+    /// The entries of the generated tables in their packed layout, whichever way they're stored
+    fn generated_entries() -> &'static [Bitboard] {
+        static ENTRIES: OnceLock<Vec<Bitboard>> = OnceLock::new();
+
+        ENTRIES.get_or_init(|| (0..MAGIC_ENTRY_COUNT).map(magic_entry).collect())
+    }
+
+    #[test]
+    fn test_packed_tables_match_lookups() {
+        // first-fit packing is quadratic, so only the tables of the first rank's rooks are
+        // packed here (the generated tables cover all of them)
+        let magic_bitboards = current_magic_bitboards()[..8].to_vec();
+        let (offsets, packed) = pack_magic_tables(&magic_bitboards);
+
+        // overlapping tables can only make things smaller
+        let total: usize = magic_bitboards
+            .iter()
+            .map(|entry| entry.entries.len())
+            .sum();
+        assert!(packed.len() <= total);
+        assert!(verify_magic_lookups(&magic_bitboards, &offsets, |index| {
+            packed[index]
+        }));
+
+        // and so do the generated tables, in whichever layout they were written
+        let generated_offsets = MAGIC_TABLE.map(|(_, offset, _)| offset);
+        assert!(verify_magic_lookups(
+            current_magic_bitboards(),
+            &generated_offsets,
+            magic_entry
+        ));
+    }
+
+    #[test]
+    fn test_dedup_magic_blocks() {
+        let magic_bitboards = current_magic_bitboards();
+        let offsets = MAGIC_TABLE.map(|(_, offset, _)| offset);
+        let packed = generated_entries();
+        let (block_indices, block_entries) = dedup_magic_blocks(packed);
+
+        assert_eq!(block_indices.len(), packed.len().div_ceil(MAGIC_BLOCK_SIZE));
+        assert!(block_entries.len() < packed.len() / 2);
+
+        assert!(verify_magic_lookups(magic_bitboards, &offsets, |index| {
+            let block = block_indices[index / MAGIC_BLOCK_SIZE] as usize;
+            block_entries[block * MAGIC_BLOCK_SIZE + index % MAGIC_BLOCK_SIZE]
+        }));
+
+        // a single wrong entry is caught
+        assert!(!verify_magic_lookups(magic_bitboards, &offsets, |index| {
+            match index == offsets[5] {
+                true => packed[index] ^ Bitboard(1),
+                false => packed[index],
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod bitboard_tests {
+    use crate::game::bitboard::{
+        ADJACENT_FILES, Bitboard, FILE_A, FILE_H, FILES, FRONT_SPANS, PASSED_PAWN_SPANS, RANK_1,
+        RANK_8, RANKS, east, forward, north, south, west,
+    };
+    use crate::game::pieces::Color;
+    use crate::game::square::BoardSquare;
+
+    fn mask(squares: &[&str]) -> Bitboard {
+        squares
+            .iter()
+            .map(|square| BoardSquare::parse(square).unwrap().to_mask())
+            .fold(Bitboard::EMPTY, |mask, square| mask | square)
+    }
+
+    #[test]
+    fn test_file_and_rank_masks() {
+        assert_eq!(
+            FILES.iter().fold(Bitboard::EMPTY, |all, &file| all | file),
+            Bitboard::FULL
+        );
+        assert_eq!(
+            RANKS.iter().fold(Bitboard::EMPTY, |all, &rank| all | rank),
+            Bitboard::FULL
+        );
+        assert_eq!(
+            FILE_H,
+            mask(&["h1", "h2", "h3", "h4", "h5", "h6", "h7", "h8"])
+        );
+        assert_eq!(
+            RANK_8,
+            mask(&["a8", "b8", "c8", "d8", "e8", "f8", "g8", "h8"])
+        );
+
+        assert_eq!(ADJACENT_FILES[0], FILES[1]);
+        assert_eq!(ADJACENT_FILES[4], FILES[3] | FILES[5]);
+    }
+
+    #[test]
+    fn test_shifts_drop_squares_off_the_board() {
+        assert!(east(FILE_H).is_empty());
+        assert!(west(FILE_A).is_empty());
+        assert!(north(RANK_8).is_empty());
+        assert!(south(RANK_1).is_empty());
+
+        assert_eq!(east(mask(&["a1", "h4"])), mask(&["b1"]));
+        assert_eq!(west(mask(&["a1", "h4"])), mask(&["g4"]));
+        assert_eq!(forward(mask(&["e4"]), Color::White), mask(&["e5"]));
+        assert_eq!(forward(mask(&["e4"]), Color::Black), mask(&["e3"]));
+    }
+
+    #[test]
+    fn test_pawn_spans() {
+        let e6 = BoardSquare::parse("e6").unwrap();
+        let a2 = BoardSquare::parse("a2").unwrap();
+
+        assert_eq!(
+            FRONT_SPANS[Color::White as usize][e6.index()],
+            mask(&["e7", "e8"])
+        );
+        assert_eq!(
+            PASSED_PAWN_SPANS[Color::White as usize][e6.index()],
+            mask(&["d7", "e7", "f7", "d8", "e8", "f8"])
+        );
+        assert_eq!(
+            PASSED_PAWN_SPANS[Color::Black as usize][a2.index()],
+            mask(&["a1", "b1"])
+        );
+        assert!(FRONT_SPANS[Color::Black as usize][BoardSquare::H1.index()].is_empty());
+    }
+}
+
+#[cfg(test)]
+mod san_tests {
+    use crate::game::board::{BoardMove, Game};
+
+    fn san(fen: &str, notation: &str) -> String {
+        let mut game = Game::new(Some(fen));
+        let board_move = game
+            .legal_move(BoardMove::parse(notation).unwrap())
+            .unwrap();
+        game.move_to_san(board_move)
+    }
+
+    #[test]
+    fn test_san_pieces_and_captures() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/3PP3/5N2/PPP2PPP/RNBQKB1R b KQkq - 0 3";
+        assert_eq!(san(fen, "e5d4"), "exd4");
+        assert_eq!(san(fen, "c6d4"), "Nxd4");
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        // knights on b1 and f3 can both go to d2
+        let fen = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(san(fen, "b1d2"), "Nbd2");
+
+        // rooks on a1 and a5 can both go to a3
+        let fen = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(san(fen, "a1a3"), "R1a3");
+
+        // queens on d4, h4 and h8 can all go to f6, sharing both a rank and a file
+        let fen = "7Q/8/8/8/3Q3Q/8/k7/4K3 w - - 0 1";
+        assert_eq!(san(fen, "h4f6"), "Qh4f6");
+    }
+
+    #[test]
+    fn test_san_special_moves() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san(fen, "e1g1"), "O-O");
+        assert_eq!(san(fen, "e1c1"), "O-O-O");
+
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        assert_eq!(san(fen, "e5d6"), "exd6");
+
+        let fen = "6bn/5Ppk/6pp/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(san(fen, "f7f8n"), "f8=N#");
+        assert_eq!(san(fen, "f7f8q"), "f8=Q");
+    }
+
+    #[test]
+    fn test_line_to_san_numbering() {
+        let mut game = Game::new(Some(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        ));
+        let fen = game.get_fen();
+
+        let line: Vec<BoardMove> = ["e7e5", "g1f3", "b8c6"]
+            .iter()
+            .map(|m| BoardMove::parse(m).unwrap())
+            .collect();
+
+        assert_eq!(game.line_to_san(&line), "1... e5 2. Nf3 Nc6");
+
+        // the position is left untouched
+        assert_eq!(game.get_fen(), fen);
+    }
+}
+
+#[cfg(test)]
+mod tt_tests {
+    use crate::engine::table::{NodeType, TranspositionTable};
+    use crate::game::board::BoardMove;
+
+    #[test]
+    fn test_store_pv() {
+        use crate::game::board::Game;
+
+        let tt = TranspositionTable::new(1);
+        let mut game = Game::new(None);
+        let pv: Vec<BoardMove> = ["e2e4", "e7e5", "g1f3"]
+            .iter()
+            .map(|m| BoardMove::parse(m).unwrap())
+            .collect();
+
+        tt.store_pv(&game, &pv, 30, 5);
+
+        for (ply, &board_move) in pv.iter().enumerate() {
+            let entry = tt.probe(game.zobrist_key).unwrap();
+            assert_eq!(entry.best_move, board_move);
+            assert_eq!(entry.depth as usize, 5 - ply);
+            assert_eq!(entry.evaluation, if ply % 2 == 0 { 30 } else { -30 });
+
+            game.make_move(board_move);
+        }
+        assert!(tt.probe(game.zobrist_key).is_none());
+    }
+
+    #[test]
+    fn test_concurrent_stores_stay_consistent() {
+        let tt = TranspositionTable::new(1);
+        let moves = [
+            BoardMove::parse("e2e4").unwrap(),
+            BoardMove::parse("d2d4").unwrap(),
+        ];
+
+        // two threads keep overwriting the same position with different entries, which the
+        // probes must only ever see whole
+        std::thread::scope(|scope| {
+            for (thread, best_move) in moves.into_iter().enumerate() {
+                let tt = &tt;
+                scope.spawn(move || {
+                    for _ in 0..20000 {
+                        let depth = 10 + thread as i8;
+                        tt.store(99, depth, depth as i32, best_move, NodeType::Exact, 0);
+                    }
+                });
+            }
+
+            for _ in 0..20000 {
+                if let Some(entry) = tt.probe(99) {
+                    assert_eq!(entry.evaluation, entry.depth as i32);
+                    assert_eq!(entry.best_move, moves[(entry.depth - 10) as usize]);
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_quiescence_entry_depths() {
+        use crate::engine::table::{DEPTH_QS_CHECKS, DEPTH_QS_NO_CHECKS};
+
+        let tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("e4d5").unwrap();
+
+        tt.store(
+            3,
+            DEPTH_QS_NO_CHECKS,
+            -25,
+            best_move,
+            NodeType::LowerBound,
+            0,
+        );
+        let entry = tt.probe(3).unwrap();
+        assert_eq!(entry.depth, DEPTH_QS_NO_CHECKS);
+        assert_eq!(entry.evaluation, -25);
+        assert_eq!(entry.best_move, best_move);
+        assert_eq!(entry.node_type, NodeType::LowerBound);
+
+        // a quiescence search with checks replaces one without them, but not a real search
+        tt.store(3, DEPTH_QS_CHECKS, 10, best_move, NodeType::Exact, 0);
+        assert_eq!(tt.probe(3).unwrap().depth, DEPTH_QS_CHECKS);
+        tt.store(3, 4, 20, best_move, NodeType::Exact, 0);
+        tt.store(3, DEPTH_QS_NO_CHECKS, 30, best_move, NodeType::Exact, 0);
+        assert_eq!(tt.probe(3).unwrap().depth, 4);
+    }
+
+    #[test]
+    fn test_entries_age_out_lazily() {
+        let mut tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("e2e4").unwrap();
+
+        tt.new_search(1);
+        tt.store(42, 5, 10, best_move, NodeType::Exact, 0);
+
+        // entries survive a couple of moves...
+        tt.new_search(2);
+        tt.new_search(3);
+        assert_eq!(tt.probe(42).unwrap().best_move, best_move);
+
+        // ...and any number of searches of the same position...
+        tt.new_search(3);
+        tt.new_search(3);
+        assert_eq!(tt.probe(42).unwrap().best_move, best_move);
+
+        // ...but not more moves
+        tt.new_search(4);
+        assert!(tt.probe(42).is_none());
+    }
+
+    #[test]
+    fn test_new_game_makes_entries_stale() {
+        let mut tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("d2d4").unwrap();
+
+        tt.store(7, 10, 0, best_move, NodeType::Exact, 0);
+        assert!(tt.probe(7).is_some());
+
+        tt.new_game();
+        assert!(tt.probe(7).is_none());
+
+        // a stale entry is replaced even by a shallower one
+        tt.store(7, 1, 0, best_move, NodeType::UpperBound, 0);
+        assert_eq!(tt.probe(7).unwrap().depth, 1);
+    }
+
+    #[test]
+    fn test_generations_wrap_around() {
+        let mut tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("d2d4").unwrap();
+
+        // 85 games take the 8-bit generation to 255, the last one before it wraps
+        for _ in 0..85 {
+            tt.new_game();
+        }
+        tt.store(7, 10, 0, best_move, NodeType::Exact, 0);
+        tt.store(8, 10, 0, best_move, NodeType::Exact, 0);
+
+        // two searches later (at generation 1) the entries are still recent enough to use
+        tt.new_search(1);
+        tt.new_search(2);
+        assert_eq!(tt.probe(7).unwrap().depth, 10);
+        assert_eq!(tt.probe(8).unwrap().depth, 10);
+
+        // but older than the current generation, so even a shallower entry replaces one
+        tt.store(7, 1, 0, best_move, NodeType::UpperBound, 0);
+        assert_eq!(tt.probe(7).unwrap().depth, 1);
+
+        // and one more search makes the other one stale
+        tt.new_search(3);
+        assert!(tt.probe(8).is_none());
+        assert!(tt.probe(7).is_some());
+    }
+
+    #[test]
+    fn test_entries_near_fifty_moves_need_same_clock() {
+        let tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("e2e4").unwrap();
+
+        // far from the fifty-move rule, the clock doesn't matter
+        tt.store(1, 8, 50, best_move, NodeType::Exact, 10);
+        let entry = tt.probe(1).unwrap();
+        assert!(entry.is_valid_for_clock(10, 8));
+        assert!(entry.is_valid_for_clock(30, 8));
+        assert!(!entry.is_valid_for_clock(95, 8));
+
+        // a search that could reach it only transfers to the same clock
+        tt.store(2, 8, 0, best_move, NodeType::Exact, 92);
+        let entry = tt.probe(2).unwrap();
+        assert!(entry.is_valid_for_clock(92, 8));
+        assert!(!entry.is_valid_for_clock(20, 8));
+    }
+
+    #[test]
+    fn test_static_eval_entries() {
+        use crate::engine::table::{DEPTH_QS_CHECKS, TTEntry};
+
+        let tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("e2e4").unwrap();
+
+        tt.store(5, 3, 10, best_move, NodeType::Exact, 0);
+        assert_eq!(tt.probe(5).unwrap().static_eval, None);
+
+        tt.store_entry(TTEntry {
+            key: 6,
+            depth: DEPTH_QS_CHECKS,
+            evaluation: 40,
+            best_move,
+            node_type: NodeType::LowerBound,
+            halfmove_clock: 7,
+            static_eval: Some(-12),
+            ..Default::default()
+        });
+        let entry = tt.probe(6).unwrap();
+        assert_eq!(entry.static_eval, Some(-12));
+        assert_eq!(entry.evaluation, 40);
+        assert_eq!(entry.node_type, NodeType::LowerBound);
+        assert_eq!(entry.halfmove_clock, 7);
+
+        // a deeper search of the position replaces the entry, but keeps the evaluation
+        tt.store(6, 5, 25, best_move, NodeType::Exact, 7);
+        let entry = tt.probe(6).unwrap();
+        assert_eq!(entry.depth, 5);
+        assert_eq!(entry.static_eval, Some(-12));
+    }
+
+    #[test]
+    fn test_mate_scores_relative_to_node() {
+        use crate::engine::evaluate::{mate_in, mated_in};
+        use crate::engine::table::{score_from_tt, score_to_tt};
+
+        // a mate found at ply 9 from a node at ply 4 is stored 5 plies from the node, and
+        // is 7 plies from the root when the node comes up again at ply 2
+        assert_eq!(score_to_tt(mate_in(9), 4), mate_in(5));
+        assert_eq!(score_from_tt(score_to_tt(mate_in(9), 4), 2), mate_in(7));
+        assert_eq!(score_from_tt(score_to_tt(mated_in(9), 4), 2), mated_in(7));
+
+        // other scores are stored as they are
+        for score in [0, 35, -250, 20_000] {
+            assert_eq!(score_to_tt(score, 6), score);
+            assert_eq!(score_from_tt(score, 6), score);
+        }
+    }
+
+    #[test]
+    fn test_packed_entries() {
+        use crate::engine::evaluate::{CHECKMATE_SCORE, INFINITE_SCORE};
+        use crate::engine::table::TTEntry;
+
+        let tt = TranspositionTable::new(1);
+        let best_move = BoardMove::parse("a7a8q").unwrap();
+        let key = 0x9E37_79B9_7F4A_7C15;
+
+        // scores are stored as whole centipawns, mates and infinities included
+        for (score, stored) in [
+            (12, 12),
+            (-13, -13),
+            (CHECKMATE_SCORE - 7, CHECKMATE_SCORE - 7),
+            (-CHECKMATE_SCORE, -CHECKMATE_SCORE),
+            (INFINITE_SCORE, CHECKMATE_SCORE),
+        ] {
+            tt.store_entry(TTEntry {
+                key,
+                depth: 20,
+                evaluation: score,
+                best_move,
+                node_type: NodeType::UpperBound,
+                halfmove_clock: 42,
+                static_eval: Some(-score),
+                ..Default::default()
+            });
+
+            let entry = tt.probe(key).unwrap();
+            assert_eq!(entry.key, key);
+            assert_eq!(entry.evaluation, stored);
+            assert_eq!(entry.static_eval, Some(-stored));
+            assert_eq!(entry.best_move, best_move);
+            assert_eq!((entry.depth, entry.halfmove_clock), (20, 42));
+            assert_eq!(entry.node_type, NodeType::UpperBound);
+        }
+
+        // clocks far past the fifty-move rule saturate
+        tt.store(key, 30, 0, best_move, NodeType::Exact, 300);
+        assert_eq!(tt.probe(key).unwrap().halfmove_clock, 127);
+    }
+}
+
+#[cfg(test)]
+mod time_tests {
+    use crate::engine::search::limits::SearchParams;
+    use crate::game::pieces::Color;
+
+    fn move_time(params: &str, fullmoves: usize) -> u64 {
+        SearchParams::parse(params.split_whitespace().map(|s| s.to_string()).collect())
+            .unwrap()
+            .calculate_move_time(Color::White, 10, fullmoves)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_movestogo_boundary() {
+        let last_move = move_time("wtime 10000 btime 10000 movestogo 1", 30);
+        let two_moves = move_time("wtime 10000 btime 10000 movestogo 2", 30);
+
+        // the last move before the control can use most of the clock, but not all of it
+        assert!(last_move > two_moves);
+        assert!(last_move < 10000 - 10);
+
+        // movestogo 0 is nonsense, but shouldn't be treated as more generous than 1
+        assert_eq!(
+            move_time("wtime 10000 btime 10000 movestogo 0", 30),
+            last_move
+        );
+    }
+
+    #[test]
+    fn test_movestogo_keeps_reserve() {
+        // 40 moves left with little time and a big increment: the increment alone would
+        // allow far more, but the other 39 moves before the control need their share too
+        let time = move_time("wtime 600 btime 600 winc 1000 binc 1000 movestogo 40", 30);
+        assert_eq!(time, 600 - 10 - 39 * 10);
+    }
+
+    #[test]
+    fn test_sudden_death_curve() {
+        let early = move_time("wtime 60000 btime 60000", 1);
+        let late = move_time("wtime 60000 btime 60000", 60);
+
+        // the same clock is worth more per move later in the game
+        assert!(late > early);
+        assert!(late <= 60000 / 2);
+    }
+
+    #[test]
+    fn test_tiny_clock() {
+        // with (almost) no time left we still return something positive and within the clock
+        assert_eq!(move_time("wtime 5 btime 5", 30), 1);
+        assert!(move_time("wtime 30 btime 30", 30) <= 20);
+        assert!(move_time("wtime 30 btime 30 winc 5000 binc 5000", 30) <= 20);
+    }
+
+    #[test]
+    fn test_adversarial_clocks() {
+        // negative clocks after lag count as no time left, not as no time control
+        assert_eq!(move_time("wtime -50 btime 10000", 30), 1);
+        assert_eq!(move_time("wtime -50 btime -50 winc 1000 binc 1000", 30), 1);
+        assert_eq!(move_time("movetime -5", 30), 0);
+
+        // increments alone are spent mostly, minus the overhead
+        assert_eq!(move_time("winc 1000 binc 1000", 30), 800 - 10);
+        assert_eq!(move_time("winc 5 binc 5", 30), 1);
+        assert_eq!(move_time("btime 10000 winc 1000", 30), 800 - 10);
+
+        // huge values saturate rather than overflow into tiny or negative times
+        let huge = move_time("wtime 18446744073709551615 btime 1", 30);
+        assert!(huge > 1_000_000);
+        assert!(move_time("wtime 9223372036854775807 winc 9223372036854775807", 30) > 0);
+
+        // without any time information there's no limit
+        let params = SearchParams::parse(vec!["winc".to_string(), "0".to_string()]).unwrap();
+        assert_eq!(params.calculate_move_time(Color::White, 10, 30), None);
+    }
+
+    #[test]
+    fn test_extended_time() {
+        let time_limits = |params: &str| {
+            SearchParams::parse(params.split_whitespace().map(|s| s.to_string()).collect())
+                .unwrap()
+                .calculate_time_limits(Color::White, 10, 30)
+                .unwrap()
+        };
+
+        // with a clock there's room to extend, but not past half of it
+        let (move_time, extended_time) = time_limits("wtime 60000 btime 60000");
+        assert!(extended_time > move_time);
+        assert!(extended_time <= (60000 - 10) / 2);
+
+        // nor past what the remaining moves before the control need
+        let (move_time, extended_time) =
+            time_limits("wtime 600 btime 600 winc 1000 binc 1000 movestogo 40");
+        assert_eq!((move_time, extended_time), (200, 200));
+
+        // a fixed movetime or just an increment can't be extended
+        assert_eq!(time_limits("movetime 1000"), (990, 990));
+        assert_eq!(time_limits("winc 1000 binc 1000"), (790, 790));
+    }
+
+    #[test]
+    fn test_time_overrun() {
+        use crate::controller::controller::TimeOverrun;
+
+        assert_eq!(TimeOverrun::check(95, Some(100), 9, 8), None);
+        assert_eq!(TimeOverrun::check(100, Some(100), 9, 8), None);
+        assert_eq!(TimeOverrun::check(5000, None, 30, 29), None);
+
+        let overrun = TimeOverrun::check(134, Some(100), 9, 8).unwrap();
+        assert_eq!(
+            overrun.to_string(),
+            "134 ms used of 100 ms allotted (+34 ms) at depth 9 (8 completed)"
+        );
+    }
+}
+
+#[cfg(test)]
+mod epd_tests {
+    use crate::game::board::Game;
+    use crate::game::epd::parse_epd_opcodes;
+
+    #[test]
+    fn test_partial_fen() {
+        let game = Game::new(Some(
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3",
+        ));
+        assert_eq!(
+            game.get_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        // only the halfmove clock
+        let game = Game::new(Some("4k3/8/8/8/8/8/8/4K2R w K - 7"));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 7 1");
+    }
+
+    #[test]
+    fn test_large_move_counters() {
+        use crate::game::board::BoardMove;
+
+        // counters past 255 from long games are kept, and keep counting
+        let mut game = Game::new(Some("4k3/8/8/8/8/8/8/4K2R w K - 300 400"));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 300 400");
+        assert!(game.is_fifty_move_rule());
+
+        game.make_move(BoardMove::parse("h1h2").unwrap());
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/7R/4K3 b - - 301 400");
+        game.unmake_move();
+        assert_eq!(game.get_halfmove_clock(), 300);
+
+        // counters that don't fit anything saturate instead of being dropped
+        let game = Game::new(Some(
+            "4k3/8/8/8/8/8/8/4K3 b - - 99999999999999999999 99999999999999999999",
+        ));
+        assert_eq!(
+            game.get_fen(),
+            format!("4k3/8/8/8/8/8/8/4K3 b - - {} {}", u16::MAX, u32::MAX)
+        );
+    }
+
+    #[test]
+    fn test_epd_suites() {
+        // WAC.001, BK.01 and a position from the ECM suite
+        let records = [
+            (
+                "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+                "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+            ),
+            (
+                "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - bm Qd1+; id \"BK.01\";",
+                "1k1r4/pp1b1R2/3q2pp/4p3/2B5/4Q3/PPP2B2/2K5 b - - 0 1",
+            ),
+            (
+                "7k/p7/1R5K/6r1/6p1/6P1/8/8 w - - bm Rb7; id \"ECM.001\"; c0 \"a; b\"; hmvc 3; fmvn 40;",
+                "7k/p7/1R5K/6r1/6p1/6P1/8/8 w - - 3 40",
+            ),
+        ];
+
+        for (epd, fen) in records {
+            assert_eq!(Game::new(Some(epd)).get_fen(), fen);
+        }
+
+        // explicit counters take precedence over the opcodes
+        let game = Game::new(Some("4k3/8/8/8/8/8/8/4K3 w - - 5 10 hmvc 1; fmvn 2;"));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 5 10");
+    }
+
+    #[test]
+    fn test_epd_opcodes() {
+        assert_eq!(
+            parse_epd_opcodes("bm Nf3 Nc3; id \"quoted; id\"; noop"),
+            vec![
+                ("bm".to_string(), "Nf3 Nc3".to_string()),
+                ("id".to_string(), "\"quoted; id\"".to_string()),
+                ("noop".to_string(), String::new()),
+            ]
+        );
+        assert!(parse_epd_opcodes("").is_empty());
+    }
+}
+
+#[cfg(test)]
+mod edit_tests {
+    use crate::controller::controller::GameController;
+    use crate::game::board::Game;
+
+    #[test]
+    fn test_board_editing() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        controller.edit_set_piece("e4", "N");
+        controller.edit_set_piece("e2", "q");
+        controller.edit_clear_square("g1");
+        controller.edit_side_to_move("b");
+        controller.edit_castling("Qk");
+        controller.edit_en_passant("c3");
+
+        let fen = "rnbqkbnr/pppppppp/8/8/4N3/8/PPPPqPPP/RNBQKB1R b Qk c3 0 1";
+        assert_eq!(controller.game.get_fen(), fen);
+
+        // the incrementally updated hash must match one computed from scratch
+        assert_eq!(
+            controller.game.zobrist_key,
+            Game::new(Some(fen)).zobrist_key
+        );
+
+        // invalid edits are ignored
+        controller.edit_set_piece("i9", "N");
+        controller.edit_set_piece("e5", "x");
+        controller.edit_side_to_move("x");
+        controller.edit_castling("KX");
+        assert_eq!(controller.game.get_fen(), fen);
+    }
+
+    #[test]
+    fn test_position_moves_with_illegal_move() {
+        let moves = |notations: &str| {
+            notations
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_board_from_moves(&moves("e2e4 e7e5"))
+            .unwrap();
+        let fen = controller.game.get_fen();
+
+        // neither an illegal nor an unparsable move gets the moves after it played
+        for bad in ["d2d4 e7e5 e1e3 g8f6", "d2d4 e7e5 x9 g8f6"] {
+            let error = controller.set_board_from_moves(&moves(bad)).unwrap_err();
+            assert!(error.to_string().contains("at index 2"), "{}", error);
+
+            assert_eq!(controller.game.get_fen(), fen);
+            assert_eq!(controller.game.history.len(), 2);
+        }
+
+        controller
+            .set_board_from_moves(&moves("d2d4 d7d5 c2c4"))
+            .unwrap();
+        assert_eq!(
+            controller.game.get_fen(),
+            "rnbqkbnr/ppp1pppp/8/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq c3 0 2"
+        );
+    }
+
+    #[test]
+    fn test_excluded_root_moves() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        // nothing excluded, nothing restricted
+        assert!(controller.root_moves(&[]).is_empty());
+
+        controller.exclude_moves(&["e2e4".to_string(), "g1f3".to_string()]);
+        let root_moves = controller.root_moves(&[]);
+        assert_eq!(root_moves.len(), 18);
+        assert!(!root_moves.iter().any(|m| m.unparse() == "e2e4"));
+
+        // exclusions stay for the following positions, applying where the moves are legal
+        controller.try_move_piece("d2d4");
+        controller.try_move_piece("d7d5");
+        assert_eq!(controller.root_moves(&[]).len(), 25);
+
+        controller.include_moves(&["all".to_string()]);
+        assert!(controller.root_moves(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_searchmoves_root_moves() {
+        use crate::game::board::BoardMove;
+
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        let parse = |moves: &[&str]| -> Vec<BoardMove> {
+            moves.iter().map(|m| BoardMove::parse(m).unwrap()).collect()
+        };
+
+        // the searchmoves restrict the root on top of the exclusions, illegal ones left out
+        controller.exclude_moves(&["d2d4".to_string()]);
+        let root_moves = controller.root_moves(&parse(&["e2e4", "d2d4", "e2e5"]));
+        assert_eq!(root_moves, parse(&["e2e4"]));
+
+        // a restriction leaving nothing is ignored: the exclusions for searchmoves that are all
+        // excluded, the searchmoves when none of them is legal
+        let root_moves = controller.root_moves(&parse(&["d2d4"]));
+        assert_eq!(root_moves, parse(&["d2d4"]));
+        assert_eq!(controller.root_moves(&parse(&["e2e5"])).len(), 19);
+
+        // all of the moves is no restriction
+        controller.include_moves(&["all".to_string()]);
+        let (move_count, moves) = controller.game.get_moves();
+        assert!(controller.root_moves(&moves[..move_count]).is_empty());
+    }
+
+    #[test]
+    fn test_repertoire_root_moves() {
+        use crate::engine::repertoire::Repertoire;
+        use crate::game::board::BoardMove;
+
+        let notation = |moves: Vec<BoardMove>| {
+            let mut moves = moves.iter().map(|m| m.unparse()).collect::<Vec<_>>();
+            moves.sort();
+            moves
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("prokopakop-repertoire-{}.pgn", std::process::id()));
+        let lines = ["1. e4 e5 2. Nf3", "1. d4 d5", "1. e4 c5"];
+        let pgn = lines.map(|line| format!("[Result \"*\"]\n\n{} *\n", line));
+        std::fs::write(&path, pgn.join("\n")).unwrap();
+
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller
+            .set_option("RepertoireFile", path.to_str().unwrap())
+            .unwrap();
+        assert_eq!(notation(controller.root_moves(&[])), ["d2d4", "e2e4"]);
+
+        // exclusions apply on top of the repertoire
+        controller.exclude_moves(&["d2d4".to_string()]);
+        assert_eq!(notation(controller.root_moves(&[])), ["e2e4"]);
+        controller.include_moves(&["all".to_string()]);
+
+        controller.try_move_piece("e2e4");
+        assert_eq!(notation(controller.root_moves(&[])), ["c7c5", "e7e5"]);
+
+        // out of the repertoire, everything is searched again
+        controller.try_move_piece("c7c5");
+        assert!(controller.root_moves(&[]).is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq -";
+        let repertoire =
+            Repertoire::from_epd(&format!("# Ruy Lopez\n{} bm Bb5 d4;\n", fen)).unwrap();
+        assert_eq!(repertoire.position_count(), 1);
+        assert_eq!(
+            notation(
+                repertoire
+                    .moves(Game::from_fen(fen).unwrap().zobrist_key)
+                    .to_vec()
+            ),
+            ["d2d4", "f1b5"]
+        );
+
+        assert!(Repertoire::from_epd(&format!("{} id \"no moves\";", fen)).is_err());
+        assert!(Repertoire::from_epd(&format!("{} bm Bc4 Bb6;", fen)).is_err());
+    }
+
+    #[test]
+    fn test_new_game_command() {
+        use crate::controller::cli::GUICommand;
+
+        // a new game ages the TT, which a bare position (sent before every search) mustn't
+        assert!(matches!(
+            GUICommand::parse("ucinewgame"),
+            GUICommand::UciNewGame
+        ));
+        assert!(matches!(
+            GUICommand::parse("position startpos"),
+            GUICommand::MovePosition(None)
+        ));
+    }
+
+    #[test]
+    fn test_debug_command() {
+        use crate::controller::cli::GUICommand;
+
+        for (command, debug) in [("debug", true), ("debug on", true), ("debug off", false)] {
+            assert!(matches!(GUICommand::parse(command), GUICommand::Debug(d) if d == debug));
+        }
+        assert!(matches!(
+            GUICommand::parse("debug maybe"),
+            GUICommand::Invalid(_)
+        ));
+    }
+
+    #[test]
+    fn test_legal_moves() {
+        use crate::controller::cli::GUICommand;
+
+        assert!(matches!(
+            GUICommand::parse("legalmoves"),
+            GUICommand::LegalMoves(false)
+        ));
+        assert!(matches!(
+            GUICommand::parse("legalmoves san"),
+            GUICommand::LegalMoves(true)
+        ));
+
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        let notations = |controller: &GameController| {
+            controller
+                .legal_moves()
+                .iter()
+                .map(|board_move| board_move.unparse())
+                .collect::<Vec<_>>()
+        };
+
+        let moves = notations(&controller);
+        assert_eq!(moves.len(), 20);
+        assert_eq!(moves[..3], ["a2a3", "a2a4", "b1a3"]);
+        assert!(moves.is_sorted());
+
+        // promotions are listed for every piece, and a finished game has none
+        controller
+            .set_board_from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1")
+            .unwrap();
+        assert_eq!(
+            notations(&controller)[..4],
+            ["b7b8b", "b7b8n", "b7b8q", "b7b8r"]
+        );
+        controller
+            .set_board_from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1")
+            .unwrap();
+        assert!(notations(&controller).is_empty());
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_node_odds() {
+        let mut controller = GameController::new();
+        controller.initialize();
+        controller.set_option("NodeOddsDivisor", "10").unwrap();
+
+        controller.search(vec!["nodes".to_string(), "50000".to_string()], false);
+        controller.wait_for_search().unwrap();
+
+        // the limit is checked between nodes, so the search may run a little over it
+        let nodes = controller.last_node_counts().total();
+        assert!(nodes > 0 && nodes < 10000);
+
+        // out of range divisors are rejected
+        controller.set_option("TimeOddsDivisor", "0").unwrap();
+        assert_eq!(controller.time_odds_divisor, 1);
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use crate::engine::cache::{AnalysisCache, CacheEntry};
+    use crate::game::board::BoardMove;
+    use std::fs;
+
+    #[test]
+    fn test_analysis_cache_persists() {
+        let path = std::env::temp_dir().join(format!("prokopakop-cache-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let entry = |notation: &str, depth: u8| CacheEntry {
+            best_move: BoardMove::parse(notation).unwrap(),
+            evaluation: 35,
+            depth,
+        };
+
+        let mut cache = AnalysisCache::open(&path).unwrap();
+        cache.store(0xdeadbeef, entry("e2e4", 12)).unwrap();
+        cache.store(0xdeadbeef, entry("d2d4", 10)).unwrap(); // shallower, ignored
+        cache.store(0xcafe, entry("g1f3", 8)).unwrap();
+        cache.store(0xcafe, entry("b1c3", 9)).unwrap(); // deeper, replaces it
+        cache.store(u64::MAX, entry("c2c4", 4)).unwrap(); // keys use all 64 bits
+        drop(cache);
+
+        let cache = AnalysisCache::open(&path).unwrap();
+        assert_eq!(cache.position_count(), 3);
+        assert_eq!(cache.get(0xdeadbeef), Some(entry("e2e4", 12)));
+        assert_eq!(cache.get(0xcafe), Some(entry("b1c3", 9)));
+        assert_eq!(cache.get(u64::MAX), Some(entry("c2c4", 4)));
+        assert_eq!(cache.get(0xbeef), None);
+        drop(cache);
+
+        // a file that isn't a database isn't taken for one
+        fs::write(
+            &path,
+            "not a database, just some text that is long enough\n".repeat(10),
+        )
+        .unwrap();
+        assert!(AnalysisCache::open(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_book_learning() {
+        use crate::engine::book::OpeningBook;
+        use crate::game::board::Game;
+        use crate::game::pieces::Color;
+
+        let pgn_path =
+            std::env::temp_dir().join(format!("prokopakop-book-{}.pgn", std::process::id()));
+        let book_path = pgn_path.with_extension("book");
+        fs::write(
+            &pgn_path,
+            "[Result \"*\"]\n\n1. e4 e5 2. Nf3 *\n\n[Result \"*\"]\n\n1. e4 c5 *\n",
+        )
+        .unwrap();
+
+        let pgn_book = OpeningBook::open(&pgn_path).unwrap();
+        assert!(!pgn_book.is_writable());
+        let mut book = pgn_book.save_as(&book_path).unwrap();
+
+        let mut game = Game::new(None);
+        let e4 = BoardMove::parse("e2e4").unwrap();
+        assert_eq!(book.moves(game.zobrist_key), &[(e4, 20)]);
+        assert_eq!(book.pick(game.zobrist_key), Some(e4));
+
+        // black won after leaving the book on move two: only the book moves are adjusted
+        for notation in ["e2e4", "c7c5", "d2d4"] {
+            game.make_move(BoardMove::parse(notation).unwrap());
+        }
+        assert_eq!(book.learn(&game, Some(Color::Black)), 2);
+        assert_eq!(book.learn(&game, None), 0);
+        book.save().unwrap();
+
+        let book = OpeningBook::open(&book_path).unwrap();
+        assert!(book.is_writable());
+        assert_eq!(book.moves(Game::new(None).zobrist_key), &[(e4, 18)]);
+
+        game.unmake_move();
+        game.unmake_move();
+        let c5 = BoardMove::parse("c7c5").unwrap();
+        assert!(book.moves(game.zobrist_key).contains(&(c5, 12)));
+
+        // only book files of the current version are read
+        fs::write(&book_path, "prokopakop-book 0\n").unwrap();
+        assert!(OpeningBook::open(&book_path).is_err());
+
+        fs::remove_file(&pgn_path).unwrap();
+        fs::remove_file(&book_path).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod match_tests {
+    use crate::controller::match_runner::{
+        MaterialRule, TimeControl, elo_difference, is_insufficient_material, parse_score,
+    };
+    use crate::game::board::Game;
+
+    #[test]
+    fn test_time_control_parse() {
+        assert_eq!(
+            TimeControl::parse("10+0.1"),
+            Some(TimeControl {
+                base_ms: 10_000,
+                increment_ms: 100
+            })
+        );
+        assert_eq!(
+            TimeControl::parse("60"),
+            Some(TimeControl {
+                base_ms: 60_000,
+                increment_ms: 0
+            })
+        );
+        assert_eq!(TimeControl::parse("abc+1"), None);
+        assert_eq!(TimeControl::parse("-5+1"), None);
+    }
+
+    #[test]
+    fn test_parse_score() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        let output = lines(&[
+            "info depth 1 score cp 20 nodes 20 pv e2e4",
+            "info depth 2 score cp -35 nodes 80 pv e2e4 e7e5",
+            "bestmove e2e4",
+        ]);
+        assert_eq!(parse_score(&output), Some(-35));
+
+        assert_eq!(
+            parse_score(&lines(&["info depth 5 score mate 2", "bestmove a1a8"])),
+            Some(99_998)
+        );
+        assert_eq!(
+            parse_score(&lines(&["info depth 5 score mate -1", "bestmove a1a8"])),
+            Some(-99_999)
+        );
+        assert_eq!(parse_score(&lines(&["bestmove e2e4"])), None);
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let insufficient =
+            |fen: &str, rule: MaterialRule| is_insufficient_material(&Game::new(Some(fen)), rule);
+
+        // dead positions are drawn by both rules
+        for rule in [MaterialRule::Fide, MaterialRule::Uscf] {
+            assert!(insufficient("8/8/4k3/8/8/3K4/8/8 w - - 0 1", rule));
+            assert!(insufficient("8/8/4k3/8/8/3KN3/8/8 w - - 0 1", rule));
+            assert!(insufficient("8/8/4kb2/8/8/3K4/8/8 b - - 0 1", rule));
+            assert!(insufficient("8/8/4kb2/8/8/3KB3/8/8 w - - 0 1", rule));
+            assert!(insufficient("8/2b5/4kb2/8/8/3K4/8/8 w - - 0 1", rule));
+            assert!(!insufficient("8/8/4k3/8/8/3KR3/8/8 w - - 0 1", rule));
+            assert!(!insufficient("8/8/4k3/8/8/3KP3/8/8 w - - 0 1", rule));
+            assert!(!insufficient("8/8/4k3/8/8/2BK1B2/8/8 w - - 0 1", rule));
+            assert!(!insufficient("8/8/4k3/8/8/2NKB3/8/8 w - - 0 1", rule));
+            assert!(!insufficient(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                rule
+            ));
+        }
+
+        // a mate is possible against a minor piece (or with two knights), but can't be forced
+        let minor_each = "8/8/4kn2/8/8/3KB3/8/8 w - - 0 1";
+        let two_knights = "8/8/4k3/8/8/2NKN3/8/8 w - - 0 1";
+        assert!(!insufficient(minor_each, MaterialRule::Fide));
+        assert!(!insufficient(two_knights, MaterialRule::Fide));
+        assert!(insufficient(minor_each, MaterialRule::Uscf));
+        assert!(insufficient(two_knights, MaterialRule::Uscf));
+        assert!(!insufficient(
+            "8/8/4kn2/8/8/2NKN3/8/8 w - - 0 1",
+            MaterialRule::Uscf
+        ));
+
+        assert_eq!(MaterialRule::parse("USCF"), Some(MaterialRule::Uscf));
+        assert_eq!(MaterialRule::parse("fide"), Some(MaterialRule::Fide));
+        assert_eq!(MaterialRule::parse("bare"), None);
+    }
+
+    #[test]
+    fn test_elo_difference() {
+        assert!(elo_difference(0.5).abs() < 1e-9);
+        assert!((elo_difference(0.75) - 190.85).abs() < 0.1);
+        assert!(elo_difference(0.25) < 0.0);
+    }
+}
+
+#[cfg(test)]
+mod training_tests {
+    use crate::controller::training::{
+        BloomFilter, Dedupe, SeenPositions, TrainingConfig, TrainingPosition,
+    };
+
+    fn position(nnue_eval: i32, hce_eval: i32) -> TrainingPosition {
+        TrainingPosition {
+            fen: "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
+            zobrist_key: 0,
+            evaluation: 0,
+            result: 0.5,
+            nnue_eval,
+            hce_eval,
+        }
+    }
+
+    #[test]
+    fn test_disagreement_sampling() {
+        let positions = vec![
+            position(20, 25),
+            position(-150, 100),
+            position(300, 180),
+            position(0, 0),
+        ];
+
+        // without sampling everything is kept
+        let config = TrainingConfig::new(1, 1, 0, 0);
+        assert_eq!(config.select_positions(positions.clone()).len(), 4);
+
+        // only the disagreeing positions are kept when none of the rest are
+        let config = config.with_disagreement_sampling(100, 0.0);
+        let selected = config.select_positions(positions.clone());
+        assert_eq!(selected.len(), 2);
+        assert!(selected.iter().all(|pos| pos.eval_disagreement() >= 100));
+
+        let config = TrainingConfig::new(1, 1, 0, 0).with_disagreement_sampling(100, 1.0);
+        assert_eq!(config.select_positions(positions).len(), 4);
+    }
+
+    #[test]
+    fn test_exact_dedupe() {
+        let mut seen = SeenPositions::new(Dedupe::Exact);
+        assert!(seen.insert(1));
+        assert!(seen.insert(2));
+        assert!(!seen.insert(1));
+        assert!(!seen.insert(2));
+    }
+
+    #[test]
+    fn test_bloom_filter_catches_every_repeat() {
+        let mut filter = BloomFilter::new(1);
+        let keys: Vec<u64> = (0..10_000u64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+
+        // far below its capacity, the filter takes (almost) every new key as new
+        let new = keys.iter().filter(|&&key| filter.insert(key)).count();
+        assert!(new >= keys.len() - 10, "{} of {} keys new", new, keys.len());
+
+        // and never lets a repeat through
+        assert!(keys.iter().all(|&key| !filter.insert(key)));
+    }
+
+    #[test]
+    fn test_dedupe_parse() {
+        assert_eq!(Dedupe::parse("exact", 64), Some(Dedupe::Exact));
+        assert_eq!(Dedupe::parse("Bloom", 64), Some(Dedupe::Approximate(64)));
+        assert_eq!(Dedupe::parse("sometimes", 64), None);
+    }
+
+    #[test]
+    fn test_progress_line() {
+        use crate::controller::training::progress_line;
+
+        assert_eq!(
+            progress_line(25, 100, 60.0),
+            "[#######-----------------------]  25.0% (25/100), elapsed 0:01:00, ETA 0:03:00"
+        );
+        assert!(progress_line(0, 100, 5.0).ends_with("ETA ?"));
+        assert!(progress_line(100, 100, 3725.0).contains("elapsed 1:02:05, ETA 0:00:00"));
+    }
+
+    #[test]
+    fn test_stopped_generation_writes_nothing() {
+        use crate::controller::training::TrainingDataGenerator;
+        use std::sync::atomic::AtomicBool;
+
+        static STOP: AtomicBool = AtomicBool::new(true);
+
+        let path = std::env::temp_dir().join("prokopakop_stopped_selfplay.txt");
+        let _ = std::fs::remove_file(&path);
+
+        let generator =
+            TrainingDataGenerator::new(TrainingConfig::new(4, 2, 0, 0)).with_stop_flag(&STOP);
+        let written = generator
+            .generate_parallel_to_file(path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(written, 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        let _ = std::fs::remove_file(&path);
+    }
+    #[test]
+    fn test_max_plies_discards_games() {
+        use crate::controller::training::{
+            GameTermination, TerminationCounts, TrainingDataGenerator,
+        };
+
+        let path = std::env::temp_dir().join("prokopakop_max_plies_selfplay.txt");
+        let _ = std::fs::remove_file(&path);
+
+        // every game is cut off before its first searched move
+        let generator =
+            TrainingDataGenerator::new(TrainingConfig::new(2, 1, 0, 0).with_max_plies(0));
+        let written = generator
+            .generate_parallel_to_file(path.to_str().unwrap())
+            .unwrap();
+
+        assert_eq!(written, 0);
+        let _ = std::fs::remove_file(&path);
+
+        let mut counts = TerminationCounts::default();
+        counts.record(GameTermination::MaxPlies);
+        counts.record(GameTermination::Checkmate);
+        counts.record(GameTermination::MaxPlies);
+        assert_eq!(
+            counts.to_string(),
+            "checkmate 1, stalemate 0, fifty move rule 0, repetition 0, insufficient material 0, \
+             max plies 2"
+        );
+    }
+
+    #[test]
+    fn test_pv_leaf_eval() {
+        use crate::controller::training::pv_leaf_eval;
+        use crate::game::board::{BoardMove, Game};
+
+        let game = Game::new(None);
+        let pv = ["e2e4", "d7d5", "e4d5"]
+            .iter()
+            .map(|m| BoardMove::parse(m).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut leaf = game.clone();
+        for &board_move in &pv {
+            leaf.make_move(board_move);
+        }
+        assert_eq!(pv_leaf_eval(&game, &pv), Some(leaf.evaluate()));
+        assert_eq!(pv_leaf_eval(&game, &[]), Some(game.evaluate()));
+
+        // a leaf in check isn't quiet
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let pv = vec![BoardMove::parse("a1a8").unwrap()];
+        assert_eq!(pv_leaf_eval(&game, &pv), None);
+    }
+}
+
+#[cfg(test)]
+mod pgn_tests {
+    use crate::game::board::{BoardMove, Game};
+    use crate::game::pgn::parse_pgn;
+
+    #[test]
+    fn test_san_to_move() {
+        // both knights can go to d2
+        let game = Game::new(Some("3rkr2/4P3/8/8/8/5N2/8/1N2K2R w K - 0 1"));
+        let parse = |san: &str| game.san_to_move(san).map(|m| m.unparse());
+
+        assert_eq!(parse("Nbd2").unwrap(), "b1d2");
+        assert_eq!(parse("N3d2").unwrap(), "f3d2");
+        assert_eq!(parse("O-O+").unwrap(), "e1g1");
+        assert_eq!(parse("exd8=Q+").unwrap(), "e7d8q");
+        assert_eq!(parse("exf8N").unwrap(), "e7f8n");
+        assert_eq!(parse("Kf2!?").unwrap(), "e1f2");
+
+        // ambiguous, impossible and malformed moves
+        assert!(parse("Nd2").is_err());
+        assert!(parse("O-O-O").is_err());
+        assert!(parse("exd8").is_err());
+        assert!(parse("Zz9").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_parse_pgn() {
+        let pgn = r#"[Event "First"]
+[Result "1-0"]
+
+1. e4 e5 {the usual} 2. Nf3 ; a line comment
+Nc6 3.Bb5 1-0
+
+[Event "Second"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+
+1. e4 Kd7 2. e5 *
+"#;
+
+        let games = parse_pgn(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+
+        assert_eq!(games[0].tag("Event"), Some("First"));
+        assert_eq!(games[0].result.as_deref(), Some("1-0"));
+        let moves: Vec<String> = games[0].moves.iter().map(|m| m.unparse()).collect();
+        assert_eq!(moves, vec!["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]);
+
+        assert_eq!(games[1].moves.len(), 3);
+        assert_eq!(games[1].moves[0], BoardMove::parse("e2e4").unwrap());
+
+        assert!(parse_pgn("1. e4 e5 2. Ke3").is_err());
+        assert!(parse_pgn("1. e4 {unterminated").is_err());
+        assert!(parse_pgn("[Event \"x\"\n1. e4").is_err());
+    }
+
+    #[test]
+    fn test_pgn_reader() {
+        use crate::game::pgn::PgnReader;
+
+        let pgn = r#"[Event "Annotated"] [Site "?"]
+[Annotator "a very long
+name"]
+
+1. e4 $1 e5 (1... c5 2. Nf3 (2. c3 d5) d6) 2. Nf3 $2 {comment (not a variation}
+Nc6 1/2-1/2
+
+[Event "Broken"]
+
+1. e4 e5 2. Ke3 *
+
+%escaped line
+[Event "Last"]
+1. d4 d5 *
+"#;
+
+        let mut reader = PgnReader::new(pgn.as_bytes());
+
+        let game = reader.next_game().unwrap().unwrap();
+        assert_eq!(game.tag("Site"), Some("?"));
+        assert_eq!(game.tag("Annotator"), Some("a very long\nname"));
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.result.as_deref(), Some("1/2-1/2"));
+
+        // a broken game doesn't keep the rest from being read
+        assert!(reader.next_game().is_err());
+
+        let game = reader.next_game().unwrap().unwrap();
+        assert_eq!(game.tag("Event"), Some("Last"));
+        assert_eq!(game.moves.len(), 2);
+
+        assert!(reader.next_game().unwrap().is_none());
+        assert_eq!(PgnReader::new(pgn.as_bytes()).count(), 3);
+    }
+
+    #[test]
+    fn test_fuzz_entry_points() {
+        use crate::fuzz::{parse_fen_bytes, parse_pgn_bytes, parse_uci_command_bytes};
+
+        assert!(parse_fen_bytes(b"4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_ok());
+        assert!(parse_fen_bytes(&[0xff, 0xfe]).is_err());
+
+        // positions the move generator can't play from
+        for fen in [
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K2K w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+            "4k3/4Q3/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            assert!(parse_fen_bytes(fen.as_bytes()).is_err(), "{}", fen);
+        }
+
+        // a FEN tag is checked before its moves are played
+        assert!(parse_pgn_bytes(b"[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n\n1. e4").is_err());
+        assert!(parse_pgn_bytes(b"1. e4 (1. d4 (1. c4)) e5").is_ok());
+        assert!(parse_pgn_bytes(b"1. e4 1. d4) e5").is_err());
+
+        assert!(parse_uci_command_bytes(b"go wtime 1000 btime 1000").is_ok());
+        assert!(parse_uci_command_bytes(b"go nonsense 12 depth x").is_err());
+        assert!(parse_uci_command_bytes(b"foo").is_err());
+        assert!(parse_uci_command_bytes(&[b'u', 0xc3]).is_err());
+    }
+}
+
+#[cfg(test)]
+mod bot_tests {
+    use crate::controller::bot::{apply_game_state, decline_reason};
+    use crate::controller::controller::GameController;
+    use serde_json::json;
+
+    #[test]
+    fn test_decline_reason() {
+        let challenge = |variant: &str, time_control: &str| {
+            json!({
+                "id": "abc",
+                "variant": { "key": variant },
+                "timeControl": { "type": time_control, "limit": 180, "increment": 2 },
+            })
+        };
+
+        assert_eq!(decline_reason(&challenge("standard", "clock")), None);
+        assert_eq!(
+            decline_reason(&challenge("chess960", "clock")),
+            Some("standard")
+        );
+        assert_eq!(
+            decline_reason(&challenge("standard", "correspondence")),
+            Some("timeControl")
+        );
+        assert_eq!(
+            decline_reason(&challenge("standard", "unlimited")),
+            Some("timeControl")
+        );
+    }
+
+    #[test]
+    fn test_apply_game_state() {
+        let mut controller = GameController::new();
+        controller.initialize();
+
+        let state = json!({
+            "type": "gameState",
+            "moves": "e2e4 e7e5 g1f3",
+            "wtime": 170000,
+            "btime": 175000,
+            "winc": 2000,
+            "binc": 2000,
+            "status": "started",
+        });
+
+        let params = apply_game_state(&mut controller, None, &state).unwrap();
+        assert_eq!(
+            params,
+            vec![
+                "wtime", "170000", "btime", "175000", "winc", "2000", "binc", "2000"
+            ]
+        );
+        assert_eq!(
+            controller.game.get_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        // each state has all moves, so the position is rebuilt from the initial one
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let state = json!({ "moves": "e2e4 e8d7", "status": "started" });
+        assert!(
+            apply_game_state(&mut controller, Some(fen), &state)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(controller.game.get_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+
+        let state = json!({ "moves": "e2e4 e2e4" });
+        assert!(apply_game_state(&mut controller, None, &state).is_err());
+    }
+}
+
+#[cfg(test)]
+mod xboard_tests {
+    use crate::controller::controller::GameController;
+    use crate::controller::xboard::XBoard;
+
+    #[test]
+    fn test_xboard_search_params() {
+        let mut xboard = XBoard::new(GameController::new());
+
+        // no time control at all
+        assert_eq!(xboard.search_params(), vec!["movetime", "5000"]);
+
+        xboard.handle("sd 6");
+        assert_eq!(xboard.search_params(), vec!["depth", "6"]);
+
+        xboard.handle("new");
+        xboard.handle("level 40 5 0");
+        xboard.handle("time 25000");
+        xboard.handle("otim 24000");
+        assert_eq!(
+            xboard.search_params(),
+            vec![
+                "wtime",
+                "240000",
+                "btime",
+                "250000",
+                "winc",
+                "0",
+                "binc",
+                "0",
+                "movestogo",
+                "40"
+            ]
+        );
+
+        xboard.handle("level 0 2:30 3");
+        assert_eq!(
+            xboard.search_params(),
+            vec![
+                "wtime", "150000", "btime", "150000", "winc", "3000", "binc", "3000"
+            ]
+        );
+
+        xboard.handle("st 2");
+        assert_eq!(xboard.search_params(), vec!["movetime", "2000"]);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_xboard_game() {
+        use crate::game::pieces::Color;
+
+        let mut xboard = XBoard::new(GameController::new());
+
+        assert!(xboard.handle("new"));
+        assert!(xboard.handle("sd 2"));
+        assert!(xboard.handle("ping 3"));
+
+        // illegal moves don't change the board
+        xboard.handle("usermove e2e5");
+        assert_eq!(xboard.controller.game.side, Color::White);
+        assert!(!xboard.is_thinking());
+
+        // the engine plays black and answers on its own
+        xboard.handle("usermove e2e4");
+        assert!(xboard.is_thinking());
+        while xboard.is_thinking() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            xboard.poll_search();
+        }
+        assert_eq!(xboard.controller.game.side, Color::White);
+
+        // in force mode, moves are only played
+        xboard.handle("force");
+        xboard.handle("d2d4");
+        assert!(!xboard.is_thinking());
+        assert_eq!(xboard.controller.game.side, Color::Black);
+
+        xboard.handle("remove");
+        assert_eq!(xboard.controller.game.side, Color::Black);
+        xboard.handle("undo");
+        assert_eq!(
+            xboard.controller.game.get_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+
+        assert!(!xboard.handle("quit"));
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use crate::controller::batch::{annotate, position_distance, similarity_order};
+    use crate::engine::search::results::SearchResult;
+    use crate::game::board::{BoardMove, Game};
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const E4: &str = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+    const E4_D5: &str = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+    const EXD5: &str = "rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2";
+    const NC3: &str = "rnbqkbnr/ppp1pppp/8/3p4/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 1 2";
+    const D4: &str = "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1";
+
+    #[test]
+    fn test_position_distance() {
+        let game = |fen| Game::new(Some(fen));
+
+        assert_eq!(position_distance(&game(START), &game(START)), 0);
+        // a quiet move, a capture, and the side to move
+        assert_eq!(position_distance(&game(START), &game(E4)), 3);
+        assert_eq!(position_distance(&game(E4_D5), &game(EXD5)), 4);
+        assert_eq!(position_distance(&game(E4), &game(D4)), 4);
+    }
+
+    #[test]
+    fn test_similarity_order() {
+        // a shuffled line gets searched move by move, the other opening (as close to the start
+        // as 1. e4, but later in the list) after it
+        let games = [START, NC3, E4, D4, E4_D5].map(|fen| Game::new(Some(fen)));
+        assert_eq!(similarity_order(&games), vec![0, 2, 4, 1, 3]);
+
+        assert!(similarity_order(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_annotate() {
+        let game = Game::new(Some(E4));
+        let e7e5 = BoardMove::parse("e7e5").unwrap();
+        let g1f3 = BoardMove::parse("g1f3").unwrap();
+        let result = SearchResult::with_pv(e7e5, -35, vec![g1f3]);
+
+        assert_eq!(
+            annotate(&format!("{} bm c5;", E4), &game, Some(&result), 12),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - bm e5; ce -35; acd 12; pv e5 Nf3;"
+        );
+        assert_eq!(
+            annotate(E4, &game, None, 12),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq -"
+        );
+    }
+}
+
+#[cfg(test)]
+mod kibitz_tests {
+    use crate::controller::kibitz::{PlayedMove, format_score};
+    use crate::engine::evaluate::CHECKMATE_SCORE;
+    use crate::game::board::BoardMove;
+    use crate::game::pieces::Color;
+
+    #[test]
+    fn test_kibitz_report() {
+        assert_eq!(format_score(35), "+0.35");
+        assert_eq!(format_score(-120), "-1.20");
+        assert_eq!(format_score(CHECKMATE_SCORE - 3), "#2");
+        assert_eq!(format_score(-CHECKMATE_SCORE + 1), "#-1");
+
+        let f6 = BoardMove::parse("f7f6").unwrap();
+        let c6 = BoardMove::parse("b8c6").unwrap();
+        let played = PlayedMove {
+            label: "2... f6".to_string(),
+            mover: Color::Black,
+            board_move: f6,
+            best: Some((c6, "Nc6".to_string())),
+            before: -26,
+        };
+
+        // scores are from white's view, the evaluation after the move from the opponent's
+        assert_eq!(
+            played.report(179),
+            "kibitz 2... f6 score +1.79 swing +1.53 better Nc6 (+0.26)"
+        );
+
+        // small losses don't get an alternative, the engine's move never does
+        assert_eq!(played.report(40), "kibitz 2... f6 score +0.40 swing +0.14");
+        let best = PlayedMove {
+            board_move: c6,
+            ..played
+        };
+        assert_eq!(best.report(CHECKMATE_SCORE - 2), "kibitz 2... f6 score #1");
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_kibitz_moves() {
+        use crate::controller::controller::GameController;
+        use crate::controller::kibitz::Kibitz;
+        use std::time::Duration;
+
+        let mut kibitz = Kibitz::new(GameController::new(), Duration::from_millis(10));
+
+        // moves in either notation, illegal ones ignored
+        for notation in ["e4", "e7e5", "Nf3", "Ke3"] {
+            assert!(kibitz.handle(notation));
+        }
+        assert_eq!(kibitz.controller.game.history.len(), 3);
+
+        assert!(kibitz.handle("undo"));
+        assert_eq!(kibitz.controller.game.history.len(), 2);
+
+        assert!(!kibitz.handle("quit"));
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use crate::controller::bench::{
+        BENCH_FENS, BenchResult, format_result, load_results, movegen_checksum, run_benchmarks,
+    };
+    use crate::engine::evaluate::evaluate_classical;
+    use crate::game::board::Game;
+    use std::time::Duration;
+
+    #[test]
+    fn test_movegen_checksum() {
+        // the root of each position is one node, with nothing generated yet
+        let roots = movegen_checksum(0);
+        assert_eq!(roots.nodes, 8);
+
+        let checksum = movegen_checksum(2);
+        assert_eq!(checksum, movegen_checksum(2));
+        assert_ne!(checksum.fingerprint(), roots.fingerprint());
+        assert_ne!(checksum.moves, movegen_checksum(1).moves);
+
+        // the node counts are those of perft
+        let perft_nodes = BENCH_FENS
+            .iter()
+            .map(|fen| crate::perft(fen, 2).unwrap() as u64)
+            .sum::<u64>();
+        assert_eq!(checksum.nodes, perft_nodes);
+    }
+
+    #[test]
+    fn test_evaluations_are_deterministic() {
+        // integer evaluations come out the same on every platform and build (the NNUE with or
+        // without AVX2), so they can be pinned down exactly: (HCE, NNUE), from white's view
+        let expected = [
+            (15, 86),
+            (42, -42),
+            (-203, -318),
+            (15, 19),
+            (263, 299),
+            (15, 157),
+            (525, 1186),
+            (115, 399),
+        ];
+
+        for (fen, expected) in BENCH_FENS.iter().zip(expected) {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(
+                (evaluate_classical(&game), game.evaluate()),
+                expected,
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_searches_are_deterministic() {
+        use crate::controller::controller::GameController;
+
+        let search = |fen: &str| {
+            let mut controller = GameController::new();
+            controller.initialize();
+            controller.set_board_from_fen(fen).unwrap();
+            controller.search(vec!["depth".to_string(), "7".to_string()], false);
+            let result = controller.wait_for_search().unwrap();
+            (result.best_move, result.evaluation, result.pv)
+        };
+
+        // a single-threaded fixed depth search only depends on the (integer) scores
+        for fen in BENCH_FENS {
+            assert_eq!(search(fen), search(fen), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_run_benchmarks() {
+        let results = run_benchmarks(Duration::ZERO);
+
+        let names = results.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "get_moves",
+                "make_unmake",
+                "evaluate_hce",
+                "evaluate_nnue",
+                "clone",
+                "fork"
+            ]
+        );
+        assert!(results.iter().all(|r| r.ns_per_op > 0.0));
+    }
+
+    #[test]
+    fn test_baseline_comparison() {
+        let path =
+            std::env::temp_dir().join(format!("prokopakop-bench-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{ "commit": "abc1234", "results": { "get_moves": 200.0 } }"#,
+        )
+        .unwrap();
+
+        let (commit, timings) = load_results(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(commit, "abc1234");
+
+        let result = BenchResult {
+            name: "get_moves".to_string(),
+            ns_per_op: 150.0,
+            spread: 0.02,
+        };
+        let before = timings["get_moves"].as_f64();
+        assert_eq!(
+            format_result(&result, before),
+            "get_moves           150.0 ns/op  ± 2.0%       200.0 ns/op   -25.0%"
+        );
+        assert_eq!(
+            format_result(&result, None),
+            "get_moves           150.0 ns/op  ± 2.0%"
+        );
+
+        assert!(load_results("/nonexistent/baseline.json").is_err());
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use crate::controller::config::EngineConfig;
+    use crate::controller::controller::GameController;
+
+    #[test]
+    fn test_parse_config() {
+        let config = EngineConfig::parse(
+            "# defaults for every GUI\n\
+             Hash = 256\n\
+             \n\
+             Threads=4   # one per core\n\
+             \"Move Overhead\" = 30\n\
+             NNUE = \"nets/latest \\\"v2\\\".nnue\"\n\
+             BookFile = 'books/main.bin' # literal\n\
+             Ponder = true\n",
+        )
+        .unwrap();
+
+        let options: Vec<(&str, &str)> = config
+            .options
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            options,
+            vec![
+                ("Hash", "256"),
+                ("Threads", "4"),
+                ("Move Overhead", "30"),
+                ("NNUE", "nets/latest \"v2\".nnue"),
+                ("BookFile", "books/main.bin"),
+                ("Ponder", "true"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert!(EngineConfig::parse("[options]\nHash = 256").is_err());
+        assert!(EngineConfig::parse("Hash 256").is_err());
+        assert!(EngineConfig::parse("NNUE = \"unclosed").is_err());
+        assert!(EngineConfig::parse("Hash = 256 512").is_err());
+        assert!(EngineConfig::parse("= 256").is_err());
+        assert_eq!(EngineConfig::parse("").unwrap(), EngineConfig::default());
+    }
+
+    #[test]
+    fn test_config_sets_options() {
+        let config = EngineConfig::parse("MoveOverhead = 30\nThreads = 3").unwrap();
+
+        let mut controller = GameController::new();
+        for (name, value) in &config.options {
+            controller.set_option(name, value).unwrap();
+        }
+        assert_eq!(controller.move_overhead, 30);
+        assert_eq!(controller.threads, 3);
+
+        // the GUI still gets the last word
+        controller.set_option("Move Overhead", "50").unwrap();
+        assert_eq!(controller.move_overhead, 50);
+
+        // a file that's gone fails its entry alone
+        let config = EngineConfig::parse("NNUE = \"/nonexistent/net.nnue\"\nThreads = 2").unwrap();
+        let failed = config
+            .options
+            .iter()
+            .filter(|(name, value)| controller.set_option(name, value).is_err())
+            .count();
+        assert_eq!((failed, controller.threads), (1, 2));
+    }
+}
+
+#[cfg(test)]
+mod estimate_tests {
+    use crate::controller::estimate::estimate_tree;
+    use crate::game::board::Game;
+
+    #[test]
+    fn test_estimate_matches_perft() {
+        let game = Game::new(None);
+
+        // the root alone, and exactly the 20 moves of the starting position
+        let estimate = estimate_tree(&game, 0, 10, None);
+        assert_eq!((estimate.nodes, estimate.leaves), (1.0, 1.0));
+
+        let estimate = estimate_tree(&game, 1, 10, None);
+        assert_eq!((estimate.nodes, estimate.leaves), (21.0, 20.0));
+        assert!((estimate.branching_factor() - 20.0).abs() < 1e-9);
+
+        // perft 3 is 8902, which the random lines land near
+        let estimate = estimate_tree(&game, 3, 2000, Some(1000.0));
+        assert!(
+            (estimate.leaves - 8902.0).abs() < 8902.0 * 0.1,
+            "estimated {} leaves",
+            estimate.leaves
+        );
+        assert!(estimate.measured_by_search);
+        assert!(estimate.alpha_beta_leaves() < estimate.leaves);
+    }
+
+    #[test]
+    fn test_estimate_of_finished_game() {
+        // lines can't go on past mate, so there are no leaves below it
+        let mated = Game::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3")
+            .unwrap();
+        let estimate = estimate_tree(&mated, 4, 10, None);
+        assert_eq!((estimate.nodes, estimate.leaves), (1.0, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod eval_diff_tests {
+    use crate::controller::eval_diff::collect_eval_diffs;
+    use crate::engine::evaluate::evaluate_classical;
+    use crate::game::board::Game;
+    use crate::game::pgn::PgnReader;
+
+    const PGN: &str = r#"[Event "First"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Bxc6 dxc6 5. Nxe5 Qd4 6. Nf3 Qxe4+ 7. Kf1 *
+
+[Event "Broken"]
+
+1. e4 e5 2. Ke3 *
+
+[Event "Second"]
+
+1. e4 e5 2. Nf3 d6 3. d4 Bg4 4. dxe5 Bxf3 5. Qxf3 dxe5 *
+"#;
+
+    #[test]
+    fn test_collect_eval_diffs() {
+        let summary = collect_eval_diffs(PgnReader::new(PGN.as_bytes()), 5).unwrap();
+
+        assert_eq!((summary.games, summary.skipped_games), (2, 1));
+        // the shared opening positions are compared once, and the check after 6... Qxe4+ not
+        // at all: 13 of the first game (the start included), 7 new ones of the second
+        assert_eq!(summary.positions, 13 + 7);
+        assert_eq!(summary.largest.len(), 5);
+
+        let differences: Vec<_> = summary
+            .largest
+            .iter()
+            .map(|diff| diff.difference().abs())
+            .collect();
+        assert!(differences.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(differences[0] as i64 * summary.positions as i64 >= summary.total_abs_difference);
+
+        // the records are positions again, with the evaluations they were written with
+        for diff in &summary.largest {
+            let epd = diff.to_epd();
+            let position = epd.split(" c0 ").next().unwrap();
+            let game = Game::new(Some(position));
+
+            assert_eq!(game.zobrist_key, Game::new(Some(&diff.fen)).zobrist_key);
+            assert_eq!(game.evaluate(), diff.nnue);
+            assert_eq!(evaluate_classical(&game), diff.hce);
+            assert!(epd.ends_with(&format!("c1 \"game {} ply {}\";", diff.game, diff.ply)));
+        }
+
+        // more than there are keeps all of them
+        let summary = collect_eval_diffs(PgnReader::new(PGN.as_bytes()), 100).unwrap();
+        assert_eq!(summary.largest.len(), summary.positions);
+    }
+}
@@ -18,7 +18,7 @@ use std::fs;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter};
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "train")]
@@ -304,12 +304,52 @@ fn run_deduplicate(input_file: Option<&str>) {
     }
 }
 
+// How often the progress line is refreshed
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+fn format_duration(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Progress bar of the bytes processed so far, with the ETA extrapolated from the time taken
+fn progress_line(done: u64, total: u64, elapsed_secs: f64) -> String {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    };
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64) as usize;
+
+    let eta = if done == 0 {
+        "?".to_string()
+    } else {
+        format_duration(elapsed_secs / fraction - elapsed_secs)
+    };
+
+    format!(
+        "[{}{}] {:5.1}% ({:.1}/{:.1} MB), elapsed {}, ETA {}",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled),
+        fraction * 100.0,
+        done as f64 / 1e6,
+        total as f64 / 1e6,
+        format_duration(elapsed_secs),
+        eta
+    )
+}
+
 fn convert_text(
     inp_path: impl AsRef<Path>,
     out_path: impl AsRef<Path>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let timer = Instant::now();
 
+    let total_bytes = fs::metadata(&inp_path)?.len();
+    let mut bytes_processed = 0u64;
+    let mut last_progress = timer;
+
     let file = BufReader::new(File::open(&inp_path)?);
 
     let mut data = Vec::new();
@@ -319,7 +359,18 @@ fn convert_text(
     let mut output = BufWriter::new(File::create(&out_path)?);
 
     for line in file.lines() {
-        match line?.parse::<ChessBoard>() {
+        let line = line?;
+        bytes_processed += line.len() as u64 + 1;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            eprint!(
+                "\r{}",
+                progress_line(bytes_processed, total_bytes, timer.elapsed().as_secs_f64())
+            );
+        }
+
+        match line.parse::<ChessBoard>() {
             Ok(pos) => {
                 results[pos.result_idx()] += 1;
                 data.push(pos);
@@ -335,6 +386,11 @@ fn convert_text(
 
     BulletFormat::write_to_bin(&mut output, &data)?;
 
+    eprintln!(
+        "\r{}",
+        progress_line(total_bytes, total_bytes, timer.elapsed().as_secs_f64())
+    );
+
     println!(
         "Summary: {} Positions in {:.2} seconds",
         results.iter().sum::<u64>(),